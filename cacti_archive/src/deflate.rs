@@ -1,7 +1,10 @@
-//! Implementation for decompressing the DEFLATE format based on RFC 1951.
+//! Implementation for decompressing and compressing the DEFLATE format based
+//! on RFC 1951.
 //!
-//! The only user-facing module here is the [Inflate](struct.Inflate.html) type.
-//! You can decompress raw DEFLATE-compressed files with it.
+//! The user-facing types here are [Inflate](struct.Inflate.html), that you can
+//! decompress raw DEFLATE-compressed files with, and
+//! [Deflate](struct.Deflate.html) for compressing with a given
+//! [CompressionLevel](enum.CompressionLevel.html).
 //!
 //! # Basic usage
 //!
@@ -20,47 +23,27 @@
 //! inflate.read_to_end(&mut result);
 //! assert_eq!(result, "abcabcabcabcabcabcabcabcabcabc".bytes().collect::<Vec<_>>());
 //! ```
+//!
+//! Compressing works through `std::io::Write`, the stream has to be finished
+//! explicitly:
+//!
+//! ```
+//! use std::io::Write;
+//! use cacti_archive::deflate::{Deflate, CompressionLevel};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut deflate = Deflate::new(Vec::new(), CompressionLevel::Default);
+//! deflate.write_all(b"abcabcabcabcabcabcabcabcabcabc")?;
+//! let compressed = deflate.finish()?;
+//! assert!(compressed.len() < 30);
+//! # Ok(())
+//! # }
+//! ```
 
-use std::io::{Result, Error, ErrorKind, Read};
-use std::mem::MaybeUninit;
-use std::hash::{Hasher, BuildHasherDefault};
+use std::io::{Result, Error, ErrorKind, Read, Write};
 use std::fmt;
-
-// ////////////////////////////////////////////////////////////////////////// //
-//                                  FNV Hash                                  //
-// ////////////////////////////////////////////////////////////////////////// //
-
-// Reference:
-// https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function#FNV-1a_hash
-
-struct FnvHasher(u64);
-
-impl Default for FnvHasher {
-    #[inline(always)]
-    fn default() -> FnvHasher {
-        FnvHasher(0xcbf29ce484222325)
-    }
-}
-
-impl Hasher for FnvHasher {
-    #[inline(always)]
-    fn finish(&self) -> u64 {
-        self.0
-    }
-
-    #[inline(always)]
-    fn write(&mut self, bytes: &[u8]) {
-        let mut hash = self.0;
-        for byte in bytes {
-            hash = hash ^ (*byte as u64);
-            hash = hash.wrapping_mul(0x100000001b3);
-        }
-        self.0 = hash;
-    }
-}
-
-type FnvBuildHasher = BuildHasherDefault<FnvHasher>;
-type HashMap<K, V> = std::collections::HashMap<K, V, FnvBuildHasher>;
+use std::mem::MaybeUninit;
+use crate::ring::SlidingWindow;
 
 // ////////////////////////////////////////////////////////////////////////// //
 //                              Bitwise reading                               //
@@ -70,11 +53,25 @@ type HashMap<K, V> = std::collections::HashMap<K, V, FnvBuildHasher>;
 const BIT_READER_CACHE_SIZE: usize = 8;
 
 /// A bitwise adapter for readers for processing data on non-byte boundlaries.
+///
+/// To survive readers that return `WouldBlock`, the state can be saved with
+/// `checkpoint` and restored with `rollback`. The bytes read since the last
+/// checkpoint are recorded, and fed in again after a rollback.
 #[derive(Debug)]
 struct BitReader<R: Read> {
     reader: R,
     cache: [u8; BIT_READER_CACHE_SIZE],
     bit_index: usize,
+    valid_bits: usize,
+    /// The number of bits consumed, that are no longer in the cache.
+    discarded_bits: u64,
+    /// The bytes read from `reader` since the last checkpoint.
+    replay: Vec<u8>,
+    /// The number of bytes of `replay` already fed into the cache.
+    replay_pos: usize,
+    /// The cache, `bit_index`, `valid_bits` and `discarded_bits` at the last
+    /// checkpoint.
+    saved: ([u8; BIT_READER_CACHE_SIZE], usize, usize, u64),
 }
 
 impl <R: Read> BitReader<R> {
@@ -84,15 +81,98 @@ impl <R: Read> BitReader<R> {
             reader,
             cache: [0u8; BIT_READER_CACHE_SIZE],
             bit_index: BIT_READER_CACHE_SIZE * 8,
+            valid_bits: BIT_READER_CACHE_SIZE * 8,
+            discarded_bits: 0,
+            replay: Vec::new(),
+            replay_pos: 0,
+            saved: ([0u8; BIT_READER_CACHE_SIZE], BIT_READER_CACHE_SIZE * 8, BIT_READER_CACHE_SIZE * 8, 0),
+        }
+    }
+
+    /// Replaces the underlying reader, discarding the cached bytes of the
+    /// previous one, so the next read starts from the new reader.
+    fn reset(&mut self, reader: R) {
+        self.reader = reader;
+        self.cache = [0u8; BIT_READER_CACHE_SIZE];
+        self.bit_index = BIT_READER_CACHE_SIZE * 8;
+        self.valid_bits = BIT_READER_CACHE_SIZE * 8;
+        self.discarded_bits = 0;
+        self.replay.clear();
+        self.replay_pos = 0;
+        self.checkpoint();
+    }
+
+    /// Returns a reference to the underlying reader.
+    fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Saves the current state, everything consumed after this can be
+    /// restored with `rollback`.
+    #[inline(always)]
+    fn checkpoint(&mut self) {
+        if self.replay_pos == self.replay.len() {
+            // The usual case, nothing is left to replay
+            self.replay.clear();
+        }
+        else {
+            self.replay.drain(..self.replay_pos);
+        }
+        self.replay_pos = 0;
+        self.saved = (self.cache, self.bit_index, self.valid_bits, self.discarded_bits);
+    }
+
+    /// Restores the state of the last checkpoint, the bytes read since then
+    /// are going to be read again.
+    fn rollback(&mut self) {
+        let (cache, bit_index, valid_bits, discarded_bits) = self.saved;
+        self.cache = cache;
+        self.bit_index = bit_index;
+        self.valid_bits = valid_bits;
+        self.discarded_bits = discarded_bits;
+        self.replay_pos = 0;
+    }
+
+    /// Returns the number of bits consumed from the underlying reader.
+    fn position(&self) -> u64 {
+        // The initial, empty cache counts as consumed
+        self.discarded_bits + self.bit_index as u64 - (BIT_READER_CACHE_SIZE * 8) as u64
+    }
+
+    /// Reads into the cache starting at the given index. The bytes recorded
+    /// before a rollback are replayed first, then the new ones are recorded.
+    #[inline(always)]
+    fn fetch(&mut self, at: usize) -> Result<usize> {
+        let pending = &self.replay[self.replay_pos..];
+        let n = if pending.is_empty() {
+            let n = self.reader.read(&mut self.cache[at..])?;
+            self.replay.extend_from_slice(&self.cache[at..(at + n)]);
+            n
         }
+        else {
+            let n = std::cmp::min(pending.len(), BIT_READER_CACHE_SIZE - at);
+            self.cache[at..(at + n)].copy_from_slice(&pending[..n]);
+            n
+        };
+        self.replay_pos += n;
+        Ok(n)
     }
 
-    /// Makes sure to have the maximum number of unread elements in the cache
-    /// possible.
+    /// Makes sure to have at least `bits` unread bits in the cache, unless the
+    /// underlying reader has ended. When refilling, the maximum number of
+    /// unread elements are read into the cache possible.
+    ///
+    /// If the reader returns an error before enough bits are read, the bytes
+    /// read so far are kept in the cache, so the call can be retried.
     #[inline(always)]
-    fn ensure_cache(&mut self, minbits: usize) -> Result<()> {
-        if self.bit_index < minbits {
-            // Cache is full with unread bytes
+    fn ensure_cache(&mut self, bits: usize) -> Result<()> {
+        if self.valid_bits.saturating_sub(self.bit_index) >= bits {
+            // Enough unread bits are cached
             return Ok(());
         }
         // We can throw away some bytes, also need to read as many
@@ -102,13 +182,54 @@ impl <R: Read> BitReader<R> {
         for i in 0..keep {
             self.cache[i] = self.cache[i + can_read];
         }
+        self.discarded_bits += (can_read * 8) as u64;
         // Make sure our bit-index now points into the first byte
         self.bit_index %= 8;
-        // Read into the extra space
-        self.reader.read(&mut self.cache[keep..])?;
+        // Read into the extra space, which starts after the real data we kept
+        let mut len = (self.valid_bits / 8).saturating_sub(can_read);
+        // Near the end of the reader the space might not get filled, zero it,
+        // so peeking past the real data gives the same bits every time
+        for b in &mut self.cache[len..] {
+            *b = 0;
+        }
+        while len < BIT_READER_CACHE_SIZE {
+            match self.fetch(len) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.valid_bits = len * 8;
+                    if self.valid_bits - self.bit_index >= bits {
+                        // We can go on with what we have
+                        return Ok(());
+                    }
+                    return Err(e);
+                },
+            }
+        }
+        self.valid_bits = len * 8;
         Ok(())
     }
 
+    /// Checks, if all the consumed bits came from the underlying reader, and
+    /// not from past the end of it.
+    #[inline(always)]
+    fn check_eof(&self) -> Result<()> {
+        if self.bit_index > self.valid_bits {
+            Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected end of DEFLATE stream!"))
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Returns the number of unread, whole bytes in the cache, that came from
+    /// the underlying reader. The rest of the cache is zeroes.
+    #[inline(always)]
+    fn cached_bytes(&self) -> usize {
+        self.valid_bits.saturating_sub(self.bit_index) / 8
+    }
+
     /// Returns the cache reinterpreted as an `u32`.
     #[inline(always)]
     fn cache_as_u64(&mut self) -> u64 {
@@ -118,7 +239,7 @@ impl <R: Read> BitReader<R> {
     /// Peeks the bit at the given offset without consuming any of the input.
     #[inline(always)]
     fn peek_bit(&mut self, offset: usize) -> Result<u8> {
-       self.ensure_cache(64)?;
+       self.ensure_cache(offset + 1)?;
        let result = ((self.cache_as_u64() >> (self.bit_index + offset)) & 1) as u8;
        Ok(result)
     }
@@ -127,7 +248,7 @@ impl <R: Read> BitReader<R> {
     #[inline(always)]
     fn read_bit(&mut self) -> Result<u8> {
         let result = self.peek_bit(0)?;
-        self.bit_index += 1;
+        self.consume_bits(1)?;
         Ok(result)
     }
 
@@ -138,7 +259,8 @@ impl <R: Read> BitReader<R> {
             0b00000000, 0b00000001, 0b00000011, 0b00000111, 0b00001111,
             0b00011111, 0b00111111, 0b01111111, 0b11111111,
         ];
-        self.ensure_cache(56)?;
+        debug_assert!(count < MASKS.len(), "Can't peek more than 8 bits into an u8!");
+        self.ensure_cache(8)?;
         let result = ((self.cache_as_u64() >> self.bit_index) & MASKS[count]) as u8;
         Ok(result)
     }
@@ -147,7 +269,7 @@ impl <R: Read> BitReader<R> {
     #[inline(always)]
     fn read_to_u8(&mut self, count: usize) -> Result<u8> {
         let result = self.peek_to_u8(count)?;
-        self.bit_index += count;
+        self.consume_bits(count)?;
         Ok(result)
     }
 
@@ -162,7 +284,8 @@ impl <R: Read> BitReader<R> {
             0b0000111111111111, 0b0001111111111111, 0b0011111111111111,
             0b0111111111111111, 0b1111111111111111,
         ];
-        self.ensure_cache(48)?;
+        debug_assert!(count < MASKS.len(), "Can't peek more than 16 bits into an u16!");
+        self.ensure_cache(16)?;
         let result = ((self.cache_as_u64() >> self.bit_index) & MASKS[count]) as u16;
         Ok(result)
     }
@@ -171,14 +294,15 @@ impl <R: Read> BitReader<R> {
     #[inline(always)]
     fn read_to_u16(&mut self, count: usize) -> Result<u16> {
         let result = self.peek_to_u16(count)?;
-        self.bit_index += count;
+        self.consume_bits(count)?;
         Ok(result)
     }
 
     /// Consumes the given amount of bits.
     #[inline(always)]
-    fn consume_bits(&mut self, count: usize) {
+    fn consume_bits(&mut self, count: usize) -> Result<()> {
         self.bit_index += count;
+        self.check_eof()
     }
 
     /// Skips to the next byte boundlary.
@@ -187,36 +311,130 @@ impl <R: Read> BitReader<R> {
         self.bit_index += (8 - self.bit_index % 8) % 8;
     }
 
-    /// Reads a little-endian `u16` aligned to bytes.
+    /// Reads a little-endian `u16` aligned to bytes. If the cache holds less
+    /// than 2 bytes, it's refilled from the underlying reader first. Nothing
+    /// is consumed, if there aren't enough bytes left.
     #[inline(always)]
     fn read_aligned_le_u16(&mut self) -> Result<u16> {
         self.skip_to_byte();
-        self.ensure_cache(8)?;
-        let result = u16::from_le_bytes([self.cache[0], self.cache[1]]);
+        self.ensure_cache(16)?;
+        if self.cached_bytes() < 2 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected end of DEFLATE stream!"));
+        }
+        let at = self.bit_index / 8;
         self.bit_index += 16;
-        Ok(result)
+        Ok(u16::from_le_bytes([self.cache[at], self.cache[at + 1]]))
     }
 
-    /// Tries to fill the given buffer to full capacity.
-    #[inline(always)]
-    fn read_aligned_to_buffer(&mut self, buffer: &mut [u8]) -> Result<()> {
+    /// Reads aligned bytes into the given buffer, returning the number of
+    /// bytes read. The cached bytes are handed out first, and only when the
+    /// cache is drained is the underlying reader read directly.
+    ///
+    /// The buffer must not be empty. Reading at the end of the input is an
+    /// error of kind `UnexpectedEof`.
+    fn read_aligned_available(&mut self, buffer: &mut [u8]) -> Result<usize> {
         self.skip_to_byte();
-        self.ensure_cache(8)?;
-        if buffer.len() <= BIT_READER_CACHE_SIZE {
-            // No extra reads
-            buffer.copy_from_slice(&self.cache[..buffer.len()]);
-            self.bit_index += buffer.len() * 8;
-            Ok(())
+        let cached = self.cached_bytes();
+        if cached > 0 {
+            let n = std::cmp::min(cached, buffer.len());
+            let at = self.bit_index / 8;
+            buffer[..n].copy_from_slice(&self.cache[at..(at + n)]);
+            self.bit_index += n * 8;
+            return Ok(n);
         }
-        else {
-            // Full cache invalidation, extra reads
-            buffer[..BIT_READER_CACHE_SIZE].copy_from_slice(&self.cache);
-            // Extra read
-            self.reader.read_exact(&mut buffer[BIT_READER_CACHE_SIZE..])?;
-            self.bit_index = BIT_READER_CACHE_SIZE * 8;
-            Ok(())
+        let pending = &self.replay[self.replay_pos..];
+        let n = loop {
+            let read = if pending.is_empty() {
+                self.reader.read(buffer)
+            }
+            else {
+                let n = std::cmp::min(pending.len(), buffer.len());
+                buffer[..n].copy_from_slice(&pending[..n]);
+                Ok(n)
+            };
+            match read {
+                Ok(n) => break n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected end of DEFLATE stream!"));
+        }
+        self.replay_pos = std::cmp::min(self.replay_pos + n, self.replay.len());
+        // The bytes are handed out right away, there is nothing to roll back
+        self.bit_index = self.valid_bits;
+        self.discarded_bits += (n * 8) as u64;
+        self.checkpoint();
+        Ok(n)
+    }
+}
+
+// ////////////////////////////////////////////////////////////////////////// //
+//                              Bitwise writing                               //
+// ////////////////////////////////////////////////////////////////////////// //
+
+/// A bitwise adapter for writers, the counterpart of `BitReader`. Bits are
+/// collected in a byte buffer, that has to be flushed explicitly.
+#[derive(Debug)]
+struct BitWriter<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    bits: u64,
+    bit_count: usize,
+}
+
+impl <W: Write> BitWriter<W> {
+    /// Creates a new `BitWriter` from the given writer.
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: Vec::new(),
+            bits: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Writes the lowest `count` bits of `bits`, least significant bit first.
+    #[inline(always)]
+    fn write_bits(&mut self, bits: u32, count: usize) {
+        debug_assert!(count <= 32, "Can't write more than 32 bits at once!");
+        let mask = (1u64 << count) - 1;
+        self.bits |= (bits as u64 & mask) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.buffer.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
         }
     }
+
+    /// Pads the written bits with zeroes to the next byte boundlary.
+    #[inline(always)]
+    fn pad_to_byte(&mut self) {
+        let padding = (8 - self.bit_count % 8) % 8;
+        self.write_bits(0, padding);
+    }
+
+    /// Writes the given bytes aligned to bytes.
+    #[inline(always)]
+    fn write_aligned_bytes(&mut self, bytes: &[u8]) {
+        self.pad_to_byte();
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Writes the complete bytes to the underlying writer. The bits of an
+    /// incomplete byte are kept.
+    fn flush_buffer(&mut self) -> Result<()> {
+        self.writer.write_all(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
 }
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -293,12 +511,14 @@ fn generate_huffman_from_lengths<F>(code_lens: &[usize], mut f: F)
 
 /// The maximum number of bits a Huffman-code allows for LUT optimizations.
 const HUFFMAN_LUT_BITS: usize = 10;
-/// The symbol value that's considered an invalid entry.
-const HUFFMAN_INVALID_SYMBOL: u16 = 999;
+/// The number of bits longer codes can have after the LUT bits. These index
+/// into the subtables.
+const HUFFMAN_SUBTABLE_BITS: usize = DEFLATE_MAX_BITS - HUFFMAN_LUT_BITS;
 
-// NOTE: Instead of invalid symbols the LUT could somehow aid the search for the
-// given symbol. This is possible because a subset of codes must start with the
-// searched bits.
+// Whole codes are peeked into an `u16` and the subtables are indexed by the
+// bits after the LUT bits, so neither can be wider than that. Indexing out of
+// bounds here fails the compilation.
+const _: () = [()][(DEFLATE_MAX_BITS > 16 || HUFFMAN_LUT_BITS > DEFLATE_MAX_BITS) as usize];
 
 // NOTE: We don't need `usize` for code-lengths, an `u8` is enough.
 
@@ -309,11 +529,25 @@ struct HuffmanCode {
     length: usize,
 }
 
+/// An entry in the lookup-tables of `HuffmanCodes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LutEntry {
+    /// No code starts with the bits of the index.
+    Invalid,
+    /// The code that starts with the bits of the index.
+    Code(HuffmanCode),
+    /// The codes starting with the bits of the index are longer than what the
+    /// LUT can hold. The remaining bits index the subtable starting at the
+    /// given offset. Only present in the primary LUT.
+    Subtable(usize),
+}
+
 /// Represents a helper-structure for canonical Huffman-codes that implements
-/// LUT optimization for short codes.
+/// LUT optimization. Short codes are decoded by a single lookup, longer ones
+/// need a second lookup in one of the subtables.
 struct HuffmanCodes {
-    lut: Box<[HuffmanCode; 1 << HUFFMAN_LUT_BITS]>,
-    dict: HashMap<u16, u16>,
+    lut: Box<[LutEntry; 1 << HUFFMAN_LUT_BITS]>,
+    subtables: Vec<LutEntry>,
 }
 
 impl fmt::Debug for HuffmanCodes {
@@ -328,15 +562,10 @@ impl fmt::Debug for HuffmanCodes {
 impl HuffmanCodes {
     /// Creates a new, empty `HuffmanCodes` structure.
     fn new() -> Self {
-        let mut lut: Box<[HuffmanCode; 1 << HUFFMAN_LUT_BITS]> =
-            Box::new(unsafe { MaybeUninit::uninit().assume_init() });
-        for entry in lut.iter_mut() {
-            entry.symbol = HUFFMAN_INVALID_SYMBOL;
-            entry.length = 0;
-        }
+        let lut = Box::new([LutEntry::Invalid; 1 << HUFFMAN_LUT_BITS]);
         Self {
             lut,
-            dict: HashMap::default(),
+            subtables: Vec::new(),
         }
     }
 
@@ -344,9 +573,9 @@ impl HuffmanCodes {
     #[inline(always)]
     fn from_code_lengths(code_lens: &[usize]) -> Self {
         let mut result = Self::new();
-        generate_huffman_from_lengths(code_lens, |mut code, desc| {
+        generate_huffman_from_lengths(code_lens, |code, desc| {
             // We reverse each code as the function generates them in the spec order
-            code = reverse_u16_bits(code) >> (16 - desc.length);
+            let code = (reverse_u16_bits(code) >> (16 - desc.length)) as usize;
             if desc.length <= HUFFMAN_LUT_BITS {
                 // We LUT optimize it
                 // We need to generate all bit-combinations that fit behind this
@@ -355,14 +584,30 @@ impl HuffmanCodes {
                 for i in 0..(1 << gen_bits) {
                     // Assemble the full bit-stream
                     let index = (i << desc.length) | code;
-                    result.lut[index as usize] = desc;
+                    result.lut[index] = LutEntry::Code(desc);
                 }
             }
             else {
-                // Goes into the dictionary
-                // 1-pad it
-                code |= 1 << desc.length;
-                result.dict.insert(code, desc.symbol);
+                // Goes into the subtable of the first bits, allocate it if
+                // this is the first code there
+                let prefix = code & ((1 << HUFFMAN_LUT_BITS) - 1);
+                let offset = match result.lut[prefix] {
+                    LutEntry::Subtable(offset) => offset,
+                    _ => {
+                        let offset = result.subtables.len();
+                        result.subtables.resize(offset + (1 << HUFFMAN_SUBTABLE_BITS), LutEntry::Invalid);
+                        result.lut[prefix] = LutEntry::Subtable(offset);
+                        offset
+                    },
+                };
+                // Same as for the LUT, but for the remaining bits
+                let rest = code >> HUFFMAN_LUT_BITS;
+                let rest_len = desc.length - HUFFMAN_LUT_BITS;
+                let gen_bits = HUFFMAN_SUBTABLE_BITS - rest_len;
+                for i in 0..(1 << gen_bits) {
+                    let index = (i << rest_len) | rest;
+                    result.subtables[offset + index] = LutEntry::Code(desc);
+                }
             }
         });
         result
@@ -371,201 +616,146 @@ impl HuffmanCodes {
     /// Decodes a Huffman-code from the given `BitReader`.
     #[inline(always)]
     fn decode_symbol<R: Read>(&self, r: &mut BitReader<R>) -> Result<u16> {
-        const ONE_PADS: [u16; 15] = [
-            0x2, 0x4, 0x8, 0x10, 0x20, 0x40, 0x80, 0x100, 0x200, 0x400,
-            0x800, 0x1000, 0x2000, 0x4000, 0x8000
-        ];
-        // First we try the LUT-way
-        let bits = r.peek_to_u16(HUFFMAN_LUT_BITS)?;
-        let desc = &self.lut[bits as usize];
-        if desc.symbol != HUFFMAN_INVALID_SYMBOL {
+        // We peek the longest possible code, it's fine to peek past the end
+        let bits = r.peek_to_u16(DEFLATE_MAX_BITS)?;
+        let code = self.lookup(bits)?;
+        r.consume_bits(code.length)?;
+        Ok(code.symbol)
+    }
+
+    /// Looks up the Huffman-code at the start of the given bits, that have to
+    /// hold at least `DEFLATE_MAX_BITS` bits.
+    #[inline(always)]
+    fn lookup(&self, bits: u16) -> Result<HuffmanCode> {
+        let bits = bits as usize;
+        let first = bits & ((1 << HUFFMAN_LUT_BITS) - 1);
+        let entry = match self.lut[first] {
+            LutEntry::Subtable(offset) => {
+                let rest = bits >> HUFFMAN_LUT_BITS;
+                debug_assert!(rest < (1 << HUFFMAN_SUBTABLE_BITS));
+                self.subtables[offset + rest]
+            },
+            entry => entry,
+        };
+        if let LutEntry::Code(code) = entry {
             // Found it
-            r.consume_bits(desc.length);
-            return Ok(desc.symbol);
-        }
-        // Not found, try in the dictionary
-        let mut code = bits;
-        for i in HUFFMAN_LUT_BITS..DEFLATE_MAX_BITS {
-            code = code | ((r.peek_bit(i)? as u16) << i);
-            // 1-pad it
-            let real_code = code | ONE_PADS[i];
-            if let Some(symbol) = self.dict.get(&real_code) {
-                // Found it
-                r.consume_bits(i + 1);
-                return Ok(*symbol);
-            }
+            return Ok(code);
         }
         // Not found
         Err(Error::new(ErrorKind::InvalidData, "No such code!"))
     }
 }
 
-// ////////////////////////////////////////////////////////////////////////// //
-//                               Sliding window                               //
-// ////////////////////////////////////////////////////////////////////////// //
-
-/// The maximum number of bytes the DEFLATE algorithm can reference back.
-const DEFLATE_WINDOW_SIZE: usize = 32768;
-
-/// A fixed-size sliding window implementation using a circular buffer. This is
-/// to store the last 32 KiB for backreferences.
-struct SlidingWindow {
-    buffer: Box<[u8; DEFLATE_WINDOW_SIZE]>,
-    cursor: usize,
-}
-
-impl std::fmt::Debug for SlidingWindow {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.debug_struct("SlidingWindow")
-            // TODO: buffer field
-            // TODO: cursor field
-            .finish()
+/// Calculates the Huffman code-lengths for the given symbol frequencies, so no
+/// code is longer than `max_bits`. Symbols with no occurrences get no code.
+fn huffman_lengths(freqs: &[u32], max_bits: usize) -> Vec<usize> {
+    let mut freqs = freqs.to_vec();
+    loop {
+        let lengths = unlimited_huffman_lengths(&freqs);
+        if lengths.iter().all(|l| *l <= max_bits) {
+            return lengths;
+        }
+        // Flatten the distribution and try again, equal frequencies result in
+        // a balanced tree at the end
+        for f in freqs.iter_mut() {
+            *f -= *f / 2;
+        }
     }
 }
 
-impl SlidingWindow {
-    /// Creates a new `SlidingWindow`.
-    fn new() -> Self {
-        Self{
-            buffer: unsafe { Box::new(MaybeUninit::uninit().assume_init()) },
-            cursor: 0,
+/// Calculates the optimal Huffman code-lengths for the given symbol
+/// frequencies, without any length limitation.
+fn unlimited_huffman_lengths(freqs: &[u32]) -> Vec<usize> {
+    use std::collections::BinaryHeap;
+    use std::cmp::Reverse;
+
+    let mut lengths = vec![0; freqs.len()];
+    // The first nodes are the leaves, then come the inner nodes
+    let mut leaf_symbols = Vec::new();
+    let mut parents = Vec::new();
+    let mut heap = BinaryHeap::new();
+    for (symbol, freq) in freqs.iter().enumerate() {
+        if *freq > 0 {
+            heap.push(Reverse((*freq as u64, parents.len())));
+            leaf_symbols.push(symbol);
+            parents.push(None);
         }
     }
-
-    /// Adds an element to the `SlidingWindow`.
-    #[inline(always)]
-    fn push(&mut self, element: u8) {
-        self.buffer[self.cursor] = element;
-        // Slide
-        self.cursor = (self.cursor + 1) % self.buffer.len();
+    if leaf_symbols.len() == 1 {
+        // A single symbol still needs a bit
+        lengths[leaf_symbols[0]] = 1;
+        return lengths;
     }
-
-    // NOTE: This could be optimized for some cases but it's not that trivial to
-    // do so.
-    /// Adds a slice to the `SlidingWindow`.
-    #[inline(always)]
-    fn push_slice(&mut self, elements: &[u8]) {
-        let copy_end = self.cursor + elements.len();
-        if copy_end <= self.buffer.len() {
-            // Trivial forward-copy
-            self.buffer[self.cursor..copy_end].copy_from_slice(elements);
-            self.cursor = copy_end;
-        }
-        else {
-            // Copy in 2 pieces
-            let part1_len = self.buffer.len() - self.cursor;
-            let cursor_end = copy_end % self.buffer.len();
-            self.buffer[self.cursor..].copy_from_slice(&elements[..part1_len]);
-            self.buffer[..cursor_end].copy_from_slice(&elements[part1_len..]);
-            self.cursor = cursor_end;
-        }
+    // Merge the two least frequent nodes until one is left
+    while heap.len() > 1 {
+        let Reverse((freq1, node1)) = heap.pop().unwrap();
+        let Reverse((freq2, node2)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(None);
+        parents[node1] = Some(node);
+        parents[node2] = Some(node);
+        heap.push(Reverse((freq1 + freq2, node)));
     }
-
-    /// Returns the buffer index corresponding to the given dostance from the
-    /// cursor.
-    #[inline(always)]
-    fn buffer_index_of_dist(&self, dist: isize) -> usize {
-        ((self.cursor as isize + dist) as usize) % self.buffer.len()
+    // Parents always come after their children, so the depths can be
+    // calculated backwards
+    let mut depths = vec![0; parents.len()];
+    for node in (0..parents.len()).rev() {
+        if let Some(parent) = parents[node] {
+            depths[node] = depths[parent] + 1;
+        }
     }
-
-    /// Returns the element the given distance away from the cursor.
-    #[inline(always)]
-    fn peek(&self, dist: isize) -> u8 {
-        let idx = self.buffer_index_of_dist(dist);
-        self.buffer[idx]
+    for (node, symbol) in leaf_symbols.iter().enumerate() {
+        lengths[*symbol] = depths[node];
     }
+    lengths
+}
 
-    /// Copies the back-referenced slice into the buffer and returns the newly
-    /// inserted region as a pair of slices.
-    ///
-    /// This implementation only goes for correctness, no optimizations are
-    /// performed.
-    #[inline(always)]
-    fn backreference_trivial(&mut self, dist: isize, len: usize) -> (&[u8], &[u8]) {
-        let start = self.cursor;
-        for _ in 0..len {
-            let e = self.peek(dist);
-            self.push(e);
+/// Makes sure that at least two symbols have a non-zero frequency, so the
+/// generated code is a complete tree.
+fn ensure_two_symbols(freqs: &mut [u32]) {
+    let mut missing = 2usize.saturating_sub(freqs.iter().filter(|f| **f > 0).count());
+    for f in freqs.iter_mut() {
+        if missing == 0 {
+            break;
         }
-        let end = self.cursor;
-        // Determine if we need to split
-        if end <= start {
-            (&self.buffer[start..], &self.buffer[..end])
-        }
-        else {
-            (&self.buffer[start..end], &self.buffer[0..0])
+        if *f == 0 {
+            *f = 1;
+            missing -= 1;
         }
     }
+}
 
-    /// Copies the back-referenced slice using memcpy. This assumes no overlaps
-    /// or wraps happen.
-    #[inline(always)]
-    fn backreference_memcopy(&mut self, start: usize, len: usize) -> (&[u8], &[u8]) {
-        // Trivial memcopy
-        let src = self.buffer[start..].as_ptr();
-        let dst = self.buffer[self.cursor..].as_mut_ptr();
-        unsafe { std::ptr::copy_nonoverlapping(src, dst, len) };
-        let result_slice = &self.buffer[self.cursor..(self.cursor + len)];
-        self.cursor = (self.cursor + len) % self.buffer.len();
-        (result_slice, &self.buffer[0..0])
-    }
-
-    /// Copies the back-referenced slice with a left-to-right bytewise copy.
-    /// This assumes no wraps happen.
-    #[inline(always)]
-    fn backreference_bytecopy(&mut self, start: usize, len: usize) -> (&[u8], &[u8]) {
-        for i in 0..len {
-            self.buffer[self.cursor + i] = self.buffer[start + i];
-        }
-        let result_slice = &self.buffer[self.cursor..(self.cursor + len)];
-        self.cursor = (self.cursor + len) % self.buffer.len();
-        (result_slice, &self.buffer[0..0])
+/// The canonical Huffman-codes of symbols for encoding.
+#[derive(Debug)]
+struct HuffmanEncoder {
+    /// The codes in reversed bit-order and their lengths for each symbol.
+    codes: Vec<(u16, usize)>,
+}
+
+impl HuffmanEncoder {
+    /// Creates a `HuffmanEncoder` from the given code-lengths.
+    fn from_code_lengths(code_lens: &[usize]) -> Self {
+        let mut codes = vec![(0, 0); code_lens.len()];
+        generate_huffman_from_lengths(code_lens, |code, desc| {
+            // We reverse each code, as they are written starting from the MSB
+            let code = reverse_u16_bits(code) >> (16 - desc.length);
+            codes[desc.symbol as usize] = (code, desc.length);
+        });
+        Self{ codes }
     }
 
-    /// Does backreference by memset-ting a single byte.
-    #[inline(always)]
-    fn backreference_memset(&mut self, byte: u8, len: usize) -> (&[u8], &[u8]) {
-        let dst = self.buffer[self.cursor..].as_mut_ptr();
-        unsafe { std::ptr::write_bytes(dst, byte, len) };
-        let result_slice = &self.buffer[self.cursor..(self.cursor + len)];
-        self.cursor = (self.cursor + len) % self.buffer.len();
-        (result_slice, &self.buffer[0..0])
-    }
-
-    // NOTE: This could be optimized further for some cases but it's not that
-    // trivial to do so.
-    /// Copies the back-referenced slice into the buffer and returns the newly
-    /// inserted region as a pair of slices.
+    /// Returns the length of the code of the given symbol.
     #[inline(always)]
-    fn backreference(&mut self, dist: isize, len: usize) -> (&[u8], &[u8]) {
-        let start_copy = self.buffer_index_of_dist(dist);
-        let end_copy = (start_copy + len) % self.buffer.len();
-
-        let cursor_nowrap = self.cursor + len <= self.buffer.len();
-        let src_no_wrap = start_copy <= end_copy;
-
-        if cursor_nowrap && src_no_wrap {
-            let no_overlap = start_copy >= self.cursor + len || end_copy < self.cursor;
-            if no_overlap {
-                // Trivial memcopy
-                return self.backreference_memcopy(start_copy, len);
-            }
-            // At least we don't need to wrap-check stuff
-            let forward_copy = !(start_copy > self.cursor);
-            if forward_copy {
-                let overlap = self.cursor - start_copy;
-                if overlap == 1 {
-                    // Byte-wise memset
-                    let byte = self.buffer[start_copy];
-                    return self.backreference_memset(byte, len);
-                }
-            }
-
-            return self.backreference_bytecopy(start_copy, len);
-        }
+    fn code_length(&self, symbol: usize) -> usize {
+        self.codes[symbol].1
+    }
 
-        // Fallback
-        self.backreference_trivial(dist, len)
+    /// Writes the code of the given symbol to the `BitWriter`.
+    #[inline(always)]
+    fn write_symbol<W: Write>(&self, w: &mut BitWriter<W>, symbol: usize) {
+        let (code, length) = self.codes[symbol];
+        debug_assert!(length > 0, "The symbol has no code!");
+        w.write_bits(code as u32, length);
     }
 }
 
@@ -573,9 +763,23 @@ impl SlidingWindow {
 //                           Deflate implementation                           //
 // ////////////////////////////////////////////////////////////////////////// //
 
+/// The maximum number of bytes the DEFLATE algorithm can reference back.
+const DEFLATE_WINDOW_SIZE: usize = 32768;
+
 /// The maximum number of bits the DEFLATE spec allows a code-length to be.
 const DEFLATE_MAX_BITS: usize = 15;
 
+/// Returns the code-lengths of the fixed literal-length and distance codes.
+/// RFC 3.2.6.
+fn fixed_huffman_lengths() -> ([usize; 288], [usize; 32]) {
+    let mut lit_len = [0usize; 288];
+    for len in &mut lit_len[000..=143] { *len = 8; }
+    for len in &mut lit_len[144..=255] { *len = 9; }
+    for len in &mut lit_len[256..=279] { *len = 7; }
+    for len in &mut lit_len[280..=287] { *len = 8; }
+    (lit_len, [5usize; 32])
+}
+
 /// State for a non-compressed DEFLATE block.
 #[derive(Debug)]
 struct NonCompressed {
@@ -602,10 +806,20 @@ struct Huffman {
     lit_len: HuffmanCodes,
     /// The distance-code dictionary.
     dist: HuffmanCodes,
+    /// `true`, if there are no distance codes, so the block can only contain
+    /// literals.
+    literals_only: bool,
     /// The currently processed backreference.
     backref: Option<Backref>,
 }
 
+/// A decoded element of a Huffman-encoded block.
+enum HuffmanElement {
+    Literal(u8),
+    Backref(Backref),
+    EndOfBlock,
+}
+
 /// State for the currently decompressed DEFLATE block.
 #[derive(Debug)]
 enum DeflateBlock {
@@ -613,6 +827,50 @@ enum DeflateBlock {
     Huffman(Huffman),
 }
 
+/// The amount of spare capacity `Inflate::read_to_end` reserves at once.
+const READ_TO_END_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Copies `src` to the beginning of `dst`, that has to be at least as long.
+fn copy_to_uninit(dst: &mut [MaybeUninit<u8>], src: &[u8]) {
+    let dst = &mut dst[..src.len()];
+    // SAFETY: The lengths are equal and `MaybeUninit<u8>` has the layout of `u8`
+    unsafe {
+        std::ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr() as *mut u8, src.len());
+    }
+}
+
+/// Zeroes the given buffer, returning it as an initialized one.
+fn fill_zeroed(buf: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    for b in buf.iter_mut() {
+        *b = MaybeUninit::new(0);
+    }
+    // SAFETY: Every element got initialized above
+    unsafe { &mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8]) }
+}
+
+/// The encoding of a DEFLATE block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    /// Non-compressed, stored block.
+    Stored,
+    /// Huffman-encoded block with the fixed codes of the specification.
+    FixedHuffman,
+    /// Huffman-encoded block with the codes stored in its header.
+    DynamicHuffman,
+}
+
+/// Statistics of a decompressed DEFLATE block, see
+/// `Inflate::set_collect_block_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockStat {
+    /// The encoding of the block.
+    pub block_type: BlockType,
+    /// The number of input bits consumed, including the block header.
+    pub input_bits: u64,
+    /// The number of decompressed bytes.
+    pub output_bytes: u64,
+}
+
 /// A type for implementing the DEFLATE decompression algorithm.
 #[derive(Debug)]
 pub struct Inflate<R: Read> {
@@ -620,6 +878,13 @@ pub struct Inflate<R: Read> {
     is_last_block: bool,
     current_block: Option<DeflateBlock>,
     window: SlidingWindow,
+    /// The number of bytes decompressed so far, saturating.
+    produced: usize,
+    size_hint: Option<usize>,
+    collect_stats: bool,
+    block_stats: Vec<BlockStat>,
+    /// The input position, where the current block started.
+    block_start: u64,
 }
 
 impl <R:  Read> Inflate<R> {
@@ -630,8 +895,190 @@ impl <R:  Read> Inflate<R> {
             is_last_block: false,
             current_block: None,
             // NOTE: We could lazily allocate this when needed
-            window: SlidingWindow::new(),
+            window: SlidingWindow::new(DEFLATE_WINDOW_SIZE),
+            produced: 0,
+            size_hint: None,
+            collect_stats: false,
+            block_stats: Vec::new(),
+            block_start: 0,
+        }
+    }
+
+    /// Creates a new `Inflate` structure from the given reader, that's
+    /// expected to decompress to `hint` bytes. The hint usually comes from
+    /// the container format - like the uncompressed size of a Zip entry or
+    /// the `ISIZE` trailer of gzip -, and is used by `read_to_end_hinted` to
+    /// allocate the output buffer up front.
+    ///
+    /// A wrong hint never changes the decompressed data, but a huge one is
+    /// still allocated, so don't pass untrusted values unchecked.
+    pub fn with_size_hint(reader: R, hint: usize) -> Self {
+        let mut result = Self::new(reader);
+        result.size_hint = Some(hint);
+        result
+    }
+
+    /// Returns the expected decompressed size of the whole stream, if it was
+    /// given with `with_size_hint`.
+    pub fn size_hint(&self) -> Option<usize> {
+        self.size_hint
+    }
+
+    /// Sets, if statistics should be collected about the decompressed blocks,
+    /// see `block_stats`. This is useful for analyzing how a stream was
+    /// compressed. Only affects the blocks starting after the call. Disabled
+    /// by default, as it costs a little time per block.
+    pub fn set_collect_block_stats(&mut self, collect: bool) {
+        self.collect_stats = collect;
+    }
+
+    /// Returns the statistics of the blocks decompressed so far, in stream
+    /// order, if enabled with `set_collect_block_stats`. The last block might
+    /// still be in progress.
+    pub fn block_stats(&self) -> &[BlockStat] {
+        &self.block_stats
+    }
+
+    /// Starts decompressing a new stream from the given reader, reusing the
+    /// allocated buffers. Nothing from the previous stream is kept, not even
+    /// the unconsumed bytes of its reader. Only the setting of
+    /// `set_collect_block_stats` is kept, the collected statistics are not.
+    pub fn reset(&mut self, reader: R) {
+        self.reader.reset(reader);
+        self.is_last_block = false;
+        self.current_block = None;
+        self.window.clear();
+        self.produced = 0;
+        self.size_hint = None;
+        self.block_stats.clear();
+    }
+
+    /// Returns a reference to the underlying reader.
+    ///
+    /// Note that the decompressor reads ahead into an internal cache, so the
+    /// reader can be positioned past the data consumed so far.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    ///
+    /// Care must be taken when reading from or repositioning the reader, as
+    /// the already cached bytes are not affected by that, which can corrupt
+    /// the decompressed stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+
+    /// Like `read`, but decompresses into a possibly uninitialized buffer,
+    /// saving the cost of zeroing it beforehand. Returns the number of bytes
+    /// read, exactly that many elements at the start of `buf` are initialized
+    /// after the call. The rest of `buf` is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error or malformed data, an error variant is returned.
+    ///
+    /// If the underlying reader returns an error of kind `WouldBlock`, the
+    /// bytes decompressed so far are returned. When there are none, the error
+    /// is returned instead. Either way, no input is lost, the read can be
+    /// retried once the reader has more data.
+    pub fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        let mut filled = 0;
+        loop {
+            // Check if we need to read more
+            if filled == buf.len() {
+                return Ok(filled);
+            }
+            // Anything unfinished from here on is rolled back, if the reader blocks
+            self.reader.checkpoint();
+            // Check if we need to read in a block
+            if self.current_block.is_none() {
+                // If was the last block, we are done
+                if self.is_last_block {
+                    return Ok(filled);
+                }
+                // We need to read in the next block
+                match self.read_block_header() {
+                    Ok(block) => self.current_block = Some(block),
+                    Err(e) => return self.recover(filled, e),
+                }
+            }
+            // We must have some block here
+            assert!(self.current_block.is_some());
+            let mut block = self.current_block.take();
+            let result = match block.as_mut().unwrap() {
+                DeflateBlock::NonCompressed(nc) =>
+                    self.read_non_compressed(&mut buf[filled..], nc),
+                DeflateBlock::Huffman(huffman) =>
+                    self.read_huffman(&mut buf[filled..], huffman),
+            };
+            let (read, is_over) = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    self.current_block = block;
+                    return self.recover(filled, e);
+                },
+            };
+            filled += read;
+            self.produced = self.produced.saturating_add(read);
+            if self.collect_stats {
+                let position = self.reader.position();
+                if let Some(stat) = self.block_stats.last_mut() {
+                    stat.input_bits = position - self.block_start;
+                    stat.output_bytes += read as u64;
+                }
+            }
+            if is_over {
+                block = None;
+            }
+            self.current_block = block;
+        }
+    }
+
+    /// Handles an error that interrupted `read_uninit` after `filled` bytes.
+    /// If the reader would block, the unfinished step is rolled back, so it
+    /// can be retried later, and the filled bytes are returned, if any.
+    fn recover(&mut self, filled: usize, e: Error) -> Result<usize> {
+        if e.kind() != ErrorKind::WouldBlock {
+            return Err(e);
+        }
+        self.reader.rollback();
+        if filled > 0 {
+            Ok(filled)
+        }
+        else {
+            Err(e)
+        }
+    }
+
+    /// Decompresses exactly enough bytes to fill `buf`.
+    ///
+    /// Unlike `read`, that can return less bytes than requested, this only
+    /// returns once `buf` is full, which is convenient for fixed-layout data.
+    ///
+    /// # Errors
+    ///
+    /// If the DEFLATE stream ends before `buf` is filled, an error of kind
+    /// `UnexpectedEof` is returned. The contents of `buf` are unspecified in
+    /// that case. In case of an IO error or malformed data, an error variant
+    /// is returned.
+    pub fn read_exact_decompressed(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.read_exact(buf)
+    }
+
+    /// Like `read_to_end`, but reserves room for the rest of the stream in
+    /// `buf` up front, based on the size hint. Without a hint, this is the
+    /// same as `read_to_end`. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error or malformed data, an error variant is returned.
+    pub fn read_to_end_hinted(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        if let Some(hint) = self.size_hint {
+            buf.reserve(hint.saturating_sub(self.produced));
         }
+        self.read_to_end(buf)
     }
 
     // Header reading //////////////////////////////////////////////////////////
@@ -653,19 +1100,15 @@ impl <R:  Read> Inflate<R> {
     /// descriptor for it.
     /// RFC 3.2.6.
     fn read_fixed_huffman_header(&mut self) -> Result<Huffman> {
+        let (litlen_lens, dist_lens) = fixed_huffman_lengths();
         // Literal and length codes
-        let mut litlen_lens = [0usize; 288];
-        for i in 000..=143 { litlen_lens[i] = 8; }
-        for i in 144..=255 { litlen_lens[i] = 9; }
-        for i in 256..=279 { litlen_lens[i] = 7; }
-        for i in 280..=287 { litlen_lens[i] = 8; }
         let lit_len = HuffmanCodes::from_code_lengths(&litlen_lens);
         // Distance codes
-        let dist_lens = [5usize; 32];
         let dist = HuffmanCodes::from_code_lengths(&dist_lens);
         Ok(Huffman{
             lit_len,
             dist,
+            literals_only: false,
             backref: None,
         })
     }
@@ -720,6 +1163,9 @@ impl <R:  Read> Inflate<R> {
                 18 => self.reader.read_to_u8(7)? as usize + 11,
                 _ => return Err(Error::new(ErrorKind::InvalidData, "Illegal code length symbol!")),
             };
+            if codelen_idx + repeat > all_codelens.len() {
+                return Err(Error::new(ErrorKind::InvalidData, "Code length repetition is out of bounds!"));
+            }
             for _ in 0..repeat {
                 all_codelens[codelen_idx] = sym_to_repeat;
                 codelen_idx += 1;
@@ -732,7 +1178,10 @@ impl <R:  Read> Inflate<R> {
 
         // Construct distance codes, but also check RFC stuff
         let dist_codelens = &all_codelens[n_litlen..];
-        let dist = if dist_codelens.len() == 1 && dist_codelens[0] == 0 {
+        let no_dist = dist_codelens.len() == 1 && dist_codelens[0] == 0;
+        // Some encoders still define distance codes, but no lengths
+        let literals_only = no_dist || litlen_codelens.iter().skip(257).all(|l| *l == 0);
+        let dist = if no_dist {
             // Just literals
             HuffmanCodes::new()
         }
@@ -763,6 +1212,7 @@ impl <R:  Read> Inflate<R> {
         Ok(Huffman{
             lit_len,
             dist,
+            literals_only,
             backref: None,
         })
     }
@@ -770,14 +1220,26 @@ impl <R:  Read> Inflate<R> {
     /// Reads in a block header, returning the `DeflateBlock` that describes it.
     /// RFC 3.2.3.
     fn read_block_header(&mut self) -> Result<DeflateBlock> {
-        self.is_last_block = self.reader.read_bit()? != 0;
+        let start = self.reader.position();
+        let is_last_block = self.reader.read_bit()? != 0;
         let btype = self.reader.read_to_u8(2)?;
-        match btype {
-            0b00 => Ok(DeflateBlock::NonCompressed(self.read_not_compressed_header()?)),
-            0b01 => Ok(DeflateBlock::Huffman(self.read_fixed_huffman_header()?)),
-            0b10 => Ok(DeflateBlock::Huffman(self.read_dynamic_huffman_header()?)),
-            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid block type!")),
+        let (block, block_type) = match btype {
+            0b00 => (DeflateBlock::NonCompressed(self.read_not_compressed_header()?), BlockType::Stored),
+            0b01 => (DeflateBlock::Huffman(self.read_fixed_huffman_header()?), BlockType::FixedHuffman),
+            0b10 => (DeflateBlock::Huffman(self.read_dynamic_huffman_header()?), BlockType::DynamicHuffman),
+            _ => return Err(Error::new(ErrorKind::InvalidData, "Invalid block type!")),
+        };
+        // Only set once the whole header is read, so a retry can start over
+        self.is_last_block = is_last_block;
+        if self.collect_stats {
+            self.block_start = start;
+            self.block_stats.push(BlockStat{
+                block_type,
+                input_bits: self.reader.position() - start,
+                output_bytes: 0,
+            });
         }
+        Ok(block)
     }
 
     // Reading non-compressed blocks ///////////////////////////////////////////
@@ -785,13 +1247,18 @@ impl <R:  Read> Inflate<R> {
     /// Reads in a non-compressed block to fill the given buffer as much as
     /// possible. Returns a tuple of filled bytes and `true`, if the block has
     /// ended.
-    fn read_non_compressed(&mut self, buf: &mut [u8], state: &mut NonCompressed) -> Result<(usize, bool)> {
+    fn read_non_compressed(&mut self, buf: &mut [MaybeUninit<u8>], state: &mut NonCompressed) -> Result<(usize, bool)> {
         let rem = state.size - state.copied;
         let can_read = std::cmp::min(rem, buf.len());
-        self.reader.read_aligned_to_buffer(&mut buf[..can_read])?;
-        self.window.push_slice(&buf[..can_read]);
-        state.copied += can_read;
-        Ok((can_read, state.size == state.copied))
+        if can_read == 0 {
+            return Ok((0, rem == 0));
+        }
+        // The underlying reader needs an initialized buffer
+        let buf = fill_zeroed(&mut buf[..can_read]);
+        let read = self.reader.read_aligned_available(buf)?;
+        self.window.push_slice(&buf[..read]);
+        state.copied += read;
+        Ok((read, state.size == state.copied))
     }
 
     // Decoding Huffman-encoded blocks /////////////////////////////////////////
@@ -838,7 +1305,10 @@ impl <R:  Read> Inflate<R> {
     /// Reads in a Huffman-encoded block to fill the given buffer as much as
     /// possible. Returns a tuple of filled bytes and `true`, if the block has
     /// ended.
-    fn read_huffman(&mut self, buf: &mut [u8], state: &mut Huffman) -> Result<(usize, bool)> {
+    fn read_huffman(&mut self, buf: &mut [MaybeUninit<u8>], state: &mut Huffman) -> Result<(usize, bool)> {
+        if state.literals_only {
+            return self.read_huffman_literals(buf, &state.lit_len);
+        }
         let mut filled = 0;
         loop {
             // Check if we have read enough
@@ -852,9 +1322,8 @@ impl <R:  Read> Inflate<R> {
                 let can_read = std::cmp::min(backref.length, rem_buf.len());
                 // Copy that amount
                 let (w1, w2) = self.window.backreference(backref.distance, can_read);
-                rem_buf[..w1.len()].copy_from_slice(w1);
-                let rem_buf = &mut rem_buf[w1.len()..];
-                rem_buf[..w2.len()].copy_from_slice(w2);
+                copy_to_uninit(rem_buf, w1);
+                copy_to_uninit(&mut rem_buf[w1.len()..], w2);
                 // We advanced that amount with the read
                 backref.length -= can_read;
                 filled += can_read;
@@ -867,73 +1336,1050 @@ impl <R:  Read> Inflate<R> {
                 }
                 continue;
             }
-            // We need to read a symbol
-            let sym = state.lit_len.decode_symbol(&mut self.reader)?;
-            // Check if end of block
-            if sym == 256 {
-                return Ok((filled, true));
-            }
-            // Not end of block
-            if sym < 256 {
-                // Simple symbol
-                buf[filled] = sym as u8;
-                self.window.push(sym as u8);
-                filled += 1;
-                continue;
+            // We need to read a symbol, if the reader blocks, we return what we
+            // have and continue from here next time
+            self.reader.checkpoint();
+            let element = match self.decode_huffman_element(state, filled) {
+                Ok(element) => element,
+                Err(e) if filled > 0 && e.kind() == ErrorKind::WouldBlock => {
+                    self.reader.rollback();
+                    return Ok((filled, false));
+                },
+                Err(e) => return Err(e),
+            };
+            match element {
+                HuffmanElement::EndOfBlock => return Ok((filled, true)),
+                HuffmanElement::Literal(byte) => {
+                    buf[filled] = MaybeUninit::new(byte);
+                    self.window.push(byte);
+                    filled += 1;
+                },
+                HuffmanElement::Backref(backref) => state.backref = Some(backref),
             }
-            // Length and distance code
-            // We decode the length from the already read symbol, since
-            // their dict. are unified
-            let length = self.decode_huffman_length(sym)?;
-            // Get distance symbol
-            let dist_sym = state.dist.decode_symbol(&mut self.reader)?;
-            // Decode the the distance symbol
-            let distance = -(self.decode_huffman_distance(dist_sym)? as isize);
-            // Add it to the state
-            state.backref = Some(Backref{ length, distance });
         }
     }
+
+    /// Decodes the next literal, end of block or backreference of a
+    /// Huffman-encoded block, `filled` bytes into the current read.
+    #[inline(always)]
+    fn decode_huffman_element(&mut self, state: &Huffman, filled: usize) -> Result<HuffmanElement> {
+        let sym = state.lit_len.decode_symbol(&mut self.reader)?;
+        // Check if end of block
+        if sym == 256 {
+            return Ok(HuffmanElement::EndOfBlock);
+        }
+        // Not end of block
+        if sym < 256 {
+            // Simple symbol
+            return Ok(HuffmanElement::Literal(sym as u8));
+        }
+        // Length and distance code
+        // We decode the length from the already read symbol, since
+        // their dict. are unified
+        let length = self.decode_huffman_length(sym)?;
+        // Get distance symbol
+        let dist_sym = state.dist.decode_symbol(&mut self.reader)?;
+        // Decode the the distance symbol
+        let distance = self.decode_huffman_distance(dist_sym)?;
+        // The distance can't reach before the start of the stream
+        let history = std::cmp::min(self.window.capacity(), self.produced.saturating_add(filled));
+        if distance > history {
+            return Err(Error::new(ErrorKind::InvalidData, "Distance too far back!"));
+        }
+        let distance = -(distance as isize);
+        Ok(HuffmanElement::Backref(Backref{ length, distance }))
+    }
+
+    /// Like `read_huffman`, but for blocks that can only contain literals.
+    /// There are no backreferences to check for, multiple symbols are decoded
+    /// from a single peek of the input, and the window is only updated once
+    /// with all the decoded literals.
+    fn read_huffman_literals(&mut self, buf: &mut [MaybeUninit<u8>], lit_len: &HuffmanCodes) -> Result<(usize, bool)> {
+        let mut filled = 0;
+        let mut ended = false;
+        let result = loop {
+            if ended || filled >= buf.len() {
+                break Ok(());
+            }
+            self.reader.checkpoint();
+            match self.reader.ensure_cache(48) {
+                Ok(()) => {},
+                // Nothing is consumed yet, we can return what we have
+                Err(e) if filled > 0 && e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+            // Decode symbols while the peeked bits surely hold the next code
+            let mut bits = self.reader.cache_as_u64() >> self.reader.bit_index;
+            // If there are less bits than that, we are at the end of the input,
+            // the last symbol is checked by consuming it
+            let mut available = std::cmp::max(
+                self.reader.valid_bits.saturating_sub(self.reader.bit_index),
+                DEFLATE_MAX_BITS);
+            let mut consumed = 0;
+            let mut error = None;
+            while available >= DEFLATE_MAX_BITS && filled < buf.len() {
+                let code = match lit_len.lookup((bits & ((1 << DEFLATE_MAX_BITS) - 1)) as u16) {
+                    Ok(code) => code,
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    },
+                };
+                consumed += code.length;
+                bits >>= code.length;
+                available -= code.length;
+                if code.symbol < 256 {
+                    buf[filled] = MaybeUninit::new(code.symbol as u8);
+                    filled += 1;
+                }
+                else if code.symbol == 256 {
+                    ended = true;
+                    break;
+                }
+                else {
+                    error = Some(Error::new(ErrorKind::InvalidData, "Backreference in a block without distance codes!"));
+                    break;
+                }
+            }
+            // This also catches reading past the end of the input
+            if let Err(e) = self.reader.consume_bits(consumed) {
+                break Err(e);
+            }
+            if let Some(e) = error {
+                break Err(e);
+            }
+        };
+        // SAFETY: The decoded part of the buffer is initialized
+        let decoded = unsafe { &*(&buf[..filled] as *const [MaybeUninit<u8>] as *const [u8]) };
+        self.window.push_slice(decoded);
+        result.map(|_| (filled, ended))
+    }
 }
 
 impl <R: Read> Read for Inflate<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let mut filled = 0;
+        // SAFETY: `MaybeUninit<u8>` has the layout of `u8` and `read_uninit`
+        // only ever writes initialized bytes
+        let buf = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        self.read_uninit(buf)
+    }
+
+    /// Decompresses straight into the spare capacity of `buf`, without
+    /// zero-initializing it first.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
         loop {
-            // Check if we need to read more
-            if filled == buf.len() {
-                return Ok(filled);
-            }
-            // Check if we need to read in a block
-            if self.current_block.is_none() {
-                // If was the last block, we are done
-                if self.is_last_block {
-                    return Ok(filled);
+            if buf.len() == buf.capacity() {
+                // Don't grow an exactly sized buffer just to find the end
+                let mut probe = [MaybeUninit::<u8>::uninit(); 32];
+                match self.read_uninit(&mut probe) {
+                    Ok(0) => return Ok(buf.len() - start),
+                    Ok(read) => {
+                        // SAFETY: `read_uninit` initialized this many bytes
+                        let probe = unsafe { &*(&probe[..read] as *const [MaybeUninit<u8>] as *const [u8]) };
+                        buf.reserve(READ_TO_END_CHUNK_SIZE);
+                        buf.extend_from_slice(probe);
+                        continue;
+                    },
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
                 }
-                // We need to read in the next block
-                self.current_block = Some(self.read_block_header()?);
             }
-            // We must have some block here
-            assert!(self.current_block.is_some());
-            let mut block = self.current_block.take();
-            let (read, is_over) = match block.as_mut().unwrap() {
-                DeflateBlock::NonCompressed(nc) =>
-                    self.read_non_compressed(&mut buf[filled..], nc)?,
-                DeflateBlock::Huffman(huffman) =>
-                    self.read_huffman(&mut buf[filled..], huffman)?,
+            let len = buf.len();
+            // SAFETY: The slice covers exactly the allocated, but unused part
+            // of the vector
+            let spare = unsafe {
+                std::slice::from_raw_parts_mut(
+                    buf.as_mut_ptr().add(len) as *mut MaybeUninit<u8>,
+                    buf.capacity() - len)
             };
-            filled += read;
-            if is_over {
-                block = None;
+            match self.read_uninit(spare) {
+                Ok(0) => return Ok(buf.len() - start),
+                // SAFETY: `read_uninit` initialized this many bytes
+                Ok(read) => unsafe { buf.set_len(len + read) },
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
             }
-            self.current_block = block;
         }
     }
 }
 
+// ////////////////////////////////////////////////////////////////////////// //
+//                         Compression implementation                         //
+// ////////////////////////////////////////////////////////////////////////// //
+
+/// The number of input bytes compressed into a single block. This is also the
+/// maximum size of a non-compressed block.
+const DEFLATE_BLOCK_SIZE: usize = 65535;
+
+/// The shortest repetition DEFLATE can encode.
+const DEFLATE_MIN_REPEAT: usize = 3;
+
+/// The longest repetition DEFLATE can encode.
+const DEFLATE_MAX_REPEAT: usize = 258;
+
+/// The number of bits the starts of repetitions are hashed to.
+const DEFLATE_HASH_BITS: usize = 15;
+
+/// The maximum number of bits the DEFLATE spec allows a code-length code to be.
+const DEFLATE_MAX_CODELEN_BITS: usize = 7;
+
+/// The base lengths of the length symbols, starting from symbol 257.
+/// RFC 3.2.5.
+const LENGTH_BASES: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+    67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+/// The number of extra bits of the length symbols, starting from symbol 257.
+/// RFC 3.2.5.
+const LENGTH_EXTRA_BITS: [usize; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3,
+    4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// The base distances of the distance symbols.
+/// RFC 3.2.5.
+const DISTANCE_BASES: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513,
+    769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+/// The number of extra bits of the distance symbols.
+/// RFC 3.2.5.
+const DISTANCE_EXTRA_BITS: [usize; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8,
+    9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// The order the code-lengths of the code-length code are written in.
+/// RFC 3.2.7.
+const CODELEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// The compression levels of `Deflate`, trading speed for output size, like
+/// the levels of zlib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// No compression, the data is stored in non-compressed blocks.
+    None,
+    /// A quick search for repetitions.
+    Fast,
+    /// A balance between speed and output size.
+    Default,
+    /// The most thorough search for repetitions, resulting in the smallest
+    /// output.
+    Best,
+}
+
+/// Parameters for searching repetitions.
+#[derive(Debug, Clone, Copy)]
+struct SearchParams {
+    /// The maximum number of earlier positions to check for a repetition.
+    max_chain: usize,
+    /// A repetition at least this long ends the search.
+    nice_length: usize,
+    /// If `true`, a repetition is only taken, if the one starting at the next
+    /// byte isn't longer.
+    lazy: bool,
+}
+
+impl CompressionLevel {
+    /// Returns the repetition search parameters for this level, `None`, if
+    /// there's no search.
+    fn search_params(self) -> Option<SearchParams> {
+        match self {
+            Self::None    => None,
+            Self::Fast    => Some(SearchParams{ max_chain: 8   , nice_length: 32 , lazy: false }),
+            Self::Default => Some(SearchParams{ max_chain: 128 , nice_length: 128, lazy: true  }),
+            Self::Best    => Some(SearchParams{ max_chain: 4096, nice_length: 258, lazy: true  }),
+        }
+    }
+}
+
+/// A single element of the LZ77-compressed data.
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Repeat{ length: usize, distance: usize },
+}
+
+/// Returns the index of the entry in the given table of base values, that
+/// encodes the given value.
+#[inline(always)]
+fn base_index(bases: &[usize], value: usize) -> usize {
+    bases.iter().rposition(|b| *b <= value).unwrap()
+}
+
+/// Counts the literal-length and distance symbols of the given tokens. The
+/// end of block symbol is counted too.
+fn token_frequencies(tokens: &[Token]) -> ([u32; 286], [u32; 30]) {
+    let mut lit_len = [0u32; 286];
+    let mut dist = [0u32; 30];
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => lit_len[byte as usize] += 1,
+            Token::Repeat{ length, distance } => {
+                lit_len[257 + base_index(&LENGTH_BASES, length)] += 1;
+                dist[base_index(&DISTANCE_BASES, distance)] += 1;
+            },
+        }
+    }
+    lit_len[256] += 1;
+    (lit_len, dist)
+}
+
+/// Returns the number of bits the symbols with the given frequencies take up,
+/// when encoded with the given codes.
+fn tokens_cost(
+    lit_len_freqs: &[u32],
+    dist_freqs: &[u32],
+    lit_len: &HuffmanEncoder,
+    dist: &HuffmanEncoder,
+) -> usize {
+    let lit_len_bits: usize = lit_len_freqs.iter().enumerate()
+        .filter(|(_, f)| **f > 0)
+        .map(|(s, f)| {
+            let extra = if s > 256 { LENGTH_EXTRA_BITS[s - 257] } else { 0 };
+            *f as usize * (lit_len.code_length(s) + extra)
+        })
+        .sum();
+    let dist_bits: usize = dist_freqs.iter().enumerate()
+        .filter(|(_, f)| **f > 0)
+        .map(|(s, f)| *f as usize * (dist.code_length(s) + DISTANCE_EXTRA_BITS[s]))
+        .sum();
+    lit_len_bits + dist_bits
+}
+
+/// Writes the given tokens and an end of block symbol with the given codes.
+fn write_tokens<W: Write>(
+    w: &mut BitWriter<W>,
+    tokens: &[Token],
+    lit_len: &HuffmanEncoder,
+    dist: &HuffmanEncoder,
+) {
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => lit_len.write_symbol(w, byte as usize),
+            Token::Repeat{ length, distance } => {
+                let index = base_index(&LENGTH_BASES, length);
+                lit_len.write_symbol(w, 257 + index);
+                w.write_bits((length - LENGTH_BASES[index]) as u32, LENGTH_EXTRA_BITS[index]);
+                let index = base_index(&DISTANCE_BASES, distance);
+                dist.write_symbol(w, index);
+                w.write_bits((distance - DISTANCE_BASES[index]) as u32, DISTANCE_EXTRA_BITS[index]);
+            },
+        }
+    }
+    lit_len.write_symbol(w, 256);
+}
+
+/// Writes a non-compressed block.
+/// RFC 3.2.4.
+fn write_non_compressed<W: Write>(w: &mut BitWriter<W>, bytes: &[u8], is_last: bool) {
+    debug_assert!(bytes.len() <= DEFLATE_BLOCK_SIZE, "Too many bytes for a non-compressed block!");
+    w.write_bits(is_last as u32, 1);
+    w.write_bits(0b00, 2);
+    let len = bytes.len() as u16;
+    w.write_aligned_bytes(&len.to_le_bytes());
+    w.write_aligned_bytes(&(!len).to_le_bytes());
+    w.write_aligned_bytes(bytes);
+}
+
+/// The header of a dynamic Huffman-encoded block.
+/// RFC 3.2.7.
+#[derive(Debug)]
+struct DynamicHeader {
+    /// The number of literal-length code-lengths written.
+    lit_len_count: usize,
+    /// The number of distance code-lengths written.
+    dist_count: usize,
+    /// The number of code-length code-lengths written.
+    codelen_count: usize,
+    /// The code-lengths of the code-length code.
+    codelen_lens: [usize; 19],
+    /// The code-length symbols with the number of their extra bits and the
+    /// extra value.
+    symbols: Vec<(usize, usize, u32)>,
+}
+
+impl DynamicHeader {
+    /// Creates the header describing the given literal-length and distance
+    /// code-lengths.
+    fn new(lit_len_lens: &[usize], dist_lens: &[usize]) -> Self {
+        let used_count = |lens: &[usize], min: usize| {
+            let used = lens.iter().rposition(|l| *l != 0).map(|i| i + 1).unwrap_or(0);
+            std::cmp::max(used, min)
+        };
+        let lit_len_count = used_count(lit_len_lens, 257);
+        let dist_count = used_count(dist_lens, 1);
+        // The code-lengths are run-length encoded in one sequence
+        let lens: Vec<_> = lit_len_lens[..lit_len_count].iter()
+            .chain(dist_lens[..dist_count].iter())
+            .cloned()
+            .collect();
+        let mut symbols = Vec::new();
+        let mut i = 0;
+        while i < lens.len() {
+            let len = lens[i];
+            let run = lens[i..].iter().take_while(|l| **l == len).count();
+            i += run;
+            let mut rem = run;
+            if len == 0 {
+                while rem >= 11 {
+                    let n = std::cmp::min(rem, 138);
+                    symbols.push((18, 7, (n - 11) as u32));
+                    rem -= n;
+                }
+                if rem >= 3 {
+                    symbols.push((17, 3, (rem - 3) as u32));
+                    rem = 0;
+                }
+            }
+            else {
+                symbols.push((len, 0, 0));
+                rem -= 1;
+                while rem >= 3 {
+                    let n = std::cmp::min(rem, 6);
+                    symbols.push((16, 2, (n - 3) as u32));
+                    rem -= n;
+                }
+            }
+            for _ in 0..rem {
+                symbols.push((len, 0, 0));
+            }
+        }
+        // Code for the code-lengths
+        let mut freqs = [0u32; 19];
+        for (symbol, _, _) in &symbols {
+            freqs[*symbol] += 1;
+        }
+        ensure_two_symbols(&mut freqs);
+        let mut codelen_lens = [0usize; 19];
+        codelen_lens.copy_from_slice(&huffman_lengths(&freqs, DEFLATE_MAX_CODELEN_BITS));
+        let codelen_count = CODELEN_ORDER.iter()
+            .rposition(|s| codelen_lens[*s] != 0)
+            .map(|i| std::cmp::max(i + 1, 4))
+            .unwrap_or(4);
+        Self{ lit_len_count, dist_count, codelen_count, codelen_lens, symbols }
+    }
+
+    /// Returns the number of bits this header takes up.
+    fn cost(&self) -> usize {
+        let symbol_bits: usize = self.symbols.iter()
+            .map(|(symbol, extra, _)| self.codelen_lens[*symbol] + extra)
+            .sum();
+        5 + 5 + 4 + 3 * self.codelen_count + symbol_bits
+    }
+
+    /// Writes this header to the given `BitWriter`.
+    fn write<W: Write>(&self, w: &mut BitWriter<W>) {
+        w.write_bits((self.lit_len_count - 257) as u32, 5);
+        w.write_bits((self.dist_count - 1) as u32, 5);
+        w.write_bits((self.codelen_count - 4) as u32, 4);
+        for symbol in &CODELEN_ORDER[..self.codelen_count] {
+            w.write_bits(self.codelen_lens[*symbol] as u32, 3);
+        }
+        let codelen_code = HuffmanEncoder::from_code_lengths(&self.codelen_lens);
+        for (symbol, extra, value) in &self.symbols {
+            codelen_code.write_symbol(w, *symbol);
+            w.write_bits(*value, *extra);
+        }
+    }
+}
+
+/// A type for implementing the DEFLATE compression algorithm. The compressed
+/// data is written into the wrapped writer. After writing all the data,
+/// `finish` has to be called to terminate the compressed stream.
+#[derive(Debug)]
+pub struct Deflate<W: Write> {
+    writer: BitWriter<W>,
+    params: Option<SearchParams>,
+    // The window of already compressed bytes, followed by the pending input
+    data: Vec<u8>,
+    // The position of the first byte of `data` in the whole stream
+    base: usize,
+    // The number of already compressed bytes in `data`
+    processed: usize,
+    // The last stream position + 1 for each hash, 0 if there's none
+    head: Vec<usize>,
+    // The previous stream position + 1 with the same hash for the positions
+    // in the window
+    prev: Vec<usize>,
+}
+
+impl <W: Write> Deflate<W> {
+    /// Creates a new `Deflate` structure, that writes the compressed data with
+    /// the given level into the given writer.
+    pub fn new(writer: W, level: CompressionLevel) -> Self {
+        let params = level.search_params();
+        let (head, prev) = if params.is_some() {
+            (vec![0; 1 << DEFLATE_HASH_BITS], vec![0; DEFLATE_WINDOW_SIZE])
+        }
+        else {
+            (Vec::new(), Vec::new())
+        };
+        Self{
+            writer: BitWriter::new(writer),
+            params,
+            data: Vec::new(),
+            base: 0,
+            processed: 0,
+            head,
+            prev,
+        }
+    }
+
+    /// Compresses the remaining input and terminates the compressed stream.
+    /// Returns the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error of the underlying writer, an error variant is
+    /// returned.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_block(true)?;
+        self.writer.pad_to_byte();
+        self.writer.flush_buffer()?;
+        Ok(self.writer.writer)
+    }
+
+    // Searching repetitions ///////////////////////////////////////////////////
+
+    /// Returns the hash of the bytes starting at the given index of `data`.
+    #[inline(always)]
+    fn hash(&self, index: usize) -> usize {
+        let bytes = &self.data[index..(index + DEFLATE_MIN_REPEAT)];
+        let hash = ((bytes[0] as usize) << 10) ^ ((bytes[1] as usize) << 5) ^ (bytes[2] as usize);
+        hash & ((1 << DEFLATE_HASH_BITS) - 1)
+    }
+
+    /// Inserts the given index of `data` into the hash chains.
+    #[inline(always)]
+    fn insert(&mut self, index: usize) {
+        if index + DEFLATE_MIN_REPEAT > self.data.len() {
+            // Can't start a repetition
+            return;
+        }
+        let hash = self.hash(index);
+        let pos = self.base + index;
+        self.prev[pos % DEFLATE_WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = pos + 1;
+    }
+
+    /// Searches the longest repetition of the bytes starting at the given
+    /// index of `data`, that is longer than `longer_than`. Returns the length
+    /// and the distance of the repetition, if found.
+    fn find_repeat(&self, index: usize, longer_than: usize, params: &SearchParams) -> Option<(usize, usize)> {
+        let max_length = std::cmp::min(DEFLATE_MAX_REPEAT, self.data.len() - index);
+        if max_length < DEFLATE_MIN_REPEAT || longer_than >= max_length {
+            return None;
+        }
+        let pos = self.base + index;
+        let mut best_length = std::cmp::max(longer_than, DEFLATE_MIN_REPEAT - 1);
+        let mut best_distance = 0;
+        let mut candidate = self.head[self.hash(index)];
+        let mut chain = params.max_chain;
+        while candidate != 0 && chain > 0 {
+            let cand_pos = candidate - 1;
+            if cand_pos < self.base || pos - cand_pos > DEFLATE_WINDOW_SIZE {
+                // Out of the window
+                break;
+            }
+            let start = cand_pos - self.base;
+            // Only compare the whole thing, if it could be longer
+            if self.data[start + best_length] == self.data[index + best_length] {
+                let length = self.data[start..(start + max_length)].iter()
+                    .zip(&self.data[index..(index + max_length)])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = pos - cand_pos;
+                    if length >= params.nice_length || length == max_length {
+                        break;
+                    }
+                }
+            }
+            let next = self.prev[cand_pos % DEFLATE_WINDOW_SIZE];
+            if next >= candidate {
+                // Overwritten by a newer position
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+        if best_distance == 0 {
+            None
+        }
+        else {
+            Some((best_length, best_distance))
+        }
+    }
+
+    /// Finds the repetitions in the pending input, returning the tokens that
+    /// describe it.
+    fn tokenize(&mut self, params: &SearchParams) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut index = self.processed;
+        while index < self.data.len() {
+            let repeat = self.find_repeat(index, 0, params);
+            self.insert(index);
+            if let Some((length, distance)) = repeat {
+                // With lazy matching, we check if it's worth to defer the
+                // repetition by a byte
+                let defer = params.lazy
+                    && length < params.nice_length
+                    && self.find_repeat(index + 1, length, params).is_some();
+                if !defer {
+                    tokens.push(Token::Repeat{ length, distance });
+                    for i in (index + 1)..(index + length) {
+                        self.insert(i);
+                    }
+                    index += length;
+                    continue;
+                }
+            }
+            tokens.push(Token::Literal(self.data[index]));
+            index += 1;
+        }
+        tokens
+    }
+
+    // Writing blocks //////////////////////////////////////////////////////////
+
+    /// Compresses the pending input into a block, choosing the block type that
+    /// results in the smallest output.
+    fn write_block(&mut self, is_last: bool) -> Result<()> {
+        let tokens = self.params.map(|params| self.tokenize(&params));
+        let w = &mut self.writer;
+        let pending = &self.data[self.processed..];
+        let padding = (8 - (w.bit_count + 3) % 8) % 8;
+        let non_compressed_cost = 3 + padding + 32 + pending.len() * 8;
+        match tokens {
+            None => write_non_compressed(w, pending, is_last),
+            Some(tokens) => {
+                let (lit_len_freqs, dist_freqs) = token_frequencies(&tokens);
+                // Fixed codes
+                let (fixed_lit_len_lens, fixed_dist_lens) = fixed_huffman_lengths();
+                let fixed_lit_len = HuffmanEncoder::from_code_lengths(&fixed_lit_len_lens);
+                let fixed_dist = HuffmanEncoder::from_code_lengths(&fixed_dist_lens);
+                let fixed_cost = 3
+                    + tokens_cost(&lit_len_freqs, &dist_freqs, &fixed_lit_len, &fixed_dist);
+                // Dynamic codes
+                let mut freqs = lit_len_freqs;
+                ensure_two_symbols(&mut freqs);
+                let lit_len_lens = huffman_lengths(&freqs, DEFLATE_MAX_BITS);
+                let dist_lens = if dist_freqs.iter().all(|f| *f == 0) {
+                    // Just literals
+                    vec![0]
+                }
+                else {
+                    let mut freqs = dist_freqs;
+                    ensure_two_symbols(&mut freqs);
+                    huffman_lengths(&freqs, DEFLATE_MAX_BITS)
+                };
+                let header = DynamicHeader::new(&lit_len_lens, &dist_lens);
+                let lit_len = HuffmanEncoder::from_code_lengths(&lit_len_lens);
+                let dist = HuffmanEncoder::from_code_lengths(&dist_lens);
+                let dynamic_cost = 3 + header.cost()
+                    + tokens_cost(&lit_len_freqs, &dist_freqs, &lit_len, &dist);
+                // Write the smallest
+                if non_compressed_cost <= fixed_cost && non_compressed_cost <= dynamic_cost {
+                    write_non_compressed(w, pending, is_last);
+                }
+                else if fixed_cost <= dynamic_cost {
+                    w.write_bits(is_last as u32, 1);
+                    w.write_bits(0b01, 2);
+                    write_tokens(w, &tokens, &fixed_lit_len, &fixed_dist);
+                }
+                else {
+                    w.write_bits(is_last as u32, 1);
+                    w.write_bits(0b10, 2);
+                    header.write(w);
+                    write_tokens(w, &tokens, &lit_len, &dist);
+                }
+            },
+        }
+        // Only keep the window for the upcoming blocks
+        self.processed = self.data.len();
+        if self.data.len() > DEFLATE_WINDOW_SIZE {
+            let dropped = self.data.len() - DEFLATE_WINDOW_SIZE;
+            self.data.drain(..dropped);
+            self.base += dropped;
+            self.processed -= dropped;
+        }
+        self.writer.flush_buffer()
+    }
+}
+
+impl <W: Write> Write for Deflate<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let pending = self.data.len() - self.processed;
+        let count = std::cmp::min(buf.len(), DEFLATE_BLOCK_SIZE - pending);
+        self.data.extend_from_slice(&buf[..count]);
+        if pending + count == DEFLATE_BLOCK_SIZE {
+            self.write_block(false)?;
+        }
+        Ok(count)
+    }
+
+    /// Writes the already compressed data to the underlying writer. The
+    /// pending input is only compressed, when a whole block is filled or the
+    /// stream is finished.
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush_buffer()?;
+        self.writer.get_mut().flush()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Testing bit reversal
+
+    /// Reverses the lowest `count` bits of `n` one bit at a time.
+    fn reference_reverse(n: u16, count: usize) -> u16 {
+        (0..count).fold(0, |acc, i| (acc << 1) | ((n >> i) & 1))
+    }
+
+    #[test]
+    fn test_reverse_u8_bits() {
+        for n in 0..=255u8 {
+            assert_eq!(reverse_u8_bits(n) as u16, reference_reverse(n as u16, 8));
+        }
+    }
+
+    #[test]
+    fn test_reverse_u16_bits() {
+        for n in 0..=0xffffu16 {
+            assert_eq!(reverse_u16_bits(n), reference_reverse(n, 16));
+        }
+    }
+
+    // Testing `BitReader`
+
+    #[test]
+    fn test_bit_reader_reset() {
+        let first = [0xffu8; 2 * BIT_READER_CACHE_SIZE];
+        let mut r = BitReader::new(&first[..]);
+        assert_eq!(r.read_to_u8(3).unwrap(), 0b111);
+        // Leftover bits of the first source must not be read
+        r.reset(&[0b1010_0101, 0b0000_0011][..]);
+        assert_eq!(r.read_to_u8(4).unwrap(), 0b0101);
+        assert_eq!(r.read_to_u8(4).unwrap(), 0b1010);
+        assert_eq!(r.read_to_u16(8).unwrap(), 0b0000_0011);
+        assert_eq!(r.read_bit().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_bit_reader_aligned_u16_refill() {
+        let data: Vec<u8> = (1..=(BIT_READER_CACHE_SIZE as u8 + 1)).collect();
+        // Leaves a single byte of the first fill after aligning
+        let skip = |r: &mut BitReader<_>| {
+            for _ in 0..(BIT_READER_CACHE_SIZE / 2 - 1) {
+                r.read_to_u16(16).unwrap();
+            }
+            r.read_to_u8(5).unwrap();
+        };
+        let last = BIT_READER_CACHE_SIZE as u8;
+
+        let mut r = BitReader::new(&data[..]);
+        skip(&mut r);
+        assert_eq!(r.read_aligned_le_u16().unwrap(), u16::from_le_bytes([last, last + 1]));
+        assert_eq!(r.read_bit().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        // One byte short at the end, nothing is consumed
+        let mut r = BitReader::new(&data[..(data.len() - 1)]);
+        skip(&mut r);
+        assert_eq!(r.read_aligned_le_u16().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        // Only the padding up to the byte boundary is skipped
+        assert_eq!(r.position(), (BIT_READER_CACHE_SIZE as u64 - 1) * 8);
+        assert_eq!(r.read_to_u8(8).unwrap(), last);
+
+        // The refill is interrupted by the reader
+        let mut r = BitReader::new(Starving{ data: &data[..], calls: 0 });
+        let retry = |r: &mut BitReader<_>, f: &dyn Fn(&mut BitReader<Starving>) -> Result<u16>| loop {
+            r.checkpoint();
+            match f(r) {
+                Ok(v) => return v,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => r.rollback(),
+                Err(e) => panic!("{}", e),
+            }
+        };
+        for _ in 0..(BIT_READER_CACHE_SIZE / 2 - 1) {
+            retry(&mut r, &|r| r.read_to_u16(16));
+        }
+        retry(&mut r, &|r| r.read_to_u8(5).map(|v| v as u16));
+        assert_eq!(retry(&mut r, &|r| r.read_aligned_le_u16()), u16::from_le_bytes([last, last + 1]));
+    }
+
+    #[test]
+    fn test_bit_reader_peek_past_end() {
+        // The last byte is read, when the cache is full of ones
+        let mut data = [0xffu8; BIT_READER_CACHE_SIZE + 1];
+        data[BIT_READER_CACHE_SIZE] = 0b0000_0101;
+        let mut r = BitReader::new(&data[..]);
+        for _ in 0..(BIT_READER_CACHE_SIZE / 2) {
+            assert_eq!(r.read_to_u16(16).unwrap(), 0xffff);
+        }
+        // The bits past the end are zeroes, not the stale ones
+        assert_eq!(r.peek_to_u16(16).unwrap(), 0b0000_0101);
+        assert_eq!(r.peek_to_u16(16).unwrap(), 0b0000_0101);
+        assert_eq!(r.read_to_u8(3).unwrap(), 0b101);
+        assert_eq!(r.peek_to_u16(13).unwrap(), 0);
+        assert_eq!(r.read_to_u16(5).unwrap(), 0);
+        assert_eq!(r.read_bit().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    // Testing `HuffmanCodes`
+
+    #[test]
+    fn test_huffman_max_code_length() {
+        // Lengths 1, 2, ..., 15, 15 form a complete code, the last two symbols
+        // get the codes 111111111111110 and 111111111111111
+        let mut code_lens: Vec<usize> = (1..=DEFLATE_MAX_BITS).collect();
+        code_lens.push(DEFLATE_MAX_BITS);
+        let codes = HuffmanCodes::from_code_lengths(&code_lens);
+        // A peeked 10-bit value indexes the LUT
+        assert_eq!(codes.lut.len(), 1024);
+
+        let mut r = BitReader::new(&[0xff, 0x7f][..]);
+        assert_eq!(codes.decode_symbol(&mut r).unwrap(), 15);
+        let mut r = BitReader::new(&[0xff, 0x3f][..]);
+        assert_eq!(codes.decode_symbol(&mut r).unwrap(), 14);
+        let mut r = BitReader::new(&[0x00][..]);
+        assert_eq!(codes.decode_symbol(&mut r).unwrap(), 0);
+    }
+
+    /// Decodes a code from the given bits bit-by-bit, by searching through all
+    /// the generated codes. Returns the symbol and the length of the code.
+    fn reference_decode(codes: &[(u16, HuffmanCode)], bits: u16) -> Option<(u16, usize)> {
+        let mut code = 0u16;
+        for length in 1..=DEFLATE_MAX_BITS {
+            code = (code << 1) | ((bits >> (length - 1)) & 1);
+            let found = codes.iter().find(|(c, d)| d.length == length && *c == code);
+            if let Some((_, desc)) = found {
+                return Some((desc.symbol, length));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_huffman_same_as_reference() {
+        let mut fixed_lens = vec![8; 144];
+        fixed_lens.extend(vec![9; 112]);
+        fixed_lens.extend(vec![7; 24]);
+        fixed_lens.extend(vec![8; 8]);
+        let mut long_lens: Vec<usize> = (1..=7).collect();
+        long_lens.extend(vec![0; 3]);
+        long_lens.extend(vec![15; 100]);
+        long_lens.extend(vec![14; 39]);
+        let code_len_sets = vec![
+            fixed_lens,
+            // Incomplete
+            vec![3, 3, 3, 3, 3, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 15],
+            // Lots of codes sharing the first bits
+            long_lens,
+        ];
+        for code_lens in &code_len_sets {
+            let codes = HuffmanCodes::from_code_lengths(code_lens);
+            let mut reference_codes = Vec::new();
+            generate_huffman_from_lengths(code_lens, |code, desc| reference_codes.push((code, desc)));
+            for bits in 0..(1u16 << DEFLATE_MAX_BITS) {
+                let data = bits.to_le_bytes();
+                let mut r = BitReader::new(&data[..]);
+                let decoded = codes.decode_symbol(&mut r).ok().map(|s| (s, r.bit_index));
+                assert_eq!(decoded, reference_decode(&reference_codes, bits), "bits: {:015b}", bits);
+            }
+        }
+    }
+
+    // Testing `Deflate`
+
+    const ALL_LEVELS: [CompressionLevel; 4] = [
+        CompressionLevel::None,
+        CompressionLevel::Fast,
+        CompressionLevel::Default,
+        CompressionLevel::Best,
+    ];
+
+    /// Generates pseudo-random bytes.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut seed = 12345u32;
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    /// Generates compressible, text-like data.
+    fn text(len: usize) -> Vec<u8> {
+        const WORDS: [&str; 8] = [
+            "lorem ", "ipsum ", "dolor ", "sit ", "amet, ", "consectetur ", "adipiscing ", "elit. ",
+        ];
+        let mut result: Vec<u8> = noise(len)
+            .iter()
+            .flat_map(|n| WORDS[*n as usize % WORDS.len()].bytes())
+            .collect();
+        result.truncate(len);
+        result
+    }
+
+    fn deflate(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+        let mut def = Deflate::new(Vec::new(), level);
+        def.write_all(data).unwrap();
+        def.finish().unwrap()
+    }
+
+    #[test]
+    fn test_huffman_lengths_limited() {
+        // Fibonacci frequencies result in the deepest possible tree
+        let mut freqs = vec![1u32, 1];
+        while freqs.len() < 30 {
+            let next = freqs[freqs.len() - 1] + freqs[freqs.len() - 2];
+            freqs.push(next);
+        }
+        let lens = huffman_lengths(&freqs, DEFLATE_MAX_BITS);
+        assert!(lens.iter().all(|l| *l >= 1 && *l <= DEFLATE_MAX_BITS));
+        // The code is still complete
+        let kraft: usize = lens.iter().map(|l| 1 << (DEFLATE_MAX_BITS - l)).sum();
+        assert_eq!(kraft, 1 << DEFLATE_MAX_BITS);
+    }
+
+    #[test]
+    fn test_deflate_empty() {
+        assert_eq!(deflate(&[], CompressionLevel::None), &[0x01, 0x00, 0x00, 0xff, 0xff]);
+        assert_eq!(deflate(&[], CompressionLevel::Default), &[0x03, 0x00]);
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let inputs = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"Hello, World!".to_vec(),
+            text(200_000),
+            noise(100_000),
+            vec![0u8; 300_000],
+        ];
+        for input in &inputs {
+            for level in &ALL_LEVELS {
+                let compressed = deflate(input, *level);
+                assert!(inflate(&compressed) == *input, "Round-trip failed with {:?}", level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_deflate_levels_size() {
+        let data = text(200_000);
+        let sizes: Vec<_> = ALL_LEVELS.iter().map(|l| deflate(&data, *l).len()).collect();
+        for pair in sizes.windows(2) {
+            assert!(pair[1] <= pair[0], "Sizes are not decreasing: {:?}", sizes);
+        }
+        assert!(sizes[1] < data.len() / 2);
+    }
+
+    #[test]
+    fn test_deflate_incompressible() {
+        // Noise is stored without compression, so it barely grows
+        let data = noise(100_000);
+        let stored_size = deflate(&data, CompressionLevel::None).len();
+        for level in &ALL_LEVELS {
+            assert!(deflate(&data, *level).len() <= stored_size);
+        }
+    }
+
+    // Round-trip corpus
+
+    /// Generates the corpus of the round-trip tests, named inputs of the given
+    /// length.
+    fn corpus(len: usize) -> Vec<(&'static str, Vec<u8>)> {
+        // Little-endian counters, like in binary formats
+        let binary: Vec<u8> = (0..(len as u32 / 4 + 1)).flat_map(|i| i.to_le_bytes().to_vec()).collect();
+        // Noise repeated exactly a window later, for maximum distance
+        // references
+        let window_noise = noise(DEFLATE_WINDOW_SIZE);
+        let max_distance: Vec<u8> = window_noise.iter().cycle().take(len).cloned().collect();
+        // A marker right at the start, that shows up again just within reach
+        let mut far = noise(len);
+        for i in (DEFLATE_WINDOW_SIZE..len).step_by(DEFLATE_WINDOW_SIZE) {
+            let end = std::cmp::min(i + DEFLATE_MAX_REPEAT, len);
+            let (head, tail) = far.split_at_mut(i);
+            tail[..(end - i)].copy_from_slice(&head[..(end - i)]);
+        }
+        // Runs much longer than the longest match, between noise
+        let runs: Vec<u8> = noise(len).chunks(1000)
+            .enumerate()
+            .flat_map(|(i, c)| if i % 2 == 0 { c.to_vec() } else { vec![c[0]; c.len()] })
+            .collect();
+        let mut inputs = vec![
+            ("zeros", vec![0u8; len]),
+            ("noise", noise(len)),
+            ("repetitive", b"abc".iter().cycle().take(len).cloned().collect()),
+            ("text", text(len)),
+            ("binary", binary),
+            ("max distance", max_distance),
+            ("far", far),
+            ("runs", runs),
+        ];
+        for (_, input) in &mut inputs {
+            input.truncate(len);
+        }
+        inputs
+    }
+
+    #[test]
+    fn test_round_trip_corpus() {
+        let sizes = [
+            1, DEFLATE_MAX_REPEAT + 1,
+            DEFLATE_WINDOW_SIZE - 1, DEFLATE_WINDOW_SIZE, DEFLATE_WINDOW_SIZE + 1,
+            2 * DEFLATE_WINDOW_SIZE + DEFLATE_MAX_REPEAT,
+        ];
+        for len in &sizes {
+            for (name, input) in corpus(*len) {
+                assert_eq!(input.len(), *len);
+                for level in &ALL_LEVELS {
+                    let compressed = deflate(&input, *level);
+                    assert!(inflate(&compressed) == input,
+                        "Round-trip of {} bytes of {} failed with {:?}", len, name, level);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_corpus_chunked() {
+        // Odd-sized writes and reads, so nothing lines up with the window
+        let len = 3 * DEFLATE_WINDOW_SIZE + 17;
+        for (name, input) in corpus(len) {
+            for level in &ALL_LEVELS {
+                let mut def = Deflate::new(Vec::new(), *level);
+                for chunk in input.chunks(4093) {
+                    def.write_all(chunk).unwrap();
+                }
+                let compressed = def.finish().unwrap();
+                let mut inf = Inflate::new(&compressed[..]);
+                let mut out = Vec::new();
+                let mut buffer = [0u8; 1021];
+                loop {
+                    let n = inf.read(&mut buffer).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    out.extend_from_slice(&buffer[..n]);
+                }
+                assert!(out == input, "Chunked round-trip of {} failed with {:?}", name, level);
+            }
+        }
+    }
+
     // Testing `Inflate`
 
     fn inflate(data: &[u8]) -> Vec<u8> {
@@ -949,6 +2395,477 @@ mod tests {
         assert_eq!(inflate(&DATA), &[]);
     }
 
+    #[test]
+    fn test_empty_stored_block() {
+        // A single, final, empty non-compressed block
+        const DATA: [u8; 5] = [0x01, 0x00, 0x00, 0xff, 0xff];
+        assert_eq!(inflate(&DATA), &[]);
+    }
+
+    #[test]
+    fn test_empty_fixed_huffman_block() {
+        // A single, final, fixed Huffman block with only an end-of-block
+        const DATA: [u8; 2] = [0x03, 0x00];
+        let mut out = Vec::new();
+        assert_eq!(Inflate::new(&DATA[..]).read_to_end(&mut out).unwrap(), 0);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_final_code_in_last_byte() {
+        // A non-final, non-compressed block of ones fills the cache, then the
+        // final, fixed Huffman block has only an end-of-block, which ends in
+        // the last, partial byte
+        let mut data = vec![0x00, 0x08, 0x00, 0xf7, 0xff];
+        data.extend_from_slice(&[0xff; 8]);
+        data.extend_from_slice(&[0x03, 0x00]);
+        assert_eq!(inflate(&data), &[0xff; 8]);
+    }
+
+    #[test]
+    fn test_truncated_header() {
+        // A final, dynamic Huffman block that ends mid-header
+        const DATA: [u8; 2] = [0x05, 0xc0];
+        let mut out = Vec::new();
+        let err = Inflate::new(&DATA[..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_truncated_stored_block() {
+        // A non-compressed block that promises 5 bytes, but has only 2
+        const DATA: [u8; 7] = [0x01, 0x05, 0x00, 0xfa, 0xff, b'a', b'b'];
+        let mut out = Vec::new();
+        let err = Inflate::new(&DATA[..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_get_ref() {
+        // A single, final, non-compressed block, followed by trailing data
+        const DATA: [u8; 8] = [0x01, 0x01, 0x00, 0xfe, 0xff, b'a', b'x', b'y'];
+        let mut def = Inflate::new(std::io::Cursor::new(&DATA[..]));
+        assert_eq!(def.get_ref().position(), 0);
+        let mut out = Vec::new();
+        def.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"a");
+        // The whole input fits into the cache
+        assert_eq!(def.get_ref().position(), DATA.len() as u64);
+        assert_eq!(def.get_mut().get_ref(), &DATA);
+    }
+
+    #[test]
+    fn test_read_uninit_same_as_read() {
+        let mut data = text(100_000);
+        data.extend(noise(20_000));
+        for level in ALL_LEVELS.iter() {
+            let compressed = deflate(&data, *level);
+
+            // Through `read` with an initialized buffer
+            let mut by_read = Vec::new();
+            let mut def = Inflate::new(&compressed[..]);
+            let mut buf = [0u8; 1000];
+            loop {
+                let read = def.read(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                by_read.extend_from_slice(&buf[..read]);
+            }
+
+            // Through `read_uninit` with an uninitialized buffer
+            let mut by_uninit = Vec::new();
+            let mut def = Inflate::new(&compressed[..]);
+            let mut buf = [MaybeUninit::<u8>::uninit(); 1000];
+            loop {
+                let read = def.read_uninit(&mut buf).unwrap();
+                if read == 0 {
+                    break;
+                }
+                by_uninit.extend(buf[..read].iter().map(|b| unsafe { b.assume_init() }));
+            }
+
+            assert_eq!(by_read, data);
+            assert_eq!(by_uninit, data);
+            assert_eq!(inflate(&compressed), data);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut def = Inflate::new(&[0x01, 0x01, 0x00, 0xfe, 0xff, b'a', b'x'][..]);
+        let mut out = Vec::new();
+        def.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"a");
+        def.reset(&[0x01, 0x02, 0x00, 0xfd, 0xff, b'b', b'c'][..]);
+        out.clear();
+        def.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"bc");
+    }
+
+    /// Writes a single, final, fixed Huffman block containing the given tokens.
+    fn fixed_huffman_block(tokens: &[Token]) -> Vec<u8> {
+        let (lit_len_lens, dist_lens) = fixed_huffman_lengths();
+        let lit_len = HuffmanEncoder::from_code_lengths(&lit_len_lens);
+        let dist = HuffmanEncoder::from_code_lengths(&dist_lens);
+        let mut w = BitWriter::new(Vec::new());
+        w.write_bits(1, 1);
+        w.write_bits(0b01, 2);
+        write_tokens(&mut w, tokens, &lit_len, &dist);
+        w.pad_to_byte();
+        w.flush_buffer().unwrap();
+        w.writer
+    }
+
+    #[test]
+    fn test_distance_too_far_back() {
+        // Nothing was decompressed yet
+        let data = fixed_huffman_block(&[Token::Repeat{ length: 3, distance: 1 }]);
+        let mut out = Vec::new();
+        let err = Inflate::new(&data[..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        // Only 2 bytes were decompressed
+        let data = fixed_huffman_block(&[
+            Token::Literal(b'a'),
+            Token::Literal(b'b'),
+            Token::Repeat{ length: 3, distance: 3 },
+        ]);
+        let err = Inflate::new(&data[..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        // Exactly as far back as possible is fine
+        let data = fixed_huffman_block(&[
+            Token::Literal(b'a'),
+            Token::Literal(b'b'),
+            Token::Repeat{ length: 3, distance: 2 },
+        ]);
+        out.clear();
+        Inflate::new(&data[..]).read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"ababa");
+    }
+
+    /// Writes a single, final, dynamic Huffman block containing the given
+    /// literal-length symbols. The distance code has the given code-lengths.
+    fn dynamic_huffman_block(symbols: &[usize], dist_lens: &[usize]) -> Vec<u8> {
+        let mut w = BitWriter::new(Vec::new());
+        write_dynamic_huffman_block(&mut w, symbols, dist_lens, true);
+        w.pad_to_byte();
+        w.flush_buffer().unwrap();
+        w.writer
+    }
+
+    /// Writes a dynamic Huffman block like `dynamic_huffman_block`, but into
+    /// the given `BitWriter`, without padding.
+    fn write_dynamic_huffman_block(w: &mut BitWriter<Vec<u8>>, symbols: &[usize], dist_lens: &[usize], is_last: bool) {
+        let mut freqs = [0u32; 286];
+        for s in symbols {
+            freqs[*s] += 1;
+        }
+        freqs[256] += 1;
+        ensure_two_symbols(&mut freqs);
+        let lit_len_lens = huffman_lengths(&freqs, DEFLATE_MAX_BITS);
+        let header = DynamicHeader::new(&lit_len_lens, dist_lens);
+        let lit_len = HuffmanEncoder::from_code_lengths(&lit_len_lens);
+        w.write_bits(is_last as u32, 1);
+        w.write_bits(0b10, 2);
+        header.write(w);
+        for s in symbols {
+            lit_len.write_symbol(w, *s);
+        }
+        lit_len.write_symbol(w, 256);
+    }
+
+    #[test]
+    fn test_block_stats() {
+        let (lit_len_lens, dist_lens) = fixed_huffman_lengths();
+        let fixed_lit_len = HuffmanEncoder::from_code_lengths(&lit_len_lens);
+        let fixed_dist = HuffmanEncoder::from_code_lengths(&dist_lens);
+        let mut w = BitWriter::new(Vec::new());
+        write_non_compressed(&mut w, b"stored", false);
+        w.write_bits(0, 1);
+        w.write_bits(0b01, 2);
+        write_tokens(&mut w, &[
+            Token::Literal(b'a'),
+            Token::Literal(b'b'),
+            Token::Repeat{ length: 3, distance: 2 },
+        ], &fixed_lit_len, &fixed_dist);
+        let symbols: Vec<_> = b"dynamic".iter().map(|b| *b as usize).collect();
+        write_dynamic_huffman_block(&mut w, &symbols, &[0], true);
+        w.pad_to_byte();
+        w.flush_buffer().unwrap();
+        let data = w.writer;
+
+        // Not collected by default
+        let mut inf = Inflate::new(&data[..]);
+        let mut out = Vec::new();
+        inf.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"storedababadynamic");
+        assert!(inf.block_stats().is_empty());
+
+        inf.reset(&data[..]);
+        inf.set_collect_block_stats(true);
+        out.clear();
+        inf.read_to_end(&mut out).unwrap();
+        let stats = inf.block_stats();
+        let types: Vec<_> = stats.iter().map(|s| s.block_type).collect();
+        assert_eq!(types, [BlockType::Stored, BlockType::FixedHuffman, BlockType::DynamicHuffman]);
+        let outputs: Vec<_> = stats.iter().map(|s| s.output_bytes).collect();
+        assert_eq!(outputs, [6, 5, 7]);
+        // Header, padding, LEN and NLEN, then the bytes
+        assert_eq!(stats[0].input_bits, 3 + 5 + 32 + 6 * 8);
+        // Only the final padding is not counted
+        let input_bits: u64 = stats.iter().map(|s| s.input_bits).sum();
+        assert!(input_bits <= data.len() as u64 * 8 && input_bits > data.len() as u64 * 8 - 8);
+        // Reset forgets the stats, but not the setting
+        inf.reset(&data[..]);
+        assert!(inf.block_stats().is_empty());
+        inf.read_to_end(&mut out).unwrap();
+        assert_eq!(inf.block_stats().len(), 3);
+    }
+
+    #[test]
+    fn test_literals_only_block() {
+        let data = text(50_000);
+        let symbols: Vec<_> = data.iter().map(|b| *b as usize).collect();
+        let tokens: Vec<_> = data.iter().map(|b| Token::Literal(*b)).collect();
+        // The general path
+        let expected = inflate(&fixed_huffman_block(&tokens));
+        assert!(expected == data);
+        // No distance codes, and distance codes without any lengths
+        for dist_lens in &[&[0][..], &[1, 1][..]] {
+            let block = dynamic_huffman_block(&symbols, dist_lens);
+            assert!(inflate(&block) == expected);
+            // Small reads stop mid-block
+            let mut inf = Inflate::new(&block[..]);
+            let mut out = Vec::new();
+            let mut chunk = [0u8; 7];
+            loop {
+                let n = inf.read(&mut chunk).unwrap();
+                if n == 0 {
+                    break;
+                }
+                out.extend_from_slice(&chunk[..n]);
+            }
+            assert!(out == expected);
+        }
+        // A length, that has no distance to go with it
+        let block = dynamic_huffman_block(&[b'a' as usize, 257], &[0]);
+        let mut out = Vec::new();
+        let err = Inflate::new(&block[..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        // Truncated
+        let block = dynamic_huffman_block(&symbols, &[0]);
+        let err = Inflate::new(&block[..block.len() / 2]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_exact_decompressed() {
+        let data = fixed_huffman_block(&[
+            Token::Literal(b'a'),
+            Token::Repeat{ length: 4, distance: 1 },
+        ]);
+        let mut def = Inflate::new(&data[..]);
+        let mut buf = [0u8; 3];
+        def.read_exact_decompressed(&mut buf).unwrap();
+        assert_eq!(&buf, b"aaa");
+        // Only 2 bytes remain
+        let err = def.read_exact_decompressed(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_to_end_hinted() {
+        let data = text(200_000);
+        let compressed = deflate(&data, CompressionLevel::Default);
+        assert!(inflate(&compressed) == data);
+
+        // Exact, too small and too big hints all give the same result
+        for hint in &[data.len(), 10, 10 * data.len()] {
+            let mut inf = Inflate::with_size_hint(&compressed[..], *hint);
+            assert_eq!(inf.size_hint(), Some(*hint));
+            let mut out = Vec::new();
+            assert_eq!(inf.read_to_end_hinted(&mut out).unwrap(), data.len());
+            assert_eq!(out, data);
+        }
+        // The exact hint needs no reallocation
+        let mut out = Vec::new();
+        Inflate::with_size_hint(&compressed[..], data.len()).read_to_end_hinted(&mut out).unwrap();
+        assert_eq!(out.capacity(), data.len());
+    }
+
+    /// A reader that hands out at most a few bytes at a time, and returns
+    /// `WouldBlock` on every other call.
+    struct Starving<'a> {
+        data: &'a [u8],
+        calls: usize,
+    }
+
+    impl <'a> Read for Starving<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            self.calls += 1;
+            if self.calls % 2 == 1 {
+                let n = std::cmp::min(self.calls % 5 + 1, buf.len());
+                return self.data.read(&mut buf[..n]);
+            }
+            Err(Error::new(ErrorKind::WouldBlock, "Starving!"))
+        }
+    }
+
+    /// Decompresses from a `Starving` reader, retrying after `WouldBlock`.
+    /// Returns the decompressed data and the number of retries.
+    fn inflate_starving(compressed: &[u8], chunk_size: usize) -> (Vec<u8>, usize) {
+        let mut inf = Inflate::new(Starving{ data: compressed, calls: 0 });
+        let mut out = Vec::new();
+        let mut chunk = vec![0u8; chunk_size];
+        let mut blocks = 0;
+        loop {
+            match inf.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => out.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => blocks += 1,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        (out, blocks)
+    }
+
+    #[test]
+    fn test_would_block() {
+        let mut retries = 0;
+        let data = text(20_000);
+        for level in &ALL_LEVELS {
+            let compressed = deflate(&data, *level);
+            for chunk_size in &[1, 1000, 20_000] {
+                let (out, blocks) = inflate_starving(&compressed, *chunk_size);
+                assert!(out == data);
+                retries += blocks;
+            }
+        }
+        // Incompressible data goes to stored blocks
+        let data = noise(100_000);
+        let compressed = deflate(&data, CompressionLevel::Default);
+        let (out, blocks) = inflate_starving(&compressed, 1000);
+        assert!(out == data);
+        retries += blocks;
+        // Blocks without distance codes
+        let data = text(20_000);
+        let symbols: Vec<_> = data.iter().map(|b| *b as usize).collect();
+        let block = dynamic_huffman_block(&symbols, &[0]);
+        let (out, blocks) = inflate_starving(&block, 1000);
+        assert!(out == data);
+        retries += blocks;
+        // Some reads had nothing to return
+        assert!(retries > 0);
+    }
+
+    // Fuzzing `Inflate`
+
+    /// A tiny, seeded pseudo-random generator, so the fuzzing is reproducible.
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self) -> u32 {
+            self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+            self.0 >> 8
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.next() as usize % n
+        }
+    }
+
+    /// Returns a randomly damaged copy of the given stream.
+    fn mutate(rng: &mut Lcg, data: &[u8]) -> Vec<u8> {
+        let mut result = data.to_vec();
+        match rng.below(5) {
+            // Bit flips
+            0 => for _ in 0..=rng.below(8) {
+                let bit = rng.below(result.len() * 8);
+                result[bit / 8] ^= 1 << (bit % 8);
+            },
+            // Truncation
+            1 => result.truncate(rng.below(result.len())),
+            // Extension with garbage
+            2 => {
+                let len = 1 + rng.below(64);
+                result.extend((0..len).map(|_| rng.next() as u8));
+            },
+            // Overwritten bytes
+            3 => for _ in 0..=rng.below(4) {
+                let index = rng.below(result.len());
+                result[index] = rng.next() as u8;
+            },
+            // Duplicated range
+            _ => {
+                let start = rng.below(result.len());
+                let end = start + rng.below(result.len() - start + 1);
+                let range = result[start..end].to_vec();
+                let at = rng.below(result.len() + 1);
+                result.splice(at..at, range);
+            },
+        }
+        result
+    }
+
+    /// Decompresses the given data, returning `Ok`, if it decompressed, `Err`
+    /// if it was rejected. Fails, if the decompression doesn't finish in a
+    /// bounded number of reads, or produces more than what DEFLATE can.
+    fn inflate_bounded(data: &[u8]) -> Result<usize> {
+        // Way more, than any valid stream of the tested size needs
+        const MAX_READS: usize = 100_000;
+        // Deflate can't compress better than about 1:1032
+        let max_len = (data.len() + 1) * 1032 + 1024;
+
+        let mut inf = Inflate::new(data);
+        let mut buffer = [0u8; 4096];
+        let mut total = 0;
+        for _ in 0..MAX_READS {
+            let read = inf.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(total);
+            }
+            total += read;
+            assert!(total <= max_len, "Produced {} bytes from {} bytes", total, data.len());
+        }
+        panic!("Decompression didn't finish in {} reads", MAX_READS);
+    }
+
+    #[test]
+    fn test_fuzz_mutated_streams() {
+        const MUTATIONS: usize = 2000;
+
+        let data = text(4000);
+        let mut streams: Vec<Vec<u8>> = ALL_LEVELS.iter().map(|l| deflate(&data, *l)).collect();
+        let symbols: Vec<_> = data.iter().take(500).map(|b| *b as usize).collect();
+        streams.push(dynamic_huffman_block(&symbols, &[0]));
+        streams.push(fixed_huffman_block(&[Token::Literal(b'a'), Token::Repeat{ length: 3, distance: 1 }]));
+
+        let mut rng = Lcg(0xcac71);
+        let mut rejected = 0;
+        for (i, stream) in streams.iter().enumerate() {
+            assert!(inflate_bounded(stream).is_ok());
+            for j in 0..MUTATIONS {
+                let mutated = mutate(&mut rng, stream);
+                let result = std::panic::catch_unwind(|| inflate_bounded(&mutated));
+                match result {
+                    Ok(Ok(_)) => {},
+                    Ok(Err(_)) => rejected += 1,
+                    Err(_) => panic!("Mutation {} of stream {} failed: {:x?}", j, i, mutated),
+                }
+            }
+        }
+        // Damaged literals can't be noticed without a checksum, but damaged
+        // structure can
+        assert!(rejected > 0);
+    }
+
+    #[test]
+    fn test_no_input() {
+        let mut out = Vec::new();
+        let err = Inflate::new(&[][..]).read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
     #[test]
     fn test_hello_world() {
         const DATA: [u8; 21] = [
@@ -1,5 +1,10 @@
 //! Cross-platform and dependency-free archive handling.
 // TODO: doc, introduce library
 
+pub mod crc32;
 pub mod deflate;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod ring;
+pub mod tar;
 pub mod zip;
@@ -0,0 +1,225 @@
+//! Read-only memory-mapping of files, available with the `mmap` feature.
+//!
+//! A mapped file can be accessed like a byte slice, the operating system reads
+//! the pages in on demand. This makes random access in big files - like the
+//! central directory and the local headers of a Zip archive - cheap, without
+//! buffering anything.
+//!
+//! # Basic usage
+//!
+//! ```no_run
+//! use cacti_archive::mmap::Mmap;
+//!
+//! let map = unsafe{ Mmap::open("archive.zip")? };
+//! println!("The file starts with {:?}", &map[..4]);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// A read-only memory-mapped file. The mapping is released when dropped.
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+// SAFETY: The mapping is read-only, and owned by this handle
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps the whole file at the given path into memory.
+    ///
+    /// # Safety
+    ///
+    /// The mapped memory is shared with the file. If the file gets modified by
+    /// another process while mapped, the contents change under the slice, and
+    /// if it gets truncated, accessing the pages past the new end crashes the
+    /// process (`SIGBUS` on Unix, an access violation on Windows). Only map
+    /// files, that nothing else writes.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "File is too big to be mapped!"))?;
+        if len == 0 {
+            // Empty mappings are not allowed, but there's nothing to map
+            return Ok(Self{ ptr: std::ptr::NonNull::dangling().as_ptr(), len });
+        }
+        let ptr = map_file(&file, len)?;
+        Ok(Self{ ptr, len })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe{ std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mmap")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe{ unmap(self.ptr, self.len) };
+        }
+    }
+}
+
+// Unix implementation /////////////////////////////////////////////////////////
+
+#[cfg(unix)]
+use unix::{map_file, unmap};
+
+#[cfg(unix)]
+mod unix {
+    use std::ffi::c_void;
+    use std::fs;
+    use std::io;
+    use std::os::raw::{c_int, c_long};
+    use std::os::unix::io::AsRawFd;
+
+    const PROT_READ: c_int = 0x1;
+    const MAP_PRIVATE: c_int = 0x2;
+
+    #[link(name = "c")]
+    extern "C" {
+        fn mmap(
+            addr  : *mut c_void,
+            len   : usize      ,
+            prot  : c_int      ,
+            flags : c_int      ,
+            fd    : c_int      ,
+            offset: c_long     ,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    /// Maps the first `len` bytes of the file for reading.
+    pub unsafe fn map_file(file: &fs::File, len: usize) -> io::Result<*const u8> {
+        let ptr = mmap(std::ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0);
+        // MAP_FAILED
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ptr as *const u8)
+    }
+
+    /// Releases a mapping created by `map_file`.
+    pub unsafe fn unmap(ptr: *const u8, len: usize) {
+        munmap(ptr as *mut c_void, len);
+    }
+}
+
+// WinAPI implementation ///////////////////////////////////////////////////////
+
+#[cfg(windows)]
+use win32::{map_file, unmap};
+
+#[cfg(windows)]
+mod win32 {
+    #![allow(non_snake_case)]
+
+    use std::ffi::c_void;
+    use std::fs;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    const PAGE_READONLY: u32 = 0x02;
+    const FILE_MAP_READ: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateFileMappingW(
+            hfile    : *mut c_void,
+            attribs  : *mut c_void,
+            protect  : u32        ,
+            size_high: u32        ,
+            size_low : u32        ,
+            name     : *const u16 ,
+        ) -> *mut c_void;
+        fn MapViewOfFile(
+            mapping    : *mut c_void,
+            access     : u32        ,
+            offset_high: u32        ,
+            offset_low : u32        ,
+            len        : usize      ,
+        ) -> *mut c_void;
+        fn UnmapViewOfFile(base: *const c_void) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    /// Maps the first `len` bytes of the file for reading.
+    pub unsafe fn map_file(file: &fs::File, len: usize) -> io::Result<*const u8> {
+        let mapping = CreateFileMappingW(
+            file.as_raw_handle(), ptr::null_mut(), PAGE_READONLY, 0, 0, ptr::null());
+        if mapping.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let view = MapViewOfFile(mapping, FILE_MAP_READ, 0, 0, len);
+        let err = io::Error::last_os_error();
+        // The view keeps the mapping alive
+        CloseHandle(mapping);
+        if view.is_null() {
+            return Err(err);
+        }
+        Ok(view as *const u8)
+    }
+
+    /// Releases a mapping created by `map_file`.
+    pub unsafe fn unmap(ptr: *const u8, _len: usize) {
+        UnmapViewOfFile(ptr as *const c_void);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use cacti_fs::temp;
+
+    #[test]
+    fn test_map_file() -> io::Result<()> {
+        let dir = temp::directory()?;
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        fs::File::create(&path)?.write_all(&data)?;
+
+        let map = unsafe{ Mmap::open(&path)? };
+        assert_eq!(map.len(), data.len());
+        assert!(*map == data[..]);
+        drop(map);
+
+        // Empty files can be mapped too
+        let path = dir.path().join("empty.bin");
+        fs::File::create(&path)?;
+        let map = unsafe{ Mmap::open(&path)? };
+        assert!(map.is_empty());
+        Ok(())
+    }
+}
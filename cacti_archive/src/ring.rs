@@ -0,0 +1,298 @@
+//! A fixed-size circular byte buffer, that keeps the last N bytes pushed into
+//! it.
+//!
+//! This is the structure LZ77-based formats - like DEFLATE - use to resolve
+//! backreferences, but it's useful anywhere a bounded history of a byte stream
+//! is needed.
+//!
+//! # Basic usage
+//!
+//! ```
+//! use cacti_archive::ring::SlidingWindow;
+//!
+//! let mut window = SlidingWindow::new(4);
+//! window.push_slice(b"abc");
+//! assert_eq!(window.peek(-1), b'c');
+//! // Copy 3 bytes starting from 2 bytes back
+//! let (w1, w2) = window.backreference(-2, 3);
+//! assert_eq!([w1, w2].concat(), b"bcb");
+//! ```
+
+use std::fmt;
+
+/// A fixed-size sliding window implementation using a circular buffer. Pushing
+/// into a full window overwrites the oldest elements.
+pub struct SlidingWindow {
+    buffer: Box<[u8]>,
+    cursor: usize,
+}
+
+impl fmt::Debug for SlidingWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SlidingWindow")
+            .field("capacity", &self.buffer.len())
+            .field("cursor", &self.cursor)
+            .finish()
+    }
+}
+
+impl SlidingWindow {
+    /// Creates a new, zero-filled `SlidingWindow` with the given capacity.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "The capacity of a SlidingWindow must be positive!");
+        Self{
+            buffer: vec![0u8; capacity].into_boxed_slice(),
+            cursor: 0,
+        }
+    }
+
+    /// Returns the number of bytes this `SlidingWindow` remembers.
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Zeroes the contents of the `SlidingWindow`, as if it was just created.
+    pub fn clear(&mut self) {
+        for b in self.buffer.iter_mut() {
+            *b = 0;
+        }
+        self.cursor = 0;
+    }
+
+    /// Adds an element to the `SlidingWindow`.
+    #[inline(always)]
+    pub fn push(&mut self, element: u8) {
+        self.buffer[self.cursor] = element;
+        // Slide
+        self.cursor = (self.cursor + 1) % self.buffer.len();
+    }
+
+    // NOTE: This could be optimized for some cases but it's not that trivial to
+    // do so.
+    /// Adds a slice to the `SlidingWindow`.
+    #[inline(always)]
+    pub fn push_slice(&mut self, elements: &[u8]) {
+        // Only the last `capacity` elements matter
+        let elements = if elements.len() > self.buffer.len() {
+            &elements[(elements.len() - self.buffer.len())..]
+        }
+        else {
+            elements
+        };
+        let copy_end = self.cursor + elements.len();
+        if copy_end <= self.buffer.len() {
+            // Trivial forward-copy
+            self.buffer[self.cursor..copy_end].copy_from_slice(elements);
+            self.cursor = copy_end % self.buffer.len();
+        }
+        else {
+            // Copy in 2 pieces
+            let part1_len = self.buffer.len() - self.cursor;
+            let cursor_end = copy_end % self.buffer.len();
+            self.buffer[self.cursor..].copy_from_slice(&elements[..part1_len]);
+            self.buffer[..cursor_end].copy_from_slice(&elements[part1_len..]);
+            self.cursor = cursor_end;
+        }
+    }
+
+    /// Returns the buffer index corresponding to the given distance from the
+    /// cursor.
+    #[inline(always)]
+    fn buffer_index_of_dist(&self, dist: isize) -> usize {
+        let len = self.buffer.len() as isize;
+        ((self.cursor as isize + dist % len + len) % len) as usize
+    }
+
+    /// Returns the element the given distance away from the cursor. A distance
+    /// of -1 means the last pushed element.
+    #[inline(always)]
+    pub fn peek(&self, dist: isize) -> u8 {
+        let idx = self.buffer_index_of_dist(dist);
+        self.buffer[idx]
+    }
+
+    /// Copies the back-referenced slice into the buffer and returns the newly
+    /// inserted region as a pair of slices.
+    ///
+    /// This implementation only goes for correctness, no optimizations are
+    /// performed.
+    #[inline(always)]
+    fn backreference_trivial(&mut self, dist: isize, len: usize) -> (&[u8], &[u8]) {
+        let start = self.cursor;
+        for _ in 0..len {
+            let e = self.peek(dist);
+            self.push(e);
+        }
+        let end = self.cursor;
+        // Determine if we need to split
+        if end <= start && len > 0 {
+            (&self.buffer[start..], &self.buffer[..end])
+        }
+        else {
+            (&self.buffer[start..end], &self.buffer[0..0])
+        }
+    }
+
+    /// Copies the back-referenced slice using memcpy. This assumes no overlaps
+    /// or wraps happen.
+    #[inline(always)]
+    fn backreference_memcopy(&mut self, start: usize, len: usize) -> (&[u8], &[u8]) {
+        // Trivial memcopy
+        self.buffer.copy_within(start..(start + len), self.cursor);
+        let result_slice = &self.buffer[self.cursor..(self.cursor + len)];
+        self.cursor = (self.cursor + len) % self.buffer.len();
+        (result_slice, &self.buffer[0..0])
+    }
+
+    /// Copies the back-referenced slice with a left-to-right bytewise copy.
+    /// This assumes no wraps happen.
+    #[inline(always)]
+    fn backreference_bytecopy(&mut self, start: usize, len: usize) -> (&[u8], &[u8]) {
+        for i in 0..len {
+            self.buffer[self.cursor + i] = self.buffer[start + i];
+        }
+        let result_slice = &self.buffer[self.cursor..(self.cursor + len)];
+        self.cursor = (self.cursor + len) % self.buffer.len();
+        (result_slice, &self.buffer[0..0])
+    }
+
+    /// Does backreference by memset-ting a single byte.
+    #[inline(always)]
+    fn backreference_memset(&mut self, byte: u8, len: usize) -> (&[u8], &[u8]) {
+        for b in &mut self.buffer[self.cursor..(self.cursor + len)] {
+            *b = byte;
+        }
+        let result_slice = &self.buffer[self.cursor..(self.cursor + len)];
+        self.cursor = (self.cursor + len) % self.buffer.len();
+        (result_slice, &self.buffer[0..0])
+    }
+
+    // NOTE: This could be optimized further for some cases but it's not that
+    // trivial to do so.
+    /// Copies `len` elements, starting from `dist` away from the cursor, to the
+    /// end of the `SlidingWindow`. The source and destination regions are
+    /// allowed to overlap, in which case the copied bytes repeat. The newly
+    /// inserted region is returned as a pair of slices, as it might wrap
+    /// around the end of the buffer.
+    ///
+    /// # Panics
+    ///
+    /// If `len` is greater than the capacity of the `SlidingWindow`, as the
+    /// inserted region then would overwrite itself.
+    #[inline(always)]
+    pub fn backreference(&mut self, dist: isize, len: usize) -> (&[u8], &[u8]) {
+        assert!(len <= self.buffer.len(), "Backreference is longer than the window!");
+
+        let start_copy = self.buffer_index_of_dist(dist);
+        let end_copy = (start_copy + len) % self.buffer.len();
+
+        let cursor_nowrap = self.cursor + len <= self.buffer.len();
+        let src_no_wrap = start_copy <= end_copy;
+
+        if len > 0 && cursor_nowrap && src_no_wrap {
+            let no_overlap = start_copy >= self.cursor + len || end_copy < self.cursor;
+            if no_overlap {
+                // Trivial memcopy
+                return self.backreference_memcopy(start_copy, len);
+            }
+            // At least we don't need to wrap-check stuff
+            let forward_copy = start_copy <= self.cursor;
+            if forward_copy {
+                let overlap = self.cursor - start_copy;
+                if overlap == 1 {
+                    // Byte-wise memset
+                    let byte = self.buffer[start_copy];
+                    return self.backreference_memset(byte, len);
+                }
+            }
+
+            return self.backreference_bytecopy(start_copy, len);
+        }
+
+        // Fallback
+        self.backreference_trivial(dist, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(w: &SlidingWindow) -> Vec<u8> {
+        (0..w.capacity()).map(|i| w.peek(-(w.capacity() as isize) + i as isize)).collect()
+    }
+
+    #[test]
+    fn test_push_wraps_around() {
+        let mut w = SlidingWindow::new(4);
+        for b in b"abcdef" {
+            w.push(*b);
+        }
+        assert_eq!(contents(&w), b"cdef");
+        assert_eq!(w.peek(-1), b'f');
+        assert_eq!(w.peek(-4), b'c');
+    }
+
+    #[test]
+    fn test_push_slice_wraps_around() {
+        let mut w = SlidingWindow::new(4);
+        w.push_slice(b"abc");
+        w.push_slice(b"de");
+        assert_eq!(contents(&w), b"bcde");
+        w.push_slice(b"fgh");
+        assert_eq!(contents(&w), b"efgh");
+    }
+
+    #[test]
+    fn test_push_slice_longer_than_capacity() {
+        let mut w = SlidingWindow::new(4);
+        w.push(b'x');
+        w.push_slice(b"abcdefg");
+        assert_eq!(contents(&w), b"defg");
+        w.push(b'h');
+        assert_eq!(contents(&w), b"efgh");
+    }
+
+    #[test]
+    fn test_backreference_repeat() {
+        let mut w = SlidingWindow::new(8);
+        w.push(b'a');
+        let (w1, w2) = w.backreference(-1, 5);
+        assert_eq!([w1, w2].concat(), b"aaaaa");
+        assert_eq!(w.peek(-1), b'a');
+    }
+
+    #[test]
+    fn test_backreference_wraps_destination() {
+        let mut w = SlidingWindow::new(8);
+        w.push_slice(b"abcdef");
+        let (w1, w2) = w.backreference(-3, 4);
+        assert_eq!(w1, b"de");
+        assert_eq!(w2, b"fd");
+        assert_eq!(contents(&w), b"cdefdefd");
+    }
+
+    #[test]
+    fn test_backreference_wraps_source() {
+        let mut w = SlidingWindow::new(8);
+        w.push_slice(b"abcdefgh");
+        w.push_slice(b"ij");
+        // Source starts at 'g' in the previous lap
+        let (w1, w2) = w.backreference(-4, 3);
+        assert_eq!([w1, w2].concat(), b"ghi");
+        assert_eq!(contents(&w), b"fghijghi");
+    }
+
+    #[test]
+    fn test_backreference_full_capacity() {
+        let mut w = SlidingWindow::new(4);
+        w.push_slice(b"abcd");
+        let (w1, w2) = w.backreference(-4, 4);
+        assert_eq!([w1, w2].concat(), b"abcd");
+        assert_eq!(contents(&w), b"abcd");
+    }
+}
@@ -0,0 +1,419 @@
+//! Tar archive handling, for the ustar format.
+//!
+//! Tar archives have no central directory, the entries are read one after the
+//! other with `TarArchive::next_entry`, which makes it possible to read them
+//! from any `Read`, even from a decompressor. For example `.tar.gz` archives
+//! can be read by putting an `Inflate` under the `TarArchive`, after skipping
+//! the gzip header.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::Read;
+//! use cacti_archive::tar::TarArchive;
+//!
+//! let mut archive = TarArchive::new(File::open("assets.tar")?);
+//! while let Some(mut entry) = archive.next_entry()? {
+//!     if entry.is_file() {
+//!         let mut content = Vec::new();
+//!         entry.read_to_end(&mut content)?;
+//!         println!("{}: {} bytes", entry.name(), content.len());
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::Read;
+use std::io;
+use std::time::{SystemTime, Duration};
+
+/// The size of the header and data blocks.
+const BLOCK_SIZE: usize = 512;
+
+/// Represents a tar archive, that's read sequentially.
+#[derive(Debug)]
+pub struct TarArchive<R: Read> {
+    reader   : R   ,
+    // The unread data of the current entry
+    remaining: u64 ,
+    // The padding after the data of the current entry
+    padding  : u64 ,
+    // The end of archive marker was found
+    finished : bool,
+}
+
+impl <R: Read> TarArchive<R> {
+    /// Creates a new `TarArchive`, that reads the entries from the given
+    /// reader.
+    pub fn new(reader: R) -> Self {
+        Self{ reader, remaining: 0, padding: 0, finished: false }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the header of the next entry, skipping the unread data of the
+    /// previous one. Returns `None` at the end of the archive.
+    ///
+    /// Only the ustar format is interpreted. The extension headers of other
+    /// formats - like the long names of GNU tar and the extended headers of
+    /// pax - are returned as entries with `TarEntryKind::Other`.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error, an error variant is returned. If the checksum
+    /// of the header is wrong, or a number in it is malformed, an error with
+    /// kind `InvalidData` is returned.
+    pub fn next_entry(&mut self) -> io::Result<Option<TarEntry<'_, R>>> {
+        if self.finished {
+            return Ok(None);
+        }
+        self.skip(self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+        let mut block = [0u8; BLOCK_SIZE];
+        if !self.read_block(&mut block)? || block.iter().all(|b| *b == 0) {
+            // The end of the archive is marked with zeroed blocks, but a
+            // missing marker is tolerated
+            self.finished = true;
+            return Ok(None);
+        }
+        let header = Header::parse(&block)?;
+        self.remaining = header.size;
+        self.padding = (BLOCK_SIZE as u64 - header.size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+        Ok(Some(TarEntry{ archive: self, header }))
+    }
+
+    /// Reads a whole block. Returns `false`, if the reader ended before the
+    /// block.
+    fn read_block(&mut self, block: &mut [u8; BLOCK_SIZE]) -> io::Result<bool> {
+        let mut filled = 0;
+        while filled < BLOCK_SIZE {
+            match self.reader.read(&mut block[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                    "Tar archive ended in a header!")),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Skips the given number of bytes in the reader.
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                "Tar archive ended in the data of an entry!"));
+        }
+        Ok(())
+    }
+}
+
+/// The kinds of entries in a `TarArchive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarEntryKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link, see `TarEntry::link_name`.
+    Symlink,
+    /// A hard link to a previous entry, see `TarEntry::link_name`.
+    HardLink,
+    /// Any other kind, with the type flag of the header.
+    Other(u8),
+}
+
+/// The parsed header of a tar entry.
+#[derive(Debug)]
+struct Header {
+    name     : String       ,
+    link_name: String       ,
+    kind     : TarEntryKind ,
+    size     : u64          ,
+    mode     : u32          ,
+    mtime    : SystemTime   ,
+}
+
+impl Header {
+    /// Parses and validates a header block.
+    fn parse(block: &[u8; BLOCK_SIZE]) -> io::Result<Self> {
+        // The checksum is calculated with the checksum field being spaces
+        let expected = parse_octal(&block[148..156])?;
+        let computed: u64 = block.iter().enumerate()
+            .map(|(i, b)| if (148..156).contains(&i) { b' ' as u64 } else { *b as u64 })
+            .sum();
+        if expected != computed {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                "Tar header checksum mismatch!"));
+        }
+        let mut name = parse_string(&block[0..100]);
+        if &block[257..262] == b"ustar" {
+            // Long names are split into a prefix and the name
+            let prefix = parse_string(&block[345..500]);
+            if !prefix.is_empty() {
+                name = format!("{}/{}", prefix, name);
+            }
+        }
+        let kind = match block[156] {
+            b'0' | b'\0' | b'7' => TarEntryKind::File,
+            b'1' => TarEntryKind::HardLink,
+            b'2' => TarEntryKind::Symlink,
+            b'5' => TarEntryKind::Directory,
+            flag => TarEntryKind::Other(flag),
+        };
+        if kind == TarEntryKind::Directory && name.ends_with('/') {
+            // We remove the '/'
+            name.pop();
+        }
+        let mode = parse_octal(&block[100..108])?;
+        let mtime = parse_octal(&block[136..148])?;
+        // A base-256 time can be way past what SystemTime can represent
+        let mtime = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(mtime))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                "Tar header modification time is out of range!"))?;
+        Ok(Self{
+            name,
+            link_name: parse_string(&block[157..257]),
+            kind,
+            size: parse_octal(&block[124..136])?,
+            mode: (mode & 0o7777) as u32,
+            mtime,
+        })
+    }
+}
+
+/// Parses a 0-terminated string field.
+fn parse_string(field: &[u8]) -> String {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+/// Parses a numeric field, that's octal digits, padded with spaces and 0s.
+/// Big numbers can be stored in base-256 instead, marked by the highest bit.
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData,
+        "Invalid number in tar header!");
+    if field[0] & 0x80 != 0 {
+        let mut result = (field[0] & 0x7f) as u64;
+        for b in &field[1..] {
+            result = result.checked_mul(256).ok_or_else(invalid)? | *b as u64;
+        }
+        return Ok(result);
+    }
+    let mut result = 0u64;
+    for b in field.iter().skip_while(|b| **b == b' ') {
+        match b {
+            b'0'..=b'7' => {
+                result = result.checked_mul(8).ok_or_else(invalid)? + (b - b'0') as u64;
+            },
+            b' ' | b'\0' => break,
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(result)
+}
+
+/// Represents a single entry in a `TarArchive`. The data of the entry can be
+/// read with the `Read` implementation.
+#[derive(Debug)]
+pub struct TarEntry<'a, R: Read> {
+    archive: &'a mut TarArchive<R>,
+    header : Header               ,
+}
+
+impl <'a, R: Read> TarEntry<'a, R> {
+    /// Returns the full path and name of this entry.
+    pub fn name(&self) -> &str { &self.header.name }
+
+    /// Returns the kind of this entry.
+    pub fn kind(&self) -> TarEntryKind { self.header.kind }
+    /// Returns `true`, if this entry is a file.
+    pub fn is_file(&self) -> bool { self.header.kind == TarEntryKind::File }
+    /// Returns `true`, if this entry is a directory.
+    pub fn is_dir(&self) -> bool { self.header.kind == TarEntryKind::Directory }
+
+    /// Returns the target of a link, `None`, if this entry is not a link.
+    pub fn link_name(&self) -> Option<&str> {
+        match self.header.kind {
+            TarEntryKind::Symlink | TarEntryKind::HardLink => Some(&self.header.link_name),
+            _ => None,
+        }
+    }
+
+    /// Returns the byte-size of the data of this entry.
+    pub fn size(&self) -> u64 { self.header.size }
+
+    /// Returns the Unix permission bits of this entry.
+    pub fn mode(&self) -> u32 { self.header.mode }
+
+    /// Returns the stored modification time, with second accuracy.
+    pub fn modification_time(&self) -> SystemTime { self.header.mtime }
+}
+
+impl <'a, R: Read> Read for TarEntry<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let archive = &mut *self.archive;
+        if archive.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = std::cmp::min(buf.len() as u64, archive.remaining) as usize;
+        let read = archive.reader.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                "Tar archive ended in the data of an entry!"));
+        }
+        archive.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deflate::Inflate;
+
+    /// A DEFLATE-compressed ustar archive with an "assets" directory, an
+    /// "assets/hello.txt" file and an "assets/link.txt" symlink to it.
+    const TEST_ARCHIVE: &[u8] = &[
+        0xed, 0xd4, 0x4d, 0x0a, 0xc2, 0x30, 0x10, 0x86, 0xe1, 0xac, 0x3d, 0x45,
+        0xdd, 0x8b, 0x66, 0xd2, 0xb4, 0xb9, 0x82, 0x37, 0x70, 0x1d, 0x6a, 0xc1,
+        0x62, 0xb1, 0xd0, 0x46, 0xf0, 0xf8, 0xf6, 0x47, 0x5c, 0x14, 0xfc, 0xd9,
+        0x44, 0x28, 0xbe, 0xcf, 0x66, 0x42, 0x92, 0x45, 0xe0, 0xcb, 0x8c, 0xef,
+        0xba, 0x32, 0x74, 0x3b, 0x15, 0x93, 0xee, 0xb9, 0x2c, 0x1b, 0x6b, 0x6f,
+        0x5e, 0xc7, 0xb5, 0xa4, 0xce, 0x38, 0x2b, 0xd3, 0xbe, 0x48, 0x2a, 0x5a,
+        0x25, 0x99, 0xfa, 0x81, 0x6b, 0x17, 0x7c, 0xdb, 0x3f, 0xa5, 0xf0, 0x45,
+        0xa8, 0xde, 0xdc, 0xfb, 0x74, 0xbe, 0x50, 0x7e, 0xca, 0xff, 0x54, 0xd6,
+        0x75, 0xb3, 0x0d, 0xb7, 0x10, 0x2b, 0xff, 0xdc, 0xda, 0xd7, 0xf9, 0x4b,
+        0xfe, 0xcc, 0x5f, 0x86, 0x7b, 0x7d, 0xfc, 0xb9, 0x51, 0x89, 0x26, 0xff,
+        0xe8, 0xf6, 0x43, 0xf0, 0x9b, 0xe4, 0xd0, 0xb4, 0xf5, 0x71, 0xbd, 0x52,
+        0xf8, 0x33, 0x8f, 0xfe, 0xaf, 0xab, 0xcb, 0x39, 0x56, 0xfb, 0x4f, 0xf3,
+        0xdf, 0xb9, 0xaf, 0xe6, 0xff, 0x30, 0xf8, 0xb5, 0x98, 0xdc, 0xa6, 0x2a,
+        0x31, 0x51, 0xa7, 0xd2, 0xac, 0xff, 0xf9, 0x0a, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0xa1, 0xee,
+    ];
+
+    fn test_archive() -> Vec<u8> {
+        let mut result = Vec::new();
+        Inflate::new(TEST_ARCHIVE).read_to_end(&mut result).unwrap();
+        result
+    }
+
+    fn secs(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_entries() -> io::Result<()> {
+        // Straight from the decompressor
+        let mut archive = TarArchive::new(Inflate::new(TEST_ARCHIVE));
+
+        let entry = archive.next_entry()?.unwrap();
+        assert_eq!(entry.name(), "assets");
+        assert!(entry.is_dir());
+        assert_eq!(entry.size(), 0);
+        assert_eq!(entry.mode(), 0o755);
+        assert_eq!(entry.modification_time(), secs(1600000000));
+
+        let mut entry = archive.next_entry()?.unwrap();
+        assert_eq!(entry.name(), "assets/hello.txt");
+        assert!(entry.is_file());
+        assert_eq!(entry.link_name(), None);
+        assert_eq!(entry.size(), 14);
+        assert_eq!(entry.mode(), 0o644);
+        assert_eq!(entry.modification_time(), secs(1600000100));
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+        assert_eq!(content, "Hello, World!\n");
+
+        let entry = archive.next_entry()?.unwrap();
+        assert_eq!(entry.name(), "assets/link.txt");
+        assert_eq!(entry.kind(), TarEntryKind::Symlink);
+        assert_eq!(entry.link_name(), Some("hello.txt"));
+
+        assert!(archive.next_entry()?.is_none());
+        assert!(archive.next_entry()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_unread_data() -> io::Result<()> {
+        let data = test_archive();
+        let mut archive = TarArchive::new(&data[..]);
+        let mut names = Vec::new();
+        while let Some(mut entry) = archive.next_entry()? {
+            // Only read a part of it
+            let mut buf = [0u8; 5];
+            let read = entry.read(&mut buf)?;
+            assert!(read <= entry.size() as usize);
+            names.push(entry.name().to_string());
+        }
+        assert_eq!(names, ["assets", "assets/hello.txt", "assets/link.txt"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut data = test_archive();
+        // Change the mode of the directory
+        data[104] = b'1';
+        let err = TarArchive::new(&data[..]).next_entry().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_mtime_out_of_range() {
+        let mut data = test_archive();
+        // Base-256 mtime of u64::MAX in the first header
+        data[136..148].copy_from_slice(&[0x80, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        // Keep the checksum valid
+        let checksum: u64 = data[..BLOCK_SIZE].iter().enumerate()
+            .map(|(i, b)| if (148..156).contains(&i) { b' ' as u64 } else { *b as u64 })
+            .sum();
+        data[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        let err = TarArchive::new(&data[..]).next_entry().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn test_truncated() -> io::Result<()> {
+        let data = test_archive();
+        // In the data of the file
+        let mut archive = TarArchive::new(&data[..(2 * BLOCK_SIZE + 5)]);
+        archive.next_entry()?;
+        let mut entry = archive.next_entry()?.unwrap();
+        let mut content = Vec::new();
+        let err = entry.read_to_end(&mut content).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        // In a header
+        let mut archive = TarArchive::new(&data[..100]);
+        let err = archive.next_entry().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        // Without the end of archive marker
+        let mut archive = TarArchive::new(&data[..(4 * BLOCK_SIZE)]);
+        let mut count = 0;
+        while archive.next_entry()?.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_octal() -> io::Result<()> {
+        assert_eq!(parse_octal(b"0000644\0")?, 0o644);
+        assert_eq!(parse_octal(b"  644 \0\0")?, 0o644);
+        assert_eq!(parse_octal(b"\0\0\0\0")?, 0);
+        // Base-256
+        assert_eq!(parse_octal(&[0x80, 0, 0, 1, 0])?, 256);
+        assert_eq!(parse_octal(b"0009").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+}
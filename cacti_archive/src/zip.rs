@@ -1,32 +1,107 @@
 //! Zip archive handling.
 // TODO: doc
 
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Write, Seek, SeekFrom};
 use std::io;
+use std::fs;
+use std::fmt;
+use std::path::{Path, PathBuf, Component};
 use std::time::{SystemTime, Duration};
 use std::convert::{TryFrom, TryInto};
-use crate::deflate::Inflate;
+use crate::crc32::Crc32;
+use crate::deflate::{Inflate, Deflate, CompressionLevel};
+#[cfg(feature = "mmap")]
+use crate::mmap::Mmap;
+use cacti_fs::temp;
+
+/// The errors specific to the Zip format. These are reported wrapped in an
+/// `io::Error`, use `ZipError::from_io` to get them back.
+///
+/// ```no_run
+/// use cacti_archive::zip::{ZipArchive, ZipError};
+///
+/// let file = std::fs::File::open("archive.zip")?;
+/// match ZipArchive::parse(file) {
+///     Ok(archive) => println!("{} entries", archive.entry_count()),
+///     Err(err) => match ZipError::from_io(&err) {
+///         Some(ZipError::NoCentralDirectory) => println!("Not a Zip archive!"),
+///         _ => return Err(err),
+///     },
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ZipError {
+    /// A structure didn't start with the expected signature.
+    BadSignature,
+    /// The archive ended in the middle of a structure.
+    Truncated,
+    /// The end of central directory record could not be found.
+    NoCentralDirectory,
+    /// The entry is compressed with the given, unsupported method.
+    UnsupportedCompression(u16),
+    /// The entry is encrypted. `strong` is true for the strong encryption
+    /// specification of PKWARE.
+    Encrypted{ strong: bool },
+    /// The CRC32 of the extracted contents doesn't match the stored one.
+    CrcMismatch{ expected: u32, got: u32 },
+    /// The local header of the entry disagrees with the central directory, in
+    /// strict mode.
+    LocalHeaderMismatch,
+    /// The entry decompresses to a different size, than the stored one.
+    SizeMismatch,
+    /// The name of the entry would point outside of the extraction
+    /// destination (known as Zip Slip).
+    UnsafePath(String),
+    /// The contents of the named entry are not valid UTF-8, while text was
+    /// requested.
+    InvalidUtf8(String),
+}
 
-/// A structure for calculating CRC32.
-struct Crc32(u32);
+impl ZipError {
+    /// Returns the `ZipError` wrapped in the given `io::Error`, if there's one.
+    pub fn from_io(err: &io::Error) -> Option<&ZipError> {
+        err.get_ref()?.downcast_ref()
+    }
 
-impl Crc32 {
-    /// The magic number used in CRC.
-    const MAGIC: u32 = 0xdebb20e3;
+    /// Returns the `io::ErrorKind` this error is reported with.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::Truncated                 => io::ErrorKind::UnexpectedEof,
+            Self::NoCentralDirectory        => io::ErrorKind::NotFound,
+            Self::UnsupportedCompression(_)
+            | Self::Encrypted{ .. }         => io::ErrorKind::Other,
+            _                               => io::ErrorKind::InvalidData,
+        }
+    }
+}
 
-    /// Creates a new `Crc32` with a default value.
-    fn new() -> Self { Self(0xffffffff) }
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadSignature => write!(f, "Wrong signature!"),
+            Self::Truncated => write!(f, "Not enough bytes!"),
+            Self::NoCentralDirectory => write!(f, "Could not find end of central directory record!"),
+            Self::UnsupportedCompression(9) => write!(f, "Deflate64 compression is not supported!"),
+            Self::UnsupportedCompression(n) => write!(f, "Unsupported compression {}!", n),
+            Self::Encrypted{ strong: true } => write!(f, "Strong encryption is not supported!"),
+            Self::Encrypted{ strong: false } => write!(f, "Encryption is not supported!"),
+            Self::CrcMismatch{ expected, got } =>
+                write!(f, "CRC32 mismatch, expected {:08x}, got {:08x}!", expected, got),
+            Self::LocalHeaderMismatch => write!(f, "Local header doesn't match the central directory!"),
+            Self::SizeMismatch => write!(f, "The decompressed contents don't match the stored size!"),
+            Self::UnsafePath(name) => write!(f, "Entry '{}' points outside of the destination!", name),
+            Self::InvalidUtf8(name) => write!(f, "The contents of '{}' are not valid UTF-8!", name),
+        }
+    }
+}
 
-    /// Returns the result of the `Crc32`.
-    fn finalize(self) -> u32 { !self.0 }
+impl std::error::Error for ZipError {}
 
-    /// Adds a byte to the `Crc32`.
-    fn push(&mut self, byte: u8) {
-        self.0 ^= byte as u32;
-        for _ in 0..8 {
-            let mask = !(self.0 & 1).wrapping_sub(1);
-            self.0 = (self.0 >> 1) ^ (Self::MAGIC & mask);
-        }
+impl From<ZipError> for io::Error {
+    fn from(err: ZipError) -> Self {
+        io::Error::new(err.kind(), err)
     }
 }
 
@@ -48,6 +123,12 @@ impl <R: Read + Seek> ByteReader<R> {
         Ok(Self{ reader, length, offset: 0 })
     }
 
+    /// Creates a new `ByteReader` from an underlying reader, that's positioned
+    /// at its start and has the given, already known length.
+    fn with_length(reader: R, length: usize) -> Self {
+        Self{ reader, length, offset: 0 }
+    }
+
     /// Returns a reference to the underlying reader.
     fn reader_ref(&mut self) -> &mut R { &mut self.reader }
 
@@ -131,12 +212,9 @@ trait Parse: Sized {
     ///
     /// The user shouldn't implement this, implement `parse_data` instead.
     fn parse_noreset<R: Read + Seek>(r: &mut ByteReader<R>) -> io::Result<(Self, usize)> {
-        use io::Error;
-        use io::ErrorKind::{UnexpectedEof, InvalidData};
-
         // Check if fix length is in range
         if r.rem_len() < Self::FIX_LEN {
-            return Err(Error::new(UnexpectedEof, "Not enough bytes!"));
+            return Err(ZipError::Truncated.into());
         }
 
         match Self::SIGNATURE {
@@ -147,11 +225,11 @@ trait Parse: Sized {
             Signature::Required(signature) => {
                 // Check if signarute and fix length are in range
                 if r.rem_len() < Self::FIX_LEN + 4 {
-                    return Err(Error::new(UnexpectedEof, "Not enough bytes!"));
+                    return Err(ZipError::Truncated.into());
                 }
                 // Check signature
                 if r.read_le_u32()? != signature {
-                    return Err(Error::new(InvalidData, "Wrong signature!"));
+                    return Err(ZipError::BadSignature.into());
                 }
                 let (result, consumed) = Self::parse_data(r)?;
                 Ok((result, Self::FIX_LEN + consumed + 4))
@@ -208,7 +286,7 @@ impl Parse for EndOfCentralDirectoryRecord {
         let comment_len            = r.read_le_u16()? as usize;
         // Now the variable-sized comment
         if r.rem_len() < comment_len {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough bytes!"));
+            return Err(ZipError::Truncated.into());
         }
         let comment = r.read_to_vec(comment_len)?;
         let result = Self{
@@ -237,8 +315,7 @@ impl EndOfCentralDirectoryRecord {
                 return Ok((r, offset));
             }
             if offset == 0 {
-                return Err(io::Error::new(io::ErrorKind::NotFound,
-                    "Could not find end of central directory rectord!"));
+                return Err(ZipError::NoCentralDirectory.into());
             }
             offset -= 1;
         }
@@ -264,7 +341,7 @@ struct FileHeader {
     internal_file_attribs: u16                     ,
     external_file_attribs: u32                     ,
     local_header_offset  : u32                     ,
-    file_name            : String                  ,
+    file_name            : LazyName                ,
     extra                : Vec<ExtensibleDataField>,
     file_comment         : String                  ,
 }
@@ -292,12 +369,12 @@ impl Parse for FileHeader {
         let local_header_offset   = r.read_le_u32()?;
         // Check for space for variable-length
         if r.rem_len() < file_name_len + extra_len + file_comment_len {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough bytes!"));
+            return Err(ZipError::Truncated.into());
         }
         // Extract
         let is_utf8 = (flags & (1 << 11)) != 0;
         let string_decode = if is_utf8 { decode_utf8 } else { decode_cp437 };
-        let file_name = string_decode(&r.read_to_vec(file_name_len)?);
+        let file_name = LazyName::new(r.read_to_vec(file_name_len)?, is_utf8);
         let (extra, _ec) = ExtensibleDataField::parse_vec(r, extra_len)?;
         let file_comment = string_decode(&r.read_to_vec(file_comment_len)?);
         // All good
@@ -329,10 +406,47 @@ impl FileHeader {
         (self.flags & (1 << index)) != 0
     }
 
+    /// Returns `true`, if the entry uses strong encryption, see
+    /// `is_strong_encrypted`.
+    fn is_strong_encrypted(&self) -> bool {
+        is_strong_encrypted(self.flags, &self.extra)
+    }
+
+    /// Checks, if the local replica of this header agrees on the name, the
+    /// CRC32 and the sizes. When a data descriptor is used, the local CRC32
+    /// and sizes are zeroes, so they are not compared.
+    /// Specification 4.4.4.
+    fn matches_local(&self, local: &LocalFileHeader) -> bool {
+        if self.file_name.raw != local.file_name.raw {
+            return false;
+        }
+        if local.flags & (1 << 3) != 0 {
+            return true;
+        }
+        self.crc32 == local.crc32
+            && self.compressed_size == local.compressed_size
+            && self.uncompressed_size == local.uncompressed_size
+    }
+
     /// Returns `true`, if this header represents a directory.
+    ///
+    /// A trailing separator in the name always marks a directory. Since some
+    /// archivers omit it, an empty entry is also a directory, if its external
+    /// attributes say so: the `S_IFDIR` mode for Unix hosts and the MS-DOS
+    /// directory attribute for everything else.
+    /// Specification 4.4.2 and 4.4.15.
     fn is_dir(&self) -> bool {
-        let lastc = self.file_name.chars().last();
-        lastc == Some('/') || lastc == Some('\\')
+        if self.file_name.ends_with_separator() {
+            return true;
+        }
+        if self.uncompressed_size != 0 {
+            return false;
+        }
+        match self.version_made >> 8 {
+            // Unix and OS X store the mode in the high word
+            3 | 19 => (self.external_file_attribs >> 16) & 0o170000 == 0o040000,
+            _ => self.external_file_attribs & 0x10 != 0,
+        }
     }
 
     /// Returns `true`, if this header represents a file.
@@ -341,6 +455,81 @@ impl FileHeader {
     }
 }
 
+/// The ID of the extensible data field holding the strong encryption header.
+/// Specification 4.5.12.
+const STRONG_ENCRYPTION_HEADER_ID: u16 = 0x0017;
+
+/// Returns `true`, if a header with the given flags and extra fields belongs
+/// to a strongly encrypted entry. This is marked by the flag, the presence of
+/// the strong encryption header, or by the flag of central directory
+/// encryption, that masks the values of the local header.
+/// Specification 4.4.4 and 7.2.
+fn is_strong_encrypted(flags: u16, extra: &[ExtensibleDataField]) -> bool {
+    (flags & (1 << 6)) != 0
+        || (flags & (1 << 13)) != 0
+        || extra.iter().any(|e| e.id == STRONG_ENCRYPTION_HEADER_ID)
+}
+
+/// The ID of the extensible data field holding the Info-ZIP extended
+/// timestamps.
+/// Specification 4.6.1.
+const EXTENDED_TIMESTAMP_ID: u16 = 0x5455;
+
+/// Returns the modification time from the extended timestamp field in the
+/// given extra fields, if there's one. The field starts with flags, marking
+/// which of the modification, access and creation times follow, each of them
+/// being a signed, 4-byte Unix timestamp. The central directory only stores
+/// the modification time, but keeps the flags of the local header.
+fn extended_modification_time(extra: &[ExtensibleDataField]) -> Option<SystemTime> {
+    let field = extra.iter().find(|e| e.id == EXTENDED_TIMESTAMP_ID)?;
+    let data = &field.data;
+    if data.len() < 5 || data[0] & 1 == 0 {
+        return None;
+    }
+    let secs = i32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+    if secs >= 0 {
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64))
+    }
+    else {
+        Some(SystemTime::UNIX_EPOCH - Duration::from_secs(-(secs as i64) as u64))
+    }
+}
+
+/// The id of the extra field, that stores the Unix user and group ids.
+const UNIX_OWNER_ID: u16 = 0x7875;
+
+/// Returns the Unix user and group ids from the Info-ZIP Unix extra field in
+/// the given extra fields, if there's one. The field starts with a version,
+/// then both ids are stored with a size byte, followed by that many
+/// little-endian bytes. Ids that don't fit 32 bits are ignored.
+fn unix_owner(extra: &[ExtensibleDataField]) -> Option<(u32, u32)> {
+    fn read_id(data: &[u8]) -> Option<(u32, &[u8])> {
+        let size = *data.first()? as usize;
+        let bytes = data.get(1..(1 + size))?;
+        let mut id = 0u64;
+        for (i, b) in bytes.iter().enumerate() {
+            if i >= 8 {
+                if *b != 0 {
+                    return None;
+                }
+                continue;
+            }
+            id |= (*b as u64) << (8 * i);
+        }
+        Some((u32::try_from(id).ok()?, &data[(1 + size)..]))
+    }
+
+    let field = extra.iter().find(|e| e.id == UNIX_OWNER_ID)?;
+    let data = &field.data;
+    // Only the first version is known
+    if data.first() != Some(&1) {
+        return None;
+    }
+    let (uid, rest) = read_id(&data[1..])?;
+    let (gid, _) = read_id(rest)?;
+    Some((uid, gid))
+}
+
 /// Extensible data fields.
 /// Specification 4.5.1.
 #[repr(C)]
@@ -359,7 +548,7 @@ impl Parse for ExtensibleDataField {
         let data_len = r.read_le_u16()? as usize;
         // Check if enough for variable-sized
         if r.rem_len() < data_len {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough bytes!"));
+            return Err(ZipError::Truncated.into());
         }
         // Extract
         let data = r.read_to_vec(data_len)?;
@@ -409,7 +598,7 @@ struct LocalFileHeader {
     crc32                : u32                     ,
     compressed_size      : usize                   ,
     uncompressed_size    : usize                   ,
-    file_name            : String                  ,
+    file_name            : LazyName                ,
     extra                : Vec<ExtensibleDataField>,
 }
 
@@ -430,12 +619,11 @@ impl Parse for LocalFileHeader {
         let extra_len         = r.read_le_u16()? as usize;
         // Check if enough for variable
         if r.rem_len() < file_name_len + extra_len {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Not enough bytes!"));
+            return Err(ZipError::Truncated.into());
         }
         // Enough, read
         let is_utf8 = (flags & (1 << 11)) != 0;
-        let string_decode = if is_utf8 { decode_utf8 } else { decode_cp437 };
-        let file_name = string_decode(&r.read_to_vec(file_name_len)?);
+        let file_name = LazyName::new(r.read_to_vec(file_name_len)?, is_utf8);
         let (extra, _ec) = ExtensibleDataField::parse_vec(r, extra_len)?;
         // All good
         let result = Self{
@@ -454,11 +642,48 @@ impl Parse for LocalFileHeader {
     }
 }
 
-/// The enumeration of supported compression algorithms.
+/// A file name, that's only decoded to UTF-8, when it's first needed. Decoding
+/// every name up front would be wasteful for huge archives, when only a few
+/// entries are accessed.
+#[derive(Debug)]
+struct LazyName {
+    raw    : Vec<u8>       ,
+    is_utf8: bool          ,
+    decoded: Option<String>,
+}
+
+impl LazyName {
+    /// Creates a `LazyName` from the raw bytes and the encoding flag.
+    fn new(raw: Vec<u8>, is_utf8: bool) -> Self {
+        Self{ raw, is_utf8, decoded: None }
+    }
+
+    /// Returns the decoded name, decoding it on the first call.
+    fn decode(&mut self) -> &str {
+        if self.decoded.is_none() {
+            let string_decode = if self.is_utf8 { decode_utf8 } else { decode_cp437 };
+            self.decoded = Some(string_decode(&self.raw));
+        }
+        self.decoded.as_deref().unwrap()
+    }
+
+    /// Returns `true`, if the name ends with a path separator. Both encodings
+    /// store these as ASCII, so no decoding is needed.
+    fn ends_with_separator(&self) -> bool {
+        matches!(self.raw.last(), Some(b'/') | Some(b'\\'))
+    }
+}
+
+/// The enumeration of recognized compression algorithms.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// `NoCompression` keeps its name from before more algorithms were recognized
+#[allow(clippy::enum_variant_names)]
 enum Compression {
     NoCompression,
     Deflate,
+    /// Enhanced Deflate. It's recognized, so the entries can be listed, but
+    /// decompression is not supported.
+    Deflate64,
 }
 
 impl TryFrom<u16> for Compression {
@@ -468,105 +693,355 @@ impl TryFrom<u16> for Compression {
         match n {
             0 => Ok(Self::NoCompression),
             8 => Ok(Self::Deflate),
-            _ => Err(io::Error::new(io::ErrorKind::Other, "Unsupported compression!")),
+            9 => Ok(Self::Deflate64),
+            _ => Err(ZipError::UnsupportedCompression(n).into()),
         }
     }
 }
 
 impl Compression {
     /// Creates a decompressor for this compression algorithm with the given
-    /// reader and given compressed length.
-    fn create_decompressor<R: Read>(&self, reader: R, compressed_size: usize) -> ZipFileDecompressor<R> {
+    /// reader and given compressed length. Returns an error, if the algorithm
+    /// is recognized, but not supported.
+    fn create_decompressor<R: Read>(&self, reader: R, compressed_size: usize) -> io::Result<ZipFileDecompressor<R>> {
         let reader = reader.take(compressed_size as u64);
         match self {
-            Self::NoCompression => ZipFileDecompressor::NoCompression(reader)        ,
-            Self::Deflate       => ZipFileDecompressor::Deflate(Inflate::new(reader)),
+            Self::NoCompression => Ok(ZipFileDecompressor::NoCompression(reader)        ),
+            Self::Deflate       => Ok(ZipFileDecompressor::Deflate(Box::new(Inflate::new(reader)))),
+            Self::Deflate64     => Err(ZipError::UnsupportedCompression(9).into()),
         }
     }
 }
 
-/// Parses a Zip archive's central directory into `FileHeader` records.
-fn parse_central_directory<R: Read + Seek>(r: &mut ByteReader<R>) -> io::Result<Vec<FileHeader>> {
+/// Locates a Zip archive's central directory. Returns the offset of the first
+/// `FileHeader` record and the number of records.
+fn find_central_directory<R: Read + Seek>(r: &mut ByteReader<R>) -> io::Result<(usize, usize)> {
     // First we have to find the end of the central directory
     let (end_of_directory, _eod_start) = EndOfCentralDirectoryRecord::find(r)?;
     // TODO: Find out if Zip64
-    // Parse central directory entries
-    let mut entries = Vec::new();
-    r.set_offset(end_of_directory.central_dir_offset as usize)?;
-    for _ in 0..end_of_directory.entries_in_central_dir {
-        let (header, _) = FileHeader::parse_noreset(r)?;
-        entries.push(header);
-    }
-    Ok(entries)
+    Ok((end_of_directory.central_dir_offset as usize, end_of_directory.entries_in_central_dir as usize))
 }
 
 /// Represents a zipped archive.
 #[derive(Debug)]
 pub struct ZipArchive<R: Read + Seek> {
-    reader : ByteReader<R>  ,
-    entries: Vec<FileHeader>,
+    reader       : ByteReader<R>  ,
+    entry_count  : usize          ,
+    // The offset of the central directory, as stored in the end record
+    central_dir  : usize          ,
+    // The already parsed prefix of the central directory
+    entries      : Vec<FileHeader>,
+    // The offset of the first `FileHeader` not in `entries`
+    next_offset  : usize          ,
+    // Reject entries, where the local header disagrees with the central one
+    strict       : bool           ,
+    // Verify the CRC32 of the extracted contents
+    verify_crc   : bool           ,
+    // Restore the Unix owners of the extracted entries
+    restore_owner: bool           ,
 }
 
 impl <R: Read + Seek> ZipArchive<R> {
     /// Tries to parse a `ZipArchive`'s central directory from the given reader.
     pub fn parse(reader: R) -> io::Result<Self> {
-        let mut reader = ByteReader::new(reader)?;
-        let entries = parse_central_directory(&mut reader)?;
-        Ok(Self{ reader, entries })
+        let mut result = Self::parse_lazy(reader)?;
+        result.parse_entries_until(result.entry_count)?;
+        Ok(result)
+    }
+
+    /// Tries to locate a `ZipArchive`'s central directory in the given reader,
+    /// without parsing the entries in it. Entries are parsed on demand, when
+    /// they are first accessed, which makes opening archives with huge
+    /// directories cheap.
+    pub fn parse_lazy(reader: R) -> io::Result<Self> {
+        Self::parse_lazy_byte_reader(ByteReader::new(reader)?)
+    }
+
+    /// Locates the central directory in the given `ByteReader`, like
+    /// `parse_lazy`.
+    fn parse_lazy_byte_reader(mut reader: ByteReader<R>) -> io::Result<Self> {
+        let (central_dir, entry_count) = find_central_directory(&mut reader)?;
+        Ok(Self{
+            reader,
+            entry_count,
+            central_dir,
+            entries: Vec::new(),
+            next_offset: central_dir,
+            strict: false,
+            verify_crc: true,
+            restore_owner: false,
+        })
+    }
+
+    /// Sets, if entries are checked against their local headers, when they
+    /// are accessed. The central directory is always the one used, but a
+    /// local header that disagrees with it on the name, CRC32 or sizes is a
+    /// sign of corruption or tampering. Disabled by default.
+    ///
+    /// When enabled, accessing such an entry returns an error with kind
+    /// `InvalidData`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Sets, if the CRC32 of the contents is verified, when entries are
+    /// extracted with `extract_to_stripped`, `ZipFile::extract_to_temp` or
+    /// `ZipFile::decompress_to_string`. Enabled by default.
+    ///
+    /// Only disable this for trusted archives, where the integrity is already
+    /// guaranteed by an outer layer, like a signed package. Without the
+    /// check, a corrupted archive can silently extract corrupted contents.
+    /// `ZipFile::check_crc32` still verifies the contents explicitly.
+    pub fn set_verify_crc32(&mut self, verify: bool) {
+        self.verify_crc = verify;
+    }
+
+    /// Sets, if `extract_to_stripped` restores the owner of the extracted
+    /// entries on Unix, when the archive stores one - see
+    /// `ZipFile::unix_uid`. Changing the owner usually needs privileges,
+    /// without them extraction fails with a permission error. Disabled by
+    /// default, and ignored on other platforms.
+    pub fn set_restore_ownership(&mut self, restore: bool) {
+        self.restore_owner = restore;
     }
 
     /// Returns the number of `ZipFile` entries this archive holds.
-    pub fn entry_count(&self) -> usize { self.entries.len() }
+    pub fn entry_count(&self) -> usize { self.entry_count }
+
+    /// Returns the offset of the central directory, as stored in the end of
+    /// central directory record. When appending entries, the new local headers
+    /// start here, overwriting the old directory, which is then rewritten
+    /// after them.
+    pub fn central_directory_offset(&self) -> u64 { self.central_dir as u64 }
+
+    /// Returns the length of the whole archive in bytes, including the central
+    /// directory and the comment after it.
+    pub fn archive_len(&self) -> u64 { self.reader.total_len() as u64 }
 
     /// Returns the `ZipFile` descriptor for the given entry index.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not less than `entry_count`.
     pub fn entry_at_index<'a>(&'a mut self, index: usize) -> io::Result<ZipFile<'a, R>> {
-        ZipFile::new(&mut self.reader, &self.entries[index])
+        assert!(index < self.entry_count, "Entry index out of range!");
+        self.parse_entries_until(index + 1)?;
+        ZipFile::new(&mut self.reader, &mut self.entries[index], self.strict, self.verify_crc)
+    }
+
+    /// Makes sure that the first `count` entries of the central directory are
+    /// parsed.
+    fn parse_entries_until(&mut self, count: usize) -> io::Result<()> {
+        if self.entries.len() >= count {
+            return Ok(());
+        }
+        self.reader.set_offset(self.next_offset)?;
+        while self.entries.len() < count {
+            let (header, _) = FileHeader::parse_noreset(&mut self.reader)?;
+            self.entries.push(header);
+        }
+        self.next_offset = self.reader.offset();
+        Ok(())
+    }
+
+    /// Extracts the entries starting with `strip_prefix` into the `dest`
+    /// directory, removing the prefix from their paths. Entries not starting
+    /// with the prefix are skipped. The prefix is matched by whole path
+    /// components, so `pkg` doesn't match `pkg-1.0/README`. This is useful for
+    /// archives that wrap everything into a single top-level directory. The
+    /// CRC32 of each file is verified.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::path::Path;
+    /// use cacti_archive::zip::ZipArchive;
+    ///
+    /// // Extracts "pkg-1.0/src/lib.rs" to "out/src/lib.rs"
+    /// let mut archive = ZipArchive::parse(File::open("pkg-1.0.zip")?)?;
+    /// archive.extract_to_stripped(Path::new("out"), "pkg-1.0/")?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned. If an
+    /// entry would be extracted outside of `dest`, or the decompressed
+    /// contents of a file don't match the stored CRC32, an error with kind
+    /// `InvalidData` is returned.
+    pub fn extract_to_stripped(&mut self, dest: &Path, strip_prefix: &str) -> io::Result<()> {
+        let restore_owner = self.restore_owner;
+        for i in 0..self.entry_count {
+            let mut entry = self.entry_at_index(i)?;
+            // The rest of a `&str` is still a `&str`
+            let rest = Path::new(entry.name()).strip_prefix(strip_prefix).ok().and_then(Path::to_str);
+            let rest = match rest {
+                Some(rest) if !rest.is_empty() => rest,
+                _ => continue,
+            };
+            let path = join_entry_path(dest, rest)?;
+            if entry.is_dir() {
+                fs::create_dir_all(&path)?;
+            }
+            else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut file = fs::File::create(&path)?;
+                entry.extract_to_writer(&mut file)?;
+            }
+            if let (true, Some((uid, gid))) = (restore_owner, entry.unix_owner) {
+                set_owner(&path, uid, gid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with the name and a decompressing reader for every file in
+    /// the archive, in the order of the central directory. Directories are
+    /// skipped. The CRC32 of the contents is not verified, use `check_crc32`
+    /// on the entries for that.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io;
+    /// use cacti_archive::zip::ZipArchive;
+    ///
+    /// let mut archive = ZipArchive::parse(File::open("assets.zip")?)?;
+    /// archive.for_each_file(|name, reader| {
+    ///     let size = io::copy(reader, &mut io::sink())?;
+    ///     println!("{}: {} bytes", name, size);
+    ///     Ok(())
+    /// })?;
+    /// # Ok::<(), io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error, an unsupported entry or an error returned by
+    /// `f`, the iteration stops and the error is returned.
+    pub fn for_each_file<F>(&mut self, mut f: F) -> io::Result<()>
+        where F: FnMut(&str, &mut dyn Read) -> io::Result<()> {
+        for i in 0..self.entry_count {
+            let entry = self.entry_at_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            entry.check_supported()?;
+            entry.reader.seek(io::SeekFrom::Start(entry.data_offset as u64))?;
+            let mut decompressor = entry.compression.create_decompressor(&mut *entry.reader, entry.compressed_size)?;
+            f(entry.name, &mut decompressor)?;
+        }
+        Ok(())
+    }
+}
+
+impl <'a> ZipArchive<io::Cursor<&'a [u8]>> {
+    /// Tries to parse a `ZipArchive`'s central directory from an in-memory
+    /// archive, like `parse`. As the length of the bytes is known, there's no
+    /// need to seek around to determine it, which makes this the preferred way
+    /// of opening archives embedded into the executable.
+    pub fn parse_slice(bytes: &'a [u8]) -> io::Result<Self> {
+        let reader = ByteReader::with_length(io::Cursor::new(bytes), bytes.len());
+        let mut result = Self::parse_lazy_byte_reader(reader)?;
+        result.parse_entries_until(result.entry_count)?;
+        Ok(result)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl ZipArchive<io::Cursor<Mmap>> {
+    /// Memory-maps the file at the given path, and parses the central
+    /// directory of the archive in it, like `parse`. Only the accessed parts of
+    /// the file are read, which is cheaper than buffering for big archives.
+    ///
+    /// # Safety
+    ///
+    /// The archive is only valid while the file is unchanged. If another
+    /// process truncates the file while it's mapped, reading the archive
+    /// crashes the process. See `Mmap::open`.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, or a malformed archive, an error
+    /// variant is returned.
+    pub unsafe fn open_mmap(path: impl AsRef<Path>) -> io::Result<Self> {
+        let map = Mmap::open(path)?;
+        let len = map.len();
+        let reader = ByteReader::with_length(io::Cursor::new(map), len);
+        let mut result = Self::parse_lazy_byte_reader(reader)?;
+        result.parse_entries_until(result.entry_count)?;
+        Ok(result)
     }
 }
 
 /// Represents a single file or directory inside a `ZipArchive`.
 #[derive(Debug)]
 pub struct ZipFile<'a, R: Read + Seek> {
-    reader           : &'a mut R  ,
-    name             : &'a str    ,
-    is_encrypted     : bool       ,
-    is_file          : bool       ,
-    last_modified    : SystemTime ,
-    compression      : Compression,
-    data_offset      : usize      ,
-    compressed_size  : usize      ,
-    uncompressed_size: usize      ,
-    crc32            : u32        ,
+    reader             : &'a mut R         ,
+    name               : &'a str           ,
+    is_encrypted       : bool              ,
+    is_strong_encrypted: bool              ,
+    is_file            : bool              ,
+    last_modified      : SystemTime        ,
+    compression        : Compression       ,
+    data_offset        : usize             ,
+    compressed_size    : usize             ,
+    uncompressed_size  : usize             ,
+    crc32              : u32               ,
+    verify_crc         : bool              ,
+    unix_owner         : Option<(u32, u32)>,
 }
 
-// TODO: Fix CRC32 checks
-
 impl <'a, R: Read + Seek> ZipFile<'a, R> {
-    /// Creates the `ZipFile` from the given reader and `FileHeader`.
-    fn new(reader: &'a mut ByteReader<R>, header: &'a FileHeader) -> io::Result<Self> {
-        // File name
-        let mut name = header.file_name.as_str();
-        if header.is_dir() {
-            // We remove the '/'
-            name = &name[..(name.len() - 1)];
-        }
+    /// Creates the `ZipFile` from the given reader and `FileHeader`. In
+    /// `strict` mode the local header has to match the central one. With
+    /// `verify_crc` the CRC32 is checked on extraction.
+    fn new(reader: &'a mut ByteReader<R>, header: &'a mut FileHeader, strict: bool, verify_crc: bool) -> io::Result<Self> {
         // Data offset
         reader.set_offset(header.local_header_offset as usize)?;
-        let _local_header = LocalFileHeader::parse_noreset(reader)?;
+        let (local_header, _) = LocalFileHeader::parse_noreset(reader)?;
         let data_offset = reader.offset();
-        // Done
-        Ok(Self {
+        if strict && !header.matches_local(&local_header) {
+            return Err(ZipError::LocalHeaderMismatch.into());
+        }
+        // Modification time, the extended timestamp is more precise than the
+        // MS-DOS one, it's only missing from the central directory for some
+        // archivers
+        let last_modified = extended_modification_time(&header.extra)
+            .or_else(|| extended_modification_time(&local_header.extra))
+            .unwrap_or_else(|| decode_ms_dos_datetime(header.mod_date, header.mod_time));
+        let mut result = Self {
             reader: reader.reader_ref(),
-            name,
+            name: "",
             is_encrypted: header.is_flag(0),
+            // The central directory is usually the one lacking the marks
+            is_strong_encrypted: header.is_strong_encrypted()
+                || is_strong_encrypted(local_header.flags, &local_header.extra),
             is_file: header.is_file(),
-            last_modified: decode_ms_dos_datetime(header.mod_date, header.mod_time),
+            last_modified,
             compression: header.compression.try_into()?,
             data_offset,
             compressed_size: header.compressed_size,
             uncompressed_size: header.uncompressed_size,
             crc32: header.crc32,
-        })
+            verify_crc,
+            unix_owner: unix_owner(&header.extra).or_else(|| unix_owner(&local_header.extra)),
+        };
+        // File name, decoded last, as it borrows the header for good
+        let has_separator = header.file_name.ends_with_separator();
+        let name = header.file_name.decode();
+        result.name = if has_separator {
+            // We remove the '/'
+            &name[..(name.len() - 1)]
+        }
+        else {
+            name
+        };
+        Ok(result)
     }
 
     /// Returns the full path and name of this file or directory.
@@ -577,47 +1052,192 @@ impl <'a, R: Read + Seek> ZipFile<'a, R> {
     /// Returns `true`, if this entry is a directory.
     pub fn is_dir(&self) -> bool { !self.is_file }
 
-    /// Returns the stored modification time.
+    /// Returns the stored modification time. If the entry has an extended
+    /// timestamp - usually written by Unix archivers -, that is returned with
+    /// second accuracy. Otherwise the MS-DOS date and time is decoded, which
+    /// has a 2 second resolution and no timezone.
     pub fn modification_time(&self) -> SystemTime { self.last_modified }
 
+    /// Returns the Unix user id of the owner, if the archive stores it. Only
+    /// Unix archivers store the owner, in the `0x7875` extra field.
+    pub fn unix_uid(&self) -> Option<u32> { self.unix_owner.map(|(uid, _)| uid) }
+    /// Returns the Unix group id of the owner, if the archive stores it. See
+    /// `unix_uid`.
+    pub fn unix_gid(&self) -> Option<u32> { self.unix_owner.map(|(_, gid)| gid) }
+
     /// Returns the byte-size of the file this represents, when compressed.
     pub fn compressed_size(&self) -> usize { self.compressed_size }
     /// Returns the byte-size of the file this represents, when uncompressed.
     /// This can be used to pre-allocate a buffer for decompression.
     pub fn uncompressed_size(&self) -> usize { self.uncompressed_size }
 
+    /// Checks, if the contents of this file can be decompressed.
+    fn check_supported(&self) -> io::Result<()> {
+        // Strong encryption also sets the regular encryption flag
+        if self.is_strong_encrypted {
+            return Err(ZipError::Encrypted{ strong: true }.into());
+        }
+        if self.is_encrypted {
+            return Err(ZipError::Encrypted{ strong: false }.into());
+        }
+        Ok(())
+    }
+
     /// Returns the decompressor for this file. Use `uncompressed_size` as a
     /// length to  pre-allocate a buffer for the optimal allocation size.
     pub fn decompressor(&'a mut self) -> io::Result<impl Read + 'a> {
-        if self.is_encrypted {
-            return Err(io::Error::new(io::ErrorKind::Other, "Encryption is not supported!"));
-        }
+        self.check_supported()?;
         self.reader.seek(io::SeekFrom::Start(self.data_offset as u64))?;
-        Ok(self.compression.create_decompressor(&mut self.reader, self.compressed_size))
+        self.compression.create_decompressor(&mut self.reader, self.compressed_size)
     }
 
-    /// Checks integrity using the stored CRC32 value. Returns `true`, if the
-    /// check was valid.
+    /// Returns the decompressor for this file as a trait object. This is
+    /// useful for storing decompressors of different entries together, in
+    /// any other case prefer `decompressor`.
+    pub fn boxed_decompressor(&'a mut self) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(Box::new(self.decompressor()?))
+    }
+
+    /// Returns the CRC32 of the decompressed contents, as stored in the
+    /// archive.
+    pub fn expected_crc32(&self) -> u32 { self.crc32 }
+
+    /// Checks integrity using the stored CRC32 value, by decompressing the
+    /// contents. Returns `true`, if the check was valid.
     pub fn check_crc32(&mut self) -> io::Result<bool> {
-        const BUFFER_SIZE: usize = 32;
+        Ok(self.computed_crc32()? == self.crc32)
+    }
 
-        let mut buffer = [0u8; BUFFER_SIZE];
+    /// Decompresses the contents to calculate their CRC32. Together with
+    /// `expected_crc32`, this can be used to report the details of a failed
+    /// integrity check.
+    pub fn computed_crc32(&mut self) -> io::Result<u32> {
+        const BUFFER_SIZE: usize = 8192;
+
+        self.check_supported()?;
         self.reader.seek(io::SeekFrom::Start(self.data_offset as u64))?;
+        let mut decompressor = self.compression.create_decompressor(&mut *self.reader, self.compressed_size)?;
 
+        let mut buffer = [0u8; BUFFER_SIZE];
         let mut crc = Crc32::new();
-        let mut remaining = self.compressed_size;
-        while remaining > 0 {
-            let can_read = std::cmp::min(remaining, BUFFER_SIZE);
-            self.reader.read_exact(&mut buffer[..can_read])?;
-
-            for i in 0..can_read {
-                crc.push(buffer[i]);
+        loop {
+            let read = decompressor.read(&mut buffer)?;
+            if read == 0 {
+                break;
             }
+            crc.update(&buffer[..read]);
+        }
+
+        Ok(crc.finalize())
+    }
+
+    /// Decompresses this file into a new temporary file, that gets deleted
+    /// automatically when closed. The CRC32 of the decompressed contents is
+    /// verified. The returned file is positioned at the start, ready for
+    /// reading.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io::Read;
+    /// use cacti_archive::zip::ZipArchive;
+    ///
+    /// let mut archive = ZipArchive::parse(File::open("archive.zip")?)?;
+    /// let mut entry = archive.entry_at_index(0)?;
+    /// let mut scratch = entry.extract_to_temp()?;
+    /// let mut content = String::new();
+    /// scratch.read_to_string(&mut content)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned. If the
+    /// decompressed contents don't match the stored CRC32, an error with kind
+    /// `InvalidData` is returned.
+    pub fn extract_to_temp(&mut self) -> io::Result<fs::File> {
+        self.check_supported()?;
+
+        let mut file = temp::file(None)?;
+        self.extract_to_writer(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+
+    /// Decompresses this file into a `String`. The CRC32 of the decompressed
+    /// contents is verified.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error, an error variant is returned. If the
+    /// decompressed contents don't match the stored CRC32, or they are not
+    /// valid UTF-8, an error with kind `InvalidData` is returned.
+    pub fn decompress_to_string(&mut self) -> io::Result<String> {
+        let mut content = Vec::with_capacity(self.uncompressed_size);
+        self.extract_to_writer(&mut content)?;
+        String::from_utf8(content).map_err(|_| ZipError::InvalidUtf8(self.name.to_string()).into())
+    }
+
+    /// Decompresses this file into memory, and returns a seekable view of the
+    /// contents. This is useful for formats embedded in an entry, that need
+    /// `Seek` over the decompressed data, as a decompressor can only be read
+    /// forward. The CRC32 of the decompressed contents is verified.
+    ///
+    /// The whole file is held in memory, which costs `uncompressed_size`
+    /// bytes. For big entries consider `extract_to_temp` instead.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error, an error variant is returned. If the stored
+    /// uncompressed size is not plausible for the compressed size, the
+    /// contents decompress to more bytes than the stored uncompressed size,
+    /// or they don't match the stored CRC32, an error with kind `InvalidData`
+    /// is returned. This protects against archives, that decompress to far
+    /// more data than they claim (known as Zip bombs).
+    pub fn seekable_reader(&mut self) -> io::Result<io::Cursor<Vec<u8>>> {
+        // Deflate can't compress better than about 1:1032
+        const MAX_COMPRESSION_RATIO: usize = 1032;
+
+        if self.uncompressed_size > self.compressed_size.saturating_mul(MAX_COMPRESSION_RATIO) {
+            return Err(ZipError::SizeMismatch.into());
+        }
+        let mut content = Vec::with_capacity(self.uncompressed_size);
+        let mut writer = BoundedWriter{ inner: &mut content, remaining: self.uncompressed_size };
+        self.extract_to_writer(&mut writer)?;
+        Ok(io::Cursor::new(content))
+    }
+
+    /// Decompresses this file into the given writer, verifying the CRC32 of
+    /// the decompressed contents, unless disabled for the archive.
+    fn extract_to_writer<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        const BUFFER_SIZE: usize = 4096;
+
+        self.check_supported()?;
+        self.reader.seek(io::SeekFrom::Start(self.data_offset as u64))?;
+        let mut decompressor = self.compression.create_decompressor(&mut *self.reader, self.compressed_size)?;
 
-            remaining -= can_read;
+        let mut buffer = [0u8; BUFFER_SIZE];
+        // Only hash, if the result is checked
+        let mut crc = if self.verify_crc { Some(Crc32::new()) } else { None };
+        loop {
+            let read = decompressor.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            if let Some(crc) = &mut crc {
+                crc.update(&buffer[..read]);
+            }
+            writer.write_all(&buffer[..read])?;
         }
 
-        Ok(crc.finalize() == self.crc32)
+        if let Some(crc) = crc {
+            let computed = crc.finalize();
+            if computed != self.crc32 {
+                return Err(ZipError::CrcMismatch{ expected: self.crc32, got: computed }.into());
+            }
+        }
+        Ok(())
     }
 }
 
@@ -625,7 +1245,8 @@ impl <'a, R: Read + Seek> ZipFile<'a, R> {
 #[derive(Debug)]
 enum ZipFileDecompressor<R: Read> {
     NoCompression(io::Take<R>),
-    Deflate(Inflate<io::Take<R>>),
+    // Boxed, as the decompressor state is much larger than a plain reader
+    Deflate(Box<Inflate<io::Take<R>>>),
 }
 
 impl <R: Read> io::Read for ZipFileDecompressor<R> {
@@ -637,6 +1258,423 @@ impl <R: Read> io::Read for ZipFileDecompressor<R> {
     }
 }
 
+/// A writer, that fails instead of writing more than the given number of
+/// bytes.
+struct BoundedWriter<W: Write> {
+    inner: W,
+    remaining: usize,
+}
+
+impl <W: Write> Write for BoundedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > self.remaining {
+            return Err(ZipError::SizeMismatch.into());
+        }
+        let written = self.inner.write(buf)?;
+        self.remaining -= written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The options of an entry written by `ZipWriter`.
+#[derive(Debug, Clone, Copy)]
+pub struct FileOptions {
+    compression: Option<CompressionLevel>,
+    last_modified: Option<SystemTime>,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileOptions {
+    /// Creates the default options, DEFLATE compression with the default
+    /// level and the time the entry is started as the modification time.
+    pub fn new() -> Self {
+        Self{ compression: Some(CompressionLevel::Default), last_modified: None }
+    }
+
+    /// Sets the level of the DEFLATE compression, `None` stores the entry
+    /// without compression.
+    pub fn with_compression(mut self, level: Option<CompressionLevel>) -> Self {
+        self.compression = level;
+        self
+    }
+
+    /// Sets the modification time of the entry. It's stored as an extended
+    /// timestamp, with a precision of seconds, and as an MS-DOS date and time
+    /// in UTC for the tools, that don't know the extension.
+    pub fn with_modification_time(mut self, time: SystemTime) -> Self {
+        self.last_modified = Some(time);
+        self
+    }
+}
+
+/// The writer of the contents of the current entry.
+#[derive(Debug)]
+enum EntryWriter<W: Write> {
+    Stored(W),
+    Deflate(Box<Deflate<W>>),
+}
+
+/// An entry written by the `ZipWriter`, that goes into the central directory.
+#[derive(Debug)]
+struct WrittenEntry {
+    name               : String ,
+    compression        : u16    ,
+    mod_time           : u16    ,
+    mod_date           : u16    ,
+    crc32              : u32    ,
+    compressed_size    : u32    ,
+    uncompressed_size  : u32    ,
+    local_header_offset: u32    ,
+    extra              : Vec<u8>,
+    is_dir             : bool   ,
+}
+
+/// The entry being written by the `ZipWriter`.
+#[derive(Debug)]
+struct PendingEntry {
+    entry     : WrittenEntry,
+    crc       : Crc32       ,
+    data_start: u64         ,
+    written   : usize       ,
+}
+
+/// Writes a Zip archive. Entries are started with `start_file`, and their
+/// contents are written through `std::io::Write`. After the last entry,
+/// `finish` has to be called to write the central directory, otherwise the
+/// archive is not readable.
+///
+/// ```
+/// use std::io::{self, Write};
+/// use cacti_archive::zip::{ZipWriter, ZipArchive, FileOptions};
+///
+/// let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+/// writer.start_file("hello.txt", FileOptions::new())?;
+/// writer.write_all(b"Hello, World!")?;
+/// writer.add_directory("dir")?;
+/// let data = writer.finish()?.into_inner();
+///
+/// let archive = ZipArchive::parse(io::Cursor::new(data))?;
+/// assert_eq!(archive.entry_count(), 2);
+/// # Ok::<(), io::Error>(())
+/// ```
+///
+/// The sizes and the CRC32 of an entry are filled in the local header after
+/// its contents are written, that's why the writer has to be seekable. Zip64
+/// is not supported, so the entries and the whole archive have to be smaller
+/// than 4 GiB, and there can be at most 65535 entries.
+#[derive(Debug)]
+pub struct ZipWriter<W: Write + Seek> {
+    // Only `None` while switching between the entries, or after an error
+    // while doing so
+    writer : Option<EntryWriter<W>>,
+    entries: Vec<WrittenEntry>     ,
+    current: Option<PendingEntry>  ,
+}
+
+impl <W: Write + Seek> ZipWriter<W> {
+    /// The version needed to extract the written entries, 2.0 for DEFLATE and
+    /// directories.
+    const VERSION_NEEDED: u16 = 20;
+    /// The version made by the written entries, Unix as the host, for the
+    /// permissions in the external attributes.
+    const VERSION_MADE: u16 = (3 << 8) | Self::VERSION_NEEDED;
+    /// The flag marking UTF-8 names.
+    const UTF8_FLAG: u16 = 1 << 11;
+
+    /// Creates a `ZipWriter`, that appends the archive to the contents of the
+    /// given writer.
+    pub fn new(writer: W) -> Self {
+        Self{ writer: Some(EntryWriter::Stored(writer)), entries: Vec::new(), current: None }
+    }
+
+    /// Starts a new file entry with the given name and options, finishing the
+    /// previous entry. The contents are written through `std::io::Write`.
+    pub fn start_file(&mut self, name: &str, options: FileOptions) -> io::Result<()> {
+        self.finish_entry()?;
+        let compression = if options.compression.is_some() { 8 } else { 0 };
+        self.start_entry(name.to_string(), compression, options.last_modified, false)?;
+        if let Some(level) = options.compression {
+            match self.writer.take() {
+                Some(EntryWriter::Stored(w)) =>
+                    self.writer = Some(EntryWriter::Deflate(Box::new(Deflate::new(w, level)))),
+                other => self.writer = other,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a directory entry with the given name, finishing the previous
+    /// entry. A trailing `/` is added to the name, if it's missing.
+    pub fn add_directory(&mut self, name: &str) -> io::Result<()> {
+        self.finish_entry()?;
+        let mut name = name.to_string();
+        if !name.ends_with('/') {
+            name.push('/');
+        }
+        self.start_entry(name, 0, None, true)?;
+        self.finish_entry()
+    }
+
+    /// Finishes the last entry and writes the central directory. Returns the
+    /// underlying writer, positioned after the archive.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_entry()?;
+        let mut w = match self.writer.take() {
+            Some(EntryWriter::Stored(w)) => w,
+            Some(EntryWriter::Deflate(_)) => unreachable!(),
+            None => return Err(broken_writer()),
+        };
+        let entry_count = u16::try_from(self.entries.len())
+            .map_err(|_| zip64_unsupported())?;
+        let central_dir_offset = Self::archive_offset(&mut w)?;
+        let mut central_dir = Vec::new();
+        for entry in &self.entries {
+            Self::push_header(&mut central_dir, entry, true);
+        }
+        w.write_all(&central_dir)?;
+        // End of central directory record
+        let mut record = Vec::new();
+        push_u32(&mut record, 0x06054b50);
+        // No multi-disk archives
+        push_u16(&mut record, 0);
+        push_u16(&mut record, 0);
+        push_u16(&mut record, entry_count);
+        push_u16(&mut record, entry_count);
+        push_u32(&mut record, central_dir.len() as u32);
+        push_u32(&mut record, central_dir_offset);
+        // No comment
+        push_u16(&mut record, 0);
+        w.write_all(&record)?;
+        Ok(w)
+    }
+
+    /// Returns the end of the writer as an offset in the archive. The writer
+    /// is positioned there, nothing is written after the end.
+    fn archive_offset(w: &mut W) -> io::Result<u32> {
+        let offset = w.seek(SeekFrom::End(0))?;
+        u32::try_from(offset).map_err(|_| zip64_unsupported())
+    }
+
+    /// Writes the local header of a new entry, and makes it the current one.
+    fn start_entry(
+        &mut self,
+        name: String,
+        compression: u16,
+        last_modified: Option<SystemTime>,
+        is_dir: bool,
+    ) -> io::Result<()> {
+        if name.len() > 0xffff {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The entry name is too long!"));
+        }
+        let time = last_modified.unwrap_or_else(SystemTime::now);
+        let secs = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        let secs = i32::try_from(secs).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+            "The modification time is out of range!"))?;
+        let (mod_date, mod_time) = encode_ms_dos_datetime(time);
+        // Only the modification time is present
+        let mut extra = Vec::new();
+        push_u16(&mut extra, EXTENDED_TIMESTAMP_ID);
+        push_u16(&mut extra, 5);
+        extra.push(1);
+        extra.extend_from_slice(&secs.to_le_bytes());
+        let w = match self.writer.as_mut() {
+            Some(EntryWriter::Stored(w)) => w,
+            Some(EntryWriter::Deflate(_)) => unreachable!(),
+            None => return Err(broken_writer()),
+        };
+        let local_header_offset = Self::archive_offset(w)?;
+        let entry = WrittenEntry{
+            name,
+            compression,
+            mod_time,
+            mod_date,
+            // Filled in when the entry is finished
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            local_header_offset,
+            extra,
+            is_dir,
+        };
+        let mut header = Vec::new();
+        Self::push_header(&mut header, &entry, false);
+        w.write_all(&header)?;
+        let data_start = w.seek(SeekFrom::End(0))?;
+        self.current = Some(PendingEntry{ entry, crc: Crc32::new(), data_start, written: 0 });
+        Ok(())
+    }
+
+    /// Finishes the current entry, if there's one, and fills in the CRC32
+    /// and the sizes in its local header.
+    fn finish_entry(&mut self) -> io::Result<()> {
+        let pending = match self.current.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let mut w = match self.writer.take() {
+            Some(EntryWriter::Stored(w)) => w,
+            Some(EntryWriter::Deflate(deflate)) => deflate.finish()?,
+            None => return Err(broken_writer()),
+        };
+        let data_end = w.seek(SeekFrom::End(0))?;
+        let PendingEntry{ mut entry, crc, data_start, written } = pending;
+        entry.crc32 = crc.finalize();
+        entry.compressed_size = u32::try_from(data_end - data_start)
+            .map_err(|_| zip64_unsupported())?;
+        entry.uncompressed_size = u32::try_from(written)
+            .map_err(|_| zip64_unsupported())?;
+        // Patch the local header, the CRC32 comes after the signature and 5
+        // 2-byte fields
+        let mut sizes = Vec::new();
+        push_u32(&mut sizes, entry.crc32);
+        push_u32(&mut sizes, entry.compressed_size);
+        push_u32(&mut sizes, entry.uncompressed_size);
+        w.seek(SeekFrom::Start(entry.local_header_offset as u64 + 14))?;
+        w.write_all(&sizes)?;
+        w.seek(SeekFrom::Start(data_end))?;
+        self.writer = Some(EntryWriter::Stored(w));
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Appends the local or the central header of the given entry to the
+    /// buffer.
+    fn push_header(v: &mut Vec<u8>, entry: &WrittenEntry, central: bool) {
+        if central {
+            push_u32(v, 0x02014b50);
+            push_u16(v, Self::VERSION_MADE);
+        }
+        else {
+            push_u32(v, 0x04034b50);
+        }
+        push_u16(v, Self::VERSION_NEEDED);
+        push_u16(v, Self::UTF8_FLAG);
+        push_u16(v, entry.compression);
+        push_u16(v, entry.mod_time);
+        push_u16(v, entry.mod_date);
+        push_u32(v, entry.crc32);
+        push_u32(v, entry.compressed_size);
+        push_u32(v, entry.uncompressed_size);
+        push_u16(v, entry.name.len() as u16);
+        push_u16(v, entry.extra.len() as u16);
+        if central {
+            // No comment, disk number and internal attributes
+            push_u16(v, 0);
+            push_u16(v, 0);
+            push_u16(v, 0);
+            // Unix mode in the high word, MS-DOS attributes in the low one
+            let external_attribs = if entry.is_dir { (0o040755 << 16) | 0x10 } else { 0o100644 << 16 };
+            push_u32(v, external_attribs);
+            push_u32(v, entry.local_header_offset);
+        }
+        v.extend_from_slice(entry.name.as_bytes());
+        v.extend_from_slice(&entry.extra);
+    }
+}
+
+impl <W: Write + Seek> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let pending = match self.current.as_mut() {
+            Some(pending) if !pending.entry.is_dir => pending,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No file entry was started!")),
+        };
+        let written = match self.writer.as_mut() {
+            Some(EntryWriter::Stored(w)) => w.write(buf)?,
+            Some(EntryWriter::Deflate(w)) => w.write(buf)?,
+            None => return Err(broken_writer()),
+        };
+        pending.crc.update(&buf[..written]);
+        pending.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(EntryWriter::Stored(w)) => w.flush(),
+            Some(EntryWriter::Deflate(w)) => w.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Appends a little-endian `u16` to the buffer.
+fn push_u16(v: &mut Vec<u8>, n: u16) {
+    v.extend_from_slice(&n.to_le_bytes());
+}
+
+/// Appends a little-endian `u32` to the buffer.
+fn push_u32(v: &mut Vec<u8>, n: u32) {
+    v.extend_from_slice(&n.to_le_bytes());
+}
+
+/// Creates the error for a `ZipWriter`, that lost its writer to an earlier
+/// error.
+fn broken_writer() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "The writer failed to finish an earlier entry!")
+}
+
+/// Creates the error for archives, that would need Zip64.
+fn zip64_unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "The archive is too big without Zip64, which is not supported!")
+}
+
+/// Joins the name of an entry to the destination directory. Returns an error,
+/// if the resulting path would point outside of `dest` (known as Zip Slip).
+fn join_entry_path(dest: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut result = dest.to_path_buf();
+    for part in name.split(&['/', '\\'][..]) {
+        if part.is_empty() || part == "." {
+            continue;
+        }
+        // Anything but a plain name (parent directory, drive letter, ...) could
+        // escape the destination
+        let is_normal = Path::new(part).components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !is_normal {
+            return Err(ZipError::UnsafePath(name.to_string()).into());
+        }
+        result.push(part);
+    }
+    Ok(result)
+}
+
+/// Changes the owner of the given path.
+#[cfg(unix)]
+fn set_owner(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+
+    extern "C" {
+        fn chown(path: *const c_char, uid: u32, gid: u32) -> i32;
+    }
+
+    let cpath = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if unsafe{ chown(cpath.as_ptr(), uid, gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Changes the owner of the given path, there are no Unix owners to restore.
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _uid: u32, _gid: u32) -> io::Result<()> {
+    Ok(())
+}
+
 /// Translates the MS-DOS date-time format to `SystemTime`.
 fn decode_ms_dos_datetime(date: u16, time: u16) -> SystemTime {
     let dos_epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(315532800);
@@ -645,6 +1683,36 @@ fn decode_ms_dos_datetime(date: u16, time: u16) -> SystemTime {
     dos_epoch + date_offs + time_offs
 }
 
+/// Encodes the given time as an MS-DOS date and time in UTC. The format can
+/// only represent the years from 1980 to 2107, the times outside of that are
+/// clamped. Returns the date first, like `decode_ms_dos_datetime` takes it.
+fn encode_ms_dos_datetime(time: SystemTime) -> (u16, u16) {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    // The civil date from the days since the Unix epoch, counting the years
+    // from March, so the leap day is the last one
+    let days = secs / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    if year < 1980 {
+        // January 1, 1980
+        return ((1 << 5) | 1, 0);
+    }
+    if year > 2107 {
+        // December 31, 2107, 23:59:58
+        return ((127 << 9) | (12 << 5) | 31, (23 << 11) | (59 << 5) | 29);
+    }
+    let secs_of_day = secs % 86400;
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    let time = ((secs_of_day / 3600) << 11) | ((secs_of_day / 60 % 60) << 5) | (secs_of_day % 60 / 2);
+    (date as u16, time as u16)
+}
+
 /// Decodes an UTF8 String.
 fn decode_utf8(bs: &[u8]) -> String {
     String::from_utf8_lossy(bs).into_owned()
@@ -695,3 +1763,868 @@ fn decode_cp437(bs: &[u8]) -> String {
     }
     result
 }
+
+// Test support ////////////////////////////////////////////////////////////////
+
+/// Synthesizes a Zip archive in memory from the given names, contents and
+/// compressions. Names ending in '/' become directories. Deflated entries are
+/// encoded with non-compressed DEFLATE blocks, the contents of unsupported
+/// compressions are written as-is.
+#[cfg(test)]
+fn build_archive(entries: &[(&str, &[u8], Compression)]) -> Vec<u8> {
+    build_archive_with_extra(entries, &[])
+}
+
+/// Like `build_archive`, but every local and central header gets the given
+/// extra field bytes.
+#[cfg(test)]
+fn build_archive_with_extra(entries: &[(&str, &[u8], Compression)], extra: &[u8]) -> Vec<u8> {
+    fn push_signature<T: Parse>(v: &mut Vec<u8>) {
+        match T::SIGNATURE {
+            Signature::Required(s) | Signature::Optional(s) => push_u32(v, s),
+            Signature::None => {},
+        }
+    }
+
+    /// Encodes the data as a sequence of non-compressed DEFLATE blocks.
+    fn deflate_stored(data: &[u8]) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut chunks = data.chunks(0xffff).peekable();
+        if chunks.peek().is_none() {
+            // Still need a final block
+            result.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+        }
+        while let Some(chunk) = chunks.next() {
+            let is_final = chunks.peek().is_none();
+            result.push(if is_final { 0x01 } else { 0x00 });
+            push_u16(&mut result, chunk.len() as u16);
+            push_u16(&mut result, !(chunk.len() as u16));
+            result.extend_from_slice(chunk);
+        }
+        result
+    }
+
+    // The fields shared by the local and central headers, starting from the
+    // version needed
+    fn push_common(v: &mut Vec<u8>, name: &str, extra: &[u8], method: u16, crc32: u32, compressed_size: usize, size: usize) {
+        push_u16(v, 20);
+        // UTF-8 names
+        push_u16(v, 1 << 11);
+        push_u16(v, method);
+        // Modification time and date
+        push_u16(v, 0);
+        push_u16(v, 0);
+        push_u32(v, crc32);
+        push_u32(v, compressed_size as u32);
+        push_u32(v, size as u32);
+        push_u16(v, name.len() as u16);
+        push_u16(v, extra.len() as u16);
+    }
+
+    let mut result = Vec::new();
+    let mut central = Vec::new();
+    for (name, data, compression) in entries {
+        let (method, compressed) = match compression {
+            Compression::NoCompression => (0, data.to_vec()       ),
+            Compression::Deflate       => (8, deflate_stored(data)),
+            Compression::Deflate64     => (9, data.to_vec()       ),
+        };
+        let mut crc = Crc32::new();
+        crc.update(data);
+        let crc32 = crc.finalize();
+        let offset = result.len();
+        // Local header and data
+        push_signature::<LocalFileHeader>(&mut result);
+        push_common(&mut result, name, extra, method, crc32, compressed.len(), data.len());
+        result.extend_from_slice(name.as_bytes());
+        result.extend_from_slice(extra);
+        result.extend_from_slice(&compressed);
+        // Central directory entry
+        push_signature::<FileHeader>(&mut central);
+        // Version made by
+        push_u16(&mut central, 20);
+        push_common(&mut central, name, extra, method, crc32, compressed.len(), data.len());
+        // Comment length, disk number, internal and external attributes
+        push_u16(&mut central, 0);
+        push_u16(&mut central, 0);
+        push_u16(&mut central, 0);
+        push_u32(&mut central, 0);
+        push_u32(&mut central, offset as u32);
+        central.extend_from_slice(name.as_bytes());
+        central.extend_from_slice(extra);
+    }
+    // End of central directory
+    let central_offset = result.len();
+    result.extend_from_slice(&central);
+    push_signature::<EndOfCentralDirectoryRecord>(&mut result);
+    push_u16(&mut result, 0);
+    push_u16(&mut result, 0);
+    push_u16(&mut result, entries.len() as u16);
+    push_u16(&mut result, entries.len() as u16);
+    push_u32(&mut result, central.len() as u32);
+    push_u32(&mut result, central_offset as u32);
+    // No comment
+    push_u16(&mut result, 0);
+    result
+}
+
+#[cfg(test)]
+impl ZipArchive<io::Cursor<Vec<u8>>> {
+    /// Creates an in-memory `ZipArchive` without entries.
+    fn new_empty() -> Self {
+        Self::from_entries(&[])
+    }
+
+    /// Creates an in-memory `ZipArchive` with the given entries. See
+    /// `build_archive` for details.
+    fn from_entries(entries: &[(&str, &[u8], Compression)]) -> Self {
+        Self::parse(io::Cursor::new(build_archive(entries))).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An archive with a deflated "hello.txt", a "dir" directory and a stored
+    /// "dir/stored.txt" in it.
+    const TEST_ARCHIVE: &[u8] = &[
+        0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+        0x21, 0x50, 0x58, 0x48, 0xb0, 0xfb, 0x13, 0x00, 0x00, 0x00, 0x70, 0x00,
+        0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2e,
+        0x74, 0x78, 0x74, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x08, 0xcf,
+        0x2f, 0xca, 0x49, 0x51, 0x54, 0xf0, 0xa0, 0x39, 0x0f, 0x00, 0x50, 0x4b,
+        0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x04, 0x00, 0x00, 0x00, 0x64, 0x69, 0x72, 0x2f, 0x50, 0x4b, 0x03, 0x04,
+        0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0xc2, 0x41,
+        0x24, 0x35, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0e, 0x00,
+        0x00, 0x00, 0x64, 0x69, 0x72, 0x2f, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+        0x2e, 0x74, 0x78, 0x74, 0x61, 0x62, 0x63, 0x50, 0x4b, 0x01, 0x02, 0x14,
+        0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x50, 0x58,
+        0x48, 0xb0, 0xfb, 0x13, 0x00, 0x00, 0x00, 0x70, 0x00, 0x00, 0x00, 0x09,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2e, 0x74,
+        0x78, 0x74, 0x50, 0x4b, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x3a, 0x00, 0x00, 0x00,
+        0x64, 0x69, 0x72, 0x2f, 0x50, 0x4b, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0xc2, 0x41, 0x24, 0x35,
+        0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x5c, 0x00,
+        0x00, 0x00, 0x64, 0x69, 0x72, 0x2f, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+        0x2e, 0x74, 0x78, 0x74, 0x50, 0x4b, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00,
+        0x03, 0x00, 0x03, 0x00, 0xa5, 0x00, 0x00, 0x00, 0x8b, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_extract_to_temp() -> io::Result<()> {
+        let mut archive = ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?;
+        assert_eq!(archive.entry_count(), 3);
+
+        let mut entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.name(), "hello.txt");
+        let mut file = entry.extract_to_temp()?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "Hello, World! ".repeat(8));
+
+        let mut entry = archive.entry_at_index(2)?;
+        assert_eq!(entry.name(), "dir/stored.txt");
+        let mut file = entry.extract_to_temp()?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        assert_eq!(content, "abc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_to_string() -> io::Result<()> {
+        let mut archive = ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?;
+        assert_eq!(archive.entry_at_index(0)?.decompress_to_string()?, "Hello, World! ".repeat(8));
+        assert_eq!(archive.entry_at_index(2)?.decompress_to_string()?, "abc");
+
+        let mut archive = ZipArchive::from_entries(&[
+            ("utf8.txt", "\u{e1}rv\u{ed}zt\u{fb}r\u{0151}".as_bytes(), Compression::Deflate),
+            ("latin2.txt", b"\xe1rv\xedzt\xfbr\xf5", Compression::NoCompression),
+        ]);
+        assert_eq!(archive.entry_at_index(0)?.decompress_to_string()?, "\u{e1}rv\u{ed}zt\u{fb}r\u{0151}");
+        let err = archive.entry_at_index(1)?.decompress_to_string().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(ZipError::from_io(&err), Some(&ZipError::InvalidUtf8("latin2.txt".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_seekable_reader() -> io::Result<()> {
+        let content: Vec<u8> = (0..10_000u32).map(|i| (i % 7) as u8 + b'a').collect();
+        let mut archive = ZipArchive::from_entries(&[
+            ("deflated.txt", &content[..], Compression::Deflate),
+            ("stored.txt", &content[..], Compression::NoCompression),
+        ]);
+        for index in 0..2 {
+            let mut reader = archive.entry_at_index(index)?.seekable_reader()?;
+            let mut buffer = [0u8; 4];
+            reader.seek(SeekFrom::Start(5000))?;
+            reader.read_exact(&mut buffer)?;
+            assert_eq!(buffer, content[5000..5004]);
+            reader.seek(SeekFrom::End(-4))?;
+            reader.read_exact(&mut buffer)?;
+            assert_eq!(buffer, content[9996..]);
+            reader.seek(SeekFrom::Current(-6000))?;
+            reader.read_exact(&mut buffer)?;
+            assert_eq!(buffer, content[4000..4004]);
+        }
+
+        // Decompresses to more than it claims
+        let mut entry = archive.entry_at_index(0)?;
+        entry.uncompressed_size = 100;
+        let err = entry.seekable_reader().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Claims more than possible
+        let mut entry = archive.entry_at_index(0)?;
+        entry.uncompressed_size = entry.compressed_size * 2000;
+        let err = entry.seekable_reader().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mmap() -> io::Result<()> {
+        let dir = temp::directory()?;
+        let path = dir.path().join("test.zip");
+        fs::write(&path, TEST_ARCHIVE)?;
+
+        let mut archive = unsafe{ ZipArchive::open_mmap(&path)? };
+        assert_eq!(archive.entry_count(), 3);
+        assert_eq!(archive.archive_len(), TEST_ARCHIVE.len() as u64);
+        let mut entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.name(), "hello.txt");
+        assert_eq!(entry.decompress_to_string()?, "Hello, World! ".repeat(8));
+        assert_eq!(archive.entry_at_index(2)?.decompress_to_string()?, "abc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_archive_round_trip() -> io::Result<()> {
+        let big: Vec<u8> = (0..70000u32).map(|i| (i % 251) as u8).collect();
+        let entries: &[(&str, &[u8], Compression)] = &[
+            ("a.txt", b"Hello", Compression::NoCompression),
+            ("empty.txt", b"", Compression::Deflate),
+            ("sub/", b"", Compression::NoCompression),
+            ("sub/big.bin", &big, Compression::Deflate),
+            ("\u{e1}rv\u{ed}zt\u{fb}r\u{0151}.txt", b"UTF-8 name", Compression::NoCompression),
+        ];
+        let mut archive = ZipArchive::from_entries(entries);
+        assert_eq!(archive.entry_count(), entries.len());
+        for (i, (name, data, _)) in entries.iter().enumerate() {
+            let mut entry = archive.entry_at_index(i)?;
+            assert_eq!(entry.name(), name.trim_end_matches('/'));
+            assert_eq!(entry.is_dir(), name.ends_with('/'));
+            assert_eq!(entry.uncompressed_size(), data.len());
+            assert!(entry.check_crc32()?);
+            let mut content = Vec::new();
+            entry.decompressor()?.read_to_end(&mut content)?;
+            assert_eq!(&content[..], *data);
+        }
+        assert_eq!(ZipArchive::new_empty().entry_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_central_directory_offset() -> io::Result<()> {
+        let archive = ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?;
+        assert_eq!(archive.central_directory_offset(), 0x8b);
+        assert_eq!(archive.archive_len(), TEST_ARCHIVE.len() as u64);
+
+        let data = build_archive(&[
+            ("a.txt", b"Hello", Compression::NoCompression),
+            ("b.txt", b"World", Compression::Deflate),
+        ]);
+        let mut reader = ByteReader::new(io::Cursor::new(&data))?;
+        let (end_of_directory, _) = EndOfCentralDirectoryRecord::find(&mut reader)?;
+        let archive = ZipArchive::parse_lazy(io::Cursor::new(&data))?;
+        assert_eq!(archive.central_directory_offset(), end_of_directory.central_dir_offset as u64);
+        assert_eq!(archive.archive_len(), data.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_lazy_same_as_eager() -> io::Result<()> {
+        let names: Vec<_> = (0..50).map(|i| format!("file{}.txt", i)).collect();
+        let entries: Vec<_> = names.iter()
+            .map(|n| (n.as_str(), n.as_bytes(), Compression::Deflate))
+            .collect();
+        let data = build_archive(&entries);
+        let mut eager = ZipArchive::parse(io::Cursor::new(&data))?;
+        let mut lazy = ZipArchive::parse_lazy(io::Cursor::new(&data))?;
+        assert_eq!(eager.entry_count(), lazy.entry_count());
+
+        // Go backwards, so the lazy one has to parse everything at once first
+        for i in (0..eager.entry_count()).rev() {
+            let e1 = eager.entry_at_index(i)?;
+            let e2 = lazy.entry_at_index(i)?;
+            assert_eq!(e1.name(), e2.name());
+            assert_eq!(e1.is_file(), e2.is_file());
+            assert_eq!(e1.modification_time(), e2.modification_time());
+            assert_eq!(e1.compressed_size(), e2.compressed_size());
+            assert_eq!(e1.uncompressed_size(), e2.uncompressed_size());
+            assert_eq!(e1.data_offset, e2.data_offset);
+            assert_eq!(e1.crc32, e2.crc32);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_boxed_decompressors() -> io::Result<()> {
+        // One archive for each entry, as entries borrow the archive mutably
+        let mut archives = Vec::new();
+        for _ in 0..3 {
+            archives.push(ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?);
+        }
+        let mut entries = Vec::new();
+        for (i, archive) in archives.iter_mut().enumerate() {
+            entries.push(archive.entry_at_index(i)?);
+        }
+        let mut decompressors: Vec<Box<dyn Read>> = Vec::new();
+        for entry in entries.iter_mut() {
+            decompressors.push(entry.boxed_decompressor()?);
+        }
+
+        let mut contents = Vec::new();
+        for d in decompressors.iter_mut() {
+            let mut content = String::new();
+            d.read_to_string(&mut content)?;
+            contents.push(content);
+        }
+        assert_eq!(contents, vec!["Hello, World! ".repeat(8), String::new(), "abc".into()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_to_temp_bad_crc() -> io::Result<()> {
+        let mut data = TEST_ARCHIVE.to_vec();
+        // Corrupt the stored data of "dir/stored.txt"
+        let pos = data.windows(3).position(|w| w == b"abc").unwrap();
+        data[pos] = b'x';
+
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let mut entry = archive.entry_at_index(2)?;
+        let err = entry.extract_to_temp().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_crc_verification() -> io::Result<()> {
+        let mut data = TEST_ARCHIVE.to_vec();
+        // Corrupt the stored CRC32 of "hello.txt" in the central directory
+        let pos = data.windows(4).position(|w| w == [0x58, 0x48, 0xb0, 0xfb]).unwrap();
+        let pos = pos + 4 + data[(pos + 4)..].windows(4).position(|w| w == [0x58, 0x48, 0xb0, 0xfb]).unwrap();
+        data[pos] ^= 0xff;
+
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let err = archive.entry_at_index(0)?.decompress_to_string().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        archive.set_verify_crc32(false);
+        let mut entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.decompress_to_string()?, "Hello, World! ".repeat(8));
+        let mut content = String::new();
+        entry.extract_to_temp()?.read_to_string(&mut content)?;
+        assert_eq!(content, "Hello, World! ".repeat(8));
+        // The explicit check still fails
+        assert!(!entry.check_crc32()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_deflate64_entry() -> io::Result<()> {
+        let mut archive = ZipArchive::from_entries(&[
+            ("hello.txt", b"Hello, World!", Compression::Deflate64),
+            ("dir/", b"", Compression::NoCompression),
+            ("dir/stored.txt", b"abc", Compression::NoCompression),
+        ]);
+        assert_eq!(archive.entry_count(), 3);
+        let mut entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.name(), "hello.txt");
+        assert_eq!(entry.uncompressed_size(), 13);
+        let err = entry.extract_to_temp().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(archive.entry_at_index(0)?.decompressor().is_err());
+        // The rest of the archive is still usable
+        let mut content = String::new();
+        archive.entry_at_index(2)?.decompressor()?.read_to_string(&mut content)?;
+        assert_eq!(content, "abc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_strong_encryption_unsupported() -> io::Result<()> {
+        let mut data = build_archive(&[("secret.txt", b"abc", Compression::NoCompression)]);
+        // Set the encrypted and strong encryption flags in the central directory
+        let pos = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        data[pos + 8] |= 0x41;
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let mut entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.name(), "secret.txt");
+        let err = entry.decompressor().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(err.to_string().contains("Strong encryption"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_error_variants() -> io::Result<()> {
+        let data = build_archive(&[
+            ("a.txt", b"Hello, World!", Compression::NoCompression),
+            ("b.txt", b"Hello, World!", Compression::Deflate64),
+        ]);
+        let zip_err = |err: io::Error| ZipError::from_io(&err).cloned();
+
+        // Not an archive at all
+        let err = ZipArchive::parse(io::Cursor::new(vec![0u8; 64])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(zip_err(err), Some(ZipError::NoCentralDirectory));
+
+        // Unsupported compression
+        let mut archive = ZipArchive::parse(io::Cursor::new(data.clone()))?;
+        let err = archive.entry_at_index(1)?.extract_to_temp().unwrap_err();
+        assert_eq!(zip_err(err), Some(ZipError::UnsupportedCompression(9)));
+
+        // Corrupt contents
+        let mut corrupt = data.clone();
+        let pos = corrupt.windows(13).position(|w| w == b"Hello, World!").unwrap();
+        corrupt[pos] = b'J';
+        let mut archive = ZipArchive::parse(io::Cursor::new(corrupt))?;
+        let err = archive.entry_at_index(0)?.extract_to_temp().unwrap_err();
+        let mut crc = Crc32::new();
+        crc.update(b"Hello, World!");
+        let crc = crc.finalize();
+        match zip_err(err) {
+            Some(ZipError::CrcMismatch{ expected, got }) => {
+                assert_eq!(expected, crc);
+                assert_ne!(got, crc);
+            },
+            other => panic!("Unexpected error {:?}!", other),
+        }
+
+        // Broken local header signature
+        let mut broken = data.clone();
+        broken[0] = b'X';
+        let mut archive = ZipArchive::parse(io::Cursor::new(broken))?;
+        let err = archive.entry_at_index(0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(zip_err(err), Some(ZipError::BadSignature));
+
+        // Encryption
+        let mut encrypted = data;
+        let pos = encrypted.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        encrypted[pos + 8] |= 0x01;
+        let mut archive = ZipArchive::parse(io::Cursor::new(encrypted))?;
+        let err = archive.entry_at_index(0)?.decompressor().err().unwrap();
+        assert_eq!(zip_err(err), Some(ZipError::Encrypted{ strong: false }));
+
+        // Central directory encryption masks the local header
+        let mut masked = build_archive(&[("a.txt", b"Hello, World!", Compression::NoCompression)]);
+        masked[7] |= 0x20;
+        let mut archive = ZipArchive::parse(io::Cursor::new(masked))?;
+        let err = archive.entry_at_index(0)?.decompressor().err().unwrap();
+        assert_eq!(zip_err(err), Some(ZipError::Encrypted{ strong: true }));
+        let mut masked = build_archive(&[("a.txt", b"Hello, World!", Compression::NoCompression)]);
+        let pos = masked.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        masked[pos + 9] |= 0x20;
+        let mut archive = ZipArchive::parse(io::Cursor::new(masked))?;
+        let err = archive.entry_at_index(0)?.extract_to_temp().unwrap_err();
+        assert_eq!(zip_err(err), Some(ZipError::Encrypted{ strong: true }));
+
+        // Strong encryption header without the flag
+        let header = [0x17, 0x00, 0x04, 0x00, 0x02, 0x00, 0x01, 0x66];
+        let strong = build_archive_with_extra(&[("a.txt", b"Hello, World!", Compression::NoCompression)], &header);
+        let mut archive = ZipArchive::parse(io::Cursor::new(strong))?;
+        let err = archive.entry_at_index(0)?.decompressor().err().unwrap();
+        assert_eq!(zip_err(err), Some(ZipError::Encrypted{ strong: true }));
+
+        // Plain IO errors carry no ZipError
+        assert_eq!(ZipError::from_io(&io::Error::new(io::ErrorKind::Other, "Other")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_writer_round_trip() -> io::Result<()> {
+        let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8 ^ (i / 1000) as u8).collect();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let files: &[(&str, &[u8], FileOptions)] = &[
+            ("hello.txt", b"Hello, World! Hello, World!", FileOptions::new()),
+            ("stored.txt", b"abc", FileOptions::new().with_compression(None)),
+            ("empty.txt", b"", FileOptions::new()),
+            ("dir/big.bin", &big, FileOptions::new().with_compression(Some(CompressionLevel::Best))),
+            ("dir/timed.txt", b"Tick", FileOptions::new().with_modification_time(mtime)),
+        ];
+
+        // Some junk before the archive, the offsets are still absolute
+        let mut writer = ZipWriter::new(io::Cursor::new(b"junk".to_vec()));
+        writer.add_directory("dir")?;
+        for (name, data, options) in files {
+            writer.start_file(name, *options)?;
+            writer.write_all(data)?;
+        }
+        let data = writer.finish()?.into_inner();
+
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        assert_eq!(archive.entry_count(), files.len() + 1);
+        let entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.name(), "dir");
+        assert!(entry.is_dir());
+        for (i, (name, data, _)) in files.iter().enumerate() {
+            let mut entry = archive.entry_at_index(i + 1)?;
+            assert_eq!(entry.name(), *name);
+            assert!(entry.is_file());
+            assert_eq!(entry.uncompressed_size(), data.len());
+            let mut crc = Crc32::new();
+            crc.update(data);
+            assert_eq!(entry.expected_crc32(), crc.finalize());
+            assert!(entry.check_crc32()?);
+            let mut content = Vec::new();
+            entry.extract_to_temp()?.read_to_end(&mut content)?;
+            assert!(content == *data);
+        }
+        // Compression happened
+        assert!(archive.entry_at_index(4)?.compressed_size() < big.len() / 2);
+        assert_eq!(archive.entry_at_index(2)?.compressed_size(), 3);
+        assert_eq!(archive.entry_at_index(5)?.modification_time(), mtime);
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_writer_ms_dos_datetime() -> io::Result<()> {
+        // 2020-05-17 13:45:30
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_589_723_130);
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.start_file("timed.txt", FileOptions::new().with_modification_time(mtime))?;
+        writer.start_file("now.txt", FileOptions::new())?;
+        let data = writer.finish()?.into_inner();
+        // The time and the date follow the signature and 3 2-byte fields
+        assert_eq!(&data[10..14], &[0xaf, 0x6d, 0xb1, 0x50]);
+
+        // Without an explicit time, the entry is stamped when it's started
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let elapsed = archive.entry_at_index(1)?.modification_time().elapsed().unwrap();
+        assert!(elapsed < Duration::from_secs(60));
+
+        // Clamped to the representable range
+        let leap_day = SystemTime::UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(encode_ms_dos_datetime(leap_day), ((20 << 9) | (2 << 5) | 29, 0));
+        assert_eq!(encode_ms_dos_datetime(SystemTime::UNIX_EPOCH), (0x21, 0));
+        let far = SystemTime::UNIX_EPOCH + Duration::from_secs(5_000_000_000);
+        assert_eq!(encode_ms_dos_datetime(far), (0xff9f, 0xbf7d));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_writer_no_entry() -> io::Result<()> {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let err = writer.write_all(b"Orphan").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        // An empty archive is still valid
+        let data = writer.finish()?.into_inner();
+        let archive = ZipArchive::parse(io::Cursor::new(data))?;
+        assert_eq!(archive.entry_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_to_stripped() -> io::Result<()> {
+        let mut archive = ZipArchive::from_entries(&[
+            ("pkg-1.0/", b"", Compression::NoCompression),
+            ("pkg-1.0/README", b"Read me!", Compression::NoCompression),
+            ("pkg-1.0/src/", b"", Compression::NoCompression),
+            ("pkg-1.0/src/lib.rs", b"fn main() {}", Compression::Deflate),
+            ("other.txt", b"Not extracted", Compression::NoCompression),
+            ("pkg-1.0.1/other.txt", b"Not extracted", Compression::NoCompression),
+        ]);
+        let dir = temp::directory()?;
+        archive.extract_to_stripped(dir.path(), "pkg-1.0")?;
+        assert_eq!(fs::read_to_string(dir.path().join("README"))?, "Read me!");
+        assert_eq!(fs::read_to_string(dir.path().join("src").join("lib.rs"))?, "fn main() {}");
+        assert!(!dir.path().join("pkg-1.0").exists());
+        assert!(!dir.path().join("other.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_to_stripped_zip_slip() -> io::Result<()> {
+        let mut archive = ZipArchive::from_entries(&[
+            ("pkg-1.0/../evil.txt", b"Evil", Compression::NoCompression),
+        ]);
+        let dir = temp::directory()?;
+        let err = archive.extract_to_stripped(&dir.path().join("out"), "pkg-1.0/").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.path().join("evil.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_crc32() -> io::Result<()> {
+        let mut archive = ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?;
+        for i in 0..archive.entry_count() {
+            assert!(archive.entry_at_index(i)?.check_crc32()?);
+        }
+
+        let mut data = TEST_ARCHIVE.to_vec();
+        let pos = data.windows(3).position(|w| w == b"abc").unwrap();
+        data[pos] = b'x';
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        assert!(!archive.entry_at_index(2)?.check_crc32()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crc32_accessors() -> io::Result<()> {
+        let mut archive = ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?;
+        let mut entry = archive.entry_at_index(2)?;
+        assert_eq!(entry.name(), "dir/stored.txt");
+        assert_eq!(entry.expected_crc32(), 0x352441c2);
+        assert_eq!(entry.computed_crc32()?, 0x352441c2);
+
+        let mut data = TEST_ARCHIVE.to_vec();
+        let pos = data.windows(3).position(|w| w == b"abc").unwrap();
+        data[pos] = b'x';
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let mut entry = archive.entry_at_index(2)?;
+        assert_eq!(entry.expected_crc32(), 0x352441c2);
+        assert_eq!(entry.computed_crc32()?, 0x26d3d93d);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extended_timestamp() -> io::Result<()> {
+        // Flags with only the modification time, then 2020-09-13T12:26:40Z
+        let mut extra = vec![0x55, 0x54, 5, 0, 0x01];
+        extra.extend_from_slice(&1_600_000_000i32.to_le_bytes());
+        let data = build_archive_with_extra(&[("foo.txt", b"Hello", Compression::NoCompression)], &extra);
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let entry = archive.entry_at_index(0)?;
+        let expected = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        assert_eq!(entry.modification_time(), expected);
+
+        // Without it, the MS-DOS epoch is decoded from the zeroed fields
+        let mut archive = ZipArchive::from_entries(&[("foo.txt", b"Hello", Compression::NoCompression)]);
+        let entry = archive.entry_at_index(0)?;
+        let dos_epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(315532800);
+        assert_eq!(entry.modification_time(), dos_epoch);
+        Ok(())
+    }
+
+    /// Creates the `0x7875` extra field with the given ids.
+    fn unix_owner_extra(uid: u32, gid: u32) -> Vec<u8> {
+        let mut extra = vec![0x75, 0x78, 11, 0, 1, 4];
+        extra.extend_from_slice(&uid.to_le_bytes());
+        extra.push(4);
+        extra.extend_from_slice(&gid.to_le_bytes());
+        extra
+    }
+
+    #[test]
+    fn test_unix_owner() -> io::Result<()> {
+        let extra = unix_owner_extra(1000, 100);
+        let data = build_archive_with_extra(&[("foo.txt", b"Hello", Compression::NoCompression)], &extra);
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.unix_uid(), Some(1000));
+        assert_eq!(entry.unix_gid(), Some(100));
+
+        // Different id sizes
+        let field = |data: &[u8]| unix_owner(&[ExtensibleDataField{ id: UNIX_OWNER_ID, data: data.to_vec() }]);
+        assert_eq!(field(&[1, 2, 0xe8, 0x03, 1, 0]), Some((1000, 0)));
+        assert_eq!(field(&[1, 8, 1, 0, 0, 0, 0, 0, 0, 0, 1, 2]), Some((1, 2)));
+        assert_eq!(field(&[1, 8, 0, 0, 0, 0, 1, 0, 0, 0, 1, 2]), None);
+        // Unknown version and truncated
+        assert_eq!(field(&[2, 1, 1, 1, 2]), None);
+        assert_eq!(field(&[1, 4, 1, 0]), None);
+
+        let mut archive = ZipArchive::from_entries(&[("foo.txt", b"Hello", Compression::NoCompression)]);
+        let entry = archive.entry_at_index(0)?;
+        assert_eq!(entry.unix_uid(), None);
+        assert_eq!(entry.unix_gid(), None);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_ownership() -> io::Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        extern "C" {
+            fn geteuid() -> u32;
+        }
+
+        let extract = |uid: u32, gid: u32| -> io::Result<temp::Directory> {
+            let dest = temp::directory()?;
+            let data = build_archive_with_extra(&[
+                ("sub/", b"", Compression::NoCompression),
+                ("sub/foo.txt", b"Hello", Compression::NoCompression),
+            ], &unix_owner_extra(uid, gid));
+            let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+            archive.set_restore_ownership(true);
+            archive.extract_to_stripped(dest.path(), "")?;
+            Ok(dest)
+        };
+        if unsafe{ geteuid() } == 0 {
+            // Root can give the files to anyone
+            let dest = extract(1234, 5678)?;
+            for path in &[dest.path().join("sub"), dest.path().join("sub").join("foo.txt")] {
+                let meta = fs::metadata(path)?;
+                assert_eq!(meta.uid(), 1234);
+                assert_eq!(meta.gid(), 5678);
+            }
+        }
+        else {
+            // Others can't give their files to root
+            let err = extract(0, 0).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_slice_same_as_parse() -> io::Result<()> {
+        let mut a1 = ZipArchive::parse_slice(TEST_ARCHIVE)?;
+        let mut a2 = ZipArchive::parse(io::Cursor::new(TEST_ARCHIVE))?;
+        assert_eq!(a1.entry_count(), a2.entry_count());
+        for i in 0..a1.entry_count() {
+            let mut e1 = a1.entry_at_index(i)?;
+            let e2 = a2.entry_at_index(i)?;
+            assert_eq!(e1.name(), e2.name());
+            assert_eq!(e1.is_file(), e2.is_file());
+            assert_eq!(e1.modification_time(), e2.modification_time());
+            assert_eq!(e1.compressed_size(), e2.compressed_size());
+            assert_eq!(e1.uncompressed_size(), e2.uncompressed_size());
+            assert_eq!(e1.expected_crc32(), e2.expected_crc32());
+            if e1.is_file() {
+                assert!(e1.check_crc32()?);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_names() -> io::Result<()> {
+        let mut name = LazyName::new(vec![0x80, b'a', b'/'], false);
+        assert!(name.ends_with_separator());
+        assert_eq!(name.decode(), decode_cp437(&[0x80, b'a', b'/']));
+        assert_eq!(name.decode(), "\u{00C7}a/");
+        let mut name = LazyName::new("\u{00C7}a".as_bytes().to_vec(), true);
+        assert!(!name.ends_with_separator());
+        assert_eq!(name.decode(), "\u{00C7}a");
+
+        let names = ["árvíztűrő/", "árvíztűrő/tükörfúrógép.txt"];
+        let mut archive = ZipArchive::from_entries(&[
+            (names[0], b"", Compression::NoCompression),
+            (names[1], b"Hello", Compression::NoCompression),
+        ]);
+        for (i, name) in names.iter().enumerate() {
+            // Twice, the second time the cached name is used
+            for _ in 0..2 {
+                let entry = archive.entry_at_index(i)?;
+                assert_eq!(entry.name(), name.trim_end_matches('/'));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_local_header_mismatch() -> io::Result<()> {
+        let mut data = build_archive(&[
+            ("a.txt", b"Hello", Compression::NoCompression),
+            ("b.txt", b"World", Compression::NoCompression),
+        ]);
+        // Corrupt the CRC32 in the second local header only
+        let local = data.windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == &0x04034b50u32.to_le_bytes()[..])
+            .map(|(i, _)| i)
+            .nth(1)
+            .unwrap();
+        data[local + 14] ^= 0xff;
+
+        // Lenient by default, the central directory is used
+        let mut archive = ZipArchive::parse(io::Cursor::new(data.clone()))?;
+        assert!(archive.entry_at_index(1)?.check_crc32()?);
+
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        archive.set_strict(true);
+        assert_eq!(archive.entry_at_index(0)?.name(), "a.txt");
+        let err = archive.entry_at_index(1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dir_by_external_attributes() -> io::Result<()> {
+        let mut data = build_archive(&[
+            ("unix_dir", b"", Compression::NoCompression),
+            ("dos_dir", b"", Compression::NoCompression),
+            ("unix_file", b"", Compression::NoCompression),
+            ("not_empty", b"Hello", Compression::NoCompression),
+        ]);
+        // Patch the host and external attributes of the central headers
+        let attribs: [(u16, u32); 4] = [
+            (3 << 8, 0o040755 << 16),
+            (0 << 8, 0x10),
+            (3 << 8, 0o100644 << 16),
+            (0 << 8, 0x10),
+        ];
+        let central = data.windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == &0x02014b50u32.to_le_bytes()[..])
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        for (offset, (host, attrib)) in central.into_iter().zip(attribs.iter()) {
+            data[(offset + 4)..(offset + 6)].copy_from_slice(&(host | 20).to_le_bytes());
+            data[(offset + 38)..(offset + 42)].copy_from_slice(&attrib.to_le_bytes());
+        }
+        let mut archive = ZipArchive::parse(io::Cursor::new(data))?;
+        let expected = [true, true, false, false];
+        for (i, is_dir) in expected.iter().enumerate() {
+            let entry = archive.entry_at_index(i)?;
+            assert_eq!(entry.is_dir(), *is_dir, "{}", entry.name());
+            assert_eq!(entry.is_file(), !*is_dir);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_each_file() -> io::Result<()> {
+        let mut archive = ZipArchive::from_entries(&[
+            ("a.txt", b"Hello", Compression::NoCompression),
+            ("dir/", b"", Compression::NoCompression),
+            ("dir/b.txt", b", World", Compression::Deflate),
+            ("dir/empty.txt", b"", Compression::Deflate),
+            ("c.txt", b"!", Compression::NoCompression),
+        ]);
+        let mut names = Vec::new();
+        let mut contents = Vec::new();
+        archive.for_each_file(|name, reader| {
+            names.push(name.to_string());
+            reader.read_to_end(&mut contents)?;
+            Ok(())
+        })?;
+        assert_eq!(names, ["a.txt", "dir/b.txt", "dir/empty.txt", "c.txt"]);
+        assert_eq!(contents, b"Hello, World!");
+
+        // Errors of the callback stop the iteration
+        let mut calls = 0;
+        let err = archive.for_each_file(|_, _| {
+            calls += 1;
+            Err(io::Error::new(io::ErrorKind::Interrupted, "Stop"))
+        }).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(calls, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! Loading assets bundled into a Zip archive.
+//!
+//! An [AssetBundle](struct.AssetBundle.html) indexes the entries of the
+//! archive by name, and loads them on demand, either as raw bytes or as
+//! dynamic libraries.
+//!
+//! # Basic usage
+//!
+//! ```no_run
+//! use std::io;
+//! use cacti_asset::archive::AssetBundle;
+//!
+//! // The archive could be embedded with `include_bytes!` too
+//! let mut bundle = AssetBundle::open("assets.zip")?;
+//! let shader = bundle.load_bytes("shaders/basic.glsl")?;
+//! let mut plugin = bundle.load_library("plugins/physics.dll")?;
+//! # Ok::<(), io::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use cacti_archive::zip::ZipArchive;
+use cacti_fs::temp;
+use crate::dyn_lib::Library;
+
+/// A collection of assets stored in a Zip archive.
+///
+/// Libraries loaded from the bundle are extracted to temporary files first.
+/// Where the system allows, the files are deleted right after loading,
+/// otherwise they are deleted when the bundle is dropped. On Windows a loaded
+/// library can't be deleted, so drop the libraries before the bundle.
+#[derive(Debug)]
+pub struct AssetBundle<R: Read + Seek> {
+    archive  : ZipArchive<R>         ,
+    // The index of each entry by name
+    entries  : HashMap<String, usize>,
+    // The extracted libraries, that couldn't be deleted yet
+    extracted: Vec<PathBuf>          ,
+}
+
+impl AssetBundle<fs::File> {
+    /// Opens the Zip archive at the given path as an `AssetBundle`.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error, or if the file is not a Zip archive, an error
+    /// variant is returned.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(fs::File::open(path)?)
+    }
+}
+
+impl <R: Read + Seek> AssetBundle<R> {
+    /// Creates an `AssetBundle` from a reader of a Zip archive. For an archive
+    /// embedded into the executable, wrap the bytes into an `io::Cursor`.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO error, or if the reader doesn't contain a Zip archive,
+    /// an error variant is returned.
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut archive = ZipArchive::parse(reader)?;
+        let mut entries = HashMap::new();
+        for i in 0..archive.entry_count() {
+            let entry = archive.entry_at_index(i)?;
+            if entry.is_file() {
+                entries.insert(entry.name().to_string(), i);
+            }
+        }
+        Ok(Self{ archive, entries, extracted: Vec::new() })
+    }
+
+    /// Returns `true`, if the bundle has a file with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Returns the names of the files in the bundle, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|n| n.as_str())
+    }
+
+    /// Loads the contents of the file with the given name.
+    ///
+    /// # Errors
+    ///
+    /// If there's no such file, an error with kind `NotFound` is returned. If
+    /// the contents can't be decompressed, or they don't match the stored
+    /// CRC32, an error variant is returned.
+    pub fn load_bytes(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let index = match self.entries.get(name) {
+            Some(index) => *index,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound,
+                format!("No asset named '{}' in the bundle!", name))),
+        };
+        let mut entry = self.archive.entry_at_index(index)?;
+        Ok(entry.seekable_reader()?.into_inner())
+    }
+
+    /// Loads the dynamic library with the given name from the bundle. The
+    /// library is extracted into a temporary file with the same extension,
+    /// and loaded from there.
+    ///
+    /// # Errors
+    ///
+    /// If there's no such file, an error with kind `NotFound` is returned. In
+    /// case of an IO or system error, an error variant is returned.
+    pub fn load_library(&mut self, name: &str) -> io::Result<Library> {
+        let bytes = self.load_bytes(name)?;
+        let extension = Path::new(name).extension().and_then(|e| e.to_str());
+        let (mut file, path) = temp::persistent_file(extension)?;
+        let written = file.write_all(&bytes);
+        drop(file);
+        let library = written.and_then(|_| Library::load(&path));
+        if fs::remove_file(&path).is_err() {
+            // Still in use
+            self.extracted.push(path);
+        }
+        library
+    }
+}
+
+impl <R: Read + Seek> Drop for AssetBundle<R> {
+    fn drop(&mut self) {
+        for path in &self.extracted {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cacti_archive::zip::{ZipWriter, FileOptions};
+
+    /// Builds an archive with the given files.
+    fn build_bundle(files: &[(&str, &[u8])]) -> io::Result<Vec<u8>> {
+        let mut writer = ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer.add_directory("text")?;
+        for (name, data) in files {
+            writer.start_file(name, FileOptions::new())?;
+            writer.write_all(data)?;
+        }
+        Ok(writer.finish()?.into_inner())
+    }
+
+    #[test]
+    fn test_load_bytes() -> io::Result<()> {
+        let data = build_bundle(&[
+            ("text/hello.txt", b"Hello, World!"),
+            ("text/empty.txt", b""),
+        ])?;
+        let mut bundle = AssetBundle::new(io::Cursor::new(&data[..]))?;
+        assert!(bundle.contains("text/hello.txt"));
+        // Directories are not assets
+        assert!(!bundle.contains("text"));
+        let mut names: Vec<_> = bundle.names().collect();
+        names.sort();
+        assert_eq!(names, vec!["text/empty.txt", "text/hello.txt"]);
+
+        assert_eq!(bundle.load_bytes("text/hello.txt")?, b"Hello, World!");
+        assert_eq!(bundle.load_bytes("text/empty.txt")?, b"");
+        let err = bundle.load_bytes("text/missing.txt").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = bundle.load_library("text/missing.so").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    #[test]
+    fn test_load_library() -> io::Result<()> {
+        // Bundle a copy of the math library of the system, glibc installs it
+        // next to the C library, that this test is linked against
+        let maps = fs::read_to_string("/proc/self/maps")?;
+        let libc = maps.lines()
+            .filter_map(|line| line.split_whitespace().nth(5))
+            .find(|path| match Path::new(path).file_name().and_then(|n| n.to_str()) {
+                Some(name) => name.starts_with("libc.so") || name.starts_with("libc-"),
+                None => false,
+            })
+            .expect("The C library is not mapped!");
+        let libm = fs::read(Path::new(libc).with_file_name("libm.so.6"))?;
+        let data = build_bundle(&[("libs/libm.so", &libm)])?;
+        let mut bundle = AssetBundle::new(io::Cursor::new(data))?;
+        let mut library = bundle.load_library("libs/libm.so")?;
+        let fabs = library.load_c_symbol::<extern "C" fn(f64) -> f64>("fabs")?;
+        assert_eq!(fabs(-2.5), 2.5);
+        Ok(())
+    }
+}
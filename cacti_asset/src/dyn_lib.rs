@@ -30,6 +30,33 @@
 //! The `Symbol` type's lifetime is tied to the `Library`'s, but de-referencing
 //! the symbol gets rid of the wrapper.
 //!
+//! Nothing checks, that the type matches the loaded symbol, so the calling
+//! convention has to be right too. Functions exported by C libraries use
+//! `extern "C"`, the Windows API uses `extern "system"`, which is the same as
+//! `extern "C"` everywhere but 32-bit Windows. Variadic C functions - like
+//! `printf` - can only be called as `extern "C"` with a trailing `...`. To
+//! make the intended convention explicit at the call site, use
+//! [load_c_symbol](struct.Library.html#method.load_c_symbol) or
+//! [load_system_symbol](struct.Library.html#method.load_system_symbol), which
+//! only accept function pointers of the matching convention:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! # use cacti_asset::dyn_lib::*;
+//! # let mut lib = Library::load("libc.so.6")?;
+//! use std::os::raw::{c_char, c_int};
+//!
+//! let printf: CSymbol<unsafe extern "C" fn(*const c_char, ...) -> c_int> =
+//!     lib.load_c_symbol("printf")?;
+//! unsafe{ printf(b"%d\n\0".as_ptr().cast(), 42) };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A `Library` is the unique owner of the loaded library. To share it between
+//! multiple owners, wrap it in a [SharedLibrary](struct.SharedLibrary.html),
+//! which is unloaded once the last clone of it is dropped.
+//!
 //! # Porting the library to other platforms
 //!
 //! To port this library to other platforms, the `trait DynLib` has to be
@@ -39,11 +66,13 @@
 //! ```no_run
 //! # use std::io::Result;
 //! # use std::path::Path;
+//! # use cacti_asset::dyn_lib::LoadFlags;
 //! # trait DynLib: Sized {
 //! #     type Symbol: std::fmt::Debug;
-//! #     fn load(path: &Path) -> Result<Self>;
-//! #     fn unload(&mut self);
-//! #     fn load_symbol(&mut self, name: &str) -> Result<Self::Symbol>;
+//! #     fn load(path: &Path, flags: LoadFlags) -> Result<Self>;
+//! #     fn unload(&mut self) -> Result<()>;
+//! #     fn load_symbol(&self, name: &str) -> Result<Self::Symbol>;
+//! #     fn exported_symbols(&self) -> Result<Vec<String>>;
 //! # }
 //! #[cfg(target_os = "new_platform")]
 //! mod my_platform {
@@ -62,19 +91,30 @@
 //!         /// Here you must load the library from the given path. Sometimes
 //!         /// there can be platform-specific behavior - like loading system
 //!         /// libraries without full path on Windows - that should be
-//!         /// documented.
-//!         fn load(path: &Path) -> Result<Self> {
+//!         /// documented. The flags should be mapped to the closest platform
+//!         /// equivalent, ignoring the ones that make no sense on the platform.
+//!         fn load(path: &Path, flags: LoadFlags) -> Result<Self> {
 //!             // ...
 //! # unimplemented!()
 //!         }
 //!
-//!         /// Here you should unload the library, freeing up the resource.
-//!         fn unload(&mut self) {
+//!         /// Here you should unload the library, freeing up the resource,
+//!         /// and report the failure of the platform. Unloading twice must be
+//!         /// a no-op.
+//!         fn unload(&mut self) -> Result<()> {
 //!             // ...
+//! # unimplemented!()
 //!         }
 //!
 //!         /// Here you should load the symbol with the given name.
-//!         fn load_symbol(&mut self, name: &str) -> Result<Self::Symbol> {
+//!         fn load_symbol(&self, name: &str) -> Result<Self::Symbol> {
+//!             // ...
+//! # unimplemented!()
+//!         }
+//!
+//!         /// Here you should list the names of the exported symbols, or
+//!         /// return an empty list, if the platform can't tell.
+//!         fn exported_symbols(&self) -> Result<Vec<String>> {
 //!             // ...
 //! # unimplemented!()
 //!         }
@@ -87,9 +127,11 @@
 // TODO: Doc platform-specific usage
 
 use std::io::Result;
+use std::io;
 use std::path::Path;
 use std::marker::PhantomData;
-use std::ops::Deref;
+use std::sync::Arc;
+use std::ops::{Deref, BitOr, BitOrAssign};
 
 // ////////////////////////////////////////////////////////////////////////// //
 //                                    API                                     //
@@ -130,7 +172,85 @@ impl Library {
     ///
     /// In case of an IO or system error, an error variant is returned.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self(DynLibImpl::load(path.as_ref())?))
+        Self::load_with_flags(path, LoadFlags::NONE)
+    }
+
+    /// Loads the dynamic/shared library from the given path, using the given
+    /// `LoadFlags` to control where the library is searched for.
+    ///
+    /// On Windows, plain [load](#method.load) uses the default DLL search
+    /// order, which includes the current working directory. That allows a
+    /// planted DLL to be picked up instead of the intended one. Passing
+    /// `LoadFlags::default()` restricts the search to the application
+    /// directory, `System32` and the user-added directories, which is the
+    /// hardened option for loading libraries by name.
+    ///
+    /// # Examples
+    ///
+    /// Loading `Kernel32` strictly from `System32` on Windows:
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use cacti_asset::dyn_lib::{Library, LoadFlags};
+    ///
+    /// let lib = Library::load_with_flags("kernel32", LoadFlags::SEARCH_SYSTEM32)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned. On
+    /// Windows, the search flags require the path to either be a plain file
+    /// name or a fully qualified path, relative paths with directories are an
+    /// error.
+    pub fn load_with_flags(path: impl AsRef<Path>, flags: LoadFlags) -> Result<Self> {
+        Ok(Self(DynLibImpl::load(path.as_ref(), flags)?))
+    }
+
+    /// Tries to load the dynamic/shared library from each of the given names
+    /// in order, returning the first one that succeeds. This is useful, when
+    /// the library is named differently on different platforms or versions.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use cacti_asset::dyn_lib::Library;
+    ///
+    /// let lib = Library::load_any(&["libfoo.so", "libfoo.so.1", "foo.dll"])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If none of the names could be loaded, an error is returned that lists
+    /// the failure for each name. Its kind is `NotFound`, if all the failures
+    /// were of that kind, `Other` otherwise. An empty list of names is an
+    /// `InvalidInput` error.
+    pub fn load_any(names: &[&str]) -> Result<Self> {
+        if names.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No library names given!"));
+        }
+        let mut errors = Vec::new();
+        for name in names {
+            match Self::load(name) {
+                Ok(lib) => return Ok(lib),
+                Err(err) => errors.push((name, err)),
+            }
+        }
+        let kind = if errors.iter().all(|(_, e)| e.kind() == io::ErrorKind::NotFound) {
+            io::ErrorKind::NotFound
+        }
+        else {
+            io::ErrorKind::Other
+        };
+        let message = errors.iter()
+            .map(|(name, err)| format!("{}: {}", name, err))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(io::Error::new(kind, format!("Could not load any of the libraries ({})", message)))
     }
 
     /// Loads a symbol from this library with the given symbolic name.
@@ -162,12 +282,236 @@ impl Library {
             phantom: PhantomData,
         })
     }
+
+    /// Loads a function with the `extern "C"` calling convention from this
+    /// library. Same as `load_symbol`, but only accepts `extern "C"` function
+    /// pointers, including variadic ones.
+    ///
+    /// # Examples
+    ///
+    /// Loading and running `abs` from the C standard library on Linux:
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use cacti_asset::dyn_lib::*;
+    ///
+    /// let mut lib = Library::load("libc.so.6")?;
+    /// let abs: CSymbol<extern "C" fn(i32) -> i32> = lib.load_c_symbol("abs")?;
+    /// assert_eq!(abs(-3), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub fn load_c_symbol<T: CFunction>(&mut self, name: &str) -> Result<CSymbol<'_, T>> {
+        self.load_symbol(name)
+    }
+
+    /// Loads a function with the `extern "system"` calling convention from
+    /// this library, like the Windows API functions. Same as `load_symbol`,
+    /// but only accepts `extern "system"` function pointers.
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub fn load_system_symbol<T: SystemFunction>(&mut self, name: &str) -> Result<SystemSymbol<'_, T>> {
+        self.load_symbol(name)
+    }
+
+    /// Returns the names of the symbols this library exports, which is useful
+    /// for debugging and discovering plugin entry points.
+    ///
+    /// This is best-effort: on Windows the export directory of the loaded
+    /// module is read, on Linux the dynamic symbol table. Elsewhere the list
+    /// is empty, and it can also be empty for libraries that only export by
+    /// ordinal or have no dynamic symbol table.
+    ///
+    /// # Examples
+    ///
+    /// Listing the exports of `Kernel32` on Windows:
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// use cacti_asset::dyn_lib::Library;
+    ///
+    /// let lib = Library::load("kernel32")?;
+    /// for name in lib.exported_symbols()? {
+    ///     println!("{}", name);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub fn exported_symbols(&self) -> Result<Vec<String>> {
+        self.0.exported_symbols()
+    }
+
+    /// Unloads the library. Dropping the library unloads it too, but ignores
+    /// the errors, this can be used to detect, when the platform fails to
+    /// unload the library.
+    ///
+    /// # Errors
+    ///
+    /// In case of a system error, an error variant is returned.
+    pub fn unload(mut self) -> Result<()> {
+        self.0.unload()
+    }
 }
 
 impl Drop for Library {
     fn drop(&mut self) {
-        self.0.unload();
+        // Nothing to do with the error here
+        let _ = self.0.unload();
+    }
+}
+
+/// A reference-counted [Library](struct.Library.html). Cloning it is cheap, and
+/// every clone refers to the same loaded library, that gets unloaded, when the
+/// last clone is dropped.
+///
+/// # Examples
+///
+/// Sharing `Kernel32` between two owners on Windows:
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use cacti_asset::dyn_lib::*;
+///
+/// let lib = SharedLibrary::new(Library::load("kernel32")?);
+/// let other = lib.clone();
+/// drop(lib);
+/// // The library is still loaded
+/// let sym: Symbol<extern "system" fn(u32) -> u32> = other.load_symbol("GetProcessVersion")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedLibrary(Arc<SharedLibraryInner>);
+
+#[derive(Debug)]
+struct SharedLibraryInner(Library);
+
+// SAFETY: The handle is not modified after loading, and the platform loaders
+// are thread-safe
+unsafe impl Send for SharedLibraryInner {}
+unsafe impl Sync for SharedLibraryInner {}
+
+impl SharedLibrary {
+    /// Creates a `SharedLibrary` from the uniquely owned `Library`.
+    pub fn new(lib: Library) -> Self {
+        Self(Arc::new(SharedLibraryInner(lib)))
+    }
+
+    /// Loads a symbol from this library with the given symbolic name. See
+    /// [Library::load_symbol](struct.Library.html#method.load_symbol).
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub fn load_symbol<T>(&self, name: &str) -> Result<Symbol<'_, T>> {
+        let lib = &(self.0).0;
+        Ok(Symbol{
+            sym: lib.0.load_symbol(name)?,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Loads a function with the `extern "C"` calling convention from this
+    /// library. See
+    /// [Library::load_c_symbol](struct.Library.html#method.load_c_symbol).
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub fn load_c_symbol<T: CFunction>(&self, name: &str) -> Result<CSymbol<'_, T>> {
+        self.load_symbol(name)
     }
+
+    /// Loads a function with the `extern "system"` calling convention from
+    /// this library. See
+    /// [Library::load_system_symbol](struct.Library.html#method.load_system_symbol).
+    ///
+    /// # Errors
+    ///
+    /// In case of an IO or system error, an error variant is returned.
+    pub fn load_system_symbol<T: SystemFunction>(&self, name: &str) -> Result<SystemSymbol<'_, T>> {
+        self.load_symbol(name)
+    }
+}
+
+impl From<Library> for SharedLibrary {
+    fn from(lib: Library) -> Self { Self::new(lib) }
+}
+
+/// Flags controlling how a [Library](struct.Library.html) is searched for and
+/// loaded. Flags can be combined with the `|` operator.
+///
+/// On Windows the search flags map directly to the `LOAD_LIBRARY_SEARCH_*`
+/// flags of `LoadLibraryExW`, and the Unix-only flags are ignored. On Unix
+/// there's no equivalent of the search flags, there a library is loaded with
+/// `RTLD_NOW | RTLD_LOCAL`, unless `LAZY` or `GLOBAL` says otherwise.
+///
+/// The default value is `SEARCH_DEFAULT_DIRS`, which is the recommended,
+/// hardened setting on Windows and means `RTLD_NOW | RTLD_LOCAL` on Unix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoadFlags(u32);
+
+impl LoadFlags {
+    /// No flags, the platform default search order is used.
+    pub const NONE: Self = Self(0);
+    /// Searches the directory containing the loaded library for its
+    /// dependencies. Requires a fully qualified path.
+    pub const SEARCH_DLL_LOAD_DIR: Self = Self(0x00000100);
+    /// Searches the directory the application was loaded from.
+    pub const SEARCH_APPLICATION_DIR: Self = Self(0x00000200);
+    /// Searches the directories added with `AddDllDirectory` or
+    /// `SetDllDirectory`.
+    pub const SEARCH_USER_DIRS: Self = Self(0x00000400);
+    /// Searches the `System32` directory.
+    pub const SEARCH_SYSTEM32: Self = Self(0x00000800);
+    /// The combination of `SEARCH_APPLICATION_DIR`, `SEARCH_SYSTEM32` and
+    /// `SEARCH_USER_DIRS`.
+    pub const SEARCH_DEFAULT_DIRS: Self = Self(0x00001000);
+    /// Unix-only, loads with `RTLD_LAZY` instead of `RTLD_NOW`, so function
+    /// symbols are only resolved when they are first called. This makes
+    /// loading faster, but an unresolvable symbol only turns out at call time,
+    /// terminating the process instead of failing the load.
+    pub const LAZY: Self = Self(0x40000000);
+    /// Unix-only, loads with `RTLD_GLOBAL` instead of `RTLD_LOCAL`, making the
+    /// symbols of the library available for resolving the libraries loaded
+    /// after it.
+    ///
+    /// **Note:** Use this with care. The exported symbols of the library can
+    /// silently interpose the same-named symbols of every subsequently loaded
+    /// library, and this can't be undone while the library stays loaded.
+    pub const GLOBAL: Self = Self(0x80000000);
+
+    /// Returns `true`, if all the flags in `other` are set in this one.
+    pub fn contains(&self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+
+    /// Returns the raw bits of this `LoadFlags`.
+    pub fn bits(&self) -> u32 { self.0 }
+}
+
+impl Default for LoadFlags {
+    fn default() -> Self { Self::SEARCH_DEFAULT_DIRS }
+}
+
+impl BitOr for LoadFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self { Self(self.0 | rhs.0) }
+}
+
+impl BitOrAssign for LoadFlags {
+    fn bitor_assign(&mut self, rhs: Self) { self.0 |= rhs.0; }
 }
 
 /// Represents a loaded symbol. This symbol's lifetime is tied to the library
@@ -204,6 +548,56 @@ impl <'a, T: 'a> Deref for Symbol<'a, T> {
     }
 }
 
+/// A [Symbol](struct.Symbol.html) of an `extern "C"` function, see
+/// [Library::load_c_symbol](struct.Library.html#method.load_c_symbol).
+pub type CSymbol<'a, T> = Symbol<'a, T>;
+
+/// A [Symbol](struct.Symbol.html) of an `extern "system"` function, see
+/// [Library::load_system_symbol](struct.Library.html#method.load_system_symbol).
+pub type SystemSymbol<'a, T> = Symbol<'a, T>;
+
+/// Implemented for the `extern "C"` function pointer types - safe, `unsafe`
+/// and variadic ones - with up to 12 parameters. It can't be implemented
+/// outside of this module.
+pub trait CFunction: Copy + sealed::Sealed {}
+
+/// Implemented for the `extern "system"` function pointer types - safe and
+/// `unsafe` ones - with up to 12 parameters. It can't be implemented outside
+/// of this module.
+pub trait SystemFunction: Copy + sealed::Sealed {}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_function_traits {
+    () => {
+        impl_function_traits!(@impl CFunction, extern "C" fn() -> R, R);
+        impl_function_traits!(@impl CFunction, unsafe extern "C" fn() -> R, R);
+        impl_function_traits!(@impl SystemFunction, extern "system" fn() -> R, R);
+        impl_function_traits!(@impl SystemFunction, unsafe extern "system" fn() -> R, R);
+    };
+    ($first:ident $(, $rest:ident)*) => {
+        impl_function_traits!(@impl CFunction,
+            extern "C" fn($first $(, $rest)*) -> R, R, $first $(, $rest)*);
+        impl_function_traits!(@impl CFunction,
+            unsafe extern "C" fn($first $(, $rest)*) -> R, R, $first $(, $rest)*);
+        impl_function_traits!(@impl CFunction,
+            unsafe extern "C" fn($first $(, $rest)*, ...) -> R, R, $first $(, $rest)*);
+        impl_function_traits!(@impl SystemFunction,
+            extern "system" fn($first $(, $rest)*) -> R, R, $first $(, $rest)*);
+        impl_function_traits!(@impl SystemFunction,
+            unsafe extern "system" fn($first $(, $rest)*) -> R, R, $first $(, $rest)*);
+        impl_function_traits!($($rest),*);
+    };
+    (@impl $trait:ident, $fn:ty, $($param:ident),*) => {
+        impl <$($param),*> sealed::Sealed for $fn {}
+        impl <$($param),*> $trait for $fn {}
+    };
+}
+
+impl_function_traits!(A, B, C, D, E, F, G, H, I, J, K, L);
+
 // ////////////////////////////////////////////////////////////////////////// //
 //                               Implementation                               //
 // ////////////////////////////////////////////////////////////////////////// //
@@ -213,14 +607,18 @@ trait DynLib: Sized {
     /// The type of symbol this platform provides.
     type Symbol: std::fmt::Debug;
 
-    /// Loads the library at the given path.
-    fn load(path: &Path) -> Result<Self>;
+    /// Loads the library at the given path with the given flags.
+    fn load(path: &Path, flags: LoadFlags) -> Result<Self>;
 
-    /// Unloads this library.
-    fn unload(&mut self);
+    /// Unloads this library. Unloading an already unloaded library does
+    /// nothing.
+    fn unload(&mut self) -> Result<()>;
 
     /// Loads the symbol with the given name.
-    fn load_symbol(&mut self, name: &str) -> Result<Self::Symbol>;
+    fn load_symbol(&self, name: &str) -> Result<Self::Symbol>;
+
+    /// Lists the names of the exported symbols.
+    fn exported_symbols(&self) -> Result<Vec<String>>;
 }
 
 // Unsupported implementation //////////////////////////////////////////////////
@@ -240,12 +638,13 @@ mod unsupported {
     impl DynLib for UnsupportedDynLib {
         type Symbol = UnsupportedSymbol;
 
-        fn load(_path: &Path) -> Result<Self> {
+        fn load(_path: &Path, _flags: LoadFlags) -> Result<Self> {
             Err(Error::new(ErrorKind::Other, "Library loading is not supported on this platform!"))
         }
 
-        fn unload(&mut self) { unreachable!() }
-        fn load_symbol(&mut self, _name: &str) -> Result<Self::Symbol> { unreachable!() }
+        fn unload(&mut self) -> Result<()> { unreachable!() }
+        fn load_symbol(&self, _name: &str) -> Result<Self::Symbol> { unreachable!() }
+        fn exported_symbols(&self) -> Result<Vec<String>> { unreachable!() }
     }
 }
 
@@ -255,7 +654,8 @@ mod unsupported {
 mod win32 {
     #![allow(non_snake_case)]
 
-    use std::ffi::{OsStr, CString, c_void};
+    use std::ffi::{OsStr, CStr, CString, c_void};
+    use std::os::raw::c_char;
     use std::os::windows::ffi::OsStrExt;
     use std::ptr;
     use std::io;
@@ -263,7 +663,7 @@ mod win32 {
 
     #[link(name = "kernel32")]
     extern "system" {
-        fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        fn LoadLibraryExW(name: *const u16, file: *mut c_void, flags: u32) -> *mut c_void;
         fn FreeLibrary(hmodule: *mut c_void) -> i32;
         fn GetProcAddress(hmodule: *mut c_void, name: *const i8) -> *mut c_void;
     }
@@ -273,6 +673,54 @@ mod win32 {
         s.encode_wide().chain(Some(0).into_iter()).collect()
     }
 
+    const IMAGE_DOS_SIGNATURE: u16 = 0x5a4d;
+    const IMAGE_NT_SIGNATURE: u32 = 0x00004550;
+    const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+    const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+
+    /// Reads a value at the given offset from the base of a loaded module.
+    unsafe fn read_at<T: Copy>(base: *const u8, offset: usize) -> T {
+        ptr::read_unaligned(base.add(offset) as *const T)
+    }
+
+    /// Lists the names in the export directory of the loaded module, walking
+    /// the PE headers from its base address.
+    fn export_names(base: *const u8) -> Result<Vec<String>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid PE image!");
+        unsafe {
+            if read_at::<u16>(base, 0) != IMAGE_DOS_SIGNATURE {
+                return Err(invalid());
+            }
+            let nt = read_at::<u32>(base, 0x3c) as usize;
+            if read_at::<u32>(base, nt) != IMAGE_NT_SIGNATURE {
+                return Err(invalid());
+            }
+            // The optional header follows the signature and the file header,
+            // the data directories are at the end of its fixed part
+            let optional = nt + 4 + 20;
+            let directories = match read_at::<u16>(base, optional) {
+                IMAGE_NT_OPTIONAL_HDR32_MAGIC => optional + 96,
+                IMAGE_NT_OPTIONAL_HDR64_MAGIC => optional + 112,
+                _ => return Err(invalid()),
+            };
+            // The export directory is the first one
+            let exports = read_at::<u32>(base, directories) as usize;
+            if exports == 0 {
+                return Ok(Vec::new());
+            }
+            // IMAGE_EXPORT_DIRECTORY.NumberOfNames and AddressOfNames
+            let name_count = read_at::<u32>(base, exports + 24) as usize;
+            let names = read_at::<u32>(base, exports + 32) as usize;
+            let mut result = Vec::with_capacity(name_count);
+            for i in 0..name_count {
+                let name = read_at::<u32>(base, names + i * 4) as usize;
+                let name = CStr::from_ptr(base.add(name) as *const c_char);
+                result.push(name.to_string_lossy().into_owned());
+            }
+            Ok(result)
+        }
+    }
+
     #[derive(Debug)]
     pub struct WinApiDynLib(*mut c_void);
 
@@ -282,24 +730,32 @@ mod win32 {
     impl DynLib for WinApiDynLib {
         type Symbol = WinApiSymbol;
 
-        fn load(path: &Path) -> Result<Self> {
+        fn load(path: &Path, flags: LoadFlags) -> Result<Self> {
             let wpath = to_wstring(path.as_os_str());
-            let hmodule = unsafe{ LoadLibraryW(wpath.as_ptr()) };
+            // The flags are the exact `LOAD_LIBRARY_SEARCH_*` values, except
+            // for the Unix-only ones
+            let flags = flags.bits() & !(LoadFlags::LAZY | LoadFlags::GLOBAL).bits();
+            let hmodule = unsafe{ LoadLibraryExW(wpath.as_ptr(), ptr::null_mut(), flags) };
             if hmodule.is_null() {
                 return Err(io::Error::last_os_error());
             }
             Ok(Self(hmodule))
         }
 
-        fn unload(&mut self) {
+        fn unload(&mut self) -> Result<()> {
             if self.0.is_null() {
-                return;
+                return Ok(());
             }
-            unsafe{ FreeLibrary(self.0) };
+            let ret = unsafe{ FreeLibrary(self.0) };
+            // Never try twice, the handle is invalid either way
             self.0 = ptr::null_mut();
+            if ret == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
         }
 
-        fn load_symbol(&mut self, name: &str) -> Result<Self::Symbol> {
+        fn load_symbol(&self, name: &str) -> Result<Self::Symbol> {
             let name = unsafe{ CString::from_vec_unchecked(name.into()) };
             let sym = unsafe{ GetProcAddress(self.0, name.as_ptr()) };
             if sym.is_null() {
@@ -307,6 +763,11 @@ mod win32 {
             }
             Ok(WinApiSymbol(sym))
         }
+
+        fn exported_symbols(&self) -> Result<Vec<String>> {
+            // The module handle is the base address of the loaded image
+            export_names(self.0 as *const u8)
+        }
     }
 
     impl WinApiSymbol {
@@ -318,14 +779,18 @@ mod win32 {
 
 #[cfg(target_family = "unix")]
 mod unix {
-    use std::ffi::{CString, c_void};
+    use std::ffi::{CStr, CString, c_void};
     use std::os::raw::{c_char, c_int};
     use std::os::unix::ffi::OsStrExt;
-    use std::io;
     use std::ptr;
     use super::*;
 
-    const RTLD_NOW: i32 = 0x2;
+    const RTLD_LAZY: c_int = 0x1;
+    const RTLD_NOW: c_int = 0x2;
+    #[cfg(target_os = "macos")] const RTLD_LOCAL: c_int = 0x4;
+    #[cfg(target_os = "macos")] const RTLD_GLOBAL: c_int = 0x8;
+    #[cfg(not(target_os = "macos"))] const RTLD_LOCAL: c_int = 0x0;
+    #[cfg(not(target_os = "macos"))] const RTLD_GLOBAL: c_int = 0x100;
 
     #[link(name = "c")]
     extern "C" {
@@ -343,32 +808,45 @@ mod unix {
 
     fn get_dlerror() -> io::Error {
         let err = unsafe{ dlerror() };
-        let err_str = unsafe{ CString::from_raw(err) }.into_string()
-            .unwrap_or_else(|_| "Unknown dlerror".into());
+        let err_str = if err.is_null() {
+            "Unknown dlerror".into()
+        }
+        else {
+            // NOTE: The string is owned by the loader, we must not free it
+            unsafe{ CStr::from_ptr(err) }.to_string_lossy().into_owned()
+        };
         io::Error::new(io::ErrorKind::Other, err_str)
     }
 
     impl DynLib for UnixDynLib {
         type Symbol = UnixSymbol;
 
-        fn load(path: &Path) -> Result<Self> {
+        fn load(path: &Path, flags: LoadFlags) -> Result<Self> {
+            // NOTE: The search flags have no dlopen equivalent
+            let binding = if flags.contains(LoadFlags::LAZY) { RTLD_LAZY } else { RTLD_NOW };
+            let visibility = if flags.contains(LoadFlags::GLOBAL) { RTLD_GLOBAL } else { RTLD_LOCAL };
             let name = unsafe{ CString::from_vec_unchecked(path.as_os_str().as_bytes().to_vec()) };
-            let handle = unsafe{ dlopen(name.as_ptr(), RTLD_NOW) };
+            let handle = unsafe{ dlopen(name.as_ptr(), binding | visibility) };
             if handle.is_null() {
                 return Err(get_dlerror());
             }
             Ok(Self(handle))
         }
 
-        fn unload(&mut self) {
+        fn unload(&mut self) -> Result<()> {
             if self.0.is_null() {
-                return;
+                return Ok(());
             }
-            unsafe{ dlclose(self.0) };
+            let ret = unsafe{ dlclose(self.0) };
+            // Never try twice, the handle is invalid either way
             self.0 = ptr::null_mut();
+            if ret != 0 {
+                return Err(get_dlerror());
+            }
+            Ok(())
         }
 
-        fn load_symbol(&mut self, name: &str) -> Result<Self::Symbol> {
+        fn load_symbol(&self, name: &str) -> Result<Self::Symbol> {
             let name = unsafe{ CString::from_vec_unchecked(name.as_bytes().to_vec()) };
             let sym = unsafe{ dlsym(self.0, name.as_ptr()) };
             if sym.is_null() {
@@ -376,11 +854,151 @@ mod unix {
             }
             Ok(UnixSymbol(sym))
         }
+
+        #[cfg(target_os = "linux")]
+        fn exported_symbols(&self) -> Result<Vec<String>> {
+            let mut map: *const LinkMap = ptr::null();
+            let ret = unsafe{ dlinfo(self.0, RTLD_DI_LINKMAP, &mut map as *mut _ as *mut c_void) };
+            if ret != 0 || map.is_null() {
+                return Err(get_dlerror());
+            }
+            Ok(unsafe{ dynamic_symbols(&*map) })
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        fn exported_symbols(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
     }
 
     impl UnixSymbol {
         pub fn ptr_ref(&self) -> &*const c_void { &self.0 }
     }
+
+    // ELF dynamic symbol table ////////////////////////////////////////////////
+
+    #[cfg(target_os = "linux")]
+    const RTLD_DI_LINKMAP: c_int = 2;
+
+    #[cfg(target_os = "linux")]
+    #[link(name = "dl")]
+    extern "C" {
+        fn dlinfo(handle: *mut c_void, request: c_int, info: *mut c_void) -> c_int;
+    }
+
+    /// The public part of the loader's `struct link_map`.
+    #[cfg(target_os = "linux")]
+    #[repr(C)]
+    struct LinkMap {
+        addr: usize        ,
+        name: *const c_char,
+        ld  : *const ElfDyn,
+        next: *const c_void,
+        prev: *const c_void,
+    }
+
+    #[cfg(target_os = "linux")]
+    #[repr(C)]
+    struct ElfDyn {
+        tag  : isize,
+        value: usize,
+    }
+
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    #[repr(C)]
+    struct ElfSym {
+        name : u32,
+        info : u8 ,
+        other: u8 ,
+        shndx: u16,
+        value: u64,
+        size : u64,
+    }
+
+    #[cfg(all(target_os = "linux", target_pointer_width = "32"))]
+    #[repr(C)]
+    struct ElfSym {
+        name : u32,
+        value: u32,
+        size : u32,
+        info : u8 ,
+        other: u8 ,
+        shndx: u16,
+    }
+
+    #[cfg(target_os = "linux")] const DT_NULL: isize = 0;
+    #[cfg(target_os = "linux")] const DT_HASH: isize = 4;
+    #[cfg(target_os = "linux")] const DT_STRTAB: isize = 5;
+    #[cfg(target_os = "linux")] const DT_SYMTAB: isize = 6;
+    #[cfg(target_os = "linux")] const DT_GNU_HASH: isize = 0x6ffffef5;
+
+    #[cfg(target_os = "linux")] const STB_GLOBAL: u8 = 1;
+    #[cfg(target_os = "linux")] const STB_WEAK: u8 = 2;
+    #[cfg(target_os = "linux")] const SHN_UNDEF: u16 = 0;
+
+    /// Counts the symbols in the dynamic symbol table from the highest index
+    /// reachable through the GNU hash table.
+    #[cfg(target_os = "linux")]
+    unsafe fn gnu_hash_symbol_count(table: *const u32) -> usize {
+        let bucket_count = *table as usize;
+        let sym_offset = *table.add(1) as usize;
+        let bloom_size = *table.add(2) as usize;
+        let buckets = (table.add(4) as *const usize).add(bloom_size) as *const u32;
+        let chains = buckets.add(bucket_count);
+        let last = (0..bucket_count).map(|i| *buckets.add(i) as usize).max().unwrap_or(0);
+        if last < sym_offset {
+            return sym_offset;
+        }
+        // The lowest bit marks the end of a chain
+        let mut index = last;
+        while *chains.add(index - sym_offset) & 1 == 0 {
+            index += 1;
+        }
+        index + 1
+    }
+
+    /// Lists the defined, global symbols of the dynamic symbol table of the
+    /// loaded object.
+    #[cfg(target_os = "linux")]
+    unsafe fn dynamic_symbols(map: &LinkMap) -> Vec<String> {
+        // Some loaders relocate the addresses in the dynamic section, others
+        // leave them relative to the load address
+        let address = |value: usize| if value < map.addr { value + map.addr } else { value };
+        let mut symtab: *const ElfSym = ptr::null();
+        let mut strtab: *const c_char = ptr::null();
+        let mut count = None;
+        let mut entry = map.ld;
+        while !entry.is_null() && (*entry).tag != DT_NULL {
+            let value = (*entry).value;
+            match (*entry).tag {
+                DT_SYMTAB => symtab = address(value) as *const ElfSym,
+                DT_STRTAB => strtab = address(value) as *const c_char,
+                // The number of chains is the number of symbols
+                DT_HASH => count = Some(*(address(value) as *const u32).add(1) as usize),
+                DT_GNU_HASH if count.is_none() =>
+                    count = Some(gnu_hash_symbol_count(address(value) as *const u32)),
+                _ => {},
+            }
+            entry = entry.add(1);
+        }
+        let count = match count {
+            Some(count) if !symtab.is_null() && !strtab.is_null() => count,
+            _ => return Vec::new(),
+        };
+        let mut result = Vec::new();
+        for i in 0..count {
+            let sym = &*symtab.add(i);
+            let binding = sym.info >> 4;
+            if sym.shndx == SHN_UNDEF || (binding != STB_GLOBAL && binding != STB_WEAK) {
+                continue;
+            }
+            let name = CStr::from_ptr(strtab.add(sym.name as usize));
+            if !name.to_bytes().is_empty() {
+                result.push(name.to_string_lossy().into_owned());
+            }
+        }
+        result
+    }
 }
 
 // Choosing the right implementation based on platform.
@@ -416,4 +1034,118 @@ mod tests {
         assert_ne!(0, v);
         Ok(())
     }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_win32_kernel32_search_system32() -> Result<()> {
+        let mut l = Library::load_with_flags("kernel32", LoadFlags::SEARCH_SYSTEM32)?;
+        let sym: Symbol<extern "system" fn(u32) -> u32> = l.load_symbol("GetProcessVersion")?;
+        let v = sym(0);
+        assert_ne!(0, v);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_unix_libm_lazy() -> Result<()> {
+        let mut l = Library::load_with_flags("libm.so.6", LoadFlags::LAZY)?;
+        let sym: Symbol<extern "C" fn(f64) -> f64> = l.load_symbol("cos")?;
+        let v = sym(0.0);
+        assert_eq!(1.0, v);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_unix_load_c_symbol() -> Result<()> {
+        use std::ffi::CStr;
+        use std::os::raw::{c_char, c_int};
+
+        let mut l = Library::load("libc.so.6")?;
+        let abs: CSymbol<extern "C" fn(c_int) -> c_int> = l.load_c_symbol("abs")?;
+        assert_eq!(abs(-3), 3);
+        // Variadic
+        let sprintf: CSymbol<unsafe extern "C" fn(*mut c_char, *const c_char, ...) -> c_int> =
+            l.load_c_symbol("sprintf")?;
+        let mut buffer = [0 as c_char; 32];
+        let format = b"%d-%s\0";
+        let len = unsafe{ sprintf(buffer.as_mut_ptr(), format.as_ptr().cast(), 42 as c_int, b"x\0".as_ptr()) };
+        assert_eq!(len, 4);
+        let text = unsafe{ CStr::from_ptr(buffer.as_ptr()) };
+        assert_eq!(text.to_str().unwrap(), "42-x");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_win32_kernel32_exported_symbols() -> Result<()> {
+        let l = Library::load("kernel32")?;
+        let symbols = l.exported_symbols()?;
+        assert!(symbols.iter().any(|s| s == "GetProcessVersion"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_unix_libm_exported_symbols() -> Result<()> {
+        let l = Library::load("libm.so.6")?;
+        let symbols = l.exported_symbols()?;
+        assert!(symbols.iter().any(|s| s == "cos"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_unload() -> Result<()> {
+        #[cfg(target_os = "windows")] const NAME: &str = "msvcrt";
+        #[cfg(target_os = "linux")] const NAME: &str = "libm.so.6";
+        #[cfg(target_os = "macos")] const NAME: &str = "libc.dylib";
+        let l = Library::load(NAME)?;
+        l.unload()?;
+        // Loading again after the unload works
+        let l = Library::load(NAME)?;
+        assert!(!l.exported_symbols()?.is_empty());
+        l.unload()
+    }
+
+    #[test]
+    fn test_load_any_skips_bogus() -> Result<()> {
+        #[cfg(target_os = "windows")] const NAMES: &[&str] = &["nonexisting", "kernel32"];
+        #[cfg(target_os = "linux")] const NAMES: &[&str] = &["libnonexisting.so", "libc.so.6"];
+        #[cfg(target_os = "macos")] const NAMES: &[&str] = &["libnonexisting.dylib", "libc.dylib"];
+        let _l = Library::load_any(NAMES)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_library_clone() -> Result<()> {
+        #[cfg(target_os = "windows")] const NAME: &str = "msvcrt";
+        #[cfg(target_os = "linux")] const NAME: &str = "libc.so.6";
+        #[cfg(target_os = "macos")] const NAME: &str = "libc.dylib";
+        let lib = SharedLibrary::new(Library::load(NAME)?);
+        let other = lib.clone();
+        drop(lib);
+        let sym: Symbol<extern "C" fn(i32) -> i32> = other.load_symbol("abs")?;
+        assert_eq!(sym(-3), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_any_all_fail() {
+        let err = Library::load_any(&["nonexisting1", "nonexisting2"]).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("nonexisting1"));
+        assert!(msg.contains("nonexisting2"));
+
+        let err = Library::load_any(&[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_load_flags_combine() {
+        let flags = LoadFlags::SEARCH_APPLICATION_DIR | LoadFlags::SEARCH_SYSTEM32;
+        assert!(flags.contains(LoadFlags::SEARCH_SYSTEM32));
+        assert!(flags.contains(LoadFlags::SEARCH_APPLICATION_DIR));
+        assert!(!flags.contains(LoadFlags::SEARCH_USER_DIRS));
+        assert_eq!(LoadFlags::default(), LoadFlags::SEARCH_DEFAULT_DIRS);
+    }
 }
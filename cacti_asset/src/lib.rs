@@ -1,5 +1,6 @@
 //! A library for managing assets.
 // TODO: doc, introduce library
 
+pub mod archive;
 pub mod dyn_lib;
 pub mod hot_reload;
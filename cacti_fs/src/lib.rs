@@ -1,4 +1,33 @@
 //! Various dependency-free, cross-platform utilities for filesystems.
+//!
+//! # Usage
+//!
+//! The most commonly used names are re-exported at the crate root, so they can
+//! be imported with a single `use`:
+//!  * From [watch](watch/index.html): `Watch`, `DefaultWatch`, `Recursion`,
+//!    `Event` and `EventKind`.
+//!  * From [path](path/index.html): `FilePath`.
+//!  * From [temp](temp/index.html): `Directory`.
+//!
+//! The modules themselves stay public, the less common items - like the
+//! functions creating temporaries - are reached through them:
+//!
+//! ```
+//! use std::fs::File;
+//! use cacti_fs::{temp, DefaultWatch, Directory, FilePath, Recursion, Watch};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let dir: Directory = temp::directory()?;
+//! let mut watch = DefaultWatch::new()?;
+//! watch.watch(dir.path(), Recursion::Recursive)?;
+//!
+//! let file = File::create(dir.path().join("foo.txt"))?;
+//! if let Ok(path) = file.path() {
+//!     assert_eq!(path.file_name().unwrap(), "foo.txt");
+//! }
+//! # Ok(())
+//! # }
+//! ```
 
 /// Provides a single trait `FilePath` that's implemented for `fs::File`, so it
 /// can return it's own pocation using a `.path()` function, if possible.
@@ -8,3 +37,7 @@ pub mod path;
 pub mod temp;
 /// Provides ways do monitor for filesystem changes under different paths.
 pub mod watch;
+
+pub use path::FilePath;
+pub use temp::Directory;
+pub use watch::{DefaultWatch, Event, EventKind, Recursion, Watch};
@@ -27,8 +27,8 @@
 //!
 //! # Porting the library to other platforms
 //!
-//! A single function named `path_for` has to be in global scope for the
-//! platform:
+//! Two functions named `path_for` and `is_path_valid_for` have to be in global
+//! scope for the platform:
 //!
 //! ```no_run
 //! # use std::io::Result;
@@ -39,9 +39,16 @@
 //!     // ...
 //! # unimplemented!()
 //! }
+//!
+//! #[cfg(target_os = "new_platform")]
+//! fn is_path_valid_for(handle: &File) -> bool {
+//!     // ...
+//! # unimplemented!()
+//! }
 //! ```
 //!
-//! Which should return the path for the handle, if possible.
+//! The first should return the path for the handle, if possible, the second
+//! whether that path still refers to the file.
 
 use std::fs::File;
 use std::path::PathBuf;
@@ -73,13 +80,43 @@ pub trait FilePath {
     /// # Errors
     ///
     /// In case of an IO or system error, an error variant is returned.
+    ///
+    /// On Linux, if the file got deleted while it was open, the path it had
+    /// before the deletion is returned. The implementation relies on `/proc`,
+    /// which might not be mounted in containers or sandboxes. In that case an
+    /// error saying that the operation is unsupported is returned.
     fn path(&self) -> Result<PathBuf>;
+
+    /// Returns `true`, if the handle still maps to an existing path, meaning
+    /// that the file wasn't deleted since it was opened. When this returns
+    /// `false`, `path` can still succeed, but the path it returns is only the
+    /// last known one, that doesn't lead to this file anymore.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::{self, File};
+    /// use cacti_fs::path::FilePath;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = File::create("foo.txt")?;
+    /// assert!(file.is_path_valid());
+    /// fs::remove_file("foo.txt")?;
+    /// assert!(!file.is_path_valid());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn is_path_valid(&self) -> bool;
 }
 
 impl FilePath for File {
     fn path(&self) -> Result<PathBuf> {
         path_for(self)
     }
+
+    fn is_path_valid(&self) -> bool {
+        is_path_valid_for(self)
+    }
 }
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -98,6 +135,21 @@ mod unsupported {
         Err(Error::new(ErrorKind::Other,
             "Asking the path of a file handle is unsupported on this platform!"))
     }
+
+    pub fn is_path_valid_for(_file: &File) -> bool {
+        false
+    }
+}
+
+/// Returns `true`, if the given path leads to the same file as the handle.
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &std::path::Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(m1), Ok(m2)) => m1.dev() == m2.dev() && m1.ino() == m2.ino(),
+        _ => false,
+    }
 }
 
 // WinAPI implementation ///////////////////////////////////////////////////////
@@ -107,13 +159,18 @@ mod win32 {
     #![allow(non_snake_case)]
 
     use std::ffi::{c_void, OsString};
-    use std::os::windows::ffi::OsStringExt;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
     use std::os::windows::io::AsRawHandle;
+    use std::path::{Path, Component, Prefix};
     use std::ptr;
     use super::*;
 
     const FILE_NAME_NORMALIZED: u32 = 0;
 
+    // Extended-length path prefixes
+    const EXTENDED_PREFIX    : &str = r"\\?\";
+    const EXTENDED_UNC_PREFIX: &str = r"\\?\UNC\";
+
     #[link(name = "kernel32")]
     extern "system" {
         fn GetFinalPathNameByHandleW(
@@ -150,6 +207,65 @@ mod win32 {
         let buffer = &buffer[..(written_size as usize)];
         Ok(OsString::from_wide(buffer).into())
     }
+
+    pub fn is_path_valid_for(file: &File) -> bool {
+        // A file pending deletion still has a final path, but it can't be
+        // opened through it anymore
+        match path_for(file) {
+            Ok(path) => path.exists(),
+            Err(_) => false,
+        }
+    }
+
+    /// Converts the path into a 0-terminated WinAPI `WCHAR` string. Absolute
+    /// paths get the extended-length prefix, so they are not limited to
+    /// `MAX_PATH` characters.
+    ///
+    /// The system doesn't normalize extended-length paths, so relative paths
+    /// and paths with `..` components are converted as they are.
+    pub fn to_extended_wstring(path: &Path) -> Vec<u16> {
+        let can_extend = path.is_absolute()
+            && !path.components().any(|c| c == Component::ParentDir);
+        // The prefix to add and the number of characters it replaces
+        let prefix = match path.components().next() {
+            Some(Component::Prefix(p)) if can_extend => match p.kind() {
+                Prefix::Disk(_) => Some((EXTENDED_PREFIX, 0)),
+                // The leading "\\" is replaced
+                Prefix::UNC(..) => Some((EXTENDED_UNC_PREFIX, 2)),
+                // Already verbatim or a device path
+                _ => None,
+            },
+            _ => None,
+        };
+        let mut result: Vec<u16> = match prefix {
+            Some((prefix, replaced)) => {
+                // The system takes extended-length paths literally, so they
+                // are rebuilt from the components, without the "." components
+                // and doubled separators. Forward slashes are not separators
+                // in them either.
+                let mut result: Vec<u16> = prefix.encode_utf16().collect();
+                for component in path.components() {
+                    match component {
+                        Component::Prefix(p) => result.extend(p.as_os_str()
+                            .encode_wide()
+                            .skip(replaced)
+                            .map(|c| if c == '/' as u16 { '\\' as u16 } else { c })),
+                        Component::RootDir => result.push('\\' as u16),
+                        c => {
+                            if result.last() != Some(&('\\' as u16)) {
+                                result.push('\\' as u16);
+                            }
+                            result.extend(c.as_os_str().encode_wide());
+                        },
+                    }
+                }
+                result
+            },
+            None => path.as_os_str().encode_wide().collect(),
+        };
+        result.push(0);
+        result
+    }
 }
 
 // Linux implementation ////////////////////////////////////////////////////////
@@ -157,14 +273,56 @@ mod win32 {
 #[cfg(target_os = "linux")]
 mod linux {
     use std::os::unix::io::AsRawFd;
+    use std::os::unix::ffi::OsStrExt;
+    use std::ffi::OsStr;
+    use std::path::Path;
     use std::fs;
     use super::*;
 
-    pub fn path_for(file: &File) -> Result<PathBuf> {
-        let fd = file.as_raw_fd();
-        let symlink = format!("/proc/self/fd/{}", fd);
+    /// The suffix the kernel appends to the link of an unlinked file.
+    const DELETED_SUFFIX: &[u8] = b" (deleted)";
+
+    /// Reads the link of the file descriptor in `/proc`.
+    fn proc_link(file: &File) -> Result<PathBuf> {
+        let symlink = format!("/proc/self/fd/{}", file.as_raw_fd());
         fs::read_link(&symlink)
     }
+
+    pub fn path_for(file: &File) -> Result<PathBuf> {
+        let path = match proc_link(file) {
+            Ok(path) => path,
+            Err(err) => {
+                if !Path::new("/proc/self/fd").is_dir() {
+                    // Most likely a container or sandbox without /proc
+                    return super::unsupported::path_for(file);
+                }
+                return Err(err);
+            },
+        };
+        // Check if the path still refers to our file, it could be a file that
+        // is literally named with the deleted suffix
+        file.metadata()?;
+        if !is_same_file(file, &path) {
+            // Paths are not necessarily UTF-8, so the marker is stripped from
+            // the raw bytes
+            let bytes = path.as_os_str().as_bytes();
+            if bytes.ends_with(DELETED_SUFFIX) {
+                // Unlinked, strip the marker
+                let stripped = &bytes[..bytes.len() - DELETED_SUFFIX.len()];
+                return Ok(PathBuf::from(OsStr::from_bytes(stripped)));
+            }
+        }
+        Ok(path)
+    }
+
+    pub fn is_path_valid_for(file: &File) -> bool {
+        // The link of an unlinked file ends in the deleted marker, which
+        // doesn't lead back to the file, unless something is named like that
+        match proc_link(file) {
+            Ok(path) => is_same_file(file, &path),
+            Err(_) => false,
+        }
+    }
 }
 
 // OSX implementation //////////////////////////////////////////////////////////
@@ -202,18 +360,26 @@ mod macos {
         buffer.drain(null_term.unwrap()..);
         return Ok(OsString::from_vec(buffer).into())
     }
+
+    pub fn is_path_valid_for(file: &File) -> bool {
+        match path_for(file) {
+            Ok(path) => super::is_same_file(file, &path),
+            Err(_) => false,
+        }
+    }
 }
 
 // Choosing the right implementation based on platform.
 
-#[cfg(target_os = "windows")] use win32::path_for;
-#[cfg(target_os = "linux")] use linux::path_for;
-#[cfg(target_os = "macos")] use macos::path_for;
+#[cfg(target_os = "windows")] use win32::{path_for, is_path_valid_for};
+#[cfg(target_os = "windows")] pub(crate) use win32::to_extended_wstring;
+#[cfg(target_os = "linux")] use linux::{path_for, is_path_valid_for};
+#[cfg(target_os = "macos")] use macos::{path_for, is_path_valid_for};
 #[cfg(not(any(
     target_os = "windows",
     target_os = "linux",
     target_os = "macos",
-)))] use unsupported::path_for;
+)))] use unsupported::{path_for, is_path_valid_for};
 
 #[cfg(test)]
 mod tests {
@@ -237,4 +403,81 @@ mod tests {
         assert!(file.path()?.ends_with(name));
         Ok(())
     }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_path_deleted() -> Result<()> {
+        // NOTE: This is kinda bad, locally creates a file
+        let name = PathBuf::from("fs_path_deleted_testing.txt");
+        let file = File::create(&name)?;
+        let _del = DelFile(name.clone());
+        let path = file.path()?;
+        std::fs::remove_file(&name)?;
+        let deleted_path = file.path()?;
+        assert_eq!(deleted_path, path);
+        assert!(!deleted_path.exists());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_path_deleted_non_utf8() -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        // NOTE: This is kinda bad, locally creates a file
+        let name = PathBuf::from(OsStr::from_bytes(b"fs_path_deleted_\xff_testing.txt"));
+        let file = File::create(&name)?;
+        let _del = DelFile(name.clone());
+        let path = file.path()?;
+        std::fs::remove_file(&name)?;
+        assert_eq!(file.path()?, path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_path_valid() -> Result<()> {
+        // NOTE: This is kinda bad, locally creates a file
+        let name = PathBuf::from("fs_path_valid_testing.txt");
+        let file = File::create(&name)?;
+        let _del = DelFile(name.clone());
+        assert!(file.is_path_valid());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_is_path_valid_deleted() -> Result<()> {
+        // NOTE: This is kinda bad, locally creates a file
+        let name = PathBuf::from("fs_path_valid_deleted_testing.txt");
+        let file = File::create(&name)?;
+        let _del = DelFile(name.clone());
+        std::fs::remove_file(&name)?;
+        assert!(!file.is_path_valid());
+        // Even if something else takes its place
+        let _other = File::create(&name)?;
+        assert!(!file.is_path_valid());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_to_extended_wstring() {
+        use std::path::Path;
+
+        let extended = |path: &str| {
+            let mut wide = to_extended_wstring(Path::new(path));
+            assert_eq!(wide.pop(), Some(0));
+            String::from_utf16(&wide).unwrap()
+        };
+        assert_eq!(extended(r"C:\a\b"), r"\\?\C:\a\b");
+        assert_eq!(extended(r"C:\"), r"\\?\C:\");
+        assert_eq!(extended(r"C:\a\.\b"), r"\\?\C:\a\b");
+        assert_eq!(extended(r"C:\a\\b\"), r"\\?\C:\a\b");
+        assert_eq!(extended("C:/a/b"), r"\\?\C:\a\b");
+        assert_eq!(extended(r"\\server\share\a"), r"\\?\UNC\server\share\a");
+        // Not extended
+        assert_eq!(extended(r"a\.\b"), r"a\.\b");
+        assert_eq!(extended(r"C:\a\..\b"), r"C:\a\..\b");
+    }
 }
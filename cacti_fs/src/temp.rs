@@ -11,6 +11,11 @@
 //! directory that gets deleted, when it's handle is dropped.
 //!  * [file_at](fn.file_at.html): Creates a temporary file at the given path
 //! that gets deleted, when it's handle is dropped.
+//!  * [persistent_file](fn.persistent_file.html): Creates a file at a unique
+//! path that's kept, even after it's handle is dropped.
+//!  * [persistent_file_in](fn.persistent_file_in.html): Creates a file at a
+//! unique path in a given root directory that's kept, even after it's handle is
+//! dropped.
 //!  * [directory](fn.directory.html): Creates a temporary directory that gets
 //! deleted, when it's handle is dropped.
 //!  * [directory_in](fn.directory_in.html): Creates a temporary directory in a
@@ -19,6 +24,8 @@
 //!  * [directory_at](fn.directory_at.html): Creates a temporary directory at
 //! the given path that gets deleted with all it's contents, when it's handle is
 //! dropped.
+//!  * [cleanup_orphans](fn.cleanup_orphans.html): Deletes the temporaries a
+//! killed process might have left behind in a root directory.
 //!  * [Directory](struct.Directory.html): Represents a directory handle that
 //! deletes it's associated directory and all of it's contents, when dropped.
 //!
@@ -41,7 +48,7 @@
 //! #    fn temp_path() -> Result<PathBuf>;
 //! #    fn temp_file(path: &Path) -> Result<fs::File>;
 //! #    fn temp_dir(path: &Path) -> Result<Self::Directory>;
-//! #    fn unique_path_in(root: &Path, extension: Option<&str>) -> Result<PathBuf>;
+//! #    fn unique_path_in(root: &Path, prefix: &str, extension: Option<&str>) -> Result<PathBuf>;
 //! # }
 //! #[cfg(target_os = "new_platform")]
 //! mod my_platform {
@@ -73,13 +80,14 @@
 //!         }
 //!
 //!         /// Here you should provide a strategy to search for a unique path
-//!         /// inside the given root and with the given optional extension.
+//!         /// inside the given root, with a name starting with the given
+//!         /// prefix and with the given optional extension.
 //!         ///
 //!         /// You can use the general `unique_path_with_timestamp` strategy,
 //!         /// that uses a timestamp. If you use that, make sure to pass in
 //!         /// some unique thread identifier as the `extra` parameter, to keep
 //!         /// things thread-safe.
-//!         fn unique_path_in(root: &Path, extension: Option<&str>) -> Result<PathBuf> {
+//!         fn unique_path_in(root: &Path, prefix: &str, extension: Option<&str>) -> Result<PathBuf> {
 //!             // ...
 //! # unimplemented!()
 //!         }
@@ -107,6 +115,8 @@
 
 use std::io::Result;
 use std::path::{Path, PathBuf};
+use std::ffi::OsStr;
+use std::time::{Duration, SystemTime};
 use std::fs;
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -122,6 +132,12 @@ use std::fs;
 /// functionality. There are more differences than just calling
 /// `fs::create_dir(path(...))`.
 ///
+/// **Note:** The path is only unique when it's returned, another process can
+/// create something at the same path before the caller does. Creating the file
+/// or directory has to fail, if it already exists - like with
+/// `OpenOptions::create_new` or `fs::create_dir` -, or use
+/// [reserve_path](fn.reserve_path.html) to hold the name.
+///
 /// # Examples
 ///
 /// Creating a temporary directory:
@@ -172,6 +188,10 @@ pub fn path(extension: Option<&str>) -> Result<PathBuf> {
 /// functionality. There are more differences than just calling
 /// `fs::create_dir(path_in(...))`.
 ///
+/// **Note:** The path is only unique when it's returned, another process can
+/// create something at the same path before the caller does. See
+/// [path](fn.path.html) and [reserve_path_in](fn.reserve_path_in.html).
+///
 /// # Examples
 ///
 /// Creating a temporary directory inside `C:/TMP`, assuming it exists:
@@ -209,7 +229,73 @@ pub fn path(extension: Option<&str>) -> Result<PathBuf> {
 ///
 /// In case of an IO or system error, an error variant is returned.
 pub fn path_in(root: impl AsRef<Path>, extension: Option<&str>) -> Result<PathBuf> {
-    FsTempImpl::unique_path_in(root.as_ref(), extension)
+    FsTempImpl::unique_path_in(root.as_ref(), TEMP_PREFIX, extension)
+}
+
+/// Like [path_in](fn.path_in.html), but the name is not considered a temporary
+/// by [cleanup_orphans](fn.cleanup_orphans.html).
+fn persistent_path_in(root: &Path, extension: Option<&str>) -> Result<PathBuf> {
+    FsTempImpl::unique_path_in(root, PERSISTENT_PREFIX, extension)
+}
+
+/// Finds a unique path like [path](fn.path.html), and atomically creates an
+/// empty placeholder file at it, so no other process can take the name. The
+/// placeholder is not deleted automatically, the caller can overwrite or remove
+/// it.
+///
+/// # Examples
+///
+/// Reserving a name for a file, that's written later:
+///
+/// ```no_run
+/// use std::fs;
+/// use cacti_fs::temp;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let path = temp::reserve_path(Some("txt"))?;
+/// // The name is ours, even if another process is looking for one too
+/// fs::write(&path, "Hello, World!")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// In case of an IO or system error, an error variant is returned.
+pub fn reserve_path(extension: Option<&str>) -> Result<PathBuf> {
+    reserve_path_in(&FsTempImpl::temp_path()?, extension)
+}
+
+/// Finds a unique path inside the given root directory like
+/// [path_in](fn.path_in.html), and atomically creates an empty placeholder file
+/// at it, so no other process can take the name. The placeholder is not
+/// deleted automatically, and [cleanup_orphans](fn.cleanup_orphans.html)
+/// doesn't consider it a leftover either.
+///
+/// # Errors
+///
+/// In case of an IO or system error, an error variant is returned.
+pub fn reserve_path_in(root: impl AsRef<Path>, extension: Option<&str>) -> Result<PathBuf> {
+    const TRY_COUNT: usize = 16;
+
+    let root = root.as_ref();
+    let mut last_err = None;
+    for _ in 0..TRY_COUNT {
+        let path = persistent_path_in(root, extension)?;
+        match reserve_at(&path) {
+            Ok(()) => return Ok(path),
+            // Someone raced us for the path, find another one
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => last_err = Some(err),
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+/// Creates an empty file at the given path, failing if anything exists there.
+fn reserve_at(path: &Path) -> Result<()> {
+    fs::OpenOptions::new().write(true).create_new(true).open(path)?;
+    Ok(())
 }
 
 /// Tries to create a temporary file at some default place, returning it's
@@ -296,6 +382,75 @@ pub fn file_at(full_path: impl AsRef<Path>) -> Result<fs::File> {
     FsTempImpl::temp_file(full_path.as_ref())
 }
 
+/// Tries to create a file at a unique path in some default place, returning
+/// it's handle and path. An optional extension can be supplied - without the
+/// dot. Unlike [file](fn.file.html), the file is not deleted when the handle
+/// gets dropped, the caller is responsible for deleting it.
+///
+/// **Note:** The file is not named like the other temporaries, so
+/// [cleanup_orphans](fn.cleanup_orphans.html) never deletes it.
+///
+/// # Examples
+///
+/// Creating a lock file, that outlives the handle:
+///
+/// ```no_run
+/// use std::fs;
+/// use cacti_fs::temp;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let (file, path) = temp::persistent_file(Some("lock"))?;
+/// drop(file);
+/// // The file still exists, we have to delete it ourselves
+/// fs::remove_file(path)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// In case of an IO or system error, an error variant is returned.
+pub fn persistent_file(extension: Option<&str>) -> Result<(fs::File, PathBuf)> {
+    persistent_file_in(&FsTempImpl::temp_path()?, extension)
+}
+
+/// Tries to create a file at a unique path inside the given root directory,
+/// returning it's handle and path. An optional extension can be supplied -
+/// without the dot. Unlike [file_in](fn.file_in.html), the file is not deleted
+/// when the handle gets dropped, the caller is responsible for deleting it.
+/// The created file is guaranteed to be directly inside the given root
+/// directory.
+///
+/// # Examples
+///
+/// Creating a TXT file inside `C:/TMP`, assuming it exists:
+///
+/// ```no_run
+/// use std::io::Write;
+/// use cacti_fs::temp;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let (mut file, path) = temp::persistent_file_in("C:/TMP", Some("txt"))?;
+/// file.write_all("Hello, World!".as_bytes())?;
+/// println!("Written to {:?}", path);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// In case of an IO or system error, an error variant is returned.
+pub fn persistent_file_in(root: impl AsRef<Path>, extension: Option<&str>) -> Result<(fs::File, PathBuf)> {
+    let path = persistent_path_in(root.as_ref(), extension)?;
+    // Never open an existing file, in case someone raced us for the path
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    Ok((file, path))
+}
+
 /// Tries to create a temporary directory at some default place, returning it's
 /// handle. When the returned handle gets dropped, the directory and all of it's
 /// contents are deleted.
@@ -383,6 +538,69 @@ impl Directory {
     pub fn path(&self) -> &Path { self.0.path() }
 }
 
+/// Deletes the temporary files and directories inside the given root
+/// directory, that were named by this module more than `older_than` time ago.
+/// Returns the number of deleted entries.
+///
+/// Temporaries are normally deleted when their handles are closed, but if the
+/// process gets killed, some can be left behind - for example a directory
+/// that had files created inside it. This is a best-effort sweep for those,
+/// entries that can't be deleted are skipped.
+///
+/// Only the exact names this module generates are considered, so the entries
+/// of other programs sharing the root directory are left alone.
+///
+/// **Note:** The age is determined from the name, so make sure `older_than`
+/// is longer than the lifetime of any temporary that's still in use.
+///
+/// # Examples
+///
+/// Deleting the leftovers older than a day from the default temporary
+/// directory:
+///
+/// ```no_run
+/// use std::env;
+/// use std::time::Duration;
+/// use cacti_fs::temp;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let removed = temp::cleanup_orphans(env::temp_dir(), Duration::from_secs(24 * 60 * 60))?;
+/// println!("Removed {} orphaned temporaries", removed);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// In case of an IO or system error while listing the root directory, an
+/// error variant is returned.
+pub fn cleanup_orphans(root: impl AsRef<Path>, older_than: Duration) -> Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let created = match temp_name_timestamp(&entry.file_name()) {
+            Some(t) => t,
+            None => continue,
+        };
+        match now.duration_since(created) {
+            Ok(age) if age > older_than => {},
+            _ => continue,
+        }
+        let path = entry.path();
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        }
+        else {
+            fs::remove_file(&path)
+        };
+        if result.is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
 // ////////////////////////////////////////////////////////////////////////// //
 //                               Implementation                               //
 // ////////////////////////////////////////////////////////////////////////// //
@@ -405,19 +623,27 @@ trait FsTemp {
 
     /// The default unique file/directory name searching strategy for the
     /// platform. Tries to search a unique file or directory name in the given
-    /// root directory, with a given an optional extension.
-    fn unique_path_in(root: &Path, extension: Option<&str>) -> Result<PathBuf>;
+    /// root directory, starting with the given prefix, with a given an optional
+    /// extension.
+    fn unique_path_in(root: &Path, prefix: &str, extension: Option<&str>) -> Result<PathBuf>;
 }
 
+/// The prefix of the temporary names, that `cleanup_orphans` sweeps.
+const TEMP_PREFIX: &str = "cacti_tmp_";
+/// The prefix of the names, that are meant to outlive the process.
+const PERSISTENT_PREFIX: &str = "cacti_persistent_";
+/// The number of names the timestamp-based unique path-finder tries with a
+/// single timestamp.
+const NAMES_PER_TIMESTAMP: usize = 4096;
+
 // A general, timestamp-based unique path-finder.
-fn unique_path_with_timestamp<E>(root: &Path, extension: Option<&str>, extra: E) -> Result<PathBuf>
+fn unique_path_with_timestamp<E>(
+    root: &Path, name_prefix: &str, extension: Option<&str>, extra: E) -> Result<PathBuf>
     where E: std::fmt::Display {
 
     use std::io::{Error, ErrorKind};
-    use std::time::SystemTime;
 
     const TRY_COUNT: usize = 256;
-    const ITER_COUNT: usize = 4096;
 
     let postfix = extension.map(|e| format!(".{}", e)).unwrap_or_else(String::new);
     let mut path = root.to_path_buf();
@@ -428,9 +654,9 @@ fn unique_path_with_timestamp<E>(root: &Path, extension: Option<&str>, extra: E)
         let timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
         // Construct prefix
-        let prefix = format!("tmp_{}_{}_", timestamp, extra);
+        let prefix = format!("{}{}_{}_", name_prefix, timestamp, extra);
 
-        for i in 0..ITER_COUNT {
+        for i in 0..NAMES_PER_TIMESTAMP {
             // Construct the full last part
             let last_part = format!("{}{}{}", prefix, i, postfix);
             // Try to append to the path, if it's unique, we are done
@@ -447,6 +673,34 @@ fn unique_path_with_timestamp<E>(root: &Path, extension: Option<&str>, extra: E)
         format!("Could not find unique path in '{:?}'!", root)))
 }
 
+/// Returns the creation time encoded in a name produced by
+/// `unique_path_with_timestamp`, or `None`, if the name is not exactly in that
+/// format: `cacti_tmp_<timestamp>_<extra>_<counter>[.<extension>]`, where the
+/// extra part is a numeric thread or process identifier.
+fn temp_name_timestamp(name: &OsStr) -> Option<SystemTime> {
+    fn parse_digits<T: std::str::FromStr>(s: &str) -> Option<T> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    }
+
+    let name = name.to_str()?;
+    if !name.starts_with(TEMP_PREFIX) {
+        return None;
+    }
+    let mut parts = name[TEMP_PREFIX.len()..].splitn(3, '_');
+    let nanos: u64 = parse_digits(parts.next()?)?;
+    let _extra: u32 = parse_digits(parts.next()?)?;
+    let rest = parts.next()?;
+    let counter_len = rest.find('.').unwrap_or(rest.len());
+    let counter: usize = parse_digits(&rest[..counter_len])?;
+    if counter >= NAMES_PER_TIMESTAMP {
+        return None;
+    }
+    Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos))
+}
+
 // Unsupported implementation //////////////////////////////////////////////////
 
 mod unsupported {
@@ -480,7 +734,7 @@ mod unsupported {
         /// The default unique file/directory name searching strategy for the
         /// platform. Tries to search a unique file or directory name in the given
         /// root directory, with a given an optional extension.
-        fn unique_path_in(_root: &Path, _extension: Option<&str>) -> Result<PathBuf> {
+        fn unique_path_in(_root: &Path, _prefix: &str, _extension: Option<&str>) -> Result<PathBuf> {
             Err(Error::new(ErrorKind::Other,
                 "Unique paths are not supported on this platform!"))
         }
@@ -500,12 +754,13 @@ mod unsupported {
 mod win32 {
     #![allow(non_snake_case)]
 
-    use std::ffi::{OsStr, OsString, c_void};
-    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::ffi::{OsString, c_void};
+    use std::os::windows::ffi::OsStringExt;
     use std::os::windows::io::FromRawHandle;
     use std::path::{Path, PathBuf};
     use std::ptr;
     use std::io;
+    use crate::path::to_extended_wstring;
     use super::*;
 
     // Access constants
@@ -542,11 +797,6 @@ mod win32 {
         fn GetCurrentThreadId() -> u32;
     }
 
-    /// Converts the Rust &OsStr into a WinAPI `WCHAR` string.
-    fn to_wstring(s: &OsStr) -> Vec<u16> {
-        s.encode_wide().chain(Some(0).into_iter()).collect()
-    }
-
     /// The Win32 implementation of `trait FsTemp`.
     pub struct WinApiTemp;
 
@@ -576,7 +826,7 @@ mod win32 {
         }
 
         fn temp_file(path: &Path) -> Result<fs::File> {
-            let path = to_wstring(path.as_os_str());
+            let path = to_extended_wstring(path);
             let handle = unsafe { CreateFileW(
                 path.as_ptr(),
                 GENERIC_READ | GENERIC_WRITE,
@@ -593,7 +843,7 @@ mod win32 {
 
         fn temp_dir(path: &Path) -> Result<Self::Directory> {
             // First create the path
-            let wpath = to_wstring(path.as_os_str());
+            let wpath = to_extended_wstring(path);
             let result = unsafe { CreateDirectoryW(
                 wpath.as_ptr(),
                 ptr::null_mut()) };
@@ -616,10 +866,10 @@ mod win32 {
             Ok(WinApiDirectory{ handle, path: path.to_path_buf() })
         }
 
-        fn unique_path_in(root: &Path, extension: Option<&str>) -> Result<PathBuf> {
+        fn unique_path_in(root: &Path, prefix: &str, extension: Option<&str>) -> Result<PathBuf> {
             // For now we default to the generic one, appending thread-id
             let extra = unsafe{ GetCurrentThreadId() };
-            unique_path_with_timestamp(root, extension, extra)
+            unique_path_with_timestamp(root, prefix, extension, extra)
         }
     }
 
@@ -693,10 +943,10 @@ mod unix {
             Ok(UnixDirectory(path.to_path_buf()))
         }
 
-        fn unique_path_in(root: &Path, extension: Option<&str>) -> Result<PathBuf> {
+        fn unique_path_in(root: &Path, prefix: &str, extension: Option<&str>) -> Result<PathBuf> {
             // For now we default to the generic one, appending thread-id
             let extra = unsafe{ getpid() };
-            unique_path_with_timestamp(root, extension, extra)
+            unique_path_with_timestamp(root, prefix, extension, extra)
         }
     }
 
@@ -729,6 +979,7 @@ mod tests {
     use super::*;
     use crate::path::FilePath;
     use std::ffi::{OsStr, OsString};
+    use std::io::Write;
 
     fn starts_with(s: &OsStr, pat: &str) -> bool {
         s.to_str().unwrap().starts_with(pat)
@@ -819,6 +1070,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_persistent_file() -> Result<()> {
+        let path;
+        {
+            let (mut file, p) = persistent_file_in(".", Some("txt"))?;
+            path = p;
+            assert_eq!(
+                fs::canonicalize(path.parent().unwrap())?,
+                fs::canonicalize(".")?
+            );
+            assert!(starts_with(path.extension().unwrap(), "txt"));
+            file.write_all(b"Hello")?;
+        }
+        assert_eq!(fs::read(&path)?, b"Hello");
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
     #[test]
     fn test_directory() -> Result<()> {
         let path;
@@ -895,4 +1164,128 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_directory_beyond_max_path() -> Result<()> {
+        let mut dirs = vec![directory()?];
+        while dirs.last().unwrap().path().as_os_str().len() <= 300 {
+            let dir = directory_in(dirs.last().unwrap().path())?;
+            dirs.push(dir);
+        }
+        {
+            let _file = file_in(dirs.last().unwrap().path(), Some("txt"))?;
+        }
+        // Delete the innermost first
+        while let Some(dir) = dirs.pop() {
+            let path = dir.path().to_path_buf();
+            drop(dir);
+            assert!(!path.exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve_path() -> Result<()> {
+        let root = directory()?;
+        let path = reserve_path_in(root.path(), Some("txt"))?;
+        assert!(path.is_file());
+        assert_eq!(fs::metadata(&path)?.len(), 0);
+        assert_eq!(path.parent().unwrap(), root.path());
+        // Not reserved twice
+        assert_ne!(reserve_path_in(root.path(), Some("txt"))?, path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve_path_concurrent() -> Result<()> {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        const THREADS: usize = 8;
+
+        let root = directory()?;
+        let barrier = Arc::new(Barrier::new(THREADS));
+        // The same name, only one of them can get it
+        let contested = root.path().join("contested.txt");
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let barrier = barrier.clone();
+            let root = root.path().to_path_buf();
+            let contested = contested.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                let won = reserve_at(&contested).is_ok();
+                let paths: Vec<_> = (0..16)
+                    .map(|_| reserve_path_in(&root, None).unwrap())
+                    .collect();
+                (won, paths)
+            })
+        }).collect();
+
+        let mut winners = 0;
+        let mut paths = Vec::new();
+        for t in threads {
+            let (won, p) = t.join().unwrap();
+            winners += won as usize;
+            paths.extend(p);
+        }
+        assert_eq!(winners, 1);
+        let count = paths.len();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), count);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_orphans() -> Result<()> {
+        let root = directory()?;
+        // Stale ones, from the seventies
+        let stale_dir = root.path().join("cacti_tmp_1000_1_0");
+        fs::create_dir(&stale_dir)?;
+        fs::File::create(stale_dir.join("foo.txt"))?;
+        fs::File::create(root.path().join("cacti_tmp_1000_1_1.txt"))?;
+        // Fresh one
+        let fresh = path_in(root.path(), None)?;
+        fs::create_dir(&fresh)?;
+        // Not ours, even if they look similar
+        let foreign = [
+            "tmp_cache", "tmp_123_foo", "tmp_1000_1_0", "cacti_tmp_1000_1_x",
+            "cacti_tmp_1000_a_0", "cacti_tmp_1000_1_4096", "cacti_tmp_1000_1_0_backup",
+        ];
+        for name in &foreign {
+            fs::create_dir(root.path().join(name))?;
+        }
+        fs::File::create(root.path().join("other.txt"))?;
+
+        let removed = cleanup_orphans(root.path(), Duration::from_secs(60 * 60))?;
+        assert_eq!(removed, 2);
+        assert!(!stale_dir.exists());
+        assert!(!root.path().join("cacti_tmp_1000_1_1.txt").exists());
+        assert!(fresh.exists());
+        for name in &foreign {
+            assert!(root.path().join(name).exists());
+        }
+        assert!(root.path().join("other.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_keeps_persistent() -> Result<()> {
+        let root = directory()?;
+        let (file, persistent) = persistent_file_in(root.path(), Some("lock"))?;
+        drop(file);
+        let reserved = reserve_path_in(root.path(), None)?;
+        let temporary = path_in(root.path(), None)?;
+        fs::create_dir(&temporary)?;
+        // Let everything age a bit
+        std::thread::sleep(Duration::from_millis(10));
+
+        let removed = cleanup_orphans(root.path(), Duration::from_millis(1))?;
+        assert_eq!(removed, 1);
+        assert!(!temporary.exists());
+        assert!(persistent.exists());
+        assert!(reserved.exists());
+        Ok(())
+    }
 }
@@ -122,12 +122,15 @@
 
 // TODO: Platform-specific docs
 
-use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
-use std::collections::{HashMap, VecDeque};
+use std::ffi::OsStr;
+use std::path::{Components, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use std::io::Result;
 use std::fs;
 use std::io;
+use std::mem;
 
 // ////////////////////////////////////////////////////////////////////////// //
 //                                    API                                     //
@@ -190,6 +193,18 @@ pub trait Watch: Sized {
     /// error is returned, the passed in path is not added to the watchlist.
     fn watch(&mut self, p: impl AsRef<Path>, rec: Recursion) -> Result<()>;
 
+    /// Starts watching all the given `Path`s with their recursion settings,
+    /// like calling `watch` for each of them. A failing path doesn't stop the
+    /// rest from being watched, the result of each path is returned in the
+    /// same order as the paths.
+    ///
+    /// Implementations can override this to watch the paths more efficiently
+    /// together, like sharing a subscription between files in the same
+    /// directory.
+    fn watch_many(&mut self, paths: &[(PathBuf, Recursion)]) -> Vec<Result<()>> {
+        paths.iter().map(|(p, rec)| self.watch(p, *rec)).collect()
+    }
+
     /// Stops watching a given `Path`.
     fn unwatch(&mut self, p: impl AsRef<Path>);
 
@@ -252,6 +267,11 @@ impl Event {
     fn delete(time: SystemTime, path: impl AsRef<Path>) -> Self {
         Self::new(time, path, EventKind::Delete)
     }
+
+    /// Creates a new `Event` with kind `EventKind::Overflow`.
+    fn overflow(time: SystemTime, path: impl AsRef<Path>) -> Self {
+        Self::new(time, path, EventKind::Overflow)
+    }
 }
 
 /// The operations that can be done on a path.
@@ -263,11 +283,118 @@ pub enum EventKind {
     Modify,
     /// The watched path has something deleted.
     Delete,
+    /// Changes were dropped, because too many of them happened at once. The
+    /// path is the watched path that was affected, and nothing is known about
+    /// what changed under it, so the consumer should rescan it - for example
+    /// with `snapshot` - to get back in sync.
+    ///
+    /// Native backends produce this, when the system drops notifications, the
+    /// polling ones, when the number of queued `Event`s is limited.
+    Overflow,
 }
 
 /// The default, recommended `Watch` implementation for the platform.
 pub type DefaultWatch = DefaultWatchImpl;
 
+/// A point-in-time capture of the watched paths, that can be compared to a
+/// later one. This is useful for detecting changes without a live event
+/// stream, even across process restarts, as the entries can be iterated and
+/// collected back into a `Snapshot`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cacti_fs::watch::*;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut watch = PollWatch::new()?;
+/// watch.watch("C:/foo", Recursion::Recursive)?;
+/// let before = watch.snapshot();
+///
+/// // ... some time later, after polling the events ...
+/// while let Some(_) = watch.poll_event() { }
+///
+/// for ev in before.diff(&watch.snapshot()) {
+///     println!("{:?} happened to {:?}", ev.kind, ev.path);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    entries: HashMap<PathBuf, SnapshotEntry>,
+}
+
+/// The captured state of a single path in a `Snapshot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    /// The last modification time of the path.
+    pub mod_time: SystemTime,
+    /// `true`, if the path is a directory.
+    pub is_dir: bool,
+}
+
+impl Snapshot {
+    /// Returns the number of paths in this `Snapshot`.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true`, if this `Snapshot` contains no paths.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the captured state of the given path, if it existed.
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&SnapshotEntry> {
+        self.entries.get(path.as_ref())
+    }
+
+    /// Returns an iterator over all the captured paths and their states.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &SnapshotEntry)> {
+        self.entries.iter().map(|(p, e)| (p.as_path(), e))
+    }
+
+    /// Returns the `Event`s that lead from this `Snapshot` to the `other`,
+    /// newer one. Deletions come first, children before their parents, then
+    /// creations and modifications, parents before their children.
+    ///
+    /// A path that changed between being a file and a directory is reported
+    /// as a deletion and a creation.
+    pub fn diff(&self, other: &Snapshot) -> Vec<Event> {
+        let now = SystemTime::now();
+
+        let mut deleted: Vec<_> = self.entries.iter()
+            .filter(|(p, e)| match other.entries.get(*p) {
+                Some(o) => o.is_dir != e.is_dir,
+                None => true,
+            })
+            .map(|(p, _)| p)
+            .collect();
+        deleted.sort_by(|a, b| b.cmp(a));
+
+        let mut changed: Vec<_> = other.entries.iter()
+            .filter_map(|(p, o)| match self.entries.get(p) {
+                Some(e) if e.is_dir == o.is_dir =>
+                    if e.mod_time != o.mod_time { Some((p, o, EventKind::Modify)) } else { None },
+                _ => Some((p, o, EventKind::Create)),
+            })
+            .collect();
+        changed.sort_by(|a, b| a.0.cmp(b.0));
+
+        deleted.into_iter()
+            .map(|p| Event::delete(now, p))
+            .chain(changed.into_iter().map(|(p, o, kind)| Event::new(o.mod_time, p, kind)))
+            .collect()
+    }
+}
+
+impl std::iter::FromIterator<(PathBuf, SnapshotEntry)> for Snapshot {
+    fn from_iter<I: IntoIterator<Item = (PathBuf, SnapshotEntry)>>(iter: I) -> Self {
+        Self{ entries: iter.into_iter().collect() }
+    }
+}
+
 // ////////////////////////////////////////////////////////////////////////// //
 //                               Implementation                               //
 // ////////////////////////////////////////////////////////////////////////// //
@@ -307,15 +434,196 @@ impl Watch for NullWatch {
 /// miss events, if the polling doesn't happen often enough. At least one change
 /// event will still arise, so this could still be fine for detecting if there
 /// was a change.
+///
+/// Overlapping watches are deduplicated: a watched path that lies inside a
+/// recursively watched directory is only scanned as part of that directory, so
+/// every change is reported once. The inner watch stays registered, and takes
+/// over when the outer one is unwatched. Paths are compared as they are stored,
+/// enable `set_canonicalize` to also catch different spellings of them.
+///
+/// Watches are keyed by path, so renaming a watched path looks like deleting
+/// it. See `set_follow_inode` for following the renames instead.
+///
+/// The watched paths are scanned in the order they were first watched, so the
+/// events of the different paths are queued in a reproducible order.
+///
+/// Listing the entries of a directory is the expensive part of a scan, so a
+/// recursively watched directory is only listed again, when its modification
+/// time changed, which happens when entries are added, removed or renamed in
+/// it. The entries that are already known are still checked on every scan, as
+/// modifying a file doesn't touch its directory.
+///
+/// Use `watch_glob` to only track the files matching a pattern in a
+/// directory tree.
 #[derive(Debug)]
 pub struct PollWatch {
     last_time: SystemTime,
     interval: Duration,
-    events: VecDeque<Result<Event>>,
+    canonicalize: bool,
+    track_metadata: bool,
+    follow_inode: bool,
+    max_age: Option<Duration>,
+    events: EventQueue,
     watched: HashMap<PathBuf, FileState>,
+    // The watched paths in the order they were watched, to scan them in a
+    // stable order
+    order: Vec<PathBuf>,
+    // Open handles of the watched paths, when following renames
+    handles: HashMap<PathBuf, fs::File>,
+    // The patterns of the watched paths, that are limited to them
+    globs: HashMap<PathBuf, Glob>,
+    // The watched paths, that are scanned by an outer watch already
+    covered: HashSet<PathBuf>,
 }
 
 impl PollWatch {
+    /// Sets, if the watched paths should be canonicalized. When enabled,
+    /// different spellings of the same path - like `./foo` and `foo` - refer
+    /// to the same watched entry, and `Event`s report canonical paths. Only
+    /// affects paths passed to `watch` and `unwatch` after the call. Disabled
+    /// by default, in which case paths are used as they were given.
+    pub fn set_canonicalize(&mut self, canonicalize: bool) {
+        self.canonicalize = canonicalize;
+    }
+
+    /// Sets, if metadata-only changes should produce `EventKind::Modify`
+    /// `Event`s. By default only the modification time is compared, which
+    /// doesn't change when only the permissions, the owner or the number of
+    /// hard links change. When enabled, the status change time (ctime) is
+    /// compared too. Disabled by default.
+    ///
+    /// **Note:** The status change time is only available on Unix platforms,
+    /// elsewhere this setting has no effect.
+    pub fn set_track_metadata_changes(&mut self, track: bool) {
+        self.track_metadata = track;
+    }
+
+    /// Sets, if the watched paths should be followed, when they are renamed.
+    /// A handle is kept open for each watched path, and when the path
+    /// disappears, the handle is asked for its current location with
+    /// `FilePath`. If the same file or directory is found there, the watch
+    /// moves to the new path: an `EventKind::Delete` `Event` is logged for the
+    /// old path, an `EventKind::Create` for the new one, and the changes are
+    /// reported under the new path from then on. Only affects paths passed to
+    /// `watch` after the call. Disabled by default.
+    ///
+    /// After a rename, the watch has to be unwatched by its new path.
+    ///
+    /// **Note:** This is only supported on Unix platforms, elsewhere this
+    /// setting has no effect.
+    pub fn set_follow_inode(&mut self, follow: bool) {
+        self.follow_inode = follow;
+        if !follow {
+            self.handles.clear();
+        }
+    }
+
+    /// Sets the maximum number of queued `Event`s. When a scan detects more
+    /// changes, than what fits, the oldest `Event`s are dropped as the new
+    /// ones arrive, and an `EventKind::Overflow` `Event` is returned before
+    /// the rest for each watched path, that lost changes. The overflows don't
+    /// count towards the maximum, so with 0 nothing but them is queued.
+    /// Unbounded by default.
+    pub fn set_max_queued_events(&mut self, max: usize) {
+        self.events.set_max(Some(max));
+    }
+
+    /// Sets the maximum age of the queued `Event`s. After every scan, the
+    /// `Event`s that were detected longer ago than that are dropped, so a
+    /// consumer that polls rarely only sees the recent changes. The age is
+    /// counted from the scan that detected the change, not from the time the
+    /// `Event` reports. Errors are never dropped. `None` means no limit, which
+    /// is the default.
+    pub fn set_max_event_age(&mut self, max_age: Option<Duration>) {
+        self.max_age = max_age;
+    }
+
+    /// Watches the given path like `watch`, but only tracks and reports the
+    /// paths below it, that match the given glob pattern. The pattern is
+    /// matched against the path relative to `root`, using `/` as a separator.
+    /// It supports `*` for any number of characters and `?` for a single
+    /// character in a path component, and `**` for any number of components.
+    /// For example `**/*.rs` matches every Rust source in the tree.
+    ///
+    /// Directories are still traversed to find the matching files, but the
+    /// files that don't match are not tracked at all. The creation and the
+    /// deletion of `root` itself are always reported. Watching the same path
+    /// again replaces the pattern.
+    pub fn watch_glob(&mut self, root: impl AsRef<Path>, pattern: &str, rec: Recursion) -> Result<()> {
+        self.watch_internal(root.as_ref(), Some(pattern), rec)
+    }
+
+    /// Starts watching the given path, limited to the given pattern, if any.
+    fn watch_internal(&mut self, p: &Path, pattern: Option<&str>, rec: Recursion) -> Result<()> {
+        let p = self.watched_key(p);
+        let glob = pattern.map(|pattern| Glob::new(&p, pattern));
+        self.events.set_root(Some(&p), None);
+        let state = FileState::new(&p, rec, glob.as_ref(), &mut self.events);
+        self.events.set_root(None, None);
+        self.handles.remove(&p);
+        if self.follow_inode {
+            if let Some(handle) = open_handle(&p) {
+                self.handles.insert(p.clone(), handle);
+            }
+        }
+        match glob {
+            Some(glob) => self.globs.insert(p.clone(), glob),
+            None => self.globs.remove(&p),
+        };
+        if self.watched.insert(p.clone(), state).is_none() {
+            self.order.push(p);
+        }
+        self.update_covered();
+        Ok(())
+    }
+
+    /// Drops the `Event`s, that were detected longer ago than allowed.
+    fn prune_events(&mut self) {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return,
+        };
+        let now = Instant::now();
+        self.events.entries.retain(|(detected, _, e)| e.is_err() || now.duration_since(*detected) <= max_age);
+    }
+
+    /// Returns the key of the given path in the watched paths, considering
+    /// the canonicalization setting.
+    fn watched_key(&self, path: &Path) -> PathBuf {
+        if !self.canonicalize {
+            return path.to_path_buf();
+        }
+        if let Ok(path) = fs::canonicalize(path) {
+            return path;
+        }
+        // The path doesn't exist (yet), we try with the parent
+        if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            if let Ok(parent) = fs::canonicalize(parent) {
+                return parent.join(name);
+            }
+        }
+        path.to_path_buf()
+    }
+
+    /// Returns `true`, if the given watched path is inside another watched
+    /// path, that is watched recursively, meaning that the outer watch scans
+    /// it already. Watches limited to a pattern don't cover anything, as they
+    /// skip the files not matching it.
+    fn is_covered(&self, path: &Path) -> bool {
+        path.ancestors().skip(1).any(|p| match self.watched.get(p) {
+            Some(state) => state.rec() == Recursion::Recursive && !self.globs.contains_key(p),
+            None => false,
+        })
+    }
+
+    /// Recomputes the watched paths, that are covered by another watch. Has
+    /// to be called, whenever the watched paths change.
+    fn update_covered(&mut self) {
+        let covered = self.watched.keys().filter(|p| self.is_covered(p)).cloned().collect();
+        self.covered = covered;
+    }
+
     /// Returns `true`, if a scan should happen, because enough time has
     /// elapsed.
     fn should_update(&mut self) -> bool {
@@ -339,15 +647,94 @@ impl PollWatch {
         }
     }
 
+    /// Captures the state of the watched paths in a `Snapshot`. The state is
+    /// the one seen by the last scan, so poll the pending `Event`s first for
+    /// an up-to-date capture.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut result = Snapshot::default();
+        for (p, state) in &self.watched {
+            if self.covered.contains(p) {
+                continue;
+            }
+            match self.globs.get(p) {
+                Some(glob) => {
+                    let mut part = Snapshot::default();
+                    state.snapshot(p, &mut part);
+                    result.entries.extend(part.entries.into_iter().filter(|(p, _)| glob.matches(p)));
+                },
+                None => state.snapshot(p, &mut result),
+            }
+        }
+        result
+    }
+
+    /// Moves the watches of the renamed paths to their new location, based on
+    /// the kept handles.
+    fn follow_renames(&mut self) {
+        let mut renamed = Vec::new();
+        for (p, handle) in &self.handles {
+            if let Ok(Some(_)) = FileState::metadata(p) {
+                // Still there
+                continue;
+            }
+            if let Some(new_path) = renamed_path(handle, p) {
+                renamed.push((p.clone(), new_path));
+            }
+        }
+        let renamed_any = !renamed.is_empty();
+        for (old_path, new_path) in renamed {
+            let handle = self.handles.remove(&old_path).unwrap();
+            let rec = match self.watched.remove(&old_path) {
+                Some(state) => state.rec(),
+                None => continue,
+            };
+            let now = SystemTime::now();
+            self.events.set_root(Some(&new_path), None);
+            self.events.push_back(Ok(Event::delete(now, &old_path)));
+            self.events.push_back(Ok(Event::create(now, &new_path)));
+            let glob = self.globs.remove(&old_path).map(|glob| glob.with_root(&new_path));
+            let state = FileState::new(&new_path, rec, glob.as_ref(), &mut self.events);
+            self.events.set_root(None, None);
+            if let Some(glob) = glob {
+                self.globs.insert(new_path.clone(), glob);
+            }
+            self.watched.insert(new_path.clone(), state);
+            // The renamed watch keeps its place in the scan order
+            self.order.retain(|p| *p != new_path);
+            if let Some(p) = self.order.iter_mut().find(|p| **p == old_path) {
+                *p = new_path.clone();
+            }
+            self.handles.insert(new_path, handle);
+        }
+        if renamed_any {
+            self.update_covered();
+        }
+    }
+
     /// Updates the `FileState`s, if enough time has elapsed.
     fn update(&mut self) {
         if !self.should_update() {
             return;
         }
 
-        for (p, state) in &mut self.watched {
-            state.update(p, &mut self.events);
+        self.follow_renames();
+        let track_metadata = self.track_metadata;
+        // Roots are scanned in the order they were watched, so the order of
+        // the events is reproducible
+        for p in &self.order {
+            if self.covered.contains(p) {
+                // Scanned by the outer watch
+                continue;
+            }
+            let state = self.watched.get_mut(p).unwrap();
+            let glob = self.globs.get(p);
+            // Directories are tracked to find the matching files, but their
+            // changes are only reported, if they match too
+            self.events.set_root(Some(p), glob);
+            state.update(p, track_metadata, glob, &mut self.events);
         }
+        self.events.set_root(None, None);
+        self.prune_events();
     }
 }
 
@@ -357,20 +744,45 @@ impl Watch for PollWatch {
         Ok(Self{
             last_time: SystemTime::UNIX_EPOCH,
             interval: Duration::from_secs(1),
-            events: VecDeque::new(),
+            canonicalize: false,
+            track_metadata: false,
+            follow_inode: false,
+            max_age: None,
+            events: EventQueue::default(),
             watched: HashMap::new(),
+            order: Vec::new(),
+            handles: HashMap::new(),
+            globs: HashMap::new(),
+            covered: HashSet::new(),
         })
     }
 
     fn watch(&mut self, p: impl AsRef<Path>, rec: Recursion) -> Result<()> {
-        let p = p.as_ref();
-        let state = FileState::new(p, rec, &mut self.events);
-        self.watched.insert(p.to_path_buf(), state);
-        Ok(())
+        self.watch_internal(p.as_ref(), None, rec)
     }
 
     fn unwatch(&mut self, p: impl AsRef<Path>) {
-        self.watched.remove(p.as_ref());
+        let p = self.watched_key(p.as_ref());
+        self.handles.remove(&p);
+        self.globs.remove(&p);
+        if self.watched.remove(&p).is_none() {
+            return;
+        }
+        self.order.retain(|q| *q != p);
+        let covered = mem::take(&mut self.covered);
+        self.update_covered();
+        // Watches that were covered by the removed one have a stale state,
+        // start them over without logging anything
+        for q in covered {
+            if q == p || self.covered.contains(&q) {
+                continue;
+            }
+            let rec = self.watched[&q].rec();
+            self.events.set_root(Some(&q), None);
+            let state = FileState::new(&q, rec, self.globs.get(&q), &mut self.events);
+            self.watched.insert(q, state);
+        }
+        self.events.set_root(None, None);
     }
 
     fn poll_event(&mut self) -> Option<Result<Event>> {
@@ -384,6 +796,135 @@ impl Watch for PollWatch {
     }
 }
 
+/// The error code for a path component not being a directory.
+#[cfg(unix)]
+const ENOTDIR: i32 = 20;
+
+/// Opens a handle to the given path, that follows it across renames.
+#[cfg(unix)]
+fn open_handle(path: &Path) -> Option<fs::File> {
+    // Directories can be opened for reading too
+    fs::File::open(path).ok()
+}
+
+/// Opens a handle to the given path, that follows it across renames.
+#[cfg(not(unix))]
+fn open_handle(_path: &Path) -> Option<fs::File> {
+    None
+}
+
+/// Returns the new path of a watched path, that has been renamed, based on
+/// its open handle. Returns `None`, if it has been deleted instead.
+#[cfg(unix)]
+fn renamed_path(handle: &fs::File, old_path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    use crate::path::FilePath;
+
+    let new_path = handle.path().ok()?;
+    if new_path == old_path {
+        // Deleted, the handle reports its last path
+        return None;
+    }
+    // Make sure it's the same file, not one only reported with the same name
+    let old_meta = handle.metadata().ok()?;
+    let new_meta = fs::metadata(&new_path).ok()?;
+    if old_meta.dev() != new_meta.dev() || old_meta.ino() != new_meta.ino() {
+        return None;
+    }
+    Some(new_path)
+}
+
+/// Returns the new path of a watched path, that has been renamed, based on
+/// its open handle. Returns `None`, if it has been deleted instead.
+#[cfg(not(unix))]
+fn renamed_path(_handle: &fs::File, _old_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// The `Event`s queued by a `PollWatch`, each stamped with the time it was
+/// detected at, to tell its age.
+#[derive(Debug, Default)]
+struct EventQueue {
+    // The changes with their detection time and the watched path they belong to
+    entries: VecDeque<(Instant, Option<Arc<Path>>, Result<Event>)>,
+    // The maximum number of entries, the oldest ones are dropped to make room
+    max: Option<usize>,
+    // The `EventKind::Overflow` `Event`s of the watched paths, that lost
+    // changes since the last poll
+    overflows: VecDeque<Event>,
+    // The watched path the pushed changes belong to
+    root: Option<Arc<Path>>,
+    // Only the `Event`s of the matching paths are queued, if set
+    filter: Option<Glob>,
+}
+
+impl EventQueue {
+    /// Sets the watched path, that the pushed changes belong to, and the
+    /// pattern limiting them, if any.
+    fn set_root(&mut self, root: Option<&Path>, filter: Option<&Glob>) {
+        self.root = root.map(Arc::from);
+        self.filter = filter.cloned();
+    }
+
+    /// Queues a change, that was detected right now, dropping the oldest one,
+    /// if the queue is full.
+    fn push_back(&mut self, event: Result<Event>) {
+        if let (Some(glob), Ok(e)) = (&self.filter, &event) {
+            if !glob.reports(e) {
+                return;
+            }
+        }
+        let root = self.root.clone();
+        match self.max {
+            Some(0) => {
+                self.overflow(root.as_deref());
+                return;
+            },
+            Some(max) if self.entries.len() >= max => {
+                if let Some((_, root, _)) = self.entries.pop_front() {
+                    self.overflow(root.as_deref());
+                }
+            },
+            _ => {},
+        }
+        self.entries.push_back((Instant::now(), root, event));
+    }
+
+    /// Takes the oldest queued change. If changes were dropped, the overflows
+    /// reporting that come first.
+    fn pop_front(&mut self) -> Option<Result<Event>> {
+        if let Some(e) = self.overflows.pop_front() {
+            return Some(Ok(e));
+        }
+        self.entries.pop_front().map(|(_, _, e)| e)
+    }
+
+    /// Sets the maximum number of queued changes, dropping the oldest ones,
+    /// that don't fit anymore.
+    fn set_max(&mut self, max: Option<usize>) {
+        self.max = max;
+        if let Some(max) = max {
+            while self.entries.len() > max {
+                if let Some((_, root, _)) = self.entries.pop_front() {
+                    self.overflow(root.as_deref());
+                }
+            }
+        }
+    }
+
+    /// Records, that a change of the given watched path was dropped.
+    fn overflow(&mut self, root: Option<&Path>) {
+        // Changes are only pushed while a watched path is being scanned
+        let root = match root {
+            Some(root) => root,
+            None => return,
+        };
+        if !self.overflows.iter().any(|e| e.path == root) {
+            self.overflows.push_back(Event::overflow(SystemTime::now(), root));
+        }
+    }
+}
+
 /// File states for the `PollWatch`.
 #[derive(Debug)]
 enum FileState {
@@ -393,15 +934,41 @@ enum FileState {
     ExistingFile{
         rec: Recursion,
         mod_time: SystemTime,
+        change_time: Option<SystemTime>,
     },
     ExistingDirectory{
         rec: Recursion,
         mod_time: SystemTime,
+        change_time: Option<SystemTime>,
+        // The modification time the entries were listed at, if the listing
+        // can be trusted to be up to date as long as it doesn't change
+        listed: Option<SystemTime>,
         substates: HashMap<PathBuf, FileState>,
     }
 }
 
+/// Modification times of directories, that are younger than this at the time
+/// of listing, are not trusted to change with every new entry. Timestamps can
+/// be coarse - up to 2 seconds on FAT -, so an entry created right after the
+/// listing could leave the modification time as it was.
+const RACY_LISTING_WINDOW: Duration = Duration::from_secs(2);
+
+#[cfg(test)]
+thread_local! {
+    // The number of directory listings on this thread, to test skipping them
+    static READ_DIR_COUNT: std::cell::Cell<usize> = std::cell::Cell::default();
+}
+
 impl FileState {
+    /// Returns the recursion strategy of this `FileState`.
+    fn rec(&self) -> Recursion {
+        match self {
+            Self::NotExisting{ rec }
+            | Self::ExistingFile{ rec, .. }
+            | Self::ExistingDirectory{ rec, .. } => *rec,
+        }
+    }
+
     /// Returns the creation-time of a path, jumping through the `Result` chain.
     fn ctime(path: impl AsRef<Path>) -> Result<SystemTime> {
         let i = fs::metadata(path)?;
@@ -414,13 +981,111 @@ impl FileState {
         fs::metadata(path).and_then(|i| i.modified())
     }
 
+    /// Returns the metadata of a path, or `None`, if it doesn't exist. Other
+    /// errors - like a momentary lock or a permission problem - don't mean
+    /// that the path is gone, so they are returned to be retried later.
+    fn metadata(path: &Path) -> Result<Option<fs::Metadata>> {
+        match fs::metadata(path) {
+            Ok(meta) => Ok(Some(meta)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            // A parent got replaced by a file
+            #[cfg(unix)]
+            Err(err) if err.raw_os_error() == Some(ENOTDIR) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the status change-time of a path, if the platform exposes it.
+    #[cfg(unix)]
+    fn change_time(path: impl AsRef<Path>) -> Option<SystemTime> {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = fs::metadata(path).ok()?;
+        let since_epoch = Duration::new(meta.ctime() as u64, meta.ctime_nsec() as u32);
+        Some(SystemTime::UNIX_EPOCH + since_epoch)
+    }
+
+    /// Returns the status change-time of a path, if the platform exposes it.
+    #[cfg(not(unix))]
+    fn change_time(_path: impl AsRef<Path>) -> Option<SystemTime> {
+        None
+    }
+
+    /// Lists the entries of a directory.
+    fn read_dir(path: &Path) -> Result<fs::ReadDir> {
+        #[cfg(test)]
+        READ_DIR_COUNT.with(|c| c.set(c.get() + 1));
+        fs::read_dir(path)
+    }
+
+    /// Returns the modification time of a directory, that's about to be
+    /// listed, if it's old enough to tell that the listing is up to date, as
+    /// long as the modification time stays the same.
+    fn listed_time(meta: &fs::Metadata) -> Option<SystemTime> {
+        let mtime = meta.modified().ok()?;
+        let age = SystemTime::now().duration_since(mtime).ok()?;
+        if age > RACY_LISTING_WINDOW { Some(mtime) } else { None }
+    }
+
+    /// Checks the modification date of a path, logging a modification, if it
+    /// changed. If `track_metadata` is `true`, the status change-time is
+    /// checked too.
+    fn check_modified(
+        path: &Path,
+        mod_time: &mut SystemTime,
+        change_time: &mut Option<SystemTime>,
+        track_metadata: bool,
+        events: &mut EventQueue,
+    ) {
+        let mut modified = false;
+        if let Ok(mtime) = Self::mtime(path) {
+            if mtime > *mod_time {
+                events.push_back(Ok(Event::modify(mtime, path)));
+                *mod_time = mtime;
+                modified = true;
+            }
+        }
+        if !track_metadata {
+            return;
+        }
+        if let Some(ctime) = Self::change_time(path) {
+            if Some(ctime) != *change_time {
+                // Content changes also touch the change-time, don't log twice
+                if !modified {
+                    events.push_back(Ok(Event::modify(ctime, path)));
+                }
+                *change_time = Some(ctime);
+            }
+        }
+    }
+
+    /// Returns `true`, if the given entry of a directory should be tracked.
+    /// Only the entries matching the filter are tracked, and the directories,
+    /// that could contain matching ones.
+    fn is_tracked(entry: &fs::DirEntry, filter: Option<&Glob>) -> bool {
+        let glob = match filter {
+            Some(glob) => glob,
+            None => return true,
+        };
+        if glob.matches(&entry.path()) {
+            return true;
+        }
+        // The type comes with the listing on most platforms, only symlinks
+        // need a lookup to see, where they point
+        match entry.file_type() {
+            Ok(t) if !t.is_symlink() => t.is_dir(),
+            _ => entry.path().is_dir(),
+        }
+    }
+
     /// Creates a `FileState`, only logging errors.
     fn new(
         path: impl AsRef<Path>,
         rec: Recursion,
-        events: &mut VecDeque<Result<Event>>,
+        filter: Option<&Glob>,
+        events: &mut EventQueue,
     ) -> Self {
-        Self::new_internal(path, rec, false, events)
+        Self::new_internal(path, rec, false, filter, events)
     }
 
     /// Creates a `FileState` assuming that the given path has been watched
@@ -428,23 +1093,32 @@ impl FileState {
     fn new_created(
         path: impl AsRef<Path>,
         rec: Recursion,
-        events: &mut VecDeque<Result<Event>>,
+        filter: Option<&Glob>,
+        events: &mut EventQueue,
     ) -> Self {
-        Self::new_internal(path, rec, true, events)
+        Self::new_internal(path, rec, true, filter, events)
     }
 
     /// Creates a `FileState` for the given path, while logging errors. If
-    /// `log_create` is `true`, creation `Event`s are also logged.
+    /// `log_create` is `true`, creation `Event`s are also logged. Entries of
+    /// directories are only tracked, if they pass the `filter`.
     fn new_internal(
         path: impl AsRef<Path>,
         rec: Recursion,
         log_create: bool,
-        events: &mut VecDeque<Result<Event>>,
+        filter: Option<&Glob>,
+        events: &mut EventQueue,
     ) -> Self {
         let path = path.as_ref();
-        if !path.exists() {
-            return Self::NotExisting{ rec };
-        }
+        let meta = match Self::metadata(path) {
+            Ok(Some(meta)) => meta,
+            Ok(None) => return Self::NotExisting{ rec },
+            Err(err) => {
+                // Log error, the next scan retries
+                events.push_back(Err(err));
+                return Self::NotExisting{ rec };
+            },
+        };
         // Exists
         let mod_time = (if log_create { Self::ctime(path) } else { Self::mtime(path) })
             .unwrap_or_else(|_| SystemTime::now());
@@ -452,14 +1126,17 @@ impl FileState {
             // Log that it got created
             events.push_back(Ok(Event::create(mod_time, path)));
         }
-        if path.is_file() {
-            return Self::ExistingFile{ rec, mod_time };
+        let change_time = Self::change_time(path);
+        if meta.is_file() {
+            return Self::ExistingFile{ rec, mod_time, change_time };
         }
         // Directory
         let mut substates = HashMap::new();
+        let mut listed = None;
         if rec == Recursion::Recursive {
             // Create recursively
-            let subdirs = fs::read_dir(path);
+            listed = Self::listed_time(&meta);
+            let subdirs = Self::read_dir(path);
             if subdirs.is_err() {
                 // Log error
                 events.push_back(Err(subdirs.unwrap_err()));
@@ -471,74 +1148,98 @@ impl FileState {
                         events.push_back(Err(subdir.unwrap_err()));
                     }
                     else {
-                        let subpath = subdir.unwrap().path();
-                        let substate = Self::new_internal(&subpath, rec, log_create, events);
+                        let subdir = subdir.unwrap();
+                        if !Self::is_tracked(&subdir, filter) {
+                            continue;
+                        }
+                        let subpath = subdir.path();
+                        let substate = Self::new_internal(&subpath, rec, log_create, filter, events);
                         substates.insert(subpath, substate);
                     }
                 }
             }
         }
-        return Self::ExistingDirectory{ rec, mod_time, substates };
+        return Self::ExistingDirectory{ rec, mod_time, change_time, listed, substates };
     }
 
     /// Updates this `FileState` at the given path.
-    fn update(&mut self, path: impl AsRef<Path>, events: &mut VecDeque<Result<Event>>) {
+    fn update(
+        &mut self,
+        path: impl AsRef<Path>,
+        track_metadata: bool,
+        filter: Option<&Glob>,
+        events: &mut EventQueue,
+    ) {
         let path = path.as_ref();
         match self {
             Self::NotExisting{ rec } => {
-                if path.exists() {
+                if let Ok(Some(_)) = Self::metadata(path) {
                     // Update state while logging everything
-                    *self = Self::new_created(path, *rec, events);
+                    *self = Self::new_created(path, *rec, filter, events);
                     return;
                 }
                 // Nothing changed
             },
 
-            Self::ExistingFile{ rec, mod_time } => {
-                if !path.exists() {
-                    // File no longer exists!
-                    let rec = *rec;
-                    self.delete_rec(path, SystemTime::now(), events);
-                    *self = Self::NotExisting{ rec };
-                    return;
-                }
-                if !path.is_file() {
+            Self::ExistingFile{ rec, mod_time, change_time } => {
+                let meta = match Self::metadata(path) {
+                    Ok(Some(meta)) => meta,
+                    Ok(None) => {
+                        // File no longer exists!
+                        let rec = *rec;
+                        self.delete_rec(path, SystemTime::now(), events);
+                        *self = Self::NotExisting{ rec };
+                        return;
+                    },
+                    Err(err) => {
+                        // Can't tell, keep the state and retry next time
+                        events.push_back(Err(err));
+                        return;
+                    },
+                };
+                if !meta.is_file() {
                     // No longer a file, first delete then update state while
                     // logging everything
                     let rec = *rec;
                     self.delete_rec(path, SystemTime::now(), events);
-                    *self = Self::new_created(path, rec, events);
+                    *self = Self::new_created(path, rec, filter, events);
                     return;
                 }
                 // Still file, check modification date
-                if let Ok(mtime) = Self::mtime(path) {
-                    if mtime > *mod_time {
-                        events.push_back(Ok(Event::modify(mtime, path)));
-                        *mod_time = mtime;
-                    }
-                }
+                Self::check_modified(path, mod_time, change_time, track_metadata, events);
             },
 
-            Self::ExistingDirectory{ rec, mod_time, substates } => {
-                if !path.exists() {
-                    // Directory no longer exists!
-                    let rec = *rec;
-                    self.delete_rec(path, SystemTime::now(), events);
-                    *self = Self::NotExisting{ rec };
-                    return;
-                }
-                if !path.is_dir() {
+            Self::ExistingDirectory{ rec, mod_time, change_time, listed, substates } => {
+                let meta = match Self::metadata(path) {
+                    Ok(Some(meta)) => meta,
+                    Ok(None) => {
+                        // Directory no longer exists!
+                        let rec = *rec;
+                        self.delete_rec(path, SystemTime::now(), events);
+                        *self = Self::NotExisting{ rec };
+                        return;
+                    },
+                    Err(err) => {
+                        // Can't tell, keep the state and retry next time
+                        events.push_back(Err(err));
+                        return;
+                    },
+                };
+                if !meta.is_dir() {
                     // No longer a directory, first delete then update state
                     // while logging everything
                     let rec = *rec;
                     self.delete_rec(path, SystemTime::now(), events);
-                    *self = Self::new_created(path, rec, events);
+                    *self = Self::new_created(path, rec, filter, events);
                     return;
                 }
-                // Still directory
-                if *rec == Recursion::Recursive {
+                // Still directory, the entries can only change, if the
+                // modification time did
+                let unchanged = listed.is_some() && meta.modified().ok() == *listed;
+                if *rec == Recursion::Recursive && !unchanged {
                     // Check for new entries
-                    let subdirs = fs::read_dir(path);
+                    *listed = Self::listed_time(&meta);
+                    let subdirs = Self::read_dir(path);
                     if subdirs.is_err() {
                         // Log error
                         events.push_back(Err(subdirs.unwrap_err()));
@@ -550,19 +1251,22 @@ impl FileState {
                                 events.push_back(Err(subdir.unwrap_err()));
                             }
                             else {
-                                let subpath = subdir.unwrap().path();
-                                if !substates.contains_key(&subpath) {
+                                let subdir = subdir.unwrap();
+                                let subpath = subdir.path();
+                                if !substates.contains_key(&subpath) && Self::is_tracked(&subdir, filter) {
                                     // New thing
-                                    let substate = Self::new_created(&subpath, *rec, events);
+                                    let substate = Self::new_created(&subpath, *rec, filter, events);
                                     substates.insert(subpath, substate);
                                 }
                             }
                         }
                     }
+                }
+                if *rec == Recursion::Recursive {
                     // Update and prune existing entries
                     let mut to_remove = Vec::new();
                     for (subpath, subdir) in substates.iter_mut() {
-                        subdir.update(subpath, events);
+                        subdir.update(subpath, track_metadata, filter, events);
                         match subdir {
                             Self::NotExisting{ .. } => to_remove.push(subpath.clone()),
                             _ => {},
@@ -573,11 +1277,26 @@ impl FileState {
                     }
                 }
                 // Check modification date
-                if let Ok(mtime) = Self::mtime(path) {
-                    if mtime > *mod_time {
-                        events.push_back(Ok(Event::modify(mtime, path)));
-                        *mod_time = mtime;
-                    }
+                Self::check_modified(path, mod_time, change_time, track_metadata, events);
+            },
+        }
+    }
+
+    /// Recursively adds this and all substates to the `Snapshot`.
+    fn snapshot(&self, path: &Path, snapshot: &mut Snapshot) {
+        match self {
+            Self::NotExisting{ .. } => { /* no-op */ },
+
+            Self::ExistingFile{ mod_time, .. } => {
+                let entry = SnapshotEntry{ mod_time: *mod_time, is_dir: false };
+                snapshot.entries.insert(path.to_path_buf(), entry);
+            },
+
+            Self::ExistingDirectory{ mod_time, substates, .. } => {
+                let entry = SnapshotEntry{ mod_time: *mod_time, is_dir: true };
+                snapshot.entries.insert(path.to_path_buf(), entry);
+                for (subpath, substate) in substates.iter() {
+                    substate.snapshot(subpath, snapshot);
                 }
             },
         }
@@ -588,7 +1307,7 @@ impl FileState {
         &self,
         path: impl AsRef<Path>,
         timestamp: SystemTime,
-        events: &mut VecDeque<Result<Event>>,
+        events: &mut EventQueue,
     ) {
         match self {
             Self::NotExisting{ .. } => { /* no-op */ },
@@ -610,21 +1329,113 @@ impl FileState {
     }
 }
 
+/// A simple glob pattern, matched against the paths below a root.
+#[derive(Debug, Clone)]
+struct Glob {
+    root: PathBuf,
+    // The pattern, split into path components
+    parts: Vec<Vec<u8>>,
+}
+
+impl Glob {
+    /// Creates a `Glob` from the given pattern for the paths below `root`.
+    fn new(root: &Path, pattern: &str) -> Self {
+        let parts = pattern.split(&['/', '\\'][..])
+            .filter(|part| !part.is_empty() && *part != ".")
+            .map(|part| part.as_bytes().to_vec())
+            .collect();
+        Self{ root: root.to_path_buf(), parts }
+    }
+
+    /// Returns the same pattern for the paths below the new `root`.
+    fn with_root(self, root: &Path) -> Self {
+        Self{ root: root.to_path_buf(), ..self }
+    }
+
+    /// Returns `true`, if the given path is below the root, and matches the
+    /// pattern.
+    fn matches(&self, path: &Path) -> bool {
+        let rel = match path.strip_prefix(&self.root) {
+            Ok(rel) => rel,
+            Err(_) => return false,
+        };
+        Self::match_parts(&self.parts, rel.components())
+    }
+
+    /// Returns `true`, if the given `Event` should be reported, meaning that
+    /// its path matches the pattern, or the root itself was created or
+    /// deleted.
+    fn reports(&self, e: &Event) -> bool {
+        let root_change = e.path == self.root && e.kind != EventKind::Modify;
+        root_change || self.matches(&e.path)
+    }
+
+    /// Matches the pattern components against the path components.
+    fn match_parts(parts: &[Vec<u8>], mut names: Components) -> bool {
+        match parts.split_first() {
+            None => names.next().is_none(),
+            // Any number of components
+            Some((part, rest)) if part[..] == b"**"[..] => loop {
+                if Self::match_parts(rest, names.clone()) {
+                    return true;
+                }
+                if names.next().is_none() {
+                    return false;
+                }
+            },
+            Some((part, rest)) => match names.next().and_then(|name| name_bytes(name.as_os_str())) {
+                Some(name) => Self::match_name(part, name) && Self::match_parts(rest, names),
+                None => false,
+            },
+        }
+    }
+
+    /// Matches a single pattern component against a single path component.
+    fn match_name(part: &[u8], name: &[u8]) -> bool {
+        match part.split_first() {
+            None => name.is_empty(),
+            Some((b'*', rest)) => (0..=name.len()).any(|i| Self::match_name(rest, &name[i..])),
+            Some((b'?', rest)) => match name.split_first() {
+                Some((_, name)) => {
+                    // A whole character, with its UTF-8 continuation bytes
+                    let len = name.iter().take_while(|b| **b & 0xc0 == 0x80).count();
+                    Self::match_name(rest, &name[len..])
+                },
+                None => false,
+            },
+            Some((c, rest)) => name.first() == Some(c) && Self::match_name(rest, &name[1..]),
+        }
+    }
+}
+
+/// Returns the bytes of a path component, to match a `Glob` against.
+#[cfg(unix)]
+fn name_bytes(name: &OsStr) -> Option<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Some(name.as_bytes())
+}
+
+/// Returns the bytes of a path component, to match a `Glob` against. The
+/// names, that aren't valid Unicode, can't be matched.
+#[cfg(not(unix))]
+fn name_bytes(name: &OsStr) -> Option<&[u8]> {
+    name.to_str().map(str::as_bytes)
+}
+
 // WinAPI, ReadDirectoryChangesW  //////////////////////////////////////////////
 
-// TODO: Finish at least the WinAPI implementation
 #[cfg(target_os = "windows")]
 mod win32 {
-    // TODO: We just use this to suppress warnings until we finish implementation
-    #![allow(dead_code)]
-
     #![allow(non_snake_case)]
 
-    use std::ffi::{c_void, OsStr};
-    use std::os::windows::ffi::OsStrExt;
+    use std::ffi::{c_void, OsStr, OsString};
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
     use std::mem;
     use std::ptr;
+    use std::slice;
     use std::io;
+    use crate::path::to_extended_wstring;
     use super::*;
 
     #[link(name = "kernel32")]
@@ -641,6 +1452,9 @@ mod win32 {
         fn CloseHandle(
             handle: *mut c_void,
         ) -> i32;
+        fn CancelIo(
+            handle: *mut c_void,
+        ) -> i32;
         fn ReadDirectoryChangesW(
             directory_handle: *mut c_void                ,
             res_buffer      : *mut c_void                ,
@@ -658,7 +1472,8 @@ mod win32 {
     }
 
     // Error type
-    const ERROR_SUCCESS: u32 = 0;
+    const ERROR_SUCCESS          : u32 = 0;
+    const ERROR_OPERATION_ABORTED: u32 = 995;
     // File access
     const FILE_LIST_DIRECTORY: u32 = 0x0001;
     // File share
@@ -674,10 +1489,19 @@ mod win32 {
     const FILE_NOTIFY_CHANGE_FILE_NAME : u32 = 0x00000001;
     const FILE_NOTIFY_CHANGE_DIR_NAME  : u32 = 0x00000002;
     const FILE_NOTIFY_CHANGE_LAST_WRITE: u32 = 0x00000010;
+    // File change actions
+    const FILE_ACTION_ADDED           : u32 = 0x00000001;
+    const FILE_ACTION_REMOVED         : u32 = 0x00000002;
+    const FILE_ACTION_MODIFIED        : u32 = 0x00000003;
+    const FILE_ACTION_RENAMED_OLD_NAME: u32 = 0x00000004;
+    const FILE_ACTION_RENAMED_NEW_NAME: u32 = 0x00000005;
 
     // Returned by handle-returning functions on failure
     const INVALID_HANDLE_VALUE: *mut c_void = -1isize as *mut c_void;
 
+    /// The number of `DWORD`s a `Subscription` can receive notifications in.
+    const NOTIFY_BUFFER_LEN: usize = 1024;
+
     type OverlappedCompletionRoutine =
         Option<unsafe extern "system" fn(u32, u32, *mut OVERLAPPED)>;
 
@@ -701,12 +1525,7 @@ mod win32 {
         NextEntryOffset: u32     ,
         Action         : u32     ,
         FileNameLength : u32     ,
-        FileName       : *mut u16,
-    }
-
-    /// Converts the Rust &OsStr into a WinAPI WCHAR string.
-    fn to_wstring(s: &OsStr) -> Vec<u16> {
-        s.encode_wide().chain(Some(0).into_iter()).collect()
+        FileName       : [u16; 1],
     }
 
     /// Forces the thread to go to sleep for the given amount of milliseconds,
@@ -718,7 +1537,7 @@ mod win32 {
     /// Opens a file/directory for observing only.
     fn open_handle_for_observe(path: &Path) -> Result<*mut c_void> {
         let handle = unsafe { CreateFileW(
-            to_wstring(path.as_os_str()).as_ptr(),
+            to_extended_wstring(path).as_ptr(),
             FILE_LIST_DIRECTORY,
             FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
             ptr::null_mut(),
@@ -761,70 +1580,307 @@ mod win32 {
         }
     }
 
-    /// Subscribes to the next change notification for the given handle, with
-    /// the given result-buffer, callback and user-pointer.
-    fn subscribe_to_next_change<U>(
-        handle: *mut c_void,
-        recursive: bool,
-        result_buffer: &mut [u32],
-        callback: OverlappedCompletionRoutine,
-        user: *mut U,
-    ) -> Result<()> {
-        let mut bw: u32 = 0;
-
-        let mut overlapped = OVERLAPPED::zeroed();
-        overlapped.hEvent = user.cast();
-
-        if unsafe { ReadDirectoryChangesW(
-            handle,
-            result_buffer.as_mut_ptr().cast(),
-            (result_buffer.len() * mem::size_of::<u32>()) as u32,
-            if recursive { 1 } else { 0 },
-            FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_DIR_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE,
-            &mut bw,
-            &mut overlapped,
-            callback) } == 0 {
-            Err(io::Error::last_os_error())
-        }
-        else {
-            Ok(())
+    /// Returns the file name stored in a `FILE_NOTIFY_INFORMATION`.
+    fn notify_file_name(entry: &FILE_NOTIFY_INFORMATION) -> OsString {
+        let len = entry.FileNameLength as usize / mem::size_of::<u16>();
+        let name = unsafe{ slice::from_raw_parts(entry.FileName.as_ptr(), len) };
+        OsString::from_wide(name)
+    }
+
+    /// Checks if two file names are the same, ignoring case like the
+    /// filesystem does. The names are compared unit by unit, as they can
+    /// contain unpaired surrogates.
+    pub(super) fn file_names_equal(n1: &OsStr, n2: &OsStr) -> bool {
+        // Only the units, that are characters on their own and have a
+        // single-unit uppercase variant are folded, like the ordinal
+        // comparison of the system does
+        fn upper(unit: u16) -> u16 {
+            let c = match std::char::from_u32(unit as u32) {
+                Some(c) => c,
+                // Surrogate
+                None => return unit,
+            };
+            let mut upper = c.to_uppercase();
+            match (upper.next(), upper.next()) {
+                (Some(u), None) if (u as u32) <= 0xffff => u as u16,
+                _ => unit,
+            }
         }
+
+        n1.encode_wide().map(upper).eq(n2.encode_wide().map(upper))
     }
 
-    /// The WinAPI-based watch, using `ReadDirectoryChangesW`.
-    pub struct WinApiWatch {
+    /// A single `ReadDirectoryChangesW` subscription on a directory. This is
+    /// always boxed, as the system refers to it while the notification is
+    /// pending.
+    #[repr(C)]
+    struct Subscription {
+        // NOTE: This must be the first field, the completion routine casts the
+        // `OVERLAPPED` pointer back to the `Subscription`
+        overlapped: OVERLAPPED,
+        handle: *mut c_void,
+        directory: PathBuf,
+        recursive: bool,
+        // If set, only the entry with this name is reported
+        file_name: Option<OsString>,
+        in_flight: bool,
+        // Set when dropping, so the completion routine doesn't subscribe again
+        closing: bool,
+        buffer: [u32; NOTIFY_BUFFER_LEN],
+        events: VecDeque<Result<Event>>,
     }
 
-    impl Watch for WinApiWatch {
-        fn new() -> Result<Self> {
-            Ok(Self{ })
+    impl Subscription {
+        /// Starts listening for changes in the given directory. If `file_name`
+        /// is given, only changes to the entry with that name are reported.
+        fn new(directory: &Path, recursive: bool, file_name: Option<OsString>) -> Result<Box<Self>> {
+            let handle = open_handle_for_observe(directory)?;
+            let mut result = Box::new(Self{
+                overlapped: OVERLAPPED::zeroed(),
+                handle,
+                directory: directory.to_path_buf(),
+                recursive,
+                file_name,
+                in_flight: false,
+                closing: false,
+                buffer: [0u32; NOTIFY_BUFFER_LEN],
+                events: VecDeque::new(),
+            });
+            result.subscribe()?;
+            Ok(result)
         }
 
-        fn watch(&mut self, p: impl AsRef<Path>, rec: Recursion) -> Result<()> {
-            let path = p.as_ref();
-            if !path.exists() {
-                // Watch the closest parent that DOES exist
-                // Or just check if it exists when updating, roughly polling existance
-                unimplemented!();
+        /// Subscribes to the next change notification.
+        fn subscribe(&mut self) -> Result<()> {
+            let filter = if self.file_name.is_some() {
+                FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE
+            }
+            else {
+                FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_DIR_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE
+            };
+            let mut bw: u32 = 0;
+            self.overlapped = OVERLAPPED::zeroed();
+            let overlapped: *mut OVERLAPPED = (self as *mut Self).cast();
+
+            if unsafe { ReadDirectoryChangesW(
+                self.handle,
+                self.buffer.as_mut_ptr().cast(),
+                (self.buffer.len() * mem::size_of::<u32>()) as u32,
+                if self.recursive { 1 } else { 0 },
+                filter,
+                &mut bw,
+                overlapped,
+                Some(Self::on_change)) } == 0 {
+                Err(io::Error::last_os_error())
             }
             else {
-                // Watch parent directory non-recursively
-                // if file, then it's enough
-                // if directory, then watch self recursively
-                unimplemented!();
+                self.in_flight = true;
+                Ok(())
             }
         }
 
+        /// Translates the received notifications into `Event`s.
+        fn process_notifications(&mut self, bytes_transferred: u32) {
+            let time = SystemTime::now();
+            if bytes_transferred == 0 {
+                // The buffer overflowed, we don't know what changed, so we
+                // tell the consumer to rescan what we watch
+                let path = match &self.file_name {
+                    Some(name) => self.directory.join(name),
+                    None => self.directory.clone(),
+                };
+                self.events.push_back(Ok(Event::overflow(time, path)));
+                return;
+            }
+            let directory = &self.directory;
+            let file_name = &self.file_name;
+            let events = &mut self.events;
+            read_file_notify_information(self.buffer.as_ptr(), bytes_transferred, |entry| {
+                let name = notify_file_name(entry);
+                if let Some(file_name) = file_name {
+                    if !file_names_equal(&name, file_name) {
+                        // Some other entry in the same directory
+                        return;
+                    }
+                }
+                let kind = match entry.Action {
+                    FILE_ACTION_ADDED | FILE_ACTION_RENAMED_NEW_NAME => EventKind::Create,
+                    FILE_ACTION_REMOVED | FILE_ACTION_RENAMED_OLD_NAME => EventKind::Delete,
+                    FILE_ACTION_MODIFIED => EventKind::Modify,
+                    _ => return,
+                };
+                events.push_back(Ok(Event::new(time, directory.join(name), kind)));
+            });
+        }
+
+        /// The completion routine for `ReadDirectoryChangesW`.
+        unsafe extern "system" fn on_change(error: u32, bytes_transferred: u32, overlapped: *mut OVERLAPPED) {
+            let this = &mut *(overlapped as *mut Self);
+            this.in_flight = false;
+            if error == ERROR_OPERATION_ABORTED {
+                // We got cancelled
+                return;
+            }
+            if error != ERROR_SUCCESS {
+                this.events.push_back(Err(io::Error::from_raw_os_error(error as i32)));
+                return;
+            }
+            this.process_notifications(bytes_transferred);
+            if this.closing {
+                // A request issued now would outlive the cancellation
+                return;
+            }
+            // Listen for the next batch
+            if let Err(err) = this.subscribe() {
+                this.events.push_back(Err(err));
+            }
+        }
+    }
+
+    impl Drop for Subscription {
+        fn drop(&mut self) {
+            // A completion, that's already queued, could run while waiting
+            unsafe{ ptr::write_volatile(&mut self.closing, true) };
+            unsafe { CancelIo(self.handle) };
+            // The system still refers to us until the completion routine runs,
+            // which writes the flag behind our back
+            while unsafe{ ptr::read_volatile(&self.in_flight) } {
+                sleep(1);
+            }
+            close_handle(self.handle);
+        }
+    }
+
+    /// The state of a single watched path in a `WinApiWatch`.
+    enum WatchState {
+        /// Not even the parent of the path exists, so there's nothing to
+        /// subscribe to yet. Existence is polled.
+        Pending{
+            rec: Recursion,
+        },
+        /// There's a live subscription for the path.
+        Subscribed(Box<Subscription>),
+    }
+
+    /// The WinAPI-based watch, using `ReadDirectoryChangesW`.
+    ///
+    /// Directories are watched directly. Since `ReadDirectoryChangesW` can
+    /// only watch directories, a single file is watched by watching its parent
+    /// directory non-recursively, filtering for the name of the file. If even
+    /// the parent directory doesn't exist, it's polled for existence with the
+    /// interval set by `set_interval`.
+    ///
+    /// Notifications are only delivered while polling `Event`s, on the thread
+    /// that created the watch. If too many changes happen between two polls,
+    /// the system drops them, which is reported as `EventKind::Overflow`.
+    pub struct WinApiWatch {
+        last_time: SystemTime,
+        interval: Duration,
+        events: VecDeque<Result<Event>>,
+        watched: HashMap<PathBuf, WatchState>,
+    }
+
+    impl WinApiWatch {
+        /// Tries to subscribe to the changes of the given path.
+        fn subscribe(path: &Path, rec: Recursion) -> Result<WatchState> {
+            if path.is_dir() {
+                let sub = Subscription::new(path, rec == Recursion::Recursive, None)?;
+                return Ok(WatchState::Subscribed(sub));
+            }
+            // A file or something that doesn't exist yet, watch the parent
+            // NOTE: If this turns out to be a directory, we only see its
+            // creation and deletion
+            let file_name = path.file_name()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The path has no file name!"))?;
+            let parent = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            if !parent.is_dir() {
+                return Ok(WatchState::Pending{ rec });
+            }
+            let sub = Subscription::new(parent, false, Some(file_name.to_os_string()))?;
+            Ok(WatchState::Subscribed(sub))
+        }
+
+        /// Returns `true`, if pending paths should be polled, because enough
+        /// time has elapsed.
+        fn should_update(&mut self) -> bool {
+            let now = SystemTime::now();
+            match now.duration_since(self.last_time) {
+                Ok(elapsed) if elapsed < self.interval => false,
+                _ => {
+                    self.last_time = now;
+                    true
+                },
+            }
+        }
+
+        /// Tries to subscribe to the paths that had no existing parent.
+        fn update_pending(&mut self) {
+            if !self.should_update() {
+                return;
+            }
+
+            for (path, state) in &mut self.watched {
+                if let WatchState::Pending{ rec } = state {
+                    match Self::subscribe(path, *rec) {
+                        Ok(WatchState::Pending{ .. }) => { /* Still nothing */ },
+                        Ok(new_state) => {
+                            if path.exists() {
+                                // It appeared together with its parent
+                                self.events.push_back(Ok(Event::create(SystemTime::now(), path)));
+                            }
+                            *state = new_state;
+                        },
+                        Err(err) => self.events.push_back(Err(err)),
+                    }
+                }
+            }
+        }
+    }
+
+    impl Watch for WinApiWatch {
+        /// Creates a new `WinApiWatch` with one second intervals between
+        /// existence polls.
+        fn new() -> Result<Self> {
+            Ok(Self{
+                last_time: SystemTime::UNIX_EPOCH,
+                interval: Duration::from_secs(1),
+                events: VecDeque::new(),
+                watched: HashMap::new(),
+            })
+        }
+
+        fn watch(&mut self, p: impl AsRef<Path>, rec: Recursion) -> Result<()> {
+            let path = p.as_ref();
+            // Stop the old subscription first
+            self.watched.remove(path);
+            let state = Self::subscribe(path, rec)?;
+            self.watched.insert(path.to_path_buf(), state);
+            Ok(())
+        }
+
         fn unwatch(&mut self, p: impl AsRef<Path>) {
-            unimplemented!();
+            self.watched.remove(p.as_ref());
         }
 
         fn poll_event(&mut self) -> Option<Result<Event>> {
-            unimplemented!();
+            // Let the completion routines run
+            sleep(0);
+            self.update_pending();
+            for state in self.watched.values_mut() {
+                if let WatchState::Subscribed(sub) = state {
+                    self.events.extend(sub.events.drain(..));
+                }
+            }
+            self.events.pop_front()
         }
 
-        fn set_interval(&mut self, _interval: Duration) {
-            unimplemented!();
+        /// Sets the time interval for polling paths, that don't even have an
+        /// existing parent directory.
+        fn set_interval(&mut self, interval: Duration) {
+            self.interval = interval;
         }
     }
 }
@@ -865,6 +1921,682 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_winapi_watch_single_file() -> Result<()> {
+        let dir = temp::directory()?;
+        let foo_path = join!(dir.path(), "foo.txt");
+        let bar_path = join!(dir.path(), "bar.txt");
+        fs::File::create(&foo_path)?;
+        fs::File::create(&bar_path)?;
+
+        let mut w = win32::WinApiWatch::new()?;
+        w.watch(&foo_path, Recursion::NotRecursive)?;
+
+        assert!(w.poll_event().is_none());
+
+        // Busy neighbours
+        fs::File::create(&bar_path)?.write_all("Hello".as_bytes())?;
+        fs::File::create(join!(dir.path(), "baz.txt"))?;
+        // The watched file
+        fs::File::create(&foo_path)?.write_all("Hello".as_bytes())?;
+        thread::sleep(Duration::from_millis(50));
+
+        let mut modified = false;
+        while let Some(e) = w.poll_event() {
+            let e = e?;
+            assert!(e.path.ends_with("foo.txt"));
+            if e.kind == EventKind::Modify {
+                modified = true;
+            }
+        }
+        assert!(modified);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_winapi_file_names_equal() {
+        use std::ffi::OsString;
+        use std::os::windows::ffi::OsStringExt;
+
+        assert!(win32::file_names_equal("Foo.TXT".as_ref(), "foo.txt".as_ref()));
+        assert!(win32::file_names_equal("\u{e1}rv\u{ed}z".as_ref(), "\u{c1}RV\u{cd}Z".as_ref()));
+        assert!(!win32::file_names_equal("foo.txt".as_ref(), "bar.txt".as_ref()));
+        // Unpaired surrogates are compared as they are
+        let n1 = OsString::from_wide(&[0xd800, 0x61]);
+        let n2 = OsString::from_wide(&[0xd801, 0x61]);
+        assert!(win32::file_names_equal(&n1, &n1));
+        assert!(!win32::file_names_equal(&n1, &n2));
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_winapi_watch_overflow() -> Result<()> {
+        let dir = temp::directory()?;
+
+        let mut w = win32::WinApiWatch::new()?;
+        w.watch(dir.path(), Recursion::Recursive)?;
+
+        assert!(w.poll_event().is_none());
+
+        // Way more changes than the notification buffer can hold, without
+        // letting the completion routine run
+        for i in 0..500 {
+            fs::File::create(join!(dir.path(), format!("overflowing_file_{}.txt", i)))?;
+        }
+        thread::sleep(Duration::from_millis(50));
+
+        let mut overflowed = false;
+        while let Some(e) = w.poll_event() {
+            let e = e?;
+            if e.kind == EventKind::Overflow {
+                assert_eq!(e.path, dir.path());
+                overflowed = true;
+            }
+        }
+        assert!(overflowed);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_winapi_watch_single_file_missing_parent() -> Result<()> {
+        let dir = temp::directory()?;
+        let sub_path = join!(dir.path(), "sub");
+        let foo_path = join!(&sub_path, "foo.txt");
+
+        let mut w = win32::WinApiWatch::new()?;
+        w.set_interval(Duration::from_millis(0));
+        w.watch(&foo_path, Recursion::NotRecursive)?;
+
+        assert!(w.poll_event().is_none());
+
+        fs::create_dir(&sub_path)?;
+        fs::File::create(&foo_path)?;
+
+        // An event for file creation
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Create);
+        assert!(e.path.ends_with("foo.txt"));
+
+        // Changes are now reported by the subscription
+        fs::File::create(&foo_path)?.write_all("Hello".as_bytes())?;
+        thread::sleep(Duration::from_millis(50));
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Modify);
+        assert!(e.path.ends_with("foo.txt"));
+
+        drop(w);
+        fs::remove_file(&foo_path)?;
+        fs::remove_dir(&sub_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_snapshot_diff() -> Result<()> {
+        let dir = temp::directory()?;
+        let foo_path = join!(dir.path(), "foo.txt");
+        let bar_path = join!(dir.path(), "bar.txt");
+        let sub_path = join!(dir.path(), "sub");
+        fs::File::create(&foo_path)?;
+        fs::File::create(&bar_path)?;
+
+        let mut w = PollWatch::new()?;
+        w.watch(dir.path(), Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+
+        let before = w.snapshot();
+        assert_eq!(before.len(), 3);
+        assert!(before.get(dir.path()).unwrap().is_dir);
+        assert!(!before.get(&foo_path).unwrap().is_dir);
+        // Identical snapshots have no difference
+        assert!(before.diff(&w.snapshot()).is_empty());
+
+        // Mutate the tree
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(&foo_path)?.write_all("Hello".as_bytes())?;
+        fs::remove_file(&bar_path)?;
+        fs::create_dir(&sub_path)?;
+        fs::File::create(join!(&sub_path, "baz.txt"))?;
+        while w.poll_event().is_some() { }
+
+        let after = w.snapshot();
+        let diff: Vec<_> = before.diff(&after).into_iter().map(|e| (e.kind, e.path)).collect();
+        assert_eq!(diff, vec![
+            (EventKind::Delete, bar_path.clone()),
+            (EventKind::Modify, dir.path().to_path_buf()),
+            (EventKind::Modify, foo_path.clone()),
+            (EventKind::Create, sub_path.clone()),
+            (EventKind::Create, join!(&sub_path, "baz.txt")),
+        ]);
+
+        // Round-trip through the entries
+        let restored: Snapshot = after.iter().map(|(p, e)| (p.to_path_buf(), *e)).collect();
+        assert_eq!(restored, after);
+
+        fs::remove_file(join!(&sub_path, "baz.txt"))?;
+        fs::remove_dir(&sub_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_canonicalize() -> Result<()> {
+        let dir_path = temp::path_in(".", None)?;
+        let _dir = temp::directory_at(&dir_path)?;
+        let name = dir_path.file_name().unwrap();
+        let canonical = fs::canonicalize(&dir_path)?;
+
+        let mut w = PollWatch::new()?;
+        w.set_canonicalize(true);
+        w.watch(join!(".", name), Recursion::NotRecursive)?;
+        w.watch(&canonical, Recursion::NotRecursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert_eq!(w.watched.len(), 1);
+
+        // Events are reported with the canonical path
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(join!(&dir_path, "foo.txt"))?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Modify);
+        assert_eq!(e.path, canonical);
+
+        w.unwatch(name);
+        assert!(w.watched.is_empty());
+
+        fs::remove_file(join!(&dir_path, "foo.txt"))?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_poll_watch_track_metadata_changes() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp::directory()?;
+        let file_path = join!(dir.path(), "foo.txt");
+        fs::File::create(&file_path)?;
+
+        let mut w = PollWatch::new()?;
+        w.set_track_metadata_changes(true);
+        w.watch(&file_path, Recursion::NotRecursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+
+        // Only the permissions change
+        thread::sleep(Duration::from_millis(5));
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o600))?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Modify);
+        assert_eq!(e.path, file_path);
+        assert!(w.poll_event().is_none());
+
+        // Without tracking, it goes unnoticed
+        w.set_track_metadata_changes(false);
+        thread::sleep(Duration::from_millis(5));
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o644))?;
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_poll_watch_transient_error() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp::directory()?;
+        let real_path = join!(dir.path(), "real");
+        let link_path = join!(dir.path(), "link");
+        fs::create_dir(&real_path)?;
+        fs::File::create(join!(&real_path, "foo.txt"))?;
+        symlink(&real_path, &link_path)?;
+        let file_path = join!(&link_path, "foo.txt");
+
+        let mut w = PollWatch::new()?;
+        w.watch(&file_path, Recursion::NotRecursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+
+        // The link briefly points to itself, the file can't be read, but it's
+        // not deleted either, every scan retries
+        fs::remove_file(&link_path)?;
+        symlink(&link_path, &link_path)?;
+        for _ in 0..3 {
+            assert!(w.poll_event().unwrap().is_err());
+        }
+
+        // Access is restored, there's nothing to report
+        fs::remove_file(&link_path)?;
+        symlink(&real_path, &link_path)?;
+        assert!(w.poll_event().is_none());
+
+        // The watch still works
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(&file_path)?.write_all("Hello".as_bytes())?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Modify);
+        assert_eq!(e.path, file_path);
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_poll_watch_follow_inode() -> Result<()> {
+        let dir = temp::directory()?;
+        let old_path = join!(dir.path(), "old");
+        let new_path = join!(dir.path(), "new");
+        let other_path = join!(dir.path(), "other");
+        fs::create_dir(&old_path)?;
+        fs::create_dir(&other_path)?;
+
+        let mut w = PollWatch::new()?;
+        w.set_follow_inode(true);
+        w.watch(&old_path, Recursion::Recursive)?;
+        w.watch(&other_path, Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+
+        // The rename is followed
+        fs::rename(&old_path, &new_path)?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!((e.kind, e.path), (EventKind::Delete, old_path.clone()));
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!((e.kind, e.path), (EventKind::Create, new_path.clone()));
+        assert!(w.poll_event().is_none());
+
+        // Events keep arriving under the new path
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(join!(&new_path, "foo.txt"))?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!((e.kind, e.path), (EventKind::Create, join!(&new_path, "foo.txt")));
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!((e.kind, e.path), (EventKind::Modify, new_path.clone()));
+        assert!(w.poll_event().is_none());
+
+        // A true deletion is still a deletion
+        fs::remove_dir(&other_path)?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!((e.kind, e.path), (EventKind::Delete, other_path));
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_skips_unchanged_listings() -> Result<()> {
+        let listings = || READ_DIR_COUNT.with(|c| c.get());
+        let dir = temp::directory()?;
+        let sub_path = join!(dir.path(), "sub");
+        let foo_path = join!(&sub_path, "foo.txt");
+        let bar_path = join!(&sub_path, "bar.txt");
+        fs::create_dir(&sub_path)?;
+        fs::File::create(&foo_path)?;
+        // Let the modification times age, so the listings can be trusted
+        thread::sleep(RACY_LISTING_WINDOW + Duration::from_millis(100));
+
+        let mut w = PollWatch::new()?;
+        w.watch(dir.path(), Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+
+        // Nothing changed, nothing is listed
+        let before = listings();
+        assert!(w.poll_event().is_none());
+        assert!(w.poll_event().is_none());
+        assert_eq!(listings(), before);
+
+        // Modifying a file is detected without listing
+        fs::File::create(&foo_path)?.write_all("Hello".as_bytes())?;
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Modify, foo_path));
+        assert_eq!(listings(), before);
+
+        // A new entry touches the directory, only that gets listed
+        fs::File::create(&bar_path)?;
+        let e = w.poll_event().unwrap()?;
+        assert_eq!(listings(), before + 1);
+        assert_eq!((e.kind, e.path), (EventKind::Create, bar_path));
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Modify, sub_path));
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_max_queued_events() -> Result<()> {
+        let dir = temp::directory()?;
+
+        let mut w = PollWatch::new()?;
+        w.watch(dir.path(), Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        w.set_max_queued_events(4);
+        assert!(w.poll_event().is_none());
+
+        thread::sleep(Duration::from_millis(5));
+        for i in 0..10 {
+            fs::File::create(join!(dir.path(), format!("{}.txt", i)))?;
+        }
+        // The overflow comes first, then the newest events
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Overflow, dir.path().to_path_buf()));
+        for _ in 0..4 {
+            assert!(w.poll_event().unwrap().is_ok());
+        }
+        assert!(w.poll_event().is_none());
+
+        // Nothing is kept but the overflow
+        w.set_max_queued_events(0);
+        thread::sleep(Duration::from_millis(5));
+        for i in 0..10 {
+            fs::File::create(join!(dir.path(), format!("{}.txt", i + 10)))?;
+        }
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Overflow, dir.path().to_path_buf()));
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_max_event_age() -> Result<()> {
+        let dir = temp::directory()?;
+
+        let mut w = PollWatch::new()?;
+        w.watch(dir.path(), Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        w.set_max_event_age(Some(Duration::from_millis(50)));
+        assert!(w.poll_event().is_none());
+
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(join!(dir.path(), "a.txt"))?;
+        fs::File::create(join!(dir.path(), "b.txt"))?;
+        // Fresh events are kept
+        assert!(w.poll_event().unwrap().is_ok());
+        // The rest of the queue gets stale until the next scan
+        w.events.push_back(Err(io::Error::new(io::ErrorKind::Other, "Test error!")));
+        thread::sleep(Duration::from_millis(100));
+        // Only the error survives
+        let err = w.poll_event().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(w.poll_event().is_none());
+
+        // A fresh change to an old file is kept, the age is counted from the
+        // detection
+        let outside = temp::directory()?;
+        let old_path = join!(outside.path(), "old.txt");
+        fs::File::create(&old_path)?;
+        thread::sleep(Duration::from_millis(100));
+        let moved_path = join!(dir.path(), "old.txt");
+        fs::rename(&old_path, &moved_path)?;
+        let mut events = Vec::new();
+        while let Some(e) = w.poll_event() {
+            let e = e?;
+            events.push((e.kind, e.path));
+        }
+        assert!(events.contains(&(EventKind::Create, moved_path)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        let glob = Glob::new(Path::new("root"), "**/*.txt");
+        assert!(glob.matches(&join!("root", "a.txt")));
+        assert!(glob.matches(&join!("root", "sub", "deeper", "b.txt")));
+        assert!(!glob.matches(&join!("root", "a.rs")));
+        assert!(!glob.matches(&join!("root", "sub")));
+        assert!(!glob.matches(&join!("other", "a.txt")));
+
+        let glob = Glob::new(Path::new("root"), "src/?.rs");
+        assert!(glob.matches(&join!("root", "src", "a.rs")));
+        assert!(!glob.matches(&join!("root", "src", "ab.rs")));
+        assert!(!glob.matches(&join!("root", "src", "sub", "a.rs")));
+        assert!(glob.matches(&join!("root", "src", "é.rs")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_glob_matches_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let glob = Glob::new(Path::new("root"), "*.txt");
+        let name = OsStr::from_bytes(b"\xff.txt");
+        assert!(glob.matches(&join!("root", name)));
+        // Not the replacement character, that a lossy conversion would give
+        let glob = Glob::new(Path::new("root"), "\u{fffd}.txt");
+        assert!(!glob.matches(&join!("root", name)));
+    }
+
+    #[test]
+    fn test_poll_watch_glob() -> Result<()> {
+        let dir = temp::directory()?;
+        let sub_path = join!(dir.path(), "sub");
+        let txt_path = join!(dir.path(), "a.txt");
+        let rs_path = join!(dir.path(), "a.rs");
+        let sub_txt_path = join!(&sub_path, "b.txt");
+        let sub_rs_path = join!(&sub_path, "b.rs");
+        fs::create_dir(&sub_path)?;
+        for p in &[&txt_path, &rs_path, &sub_txt_path, &sub_rs_path] {
+            fs::File::create(p)?;
+        }
+
+        let mut w = PollWatch::new()?;
+        w.watch_glob(dir.path(), "**/*.txt", Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+
+        // Only the matching files are tracked
+        let snapshot = w.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert!(snapshot.get(&txt_path).is_some());
+        assert!(snapshot.get(&sub_txt_path).is_some());
+
+        // Only the changes of the matching files surface
+        thread::sleep(Duration::from_millis(5));
+        for p in &[&txt_path, &rs_path, &sub_txt_path, &sub_rs_path] {
+            fs::File::create(p)?.write_all("Hello".as_bytes())?;
+        }
+        let new_txt_path = join!(&sub_path, "c.txt");
+        fs::File::create(&new_txt_path)?;
+        fs::File::create(join!(&sub_path, "c.rs"))?;
+        fs::remove_file(&rs_path)?;
+
+        let mut events = Vec::new();
+        while let Some(e) = w.poll_event() {
+            let e = e?;
+            events.push((e.kind, e.path));
+        }
+        events.sort_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(events, vec![
+            (EventKind::Modify, txt_path),
+            (EventKind::Modify, sub_txt_path),
+            (EventKind::Create, new_txt_path),
+        ]);
+
+        // The root itself is reported, even if it doesn't match
+        let root = dir.path().to_path_buf();
+        fs::remove_dir_all(&root)?;
+        let mut events = Vec::new();
+        while let Some(e) = w.poll_event() {
+            let e = e?;
+            events.push((e.kind, e.path));
+        }
+        assert!(events.contains(&(EventKind::Delete, root.clone())));
+        assert!(events.iter().all(|(kind, _)| *kind == EventKind::Delete));
+        fs::create_dir(&root)?;
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Create, root));
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_many_partial_failure() -> Result<()> {
+        // Only accepts existing paths
+        struct ExistingWatch(Vec<PathBuf>);
+        impl Watch for ExistingWatch {
+            fn new() -> Result<Self> { Ok(Self(Vec::new())) }
+            fn watch(&mut self, p: impl AsRef<Path>, _rec: Recursion) -> Result<()> {
+                fs::metadata(p.as_ref())?;
+                self.0.push(p.as_ref().to_path_buf());
+                Ok(())
+            }
+            fn unwatch(&mut self, _p: impl AsRef<Path>) { }
+            fn poll_event(&mut self) -> Option<Result<Event>> { None }
+        }
+
+        let dir = temp::directory()?;
+        let foo_path = join!(dir.path(), "foo.txt");
+        let missing_path = join!(dir.path(), "missing.txt");
+        fs::File::create(&foo_path)?;
+
+        let mut w = ExistingWatch::new()?;
+        let results = w.watch_many(&[
+            (missing_path, Recursion::NotRecursive),
+            (foo_path.clone(), Recursion::NotRecursive),
+            (dir.path().to_path_buf(), Recursion::Recursive),
+        ]);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(results[1].is_ok());
+        assert!(results[2].is_ok());
+        assert_eq!(w.0, vec![foo_path, dir.path().to_path_buf()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_watch_many() -> Result<()> {
+        let dir = temp::directory()?;
+        let foo_path = join!(dir.path(), "foo.txt");
+        let bar_path = join!(dir.path(), "bar.txt");
+        let missing_path = join!(dir.path(), "missing.txt");
+        let missing_dir_path = join!(dir.path(), "missing", "baz.txt");
+        fs::File::create(&foo_path)?;
+        fs::File::create(&bar_path)?;
+
+        let mut w = PollWatch::new()?;
+        w.set_interval(Duration::from_millis(0));
+        let results = w.watch_many(&[
+            (foo_path.clone(), Recursion::NotRecursive),
+            (missing_path.clone(), Recursion::NotRecursive),
+            (bar_path.clone(), Recursion::NotRecursive),
+            (missing_dir_path.clone(), Recursion::NotRecursive),
+        ]);
+        // Missing paths are watched for creation
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert!(w.poll_event().is_none());
+
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(&bar_path)?.write_all("Hello".as_bytes())?;
+        fs::File::create(&missing_path)?;
+        fs::create_dir(join!(dir.path(), "missing"))?;
+        fs::File::create(&missing_dir_path)?;
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Create, missing_path));
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Modify, bar_path));
+        let e = w.poll_event().unwrap()?;
+        assert_eq!((e.kind, e.path), (EventKind::Create, missing_dir_path));
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_stable_root_order() -> Result<()> {
+        let dir1 = temp::directory()?;
+        let dir2 = temp::directory()?;
+
+        let mut w = PollWatch::new()?;
+        // Not in the order of the paths
+        w.watch(dir2.path(), Recursion::Recursive)?;
+        w.watch(dir1.path(), Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+
+        for round in 0..3 {
+            thread::sleep(Duration::from_millis(5));
+            let name = format!("{}.txt", round);
+            fs::File::create(join!(dir1.path(), &name))?;
+            fs::File::create(join!(dir2.path(), &name))?;
+            let mut events = Vec::new();
+            while let Some(e) = w.poll_event() {
+                events.push(e?);
+            }
+            // All events of the first watched root come first
+            let split = events.iter().position(|e| e.path.starts_with(dir1.path())).unwrap();
+            assert!(events[..split].iter().all(|e| e.path.starts_with(dir2.path())));
+            assert!(events[split..].iter().all(|e| e.path.starts_with(dir1.path())));
+            let created: Vec<_> = events.iter()
+                .filter(|e| e.kind == EventKind::Create)
+                .map(|e| e.path.clone())
+                .collect();
+            assert_eq!(created, [join!(dir2.path(), &name), join!(dir1.path(), &name)]);
+        }
+
+        // Watching again keeps the place
+        w.watch(dir2.path(), Recursion::Recursive)?;
+        assert_eq!(w.order, [dir2.path().to_path_buf(), dir1.path().to_path_buf()]);
+        // Rewatching after an unwatch moves it to the end
+        w.unwatch(dir2.path());
+        w.watch(dir2.path(), Recursion::Recursive)?;
+        assert_eq!(w.order, [dir1.path().to_path_buf(), dir2.path().to_path_buf()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_nested_recursive() -> Result<()> {
+        let dir = temp::directory()?;
+        let sub_path = join!(dir.path(), "sub");
+        let file_path = join!(&sub_path, "foo.txt");
+        fs::create_dir(&sub_path)?;
+
+        let mut w = PollWatch::new()?;
+        w.watch(dir.path(), Recursion::Recursive)?;
+        w.watch(&sub_path, Recursion::Recursive)?;
+        w.set_interval(Duration::from_millis(0));
+        assert!(w.poll_event().is_none());
+        assert!(w.covered.contains(&sub_path));
+        assert_eq!(w.covered.len(), 1);
+
+        // Every change is reported by exactly one of the watches
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(&file_path)?;
+        let mut events = Vec::new();
+        while let Some(e) = w.poll_event() {
+            events.push(e?);
+        }
+        assert_eq!(events.iter().filter(|e| e.path == file_path).count(), 1);
+        assert_eq!(events.iter().filter(|e| e.path == sub_path).count(), 1);
+        assert_eq!(events.len(), 2);
+
+        // The inner watch takes over, without reporting old changes
+        w.unwatch(dir.path());
+        assert!(w.covered.is_empty());
+        assert!(w.poll_event().is_none());
+        thread::sleep(Duration::from_millis(5));
+        fs::File::create(&file_path)?.write_all("Hello".as_bytes())?;
+        let e = w.poll_event().unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Modify);
+        assert_eq!(e.path, file_path);
+        assert!(w.poll_event().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_watch_canonicalize_disabled() -> Result<()> {
+        let dir_path = temp::path_in(".", None)?;
+        let _dir = temp::directory_at(&dir_path)?;
+        let name = dir_path.file_name().unwrap();
+
+        let mut w = PollWatch::new()?;
+        w.watch(join!(".", name), Recursion::NotRecursive)?;
+        w.watch(fs::canonicalize(&dir_path)?, Recursion::NotRecursive)?;
+        assert_eq!(w.watched.len(), 2);
+        Ok(())
+    }
+
     #[test]
     fn test_poll_watch_directory_created_deleted() -> Result<()> {
         let mut w = PollWatch::new()?;
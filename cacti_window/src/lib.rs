@@ -4,13 +4,17 @@
 #![cfg(not(CI))]
 
 use std::io;
+use std::fmt;
 use std::ffi::c_void;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::{Arc, mpsc};
+use std::time::Instant;
 
 // ////////////////////////////////////////////////////////////////////////// //
 //                                    API                                     //
 // ////////////////////////////////////////////////////////////////////////// //
 
-#[derive(Debug)]
 pub struct Monitor(MonitorImpl);
 
 impl Monitor {
@@ -21,6 +25,9 @@ impl Monitor {
     pub fn handle_ptr(&self) -> *const c_void { self.0.handle_ptr() }
     pub fn handle_mut_ptr(&mut self) -> *mut c_void { self.0.handle_ptr() }
 
+    /// Returns the name of the monitor, if the platform provides one. On X11
+    /// every screen is a monitor, and screens have no names, so this is always
+    /// `None` there.
     pub fn name(&self) -> Option<String> {
         self.0.name()
     }
@@ -37,6 +44,26 @@ impl Monitor {
         self.0.size()
     }
 
+    /// Returns the position and size of the work area of the monitor, which
+    /// is the part not covered by the taskbar, docks and panels. Falls back
+    /// to the whole monitor, when the platform can't tell.
+    pub fn work_area(&self) -> (PhysicalPosition, PhysicalSize) {
+        self.0.work_area()
+    }
+
+    /// Converts a desktop position to a position relative to the top-left
+    /// corner of this monitor. Monitors can lie at negative desktop
+    /// coordinates, for example the ones left of the primary one.
+    pub fn to_monitor_local(&self, p: PhysicalPosition) -> PhysicalPosition {
+        p.relative_to(self.position())
+    }
+
+    /// Converts a position relative to the top-left corner of this monitor to
+    /// a desktop position. The inverse of `to_monitor_local`.
+    pub fn from_monitor_local(&self, p: PhysicalPosition) -> PhysicalPosition {
+        p.offset_by(self.position())
+    }
+
     pub fn dpi(&self) -> Dpi {
         self.0.dpi()
     }
@@ -44,29 +71,157 @@ impl Monitor {
     pub fn scale(&self) -> f64 {
         self.0.scale()
     }
+
+    /// Returns the video modes the monitor supports, without duplicates. The
+    /// list is empty, if the modes can't be queried.
+    pub fn video_modes(&self) -> Vec<VideoMode> {
+        let mut result = Vec::new();
+        for mode in self.0.video_modes() {
+            if !result.contains(&mode) {
+                result.push(mode);
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("Monitor");
+        d.field("handle", &self.handle_ptr());
+        // The queries could fail for a disconnected monitor
+        if self.0.is_valid() {
+            d.field("name", &self.name())
+                .field("position", &self.position())
+                .field("size", &self.size())
+                .field("dpi", &self.dpi())
+                .field("is_primary", &self.is_primary());
+        }
+        d.finish()
+    }
+}
+
+/// A resolution, color depth and refresh rate combination a monitor supports,
+/// see `Monitor::video_modes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMode {
+    pub size: PhysicalSize,
+    /// The number of bits per pixel.
+    pub bit_depth: u32,
+    /// The refresh rate in Hz, 0 if unknown.
+    pub refresh_rate: u32,
 }
 
 #[derive(Debug)]
-pub struct EventLoop(EventLoopImpl);
+pub struct EventLoop<T = ()> {
+    inner: EventLoopImpl,
+    // The user events sent by the `EventLoopProxy`s
+    sender: mpsc::Sender<T>,
+    receiver: Rc<mpsc::Receiver<T>>,
+}
+
+impl EventLoop<()> {
+    pub fn new() -> Self { Self::with_user_event() }
+}
+
+impl Default for EventLoop<()> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> EventLoop<T> {
+    /// Creates an `EventLoop`, that can receive user events of type `T` from
+    /// other threads, see `create_proxy`.
+    pub fn with_user_event() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self{
+            inner: EventLoopImpl::new(),
+            sender,
+            receiver: Rc::new(receiver),
+        }
+    }
 
-impl EventLoop {
-    pub fn new() -> Self { Self(EventLoopImpl::new()) }
+    /// Creates an `EventLoopProxy`, that can send `Event::UserEvent`s to this
+    /// `EventLoop` from any thread, waking it up, if it's waiting.
+    pub fn create_proxy(&self) -> EventLoopProxy<T> {
+        EventLoopProxy{
+            sender: self.sender.clone(),
+            waker: Arc::new(self.inner.create_waker()),
+        }
+    }
+
+    pub fn add_window(&self, wnd: &Window) {
+        self.inner.add_window(&wnd.0);
+    }
 
-    pub fn add_window(&mut self, wnd: &Window) {
-        self.0.add_window(&wnd.0);
+    /// Returns the text on the system clipboard, `None`, if the clipboard is
+    /// empty or holds no text.
+    ///
+    /// On X11 the clipboard is the `CLIPBOARD` selection. There's no central
+    /// storage for it, the text is asked from the client that copied it, and
+    /// this waits for at most a second for the answer.
+    pub fn clipboard_text(&self) -> Option<String> {
+        self.inner.clipboard_text()
     }
 
-    pub fn run<F>(&mut self, f: F)
-        where F: FnMut(&mut ControlFlow, Event) + 'static {
-        self.0.run(f);
+    /// Puts the given text on the system clipboard. Returns `true`, if it
+    /// succeeded.
+    ///
+    /// On Windows the first added window becomes the owner of the clipboard.
+    /// On X11 this takes the ownership of the `CLIPBOARD` selection, and the
+    /// text is handed out by the event loop, when other clients ask for it.
+    /// This means, that the text is only available to others while `run` is
+    /// processing events, and it's lost when this `EventLoop` is dropped.
+    pub fn set_clipboard_text(&mut self, text: &str) -> bool {
+        self.inner.set_clipboard_text(text)
+    }
+
+    pub fn run<F>(&mut self, mut f: F)
+        where F: FnMut(&mut ControlFlow, Event<T>) + 'static, T: 'static {
+        self.run_timed(move |control_flow, e| f(control_flow, e.event));
+    }
+
+    /// Like `run`, but every `Event` is paired with the time it was taken from
+    /// the system queue.
+    ///
+    /// The timestamps are monotonic: an `Event` is never reported earlier than
+    /// the one before it. Where the system provides the time a message was
+    /// posted at, that is used, otherwise the time of dequeuing.
+    pub fn run_timed<F>(&mut self, mut f: F)
+        where F: FnMut(&mut ControlFlow, TimedEvent<T>) + 'static, T: 'static {
+        let receiver = self.receiver.clone();
+        let mut last_time: Option<Instant> = None;
+        let mut emit = move |control_flow: &mut ControlFlow, mut e: TimedEvent<T>| {
+            // The sources of the timestamps can differ, never go backwards
+            if let Some(last_time) = last_time {
+                if e.time < last_time {
+                    e.time = last_time;
+                }
+            }
+            last_time = Some(e.time);
+            f(control_flow, e);
+        };
+        self.inner.run(move |control_flow, e| {
+            let time = e.time;
+            match e.event.with_user_event() {
+                Some(event) => emit(control_flow, TimedEvent::new(time, event)),
+                None => {
+                    // A wakeup from a proxy, one wakeup can carry many events
+                    for event in receiver.try_iter() {
+                        emit(control_flow, TimedEvent::new(time, Event::UserEvent(event)));
+                    }
+                },
+            }
+        });
     }
 }
 
-#[derive(Debug)]
 pub struct Window(WindowImpl);
 
 impl Window {
-    pub fn new() -> Self { Self(WindowImpl::new()) }
+    pub fn new() -> Self {
+        let attribs = WindowAttributes::default();
+        Self(WindowImpl::new(&attribs).expect("Failed to create the window!"))
+    }
 
     pub fn id(&self) -> WindowId { WindowId(self.handle_ptr()) }
     pub fn handle_ptr(&self) -> *const c_void { self.0.handle_ptr() }
@@ -74,8 +229,19 @@ impl Window {
 
     pub fn monitor(&self) -> Monitor { Monitor(self.0.monitor()) }
 
-    pub fn dpi(&self) -> Dpi { self.monitor().dpi() }
-    pub fn scale(&self) -> f64 { self.monitor().scale() }
+    /// Returns the current DPI of the window. This can differ from the DPI
+    /// of the monitor, for example while the window is dragged between
+    /// monitors. When the platform doesn't track the DPI per window, the
+    /// monitor value is returned.
+    pub fn dpi(&self) -> Dpi {
+        self.0.dpi().unwrap_or_else(|| self.monitor().dpi())
+    }
+
+    /// Returns the current scale factor of the window. Like `dpi`, this falls
+    /// back to the monitor value, when there's no per-window information.
+    pub fn scale(&self) -> f64 {
+        self.0.scale().unwrap_or_else(|| self.monitor().scale())
+    }
 
     pub fn inner_size(&self) -> PhysicalSize {
         self.0.inner_size()
@@ -85,6 +251,14 @@ impl Window {
         self.0.outer_size()
     }
 
+    pub fn position(&self) -> PhysicalPosition {
+        self.0.position()
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.0.title()
+    }
+
     pub fn set_visible(&mut self, vis: bool) {
         self.0.set_visible(vis)
     }
@@ -93,6 +267,16 @@ impl Window {
         self.0.set_resizable(res)
     }
 
+    /// Shows or hides the decorations of the window - the title bar and the
+    /// border -, for applications drawing their own. The inner size of the
+    /// window is kept. Returns `true`, if the decorations were changed.
+    ///
+    /// On X11 this is a hint for the window manager through
+    /// `_MOTIF_WM_HINTS`, which most of them honor.
+    pub fn set_decorations(&mut self, d: bool) -> bool {
+        self.0.set_decorations(d)
+    }
+
     pub fn set_title(&mut self, title: &str) -> bool {
         self.0.set_title(title)
     }
@@ -113,13 +297,299 @@ impl Window {
         self.0.set_pinned(p)
     }
 
+    /// Sets the opacity of the whole window, where `0.0` is fully transparent
+    /// and `1.0` is opaque. Values outside of that range are clamped. Returns
+    /// `true`, if the opacity was applied.
+    ///
+    /// On Windows this makes the window layered, if it wasn't already. Only a
+    /// uniform alpha is supported, per-pixel alpha would need
+    /// `UpdateLayeredWindow` instead. On X11 the opacity is a hint for the
+    /// compositing window manager, without one it has no visible effect.
     pub fn set_transparency(&mut self, t: f64) -> bool {
         self.0.set_transparency(t)
     }
 
-    pub fn set_fullscreen(&mut self, fs: bool) -> bool {
+    /// Sets, if the contents of the window should be excluded from screen
+    /// captures and recordings. Returns `true`, if the platform honored it.
+    ///
+    /// On Windows 10 version 2004 and later the window is left out of the
+    /// captures, on earlier versions it's shown as a black rectangle instead.
+    /// On X11 this is unsupported, and always returns `false`.
+    pub fn set_content_protected(&mut self, p: bool) -> bool {
+        self.0.set_content_protected(p)
+    }
+
+    /// Sets, if the window should receive `WindowEvent::RawMouseMotion`
+    /// events. Returns `true`, if raw mouse input could be enabled or
+    /// disabled.
+    ///
+    /// Raw motion comes directly from the mouse, before the system applies
+    /// its acceleration and clamps the cursor to the screen, which makes it
+    /// the right choice for first-person controls. It's only delivered while
+    /// the window has focus, and every window can enable or disable it
+    /// independently. On X11 this requires the XInput 2 extension.
+    pub fn set_raw_mouse(&mut self, raw: bool) -> bool {
+        self.0.set_raw_mouse(raw)
+    }
+
+    /// Switches the window between windowed and the fullscreen modes. Going
+    /// back to `Fullscreen::None` restores the window to the placement it had
+    /// before entering fullscreen, and the monitor to its original video mode.
+    /// Returns `true`, if the mode was applied.
+    pub fn set_fullscreen(&mut self, fs: Fullscreen) -> bool {
         self.0.set_fullscreen(fs)
     }
+
+    /// Makes the inner size of the window snap to multiples of the given
+    /// increments, when the user resizes it. This is useful for grid-based
+    /// content, like a terminal with fixed size character-cells. `None`
+    /// removes the increments. Returns `true`, if the setting was applied.
+    ///
+    /// The increments only affect user resizing, not `set_inner_size` or
+    /// `set_outer_size`. When the window is not resizable, its fixed size
+    /// takes precedence over the increments.
+    pub fn set_resize_increments(&mut self, inc: Option<PhysicalSize>) -> bool {
+        self.0.set_resize_increments(inc)
+    }
+
+    /// Sets the shape of the mouse cursor, while it's over the window. Returns
+    /// `true`, if the cursor could be changed.
+    ///
+    /// The shape is remembered by the window. When it loses focus or the
+    /// cursor leaves it, other windows are free to change the shape, but this
+    /// one is restored once the cursor is over the window again.
+    pub fn set_cursor_icon(&mut self, icon: CursorIcon) -> bool {
+        self.0.set_cursor_icon(icon)
+    }
+
+    /// Asks the user to pay attention to the window, usually by flashing its
+    /// taskbar entry. This is useful for background windows, for example when
+    /// a long task completes. `None` cancels a previous request, and the
+    /// request is cancelled automatically when the window gets focused.
+    /// Returns `true`, if the request was made.
+    pub fn request_attention(&mut self, level: Option<AttentionLevel>) -> bool {
+        self.0.request_attention(level)
+    }
+
+    /// Starts moving the window with the mouse, as if the user grabbed its
+    /// title bar. This allows borderless windows to implement their own title
+    /// bars. The window manager takes over until the mouse button is released.
+    ///
+    /// This must be called while handling a mouse button press, otherwise the
+    /// window manager can ignore the request. Returns `true`, if the request
+    /// was sent.
+    pub fn begin_drag(&mut self) -> bool {
+        self.0.begin_drag()
+    }
+
+    /// Draws the given pixels into the client area of the window, starting at
+    /// its top-left corner, without any scaling. This allows simple rendering
+    /// on the CPU, without a graphics API. Returns `true`, if the pixels were
+    /// drawn.
+    ///
+    /// The pixels are 4 bytes each, in red, green, blue, alpha order, and the
+    /// alpha is ignored. The rows are top-down, so the first `width * 4` bytes
+    /// are the top row. This is the order most image libraries use, but not
+    /// the bottom-up order of the Windows bitmaps, the conversion is done
+    /// here. Returns `false`, if `pixels` isn't exactly `width * height * 4`
+    /// bytes long.
+    ///
+    /// The window doesn't keep the pixels, so they have to be presented again
+    /// when the window is exposed or resized.
+    pub fn present_rgba(&mut self, pixels: &[u8], width: u32, height: u32) -> bool {
+        let len = (width as u64) * (height as u64) * 4;
+        if width == 0 || height == 0 || pixels.len() as u64 != len {
+            return false;
+        }
+        self.0.present_rgba(pixels, width, height)
+    }
+
+    /// Moves the window, so it's centered in the work area of the given
+    /// monitor. The whole window is centered, including its decorations.
+    /// Returns `false` without moving the window, if it's larger than the work
+    /// area, otherwise `true`, if the window was moved.
+    pub fn center_on_monitor(&mut self, monitor: &Monitor) -> bool {
+        let (pos, siz) = monitor.work_area();
+        match centered_position(pos, siz, self.outer_size()) {
+            Some(pos) => self.set_position(pos),
+            None => false,
+        }
+    }
+
+    /// Moves the window, so it's centered in the work area of the monitor it's
+    /// currently on. See `center_on_monitor`.
+    pub fn center_on_current_monitor(&mut self) -> bool {
+        let monitor = self.monitor();
+        self.center_on_monitor(&monitor)
+    }
+
+    /// Moves the window onto the primary monitor, if it doesn't intersect any
+    /// of the connected monitors. This is useful when restoring a saved
+    /// position, as the monitor it was on might have been disconnected since.
+    /// Returns `true`, if the window is visible on some monitor.
+    pub fn ensure_visible(&mut self) -> bool {
+        let pos = self.position();
+        let siz = self.outer_size();
+        let monitors = Monitor::all_monitors();
+        if monitors.iter().any(|m| rects_intersect(pos, siz, m.position(), m.size())) {
+            return true;
+        }
+        let monitor = match monitors.iter().find(|m| m.is_primary()).or_else(|| monitors.first()) {
+            Some(m) => m,
+            None => return false,
+        };
+        // The range of top-left positions that keeps the whole window on the
+        // monitor, or just the top-left corner, if the window is too big
+        let msiz = monitor.size();
+        let room = PhysicalSize::new(
+            msiz.width.saturating_sub(siz.width) + 1,
+            msiz.height.saturating_sub(siz.height) + 1);
+        self.set_position(pos.clamp_to_bounds(monitor.position(), room))
+    }
+}
+
+/// Configures a `Window` before creating it. Unlike calling the setters after
+/// `Window::new`, the window is created with these settings at once, so it
+/// doesn't flicker while it's being configured.
+///
+/// Without any configuration, the window is built just like `Window::new`
+/// would create it: hidden, resizable and with the size and position chosen
+/// by the system.
+///
+/// # Examples
+///
+/// ```ignore
+/// use cacti_window::{EventLoop, PhysicalSize, WindowBuilder};
+///
+/// let event_loop = EventLoop::new();
+/// let window = WindowBuilder::new()
+///     .with_title("Hello")
+///     .with_inner_size(PhysicalSize::new(640, 480))
+///     .with_resizable(false)
+///     .with_visible(true)
+///     .build(&event_loop)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowBuilder {
+    attribs: WindowAttributes,
+}
+
+impl WindowBuilder {
+    pub fn new() -> Self {
+        Self{ attribs: WindowAttributes::default() }
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.attribs.title = title.to_string();
+        self
+    }
+
+    pub fn with_inner_size(mut self, siz: PhysicalSize) -> Self {
+        self.attribs.inner_size = Some(siz);
+        self
+    }
+
+    pub fn with_position(mut self, pos: PhysicalPosition) -> Self {
+        self.attribs.position = Some(pos);
+        self
+    }
+
+    pub fn with_resizable(mut self, res: bool) -> Self {
+        self.attribs.resizable = res;
+        self
+    }
+
+    pub fn with_visible(mut self, vis: bool) -> Self {
+        self.attribs.visible = vis;
+        self
+    }
+
+    /// Sets, if files can be dropped on the window, see
+    /// `WindowEvent::FileDropped`. Disabled by default. This can only be
+    /// decided at creation, the system has to know before the first drag
+    /// reaches the window.
+    pub fn with_file_drop(mut self, drop: bool) -> Self {
+        self.attribs.file_drop = drop;
+        self
+    }
+
+    /// Creates the configured `Window`, and adds it to the given `EventLoop`.
+    ///
+    /// # Errors
+    ///
+    /// In case of a system error, an error variant is returned.
+    pub fn build<T>(&self, event_loop: &EventLoop<T>) -> io::Result<Window> {
+        let wnd = Window(WindowImpl::new(&self.attribs)?);
+        event_loop.add_window(&wnd);
+        Ok(wnd)
+    }
+}
+
+impl Default for WindowBuilder {
+    fn default() -> Self { Self::new() }
+}
+
+impl fmt::Debug for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("Window");
+        d.field("id", &self.id());
+        // The queries could fail for a destroyed window
+        if self.0.is_valid() {
+            d.field("inner_size", &self.inner_size())
+                .field("title", &self.title());
+        }
+        d.finish()
+    }
+}
+
+/// The fullscreen modes of a window, see `Window::set_fullscreen`.
+#[derive(Debug)]
+pub enum Fullscreen {
+    /// A regular, windowed window.
+    None,
+    /// Removes the decorations and covers the given monitor - or the one the
+    /// window is on, when `None` - without changing its video mode. This is
+    /// cheap to switch to and from, and plays well with other windows.
+    Borderless(Option<Monitor>),
+    /// Switches the monitor the window is on to the given video mode, and
+    /// covers it. Switching is slower than `Borderless`, but applications can
+    /// pick a resolution, that suits them.
+    Exclusive(VideoMode),
+}
+
+/// The urgency of an attention request, see `Window::request_attention`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttentionLevel {
+    /// Notify until the user focuses the window.
+    Critical,
+    /// Notify briefly, once.
+    Informational,
+}
+
+/// The standard mouse cursor shapes, see `Window::set_cursor_icon`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CursorIcon {
+    /// The usual arrow.
+    Default,
+    /// A pointing hand, usually for links.
+    Pointer,
+    /// A text-beam, for selecting text.
+    Text,
+    /// A crosshair, for precise selection.
+    Crosshair,
+    /// Signals that the application is busy.
+    Wait,
+    /// A vertical, north-south resize arrow.
+    ResizeNS,
+    /// A horizontal, east-west resize arrow.
+    ResizeEW,
+    /// A diagonal resize arrow, from the north-east to the south-west.
+    ResizeNESW,
+    /// A diagonal resize arrow, from the north-west to the south-east.
+    ResizeNWSE,
+    /// Signals that the hovered action can't be done.
+    NotAllowed,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -132,8 +602,38 @@ pub enum ControlFlow {
     Exit,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Event {
+/// Sends user events to an `EventLoop` from any thread, see
+/// `EventLoop::create_proxy`.
+#[derive(Debug)]
+pub struct EventLoopProxy<T> {
+    sender: mpsc::Sender<T>,
+    waker: Arc<WakerImpl>,
+}
+
+impl<T> EventLoopProxy<T> {
+    /// Sends the given event to the `EventLoop`, that reports it as an
+    /// `Event::UserEvent`. Returns `true`, if it succeeded, `false`, if the
+    /// `EventLoop` is gone.
+    ///
+    /// The events are delivered in the order they were sent. Sending to an
+    /// `EventLoop`, that is not running yet, delivers the events once it
+    /// starts running.
+    pub fn send_event(&self, event: T) -> bool {
+        self.sender.send(event).is_ok() && self.waker.wake()
+    }
+}
+
+impl<T> Clone for EventLoopProxy<T> {
+    fn clone(&self) -> Self {
+        Self{
+            sender: self.sender.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<T = ()> {
     WindowEvent{
         window_id: WindowId,
         event: WindowEvent,
@@ -142,16 +642,76 @@ pub enum Event {
     Redraw(WindowId),
     AfterRedraw,
     LoopExited,
+    /// An event sent by an `EventLoopProxy`.
+    UserEvent(T),
+}
+
+impl Event {
+    /// Converts an `Event` reported by the platform to one with user events
+    /// of type `T`. Returns `None` for `UserEvent`, which is only a wakeup on
+    /// the platform level.
+    fn with_user_event<T>(self) -> Option<Event<T>> {
+        Some(match self {
+            Event::WindowEvent{ window_id, event } => Event::WindowEvent{ window_id, event },
+            Event::LogicUpdate => Event::LogicUpdate,
+            Event::Redraw(window_id) => Event::Redraw(window_id),
+            Event::AfterRedraw => Event::AfterRedraw,
+            Event::LoopExited => Event::LoopExited,
+            Event::UserEvent(()) => return None,
+        })
+    }
+}
+
+/// An `Event` with the time it happened at, see `EventLoop::run_timed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEvent<T = ()> {
+    /// The time the `Event` was taken from the system queue.
+    pub time: Instant,
+    /// The `Event` itself.
+    pub event: Event<T>,
+}
+
+impl<T> TimedEvent<T> {
+    fn new(time: Instant, event: Event<T>) -> Self {
+        Self{ time, event }
+    }
+
+    fn now(event: Event<T>) -> Self {
+        Self::new(Instant::now(), event)
+    }
 }
 
 // TODO: Event for DPI/scale changes
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WindowEvent {
     Created,
     CloseRequested,
     Closed,
     FocusChanged(bool),
     Resized(PhysicalSize),
+    /// A file is dragged over the window. Sent once for each file being
+    /// dragged. Not reported on Windows, where only the drop is observable.
+    FileHovered(PathBuf),
+    /// A file was dropped on the window. Dropping multiple files at once
+    /// sends one event for each file.
+    ///
+    /// Only sent, if the window was built with
+    /// `WindowBuilder::with_file_drop(true)`.
+    FileDropped(PathBuf),
+    /// The files reported by `FileHovered` left the window without being
+    /// dropped.
+    FileHoverCancelled,
+    /// The mouse moved, reported with the unaccelerated device deltas. The
+    /// motion is relative, it's not a position and it's not in pixels, a
+    /// mouse with a higher resolution reports bigger deltas for the same
+    /// physical distance. It's also reported, when the cursor is stuck at
+    /// the edge of the screen.
+    ///
+    /// Only sent, if enabled with `Window::set_raw_mouse`.
+    RawMouseMotion{
+        dx: f64,
+        dy: f64,
+    },
 }
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -187,6 +747,34 @@ impl PhysicalPosition {
             y: self.y as f64 / scale,
         }
     }
+
+    /// Returns the closest position that lies on the given monitor.
+    pub fn clamp_to(&self, monitor: &Monitor) -> PhysicalPosition {
+        self.clamp_to_bounds(monitor.position(), monitor.size())
+    }
+
+    fn relative_to(&self, origin: PhysicalPosition) -> PhysicalPosition {
+        PhysicalPosition{
+            x: self.x.saturating_sub(origin.x),
+            y: self.y.saturating_sub(origin.y),
+        }
+    }
+
+    fn offset_by(&self, origin: PhysicalPosition) -> PhysicalPosition {
+        PhysicalPosition{
+            x: self.x.saturating_add(origin.x),
+            y: self.y.saturating_add(origin.y),
+        }
+    }
+
+    fn clamp_to_bounds(&self, pos: PhysicalPosition, siz: PhysicalSize) -> PhysicalPosition {
+        let max_x = pos.x.saturating_add((siz.width as i32).saturating_sub(1).max(0));
+        let max_y = pos.y.saturating_add((siz.height as i32).saturating_sub(1).max(0));
+        PhysicalPosition{
+            x: self.x.max(pos.x).min(max_x),
+            y: self.y.max(pos.y).min(max_y),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -219,10 +807,16 @@ impl LogicalPosition {
         Self{ x, y }
     }
 
+    /// Converts to a physical position, rounding to the nearest pixel.
     pub fn to_physical(&self, scale: f64) -> PhysicalPosition {
+        self.to_physical_rounded(scale, Rounding::Nearest)
+    }
+
+    /// Converts to a physical position, rounding with the given mode.
+    pub fn to_physical_rounded(&self, scale: f64, rounding: Rounding) -> PhysicalPosition {
         PhysicalPosition{
-            x: (self.x * scale) as i32,
-            y: (self.y * scale) as i32,
+            x: rounding.apply(self.x * scale) as i32,
+            y: rounding.apply(self.y * scale) as i32,
         }
     }
 }
@@ -238,14 +832,88 @@ impl LogicalSize {
         Self{ width, height }
     }
 
+    /// Converts to a physical size, rounding to the nearest pixel. This way
+    /// converting a `PhysicalSize` to logical and back gives the original.
     pub fn to_physical(&self, scale: f64) -> PhysicalSize {
+        self.to_physical_rounded(scale, Rounding::Nearest)
+    }
+
+    /// Converts to a physical size, rounding with the given mode. `Ceil` makes
+    /// sure the whole logical area fits, `Floor` makes sure nothing outside of
+    /// it is covered.
+    pub fn to_physical_rounded(&self, scale: f64, rounding: Rounding) -> PhysicalSize {
         PhysicalSize{
-            width: (self.width * scale) as u32,
-            height: (self.height * scale) as u32,
+            width: rounding.apply(self.width * scale) as u32,
+            height: rounding.apply(self.height * scale) as u32,
+        }
+    }
+}
+
+/// The ways of rounding logical coordinates to physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Rounds to the nearest pixel, halfway cases away from zero.
+    Nearest,
+    /// Rounds towards negative infinity.
+    Floor,
+    /// Rounds towards positive infinity.
+    Ceil,
+}
+
+impl Rounding {
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            Self::Nearest => value.round(),
+            Self::Floor => value.floor(),
+            Self::Ceil => value.ceil(),
         }
     }
 }
 
+fn rects_intersect(
+    p1: PhysicalPosition, s1: PhysicalSize,
+    p2: PhysicalPosition, s2: PhysicalSize) -> bool {
+    let (x1, y1, w1, h1) = (p1.x as i64, p1.y as i64, s1.width as i64, s1.height as i64);
+    let (x2, y2, w2, h2) = (p2.x as i64, p2.y as i64, s2.width as i64, s2.height as i64);
+    x1 < x2 + w2 && x2 < x1 + w1 && y1 < y2 + h2 && y2 < y1 + h1
+}
+
+/// Returns the top-left position, that centers a rectangle of size `siz` in
+/// the area at `area_pos` with size `area_siz`. Returns `None`, if it doesn't
+/// fit.
+fn centered_position(
+    area_pos: PhysicalPosition, area_siz: PhysicalSize,
+    siz: PhysicalSize) -> Option<PhysicalPosition> {
+    if siz.width > area_siz.width || siz.height > area_siz.height {
+        return None;
+    }
+    let x = area_pos.x as i64 + ((area_siz.width - siz.width) / 2) as i64;
+    let y = area_pos.y as i64 + ((area_siz.height - siz.height) / 2) as i64;
+    Some(PhysicalPosition::new(x as i32, y as i32))
+}
+
+/// Clamps an opacity into the `[0, 1]` range, treating NaN as transparent.
+fn clamp_opacity(t: f64) -> f64 {
+    if t.is_nan() || t < 0.0 {
+        0.0
+    }
+    else if t > 1.0 {
+        1.0
+    }
+    else {
+        t
+    }
+}
+
+/// Converts RGBA pixels to BGRA, the order the native bitmaps expect.
+fn rgba_to_bgra(pixels: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(pixels.len());
+    for p in pixels.chunks_exact(4) {
+        result.extend_from_slice(&[p[2], p[1], p[0], p[3]]);
+    }
+    result
+}
+
 // ////////////////////////////////////////////////////////////////////////// //
 //                               Implementation                               //
 // ////////////////////////////////////////////////////////////////////////// //
@@ -254,43 +922,98 @@ trait MonitorTrait: Sized {
     fn all_monitors() -> Vec<Self>;
 
     fn handle_ptr(&self) -> *mut c_void;
+    fn is_valid(&self) -> bool;
 
     fn name(&self) -> Option<String>;
     fn is_primary(&self) -> bool;
     fn position(&self) -> PhysicalPosition;
     fn size(&self) -> PhysicalSize;
+    fn work_area(&self) -> (PhysicalPosition, PhysicalSize);
     fn dpi(&self) -> Dpi;
     fn scale(&self) -> f64;
+    fn video_modes(&self) -> Vec<VideoMode>;
 }
 
 trait EventLoopTrait {
     fn new() -> Self;
 
-    fn add_window(&mut self, wnd: &WindowImpl);
+    fn add_window(&self, wnd: &WindowImpl);
+
+    fn clipboard_text(&self) -> Option<String>;
+    fn set_clipboard_text(&mut self, text: &str) -> bool;
+
+    fn create_waker(&self) -> WakerImpl;
 
     fn run<F>(&mut self, f: F)
-        where F: FnMut(&mut ControlFlow, Event) + 'static;
+        where F: FnMut(&mut ControlFlow, TimedEvent) + 'static;
+}
+
+/// The settings a window is created with.
+#[derive(Debug, Clone, PartialEq)]
+struct WindowAttributes {
+    title: String,
+    // `None` lets the system decide
+    inner_size: Option<PhysicalSize>,
+    // `None` lets the system decide
+    position: Option<PhysicalPosition>,
+    resizable: bool,
+    visible: bool,
+    // Accept drag-and-dropped files
+    file_drop: bool,
+}
+
+impl Default for WindowAttributes {
+    fn default() -> Self {
+        Self{
+            title: "Window Name".to_string(),
+            inner_size: None,
+            position: None,
+            resizable: true,
+            visible: false,
+            file_drop: false,
+        }
+    }
+}
+
+/// Wakes up an `EventLoop` from another thread, making it report an
+/// `Event::UserEvent(())`.
+trait WakerTrait: Send + Sync {
+    fn wake(&self) -> bool;
 }
 
 trait WindowTrait: Sized {
-    fn new() -> Self;
+    fn new(attribs: &WindowAttributes) -> io::Result<Self>;
 
     fn handle_ptr(&self) -> *mut c_void;
 
+    fn is_valid(&self) -> bool;
+
     fn monitor(&self) -> MonitorImpl;
 
     fn inner_size(&self) -> PhysicalSize;
     fn outer_size(&self) -> PhysicalSize;
+    fn dpi(&self) -> Option<Dpi>;
+    fn scale(&self) -> Option<f64>;
+    fn position(&self) -> PhysicalPosition;
+    fn title(&self) -> Option<String>;
 
     fn set_visible(&mut self, vis: bool);
     fn set_resizable(&mut self, res: bool) -> bool;
+    fn set_decorations(&mut self, d: bool) -> bool;
     fn set_title(&mut self, title: &str) -> bool;
     fn set_position(&mut self, pos: PhysicalPosition) -> bool;
     fn set_inner_size(&mut self, siz: PhysicalSize) -> bool;
     fn set_outer_size(&mut self, siz: PhysicalSize) -> bool;
     fn set_pinned(&mut self, p: bool) -> bool;
     fn set_transparency(&mut self, t: f64) -> bool;
-    fn set_fullscreen(&mut self, fs: bool) -> bool;
+    fn set_content_protected(&mut self, p: bool) -> bool;
+    fn set_raw_mouse(&mut self, raw: bool) -> bool;
+    fn set_fullscreen(&mut self, fs: Fullscreen) -> bool;
+    fn set_resize_increments(&mut self, inc: Option<PhysicalSize>) -> bool;
+    fn set_cursor_icon(&mut self, icon: CursorIcon) -> bool;
+    fn request_attention(&mut self, level: Option<AttentionLevel>) -> bool;
+    fn begin_drag(&mut self) -> bool;
+    fn present_rgba(&mut self, pixels: &[u8], width: u32, height: u32) -> bool;
 }
 
 mod win32;
@@ -302,6 +1025,7 @@ mod impls {
 
     pub type MonitorImpl = Win32Monitor;
     pub type EventLoopImpl = Win32EventLoop;
+    pub type WakerImpl = Win32Waker;
     pub type WindowImpl = Win32Window;
 }
 
@@ -311,11 +1035,13 @@ mod impls {
 
     pub type MonitorImpl = X11Monitor;
     pub type EventLoopImpl = X11EventLoop;
+    pub type WakerImpl = X11Waker;
     pub type WindowImpl = X11Window;
 }
 
 type MonitorImpl = impls::MonitorImpl;
 type EventLoopImpl = impls::EventLoopImpl;
+type WakerImpl = impls::WakerImpl;
 type WindowImpl = impls::WindowImpl;
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -344,4 +1070,460 @@ mod tests {
         });
         assert_eq!(events.borrow().as_slice(), &[]);
     }
+
+    #[test]
+    fn test_clone_recorded_events() {
+        let (events, events_in) = new_event_vec();
+        let wnd = Window::new();
+        let mut event_loop = EventLoop::new();
+        event_loop.add_window(&wnd);
+        event_loop.run(move |control_flow, event| {
+            *control_flow = ControlFlow::Exit;
+
+            events_in.borrow_mut().push(event);
+        });
+        let recorded = events.borrow().clone();
+        assert_eq!(recorded, *events.borrow());
+        let duplicated: Vec<_> = recorded.iter().cloned().chain(recorded.iter().cloned()).collect();
+        assert_eq!(duplicated.len(), recorded.len() * 2);
+        assert_eq!(&duplicated[recorded.len()..], recorded.as_slice());
+    }
+
+    #[test]
+    fn test_multiple_windows() {
+        let (events, events_in) = new_event_vec();
+        let mut wnd1 = Window::new();
+        let mut wnd2 = Window::new();
+        wnd1.set_visible(true);
+        wnd2.set_visible(true);
+        let ids = [wnd1.id(), wnd2.id()];
+        assert_ne!(ids[0], ids[1]);
+
+        let mut event_loop = EventLoop::new();
+        event_loop.add_window(&wnd1);
+        event_loop.add_window(&wnd2);
+        let mut iterations = 0;
+        event_loop.run(move |control_flow, event| {
+            if let Event::AfterRedraw = event {
+                iterations += 1;
+                if iterations == 10 {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            events_in.borrow_mut().push(event);
+        });
+
+        let mut redrawn = Vec::new();
+        for e in events.borrow().iter() {
+            match e {
+                Event::WindowEvent{ window_id, .. } => assert!(ids.contains(window_id)),
+                Event::Redraw(window_id) => {
+                    assert!(ids.contains(window_id));
+                    redrawn.push(*window_id);
+                },
+                _ => {},
+            }
+        }
+        // Both windows got their own redraw
+        assert!(redrawn.contains(&ids[0]));
+        assert!(redrawn.contains(&ids[1]));
+    }
+
+    #[test]
+    fn test_timed_events_monotonic() {
+        let times = Rc::new(RefCell::new(Vec::new()));
+        let times_in = times.clone();
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        let mut event_loop = EventLoop::new();
+        event_loop.add_window(&wnd);
+        let start = Instant::now();
+        let mut iterations = 0;
+        event_loop.run_timed(move |control_flow, e| {
+            if let Event::AfterRedraw = e.event {
+                iterations += 1;
+                if iterations == 10 {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            times_in.borrow_mut().push(e.time);
+        });
+
+        let times = times.borrow();
+        assert!(times.len() >= 10);
+        assert!(times.windows(2).all(|w| w[0] <= w[1]));
+        assert!(*times.last().unwrap() >= start);
+    }
+
+    #[test]
+    fn test_proxy_user_events() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received_in = received.clone();
+        let mut event_loop = EventLoop::with_user_event();
+        let proxy = event_loop.create_proxy();
+        let sender = std::thread::spawn(move || {
+            // Make sure the loop is already waiting
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            (0..3).all(|i| proxy.send_event(i))
+        });
+        event_loop.run(move |control_flow, event| {
+            *control_flow = ControlFlow::Wait;
+            if let Event::UserEvent(i) = event {
+                received_in.borrow_mut().push(i);
+            }
+            if received_in.borrow().len() == 3 {
+                *control_flow = ControlFlow::Exit;
+            }
+        });
+
+        assert!(sender.join().unwrap());
+        assert_eq!(received.borrow().as_slice(), &[0, 1, 2]);
+        // The loop is gone, nothing receives the events
+        let proxy = event_loop.create_proxy();
+        drop(event_loop);
+        assert!(!proxy.send_event(3));
+    }
+
+    #[test]
+    fn test_begin_drag_smoke() {
+        // There's no mouse button held, this should just be ignored
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        wnd.begin_drag();
+        let mut event_loop = EventLoop::new();
+        event_loop.add_window(&wnd);
+        event_loop.run(move |control_flow, _| {
+            *control_flow = ControlFlow::Exit;
+        });
+    }
+
+    #[test]
+    fn test_set_resize_increments() {
+        let mut wnd = Window::new();
+        assert!(wnd.set_resize_increments(Some(PhysicalSize::new(8, 16))));
+        assert!(wnd.set_resizable(false));
+        assert!(wnd.set_resizable(true));
+        assert!(wnd.set_resize_increments(None));
+        // Degenerate increments are rejected
+        assert!(!wnd.set_resize_increments(Some(PhysicalSize::new(0, 16))));
+    }
+
+    #[test]
+    fn test_set_decorations() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        let siz = wnd.inner_size();
+        assert!(wnd.set_decorations(false));
+        assert_eq!(wnd.inner_size(), siz);
+        assert!(wnd.set_decorations(true));
+        assert_eq!(wnd.inner_size(), siz);
+    }
+
+    #[test]
+    fn test_set_cursor_icon_smoke() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        let icons = [
+            CursorIcon::Pointer, CursorIcon::Text, CursorIcon::Crosshair,
+            CursorIcon::Wait, CursorIcon::ResizeNS, CursorIcon::ResizeEW,
+            CursorIcon::ResizeNESW, CursorIcon::ResizeNWSE, CursorIcon::NotAllowed,
+            CursorIcon::Default,
+        ];
+        for icon in icons.iter() {
+            assert!(wnd.set_cursor_icon(*icon));
+        }
+    }
+
+    #[test]
+    fn test_request_attention_smoke() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        assert!(wnd.request_attention(Some(AttentionLevel::Informational)));
+        assert!(wnd.request_attention(Some(AttentionLevel::Critical)));
+        assert!(wnd.request_attention(None));
+    }
+
+    #[test]
+    fn test_window_dpi() {
+        let wnd = Window::new();
+        let dpi = wnd.dpi();
+        assert!(dpi.horizontal > 0.0 && dpi.horizontal < 10000.0);
+        assert!(dpi.vertical > 0.0 && dpi.vertical < 10000.0);
+        assert!(wnd.scale() > 0.0);
+    }
+
+    #[test]
+    fn test_window_builder() -> io::Result<()> {
+        let event_loop = EventLoop::new();
+        let wnd = WindowBuilder::new()
+            .with_title("Built window")
+            .with_inner_size(PhysicalSize::new(320, 240))
+            .with_position(PhysicalPosition::new(50, 60))
+            .with_resizable(false)
+            .with_visible(true)
+            .build(&event_loop)?;
+        assert_eq!(wnd.title().as_deref(), Some("Built window"));
+        assert_eq!(wnd.inner_size(), PhysicalSize::new(320, 240));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_drop_events() -> io::Result<()> {
+        // Can't drop files from a test, make sure the events can be handled
+        let mut event_loop = EventLoop::new();
+        let _wnd = WindowBuilder::new()
+            .with_file_drop(true)
+            .build(&event_loop)?;
+        let hovered = Rc::new(RefCell::new(Vec::new()));
+        let hovered_in = hovered.clone();
+        event_loop.run(move |control_flow, event| {
+            *control_flow = ControlFlow::Exit;
+
+            if let Event::WindowEvent{ event, .. } = event {
+                match event {
+                    WindowEvent::FileHovered(path) => hovered_in.borrow_mut().push(path),
+                    WindowEvent::FileDropped(path) => assert!(path.is_absolute()),
+                    WindowEvent::FileHoverCancelled => hovered_in.borrow_mut().clear(),
+                    _ => {},
+                }
+            }
+        });
+        assert!(hovered.borrow().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_mouse_events() -> io::Result<()> {
+        // Can't move the mouse from a test, make sure the events can be handled
+        let mut event_loop = EventLoop::new();
+        let mut wnd = WindowBuilder::new().build(&event_loop)?;
+        wnd.set_raw_mouse(true);
+        let motion = Rc::new(RefCell::new((0.0, 0.0)));
+        let motion_in = motion.clone();
+        event_loop.run(move |control_flow, event| {
+            *control_flow = ControlFlow::Exit;
+
+            if let Event::WindowEvent{ event: WindowEvent::RawMouseMotion{ dx, dy }, .. } = event {
+                let mut motion = motion_in.borrow_mut();
+                motion.0 += dx;
+                motion.1 += dy;
+            }
+        });
+        assert!(motion.borrow().0.is_finite());
+        wnd.set_raw_mouse(false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_transparency() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        assert!(wnd.set_transparency(0.5));
+        // Out of range values are clamped
+        assert!(wnd.set_transparency(-1.0));
+        assert!(wnd.set_transparency(2.0));
+    }
+
+    #[test]
+    fn test_clipboard_round_trip() {
+        let wnd = Window::new();
+        let mut event_loop = EventLoop::new();
+        event_loop.add_window(&wnd);
+        let text = "Hello, clipboard! \u{e1}rv\u{ed}zt\u{fb}r\u{0151}";
+        assert!(event_loop.set_clipboard_text(text));
+        assert_eq!(event_loop.clipboard_text().as_deref(), Some(text));
+        assert!(event_loop.set_clipboard_text(""));
+        assert_eq!(event_loop.clipboard_text().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_set_content_protected() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        let protected = wnd.set_content_protected(true);
+        if cfg!(unix) {
+            assert!(!protected);
+        }
+        wnd.set_content_protected(false);
+    }
+
+    #[test]
+    fn test_present_rgba() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        // A red-green gradient
+        let (width, height) = (64, 32);
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend_from_slice(&[(x * 4) as u8, (y * 8) as u8, 0, 255]);
+            }
+        }
+        assert!(wnd.present_rgba(&pixels, width, height));
+        // Wrong sizes
+        assert!(!wnd.present_rgba(&pixels[4..], width, height));
+        assert!(!wnd.present_rgba(&pixels, width, height + 1));
+        assert!(!wnd.present_rgba(&[], 0, 0));
+    }
+
+    #[test]
+    fn test_video_modes() {
+        let monitors = Monitor::all_monitors();
+        let primary = monitors.iter().find(|m| m.is_primary()).expect("No primary monitor!");
+        let modes = primary.video_modes();
+        assert!(!modes.is_empty());
+        assert!(modes.iter().any(|m| m.size == primary.size()));
+        for (i, m) in modes.iter().enumerate() {
+            assert!(!modes[(i + 1)..].contains(m));
+        }
+    }
+
+    #[test]
+    fn test_borderless_fullscreen() {
+        let mut wnd = Window::new();
+        wnd.set_visible(true);
+        let primary = Monitor::all_monitors().into_iter()
+            .find(|m| m.is_primary())
+            .expect("No primary monitor!");
+        let msiz = primary.size();
+        assert!(wnd.set_fullscreen(Fullscreen::Borderless(Some(primary))));
+        // Window managers apply it asynchronously on X11
+        if cfg!(target_os = "windows") {
+            assert_eq!(wnd.outer_size(), msiz);
+        }
+        assert!(wnd.set_fullscreen(Fullscreen::None));
+        assert!(wnd.set_fullscreen(Fullscreen::None));
+    }
+
+    #[test]
+    fn test_debug_output() {
+        for m in Monitor::all_monitors() {
+            let dbg = format!("{:?}", m);
+            assert!(dbg.starts_with("Monitor"));
+            assert!(dbg.contains("position"));
+            if cfg!(target_os = "windows") {
+                assert!(dbg.contains(&m.name().unwrap()));
+            }
+            else {
+                // X11 screens have no names
+                assert!(m.name().is_none());
+            }
+        }
+        let mut wnd = Window::new();
+        wnd.set_title("Debug test window");
+        let dbg = format!("{:?}", wnd);
+        assert!(dbg.contains("inner_size"));
+        assert!(dbg.contains("Debug test window"));
+    }
+
+    #[test]
+    fn test_clamp_to_bounds() {
+        let mpos = PhysicalPosition::new(-1920, 100);
+        let msiz = PhysicalSize::new(1920, 1080);
+        // Inside stays
+        let p = PhysicalPosition::new(-1000, 500);
+        assert_eq!(p.clamp_to_bounds(mpos, msiz), p);
+        // Left and above
+        let p = PhysicalPosition::new(-5000, 0);
+        assert_eq!(p.clamp_to_bounds(mpos, msiz), PhysicalPosition::new(-1920, 100));
+        // Right and below
+        let p = PhysicalPosition::new(300, 4000);
+        assert_eq!(p.clamp_to_bounds(mpos, msiz), PhysicalPosition::new(-1, 1179));
+        // Degenerate size
+        let p = PhysicalPosition::new(300, 4000);
+        assert_eq!(p.clamp_to_bounds(mpos, PhysicalSize::new(0, 0)), mpos);
+    }
+
+    #[test]
+    fn test_size_round_trip() {
+        for scale in &[1.0, 1.25, 1.5, 1.75, 2.0] {
+            for width in 0..2000 {
+                let siz = PhysicalSize::new(width, 2000 - width);
+                assert_eq!(siz.to_logical(*scale).to_physical(*scale), siz);
+                let pos = PhysicalPosition::new(width as i32 - 1000, width as i32);
+                assert_eq!(pos.to_logical(*scale).to_physical(*scale), pos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_physical_rounded() {
+        let siz = LogicalSize::new(10.5, 3.2);
+        assert_eq!(siz.to_physical_rounded(1.0, Rounding::Nearest), PhysicalSize::new(11, 3));
+        assert_eq!(siz.to_physical_rounded(1.0, Rounding::Floor), PhysicalSize::new(10, 3));
+        assert_eq!(siz.to_physical_rounded(1.0, Rounding::Ceil), PhysicalSize::new(11, 4));
+        let pos = LogicalPosition::new(-2.5, 7.4);
+        assert_eq!(pos.to_physical_rounded(2.0, Rounding::Nearest), PhysicalPosition::new(-5, 15));
+        assert_eq!(pos.to_physical_rounded(1.0, Rounding::Floor), PhysicalPosition::new(-3, 7));
+        assert_eq!(pos.to_physical_rounded(1.0, Rounding::Ceil), PhysicalPosition::new(-2, 8));
+    }
+
+    #[test]
+    fn test_centered_position() {
+        // A 800x600 window on a 1920x1080 monitor, left of the primary one
+        let mpos = PhysicalPosition::new(-1920, 0);
+        let msiz = PhysicalSize::new(1920, 1080);
+        let siz = PhysicalSize::new(800, 600);
+        assert_eq!(centered_position(mpos, msiz, siz), Some(PhysicalPosition::new(-1360, 240)));
+        // Same size fills the area
+        assert_eq!(centered_position(mpos, msiz, msiz), Some(mpos));
+        // Too wide or too tall
+        assert_eq!(centered_position(mpos, msiz, PhysicalSize::new(1921, 600)), None);
+        assert_eq!(centered_position(mpos, msiz, PhysicalSize::new(800, 1081)), None);
+        assert_eq!(centered_position(mpos, msiz, PhysicalSize::new(2560, 1440)), None);
+        // Odd leftover space rounds towards the top-left
+        let siz = PhysicalSize::new(801, 599);
+        assert_eq!(centered_position(mpos, msiz, siz), Some(PhysicalPosition::new(-1361, 240)));
+        let apos = PhysicalPosition::new(0, 30);
+        let asiz = PhysicalSize::new(1365, 737);
+        assert_eq!(centered_position(apos, asiz, PhysicalSize::new(800, 600)), Some(PhysicalPosition::new(282, 98)));
+        // Empty window in an empty area
+        let empty = PhysicalSize::new(0, 0);
+        assert_eq!(centered_position(apos, empty, empty), Some(apos));
+    }
+
+    #[test]
+    fn test_monitor_local() {
+        // Left of the primary monitor
+        let origin = PhysicalPosition::new(-1920, -200);
+        let p = PhysicalPosition::new(-1000, 300);
+        assert_eq!(p.relative_to(origin), PhysicalPosition::new(920, 500));
+        assert_eq!(p.relative_to(origin).offset_by(origin), p);
+        // The origin is the top-left corner
+        assert_eq!(origin.relative_to(origin), PhysicalPosition::new(0, 0));
+        // Points on the primary monitor are outside
+        let p = PhysicalPosition::new(100, 0);
+        assert_eq!(p.relative_to(origin), PhysicalPosition::new(2020, 200));
+        assert_eq!(PhysicalPosition::new(-10, 0).offset_by(origin), PhysicalPosition::new(-1930, -200));
+
+        // A point on the right edge of a left monitor, converted to the local
+        // space of a monitor above the primary one and back
+        let left = PhysicalPosition::new(-1920, 0);
+        let above = PhysicalPosition::new(0, -1080);
+        let p = PhysicalPosition::new(-1, 540);
+        let local = p.relative_to(left);
+        assert_eq!(local, PhysicalPosition::new(1919, 540));
+        let global = local.offset_by(left);
+        assert_eq!(global.relative_to(above), PhysicalPosition::new(-1, 1620));
+        assert_eq!(global.relative_to(above).offset_by(above), p);
+        // Far away points saturate instead of overflowing
+        let far = PhysicalPosition::new(2_147_483_000, -2_147_483_000);
+        assert_eq!(far.relative_to(left), PhysicalPosition::new(2_147_483_647, -2_147_483_000));
+        assert_eq!(far.offset_by(above), PhysicalPosition::new(2_147_483_000, -2_147_483_648));
+    }
+
+    #[test]
+    fn test_rects_intersect() {
+        let mpos = PhysicalPosition::new(0, 0);
+        let msiz = PhysicalSize::new(1920, 1080);
+        let wsiz = PhysicalSize::new(800, 600);
+        assert!(rects_intersect(PhysicalPosition::new(100, 100), wsiz, mpos, msiz));
+        // Partially off-screen
+        assert!(rects_intersect(PhysicalPosition::new(-700, -500), wsiz, mpos, msiz));
+        assert!(rects_intersect(PhysicalPosition::new(1919, 1079), wsiz, mpos, msiz));
+        // Fully off-screen
+        assert!(!rects_intersect(PhysicalPosition::new(-800, 0), wsiz, mpos, msiz));
+        assert!(!rects_intersect(PhysicalPosition::new(1920, 0), wsiz, mpos, msiz));
+        assert!(!rects_intersect(PhysicalPosition::new(3000, 2000), wsiz, mpos, msiz));
+    }
 }
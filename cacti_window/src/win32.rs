@@ -2,10 +2,15 @@
 #![cfg(target_os = "windows")]
 #![allow(non_snake_case)]
 
-use std::ffi::{OsStr, c_void};
-use std::os::windows::ffi::OsStrExt;
+use std::ffi::{OsStr, OsString, c_void};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::ptr;
 use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
 use super::*;
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -15,6 +20,16 @@ use super::*;
 #[link(name = "kernel32")]
 extern "system" {
     fn GetModuleHandleW(name: *const u16) -> *mut c_void;
+    fn GetProcAddress(module: *mut c_void, name: *const u8) -> *mut c_void;
+    fn GetLastError() -> u32;
+    fn GetTickCount() -> u32;
+    fn GetCurrentThreadId() -> u32;
+    // Global memory for the clipboard
+    fn GlobalAlloc(flags: u32, bytes: usize) -> *mut c_void;
+    fn GlobalFree(mem: *mut c_void) -> *mut c_void;
+    fn GlobalLock(mem: *mut c_void) -> *mut c_void;
+    fn GlobalUnlock(mem: *mut c_void) -> i32;
+    fn GlobalSize(mem: *mut c_void) -> usize;
 }
 
 #[link(name = "user32")]
@@ -28,6 +43,14 @@ extern "system" {
     ) -> i32;
     fn GetMonitorInfoW(hmonitor: *mut c_void, info: *mut MONITORINFOEXW) -> i32;
     fn MonitorFromWindow(hwnd: *mut c_void, flags: u32) -> *mut c_void;
+    fn EnumDisplaySettingsW(device: *const u16, mode: u32, devmode: *mut DEVMODEW) -> i32;
+    fn ChangeDisplaySettingsExW(
+        device : *const u16   ,
+        devmode: *mut DEVMODEW,
+        hwnd   : *mut c_void  ,
+        flags  : u32          ,
+        param  : *mut c_void  ,
+    ) -> i32;
     // Window class
     fn RegisterClassW(class: *const WNDCLASSW) -> u16;
     // Window creation
@@ -49,6 +72,9 @@ extern "system" {
     // Window attributes
     fn ShowWindow(hwnd: *mut c_void, cmd: i32) -> i32;
     fn SetWindowTextW(hwnd: *mut c_void, title: *const u16) -> i32;
+    fn GetWindowTextLengthW(hwnd: *mut c_void) -> i32;
+    fn GetWindowTextW(hwnd: *mut c_void, buffer: *mut u16, max_count: i32) -> i32;
+    fn IsWindow(hwnd: *mut c_void) -> i32;
     fn SetWindowPos(
         hwnd      : *mut c_void,
         hwnd_after: *mut c_void,
@@ -64,6 +90,7 @@ extern "system" {
         alpha: u8         ,
         flags: u32        ,
     ) -> i32;
+    fn SetWindowDisplayAffinity(hwnd: *mut c_void, affinity: u32) -> i32;
     fn AdjustWindowRectEx(
         rect    : *mut RECT,
         style   : u32      ,
@@ -72,7 +99,18 @@ extern "system" {
     ) -> i32;
     fn GetWindowRect(hwnd: *mut c_void, rect: *mut RECT) -> i32;
     fn GetClientRect(hwnd: *mut c_void, rect: *mut RECT) -> i32;
+    fn GetDC(hwnd: *mut c_void) -> *mut c_void;
+    fn ReleaseDC(hwnd: *mut c_void, hdc: *mut c_void) -> i32;
     fn GetWindowPlacement(hwnd: *mut c_void, placement: *mut WINDOWPLACEMENT) -> i32;
+    fn SetWindowPlacement(hwnd: *mut c_void, placement: *const WINDOWPLACEMENT) -> i32;
+    // Mouse
+    fn GetCursorPos(point: *mut POINT) -> i32;
+    fn ReleaseCapture() -> i32;
+    fn LoadCursorW(hinstance: *mut c_void, name: *const u16) -> *mut c_void;
+    fn SetCursor(hcursor: *mut c_void) -> *mut c_void;
+    fn WindowFromPoint(point: POINT) -> *mut c_void;
+    // Attention
+    fn FlashWindowEx(info: *const FLASHWINFO) -> i32;
     // Custom window properties
     fn GetWindowLongW(hwnd: *mut c_void, index: i32) -> i32;
     fn SetWindowLongW(hwnd: *mut c_void, index: i32, new: i32) -> i32;
@@ -91,6 +129,7 @@ extern "system" {
         min : u32        ,
         max : u32        ,
     ) -> i32;
+    fn GetMessageTime() -> i32;
     fn PeekMessageW(
         msg   : *mut MSG   ,
         hwnd  : *mut c_void,
@@ -104,9 +143,71 @@ extern "system" {
         wparam: usize      ,
         lparam: isize      ,
     ) -> isize;
+    fn PostMessageW(
+        hwnd  : *mut c_void,
+        msg   : u32        ,
+        wparam: usize      ,
+        lparam: isize      ,
+    ) -> i32;
+    fn PostThreadMessageW(
+        thread: u32  ,
+        msg   : u32  ,
+        wparam: usize,
+        lparam: isize,
+    ) -> i32;
+    fn RegisterWindowMessageW(name: *const u16) -> u32;
     fn PostQuitMessage(code: i32);
     fn TranslateMessage(msg: *const MSG) -> i32;
     fn DispatchMessageW(msg: *const MSG) -> i32;
+    // Clipboard
+    fn OpenClipboard(hwnd: *mut c_void) -> i32;
+    fn CloseClipboard() -> i32;
+    fn EmptyClipboard() -> i32;
+    fn GetClipboardData(format: u32) -> *mut c_void;
+    fn SetClipboardData(format: u32, mem: *mut c_void) -> *mut c_void;
+    // Raw input
+    fn RegisterRawInputDevices(
+        devices: *const RAWINPUTDEVICE,
+        count  : u32                  ,
+        size   : u32                  ,
+    ) -> i32;
+    fn GetRawInputData(
+        raw        : *mut c_void,
+        command    : u32        ,
+        data       : *mut c_void,
+        size       : *mut u32   ,
+        header_size: u32        ,
+    ) -> u32;
+}
+
+#[link(name = "gdi32")]
+extern "system" {
+    fn SetDIBitsToDevice(
+        hdc       : *mut c_void      ,
+        x_dest    : i32              ,
+        y_dest    : i32              ,
+        width     : u32              ,
+        height    : u32              ,
+        x_src     : i32              ,
+        y_src     : i32              ,
+        start_scan: u32              ,
+        lines     : u32              ,
+        bits      : *const c_void    ,
+        info      : *const BITMAPINFO,
+        color_use : u32              ,
+    ) -> i32;
+}
+
+#[link(name = "shell32")]
+extern "system" {
+    fn DragAcceptFiles(hwnd: *mut c_void, accept: i32);
+    fn DragQueryFileW(
+        hdrop: *mut c_void,
+        index: u32        ,
+        file : *mut u16   ,
+        len  : u32        ,
+    ) -> u32;
+    fn DragFinish(hdrop: *mut c_void);
 }
 
 #[link(name = "shcore")]
@@ -120,6 +221,8 @@ extern "system" {
     fn GetScaleFactorForMonitor(hmonitor: *mut c_void, factor: *mut u32) -> i32;
 }
 
+const ERROR_CLASS_ALREADY_EXISTS: u32 = 1410;
+
 const MONITORINFOF_PRIMARY: u32 = 1;
 
 const MONITOR_DEFAULTTONEAREST: u32 = 2;
@@ -130,6 +233,8 @@ const MDT_RAW_DPI: u32 = 2;
 
 const DEVICE_SCALE_FACTOR_INVALID: u32 = 0;
 
+const USER_DEFAULT_SCREEN_DPI: u32 = 96;
+
 const WS_OVERLAPPED: u32 = 0x00000000;
 const WS_THICKFRAME: u32 = 0x00040000;
 const WS_CAPTION: u32 = 0x00C00000;
@@ -140,7 +245,12 @@ const WS_OVERLAPPEDWINDOW: u32 =
       WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU
     | WS_THICKFRAME | WS_MINIMIZEBOX | WS_MAXIMIZEBOX;
 
+const WS_VISIBLE: u32 = 0x10000000;
+
 const WS_EX_LAYERED: u32 = 0x00080000;
+
+const CF_UNICODETEXT: u32 = 13;
+const GMEM_MOVEABLE: u32 = 0x0002;
 const WS_EX_DLGMODALFRAME: u32 = 0x00000001;
 const WS_EX_WINDOWEDGE: u32 = 0x00000100;
 const WS_EX_CLIENTEDGE: u32 = 0x00000200;
@@ -149,7 +259,6 @@ const WS_EX_STATICEDGE: u32 = 0x00020000;
 const CW_USEDEFAULT: i32 = 0x80000000u32 as i32;
 
 const SW_HIDE: i32 = 0;
-const SW_MAXIMIZE: i32 = 3;
 const SW_SHOW: i32 = 5;
 
 const HWND_TOP: *mut c_void = 0 as *mut c_void;
@@ -162,6 +271,15 @@ const SWP_NOZORDER: u32 = 0x0004;
 const SWP_NOACTIVATE: u32 = 0x0010;
 const SWP_FRAMECHANGED: u32 = 0x0020;
 
+const DM_BITSPERPEL: u32 = 0x00040000;
+const DM_PELSWIDTH: u32 = 0x00080000;
+const DM_PELSHEIGHT: u32 = 0x00100000;
+const DM_DISPLAYFREQUENCY: u32 = 0x00400000;
+
+const CDS_FULLSCREEN: u32 = 0x00000004;
+
+const DISP_CHANGE_SUCCESSFUL: i32 = 0;
+
 const CS_OWNDC: u32 = 0x0020;
 
 const GWL_STYLE: i32 = -16;
@@ -176,6 +294,10 @@ const PM_QS_PAINT: u32 = 0x200000;
 
 const LWA_ALPHA: u32 = 0x00000002;
 
+const WDA_NONE: u32 = 0x00000000;
+const WDA_MONITOR: u32 = 0x00000001;
+const WDA_EXCLUDEFROMCAPTURE: u32 = 0x00000011;
+
 const WM_CREATE: u32 = 0x0001;
 const WM_CLOSE: u32 = 0x0010;
 const WM_QUIT: u32 = 0x0012;
@@ -185,6 +307,46 @@ const WM_SETFOCUS: u32 = 0x0007;
 const WM_SIZING: u32 = 0x0214;
 const WM_SIZE: u32 = 0x0005;
 const WM_PAINT: u32 = 0x000f;
+const WM_NCLBUTTONDOWN: u32 = 0x00a1;
+const WM_DPICHANGED: u32 = 0x02e0;
+const WM_SETCURSOR: u32 = 0x0020;
+const WM_DROPFILES: u32 = 0x0233;
+const WM_INPUT: u32 = 0x00ff;
+
+const WMSZ_LEFT: usize = 1;
+const WMSZ_TOP: usize = 3;
+const WMSZ_TOPLEFT: usize = 4;
+const WMSZ_TOPRIGHT: usize = 5;
+const WMSZ_BOTTOMLEFT: usize = 7;
+
+const HTCLIENT: isize = 1;
+const HTCAPTION: usize = 2;
+
+const FLASHW_STOP: u32 = 0x00000000;
+const FLASHW_ALL: u32 = 0x00000003;
+const FLASHW_TIMERNOFG: u32 = 0x0000000c;
+
+const HID_USAGE_PAGE_GENERIC: u16 = 0x01;
+const HID_USAGE_GENERIC_MOUSE: u16 = 0x02;
+
+const RIDEV_REMOVE: u32 = 0x00000001;
+const RID_INPUT: u32 = 0x10000003;
+const RIM_TYPEMOUSE: u32 = 0;
+const MOUSE_MOVE_ABSOLUTE: u16 = 0x01;
+
+const BI_RGB: u32 = 0;
+const DIB_RGB_COLORS: u32 = 0;
+
+const IDC_ARROW: u16 = 32512;
+const IDC_IBEAM: u16 = 32513;
+const IDC_WAIT: u16 = 32514;
+const IDC_CROSS: u16 = 32515;
+const IDC_SIZENWSE: u16 = 32642;
+const IDC_SIZENESW: u16 = 32643;
+const IDC_SIZEWE: u16 = 32644;
+const IDC_SIZENS: u16 = 32645;
+const IDC_NO: u16 = 32648;
+const IDC_HAND: u16 = 32649;
 
 type MONITORENUMPROC =
     Option<extern "system" fn(*mut c_void, *mut c_void, *mut RECT, isize) -> i32>;
@@ -243,6 +405,50 @@ impl MONITORINFOEXW {
     }
 }
 
+// NOTE: The printer-specific union members are left out, the display ones are
+// the same size
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DEVMODEW {
+    device_name         : [u16; 32],
+    spec_version        : u16      ,
+    driver_version      : u16      ,
+    size                : u16      ,
+    driver_extra        : u16      ,
+    fields              : u32      ,
+    position            : POINT    ,
+    display_orientation : u32      ,
+    display_fixed_output: u32      ,
+    color               : i16      ,
+    duplex              : i16      ,
+    y_resolution        : i16      ,
+    tt_option           : i16      ,
+    collate             : i16      ,
+    form_name           : [u16; 32],
+    log_pixels          : u16      ,
+    bits_per_pel        : u32      ,
+    pels_width          : u32      ,
+    pels_height         : u32      ,
+    display_flags       : u32      ,
+    display_frequency   : u32      ,
+    icm_method          : u32      ,
+    icm_intent          : u32      ,
+    media_type          : u32      ,
+    dither_type         : u32      ,
+    reserved1           : u32      ,
+    reserved2           : u32      ,
+    panning_width       : u32      ,
+    panning_height      : u32      ,
+}
+
+impl DEVMODEW {
+    fn new() -> Self {
+        let mut res: Self = unsafe{ mem::zeroed() };
+        res.size = mem::size_of::<Self>() as u16;
+        res
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct WNDCLASSW {
@@ -285,6 +491,39 @@ impl MSG {
     fn new() -> Self { unsafe{ mem::zeroed() } }
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct FLASHWINFO {
+    size   : u32        ,
+    hwnd   : *mut c_void,
+    flags  : u32        ,
+    count  : u32        ,
+    timeout: u32        ,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct BITMAPINFOHEADER {
+    size            : u32,
+    width           : i32,
+    height          : i32,
+    planes          : u16,
+    bit_count       : u16,
+    compression     : u32,
+    size_image      : u32,
+    x_pels_per_meter: i32,
+    y_pels_per_meter: i32,
+    clr_used        : u32,
+    clr_important   : u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct BITMAPINFO {
+    header: BITMAPINFOHEADER,
+    colors: [u32; 1]        ,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 struct WINDOWPLACEMENT {
@@ -305,6 +544,43 @@ impl WINDOWPLACEMENT {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RAWINPUTDEVICE {
+    usage_page: u16        ,
+    usage     : u16        ,
+    flags     : u32        ,
+    target    : *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RAWINPUTHEADER {
+    ty    : u32        ,
+    size  : u32        ,
+    device: *mut c_void,
+    wparam: usize      ,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RAWMOUSE {
+    flags      : u16,
+    buttons    : u32,
+    raw_buttons: u32,
+    last_x     : i32,
+    last_y     : i32,
+    extra_info : u32,
+}
+
+// NOTE: The data is a union in the system headers, we only ever ask for mice
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RAWINPUT {
+    header: RAWINPUTHEADER,
+    mouse : RAWMOUSE      ,
+}
+
 #[repr(C)]
 struct CREATESTRUCTW {
     param    : *mut c_void,
@@ -326,6 +602,114 @@ fn to_wstring(s: &OsStr) -> Vec<u16> {
     s.encode_wide().chain(Some(0).into_iter()).collect()
 }
 
+/// Opens the clipboard for the given owner window. Other processes can hold
+/// the clipboard for short periods, so opening is retried a few times.
+fn open_clipboard(owner: *mut c_void) -> bool {
+    const TRY_COUNT: usize = 10;
+
+    for _ in 0..TRY_COUNT {
+        if unsafe{ OpenClipboard(owner) } != 0 {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    false
+}
+
+/// Returns the handles of the windows, that asked for raw mouse motion. The
+/// registration is for the whole process, so the windows of every thread are
+/// counted together.
+fn raw_mouse_windows() -> &'static Mutex<HashSet<usize>> {
+    static WINDOWS: AtomicPtr<Mutex<HashSet<usize>>> = AtomicPtr::new(ptr::null_mut());
+
+    let windows = WINDOWS.load(Ordering::Acquire);
+    if !windows.is_null() {
+        return unsafe{ &*windows };
+    }
+    let new = Box::into_raw(Box::new(Mutex::new(HashSet::new())));
+    match WINDOWS.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => unsafe{ &*new },
+        Err(existing) => {
+            // Another thread was faster
+            drop(unsafe{ Box::from_raw(new) });
+            unsafe{ &*existing }
+        },
+    }
+}
+
+/// Registers or unregisters the process for raw mouse input. The input follows
+/// the keyboard focus, so every window of the process can receive it.
+fn register_raw_mouse(raw: bool) -> bool {
+    let device = RAWINPUTDEVICE{
+        usage_page: HID_USAGE_PAGE_GENERIC,
+        usage: HID_USAGE_GENERIC_MOUSE,
+        flags: if raw { 0 } else { RIDEV_REMOVE },
+        target: ptr::null_mut(),
+    };
+    unsafe{ RegisterRawInputDevices(
+        &device, 1, mem::size_of::<RAWINPUTDEVICE>() as u32) != 0 }
+}
+
+/// Enables or disables raw mouse motion for the given window. The
+/// registration is for the whole process, so it's only changed, when the
+/// first window asks for raw motion, or the last one gives it up.
+fn set_raw_mouse_window(hwnd: *mut c_void, raw: bool) -> bool {
+    let hwnd = hwnd as usize;
+    let mut windows = raw_mouse_windows().lock().unwrap();
+    let was_empty = windows.is_empty();
+    let changed = if raw { windows.insert(hwnd) } else { windows.remove(&hwnd) };
+    if !changed || was_empty == windows.is_empty() {
+        return true;
+    }
+    if register_raw_mouse(raw) {
+        return true;
+    }
+    // Roll back, nothing changed
+    if raw {
+        windows.remove(&hwnd);
+    }
+    else {
+        windows.insert(hwnd);
+    }
+    false
+}
+
+/// Returns the relative mouse motion in a `WM_INPUT` message, if it's a
+/// relative motion of a mouse.
+fn raw_mouse_motion(hraw: *mut c_void) -> Option<(i32, i32)> {
+    let mut raw: RAWINPUT = unsafe{ mem::zeroed() };
+    let mut size = mem::size_of::<RAWINPUT>() as u32;
+    let ret = unsafe{ GetRawInputData(
+        hraw, RID_INPUT, (&mut raw as *mut RAWINPUT).cast(),
+        &mut size, mem::size_of::<RAWINPUTHEADER>() as u32) };
+    // The error is (UINT)-1
+    if ret == !0 || raw.header.ty != RIM_TYPEMOUSE {
+        return None;
+    }
+    // Tablets and remote desktops report absolute positions, those are not
+    // raw motion
+    if raw.mouse.flags & MOUSE_MOVE_ABSOLUTE != 0 {
+        return None;
+    }
+    Some((raw.mouse.last_x, raw.mouse.last_y))
+}
+
+/// Returns the paths of the files in a `WM_DROPFILES` drop.
+fn dropped_files(hdrop: *mut c_void) -> Vec<PathBuf> {
+    // Index 0xffffffff queries the number of files
+    let count = unsafe{ DragQueryFileW(hdrop, 0xffffffff, ptr::null_mut(), 0) };
+    let mut result = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        // The length doesn't include the terminator
+        let len = unsafe{ DragQueryFileW(hdrop, i, ptr::null_mut(), 0) };
+        let mut buffer = vec![0u16; len as usize + 1];
+        let len = unsafe{ DragQueryFileW(hdrop, i, buffer.as_mut_ptr(), buffer.len() as u32) };
+        buffer.truncate(len as usize);
+        result.push(PathBuf::from(OsString::from_wide(&buffer)));
+    }
+    result
+}
+
 // ////////////////////////////////////////////////////////////////////////// //
 //                               Implementation                               //
 // ////////////////////////////////////////////////////////////////////////// //
@@ -363,6 +747,11 @@ impl MonitorTrait for Win32Monitor {
 
     fn handle_ptr(&self) -> *mut c_void { self.hmonitor }
 
+    fn is_valid(&self) -> bool {
+        let mut info = MONITORINFO::new();
+        unsafe{ GetMonitorInfoW(self.hmonitor, (&mut info as *mut MONITORINFO).cast()) != 0 }
+    }
+
     fn name(&self) -> Option<String> {
         let mut info = MONITORINFOEXW::new();
         let ret = unsafe{ GetMonitorInfoW(self.hmonitor, &mut info) };
@@ -408,6 +797,17 @@ impl MonitorTrait for Win32Monitor {
         PhysicalSize::new(rect.width() as u32, rect.height() as u32)
     }
 
+    fn work_area(&self) -> (PhysicalPosition, PhysicalSize) {
+        let mut info = MONITORINFO::new();
+        let ret = unsafe{ GetMonitorInfoW(self.hmonitor, (&mut info as *mut MONITORINFO).cast()) };
+        if ret == 0 {
+            return (self.position(), self.size());
+        }
+        let rect = info.work_rect;
+        (PhysicalPosition::new(rect.left, rect.top),
+            PhysicalSize::new(rect.width() as u32, rect.height() as u32))
+    }
+
     fn dpi(&self) -> Dpi {
         let mut dpix = 0u32;
         let mut dpiy = 0u32;
@@ -432,29 +832,187 @@ impl MonitorTrait for Win32Monitor {
         }
         (sfactor as f64) / 100.0
     }
+
+    fn video_modes(&self) -> Vec<VideoMode> {
+        let mut result = Vec::new();
+        let mut info = MONITORINFOEXW::new();
+        let ret = unsafe{ GetMonitorInfoW(self.hmonitor, &mut info) };
+        if ret == 0 {
+            return result;
+        }
+        // The modes are enumerated by index, until there are no more
+        for i in 0.. {
+            let mut mode = DEVMODEW::new();
+            let ret = unsafe{ EnumDisplaySettingsW(info.dev_name.as_ptr(), i, &mut mode) };
+            if ret == 0 {
+                break;
+            }
+            // NOTE: 0 and 1 both mean the hardware default
+            let refresh_rate = if mode.display_frequency > 1 { mode.display_frequency } else { 0 };
+            result.push(VideoMode{
+                size: PhysicalSize::new(mode.pels_width, mode.pels_height),
+                bit_depth: mode.bits_per_pel,
+                refresh_rate,
+            });
+        }
+        result
+    }
+}
+
+/// Returns the message the `Win32Waker`s post to wake up the event loop.
+fn wakeup_message() -> u32 {
+    static MESSAGE: AtomicU32 = AtomicU32::new(0);
+    let mut msg = MESSAGE.load(Ordering::Relaxed);
+    if msg == 0 {
+        // Registering the same name always gives the same message
+        let name = to_wstring(OsStr::new("Cacti Wakeup"));
+        msg = unsafe{ RegisterWindowMessageW(name.as_ptr()) };
+        MESSAGE.store(msg, Ordering::Relaxed);
+    }
+    msg
+}
+
+/// Returns the DPI of the given window, or `None`, if the system can't tell
+/// it. `GetDpiForWindow` is only available since Windows 10 version 1607, so
+/// it's looked up at runtime.
+fn dpi_for_window(hwnd: *mut c_void) -> Option<u32> {
+    type GetDpiForWindowFn = unsafe extern "system" fn(*mut c_void) -> u32;
+    // 0 means not looked up yet, 1 that it's missing
+    static FUNC: AtomicUsize = AtomicUsize::new(0);
+    let mut func = FUNC.load(Ordering::Relaxed);
+    if func == 0 {
+        let name = to_wstring(OsStr::new("user32.dll"));
+        let module = unsafe{ GetModuleHandleW(name.as_ptr()) };
+        let addr = if module.is_null() {
+            ptr::null_mut()
+        }
+        else {
+            unsafe{ GetProcAddress(module, b"GetDpiForWindow\0".as_ptr()) }
+        };
+        func = if addr.is_null() { 1 } else { addr as usize };
+        FUNC.store(func, Ordering::Relaxed);
+    }
+    if func == 1 {
+        return None;
+    }
+    let get_dpi_for_window: GetDpiForWindowFn = unsafe{ mem::transmute(func) };
+    Some(unsafe{ get_dpi_for_window(hwnd) })
+}
+
+/// Posts the wakeup message to the first window of the event loop, or to its
+/// thread, if there's no window. Messages posted to a window still arrive
+/// while a modal loop - like the one resizing the window - runs, thread
+/// messages would be lost there.
+#[derive(Debug)]
+pub struct Win32Waker {
+    thread_id: u32,
+    target: Arc<AtomicPtr<c_void>>,
+}
+
+impl WakerTrait for Win32Waker {
+    fn wake(&self) -> bool {
+        let msg = wakeup_message();
+        let hwnd = self.target.load(Ordering::Acquire);
+        if !hwnd.is_null() && unsafe{ PostMessageW(hwnd, msg, 0, 0) } != 0 {
+            return true;
+        }
+        unsafe{ PostThreadMessageW(self.thread_id, msg, 0, 0) != 0 }
+    }
 }
 
 #[derive(Debug)]
 pub struct Win32EventLoop {
-    window_handles: Vec<*mut c_void>,
+    // Windows are added through a shared reference by the `WindowBuilder`
+    window_handles: RefCell<Vec<*mut c_void>>,
+    thread_id: u32,
+    // The window the wakeups are posted to
+    wakeup_target: Arc<AtomicPtr<c_void>>,
 }
 
 impl EventLoopTrait for Win32EventLoop {
     fn new() -> Self {
         Self{
-            window_handles: Vec::new(),
+            window_handles: RefCell::new(Vec::new()),
+            thread_id: unsafe{ GetCurrentThreadId() },
+            wakeup_target: Arc::new(AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    fn add_window(&self, wnd: &Win32Window) {
+        let mut window_handles = self.window_handles.borrow_mut();
+        if window_handles.is_empty() {
+            self.wakeup_target.store(wnd.handle_ptr(), Ordering::Release);
+        }
+        window_handles.push(wnd.handle_ptr());
+    }
+
+    fn create_waker(&self) -> Win32Waker {
+        Win32Waker{
+            thread_id: self.thread_id,
+            target: self.wakeup_target.clone(),
+        }
+    }
+
+    fn clipboard_text(&self) -> Option<String> {
+        let owner = self.window_handles.borrow().first().cloned().unwrap_or(ptr::null_mut());
+        if !open_clipboard(owner) {
+            return None;
         }
+        let mut result = None;
+        let mem = unsafe{ GetClipboardData(CF_UNICODETEXT) };
+        if !mem.is_null() {
+            let data = unsafe{ GlobalLock(mem) } as *const u16;
+            if !data.is_null() {
+                // The text is 0-terminated, but never trust it to be
+                let len = unsafe{ GlobalSize(mem) } / 2;
+                let text = unsafe{ std::slice::from_raw_parts(data, len) };
+                let len = text.iter().position(|c| *c == 0).unwrap_or(len);
+                result = Some(String::from_utf16_lossy(&text[..len]));
+                unsafe{ GlobalUnlock(mem) };
+            }
+        }
+        unsafe{ CloseClipboard() };
+        result
     }
 
-    fn add_window(&mut self, wnd: &Win32Window) {
-        self.window_handles.push(wnd.handle_ptr());
+    fn set_clipboard_text(&mut self, text: &str) -> bool {
+        let text: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+        let mem = unsafe{ GlobalAlloc(GMEM_MOVEABLE, text.len() * 2) };
+        if mem.is_null() {
+            return false;
+        }
+        let data = unsafe{ GlobalLock(mem) } as *mut u16;
+        if data.is_null() {
+            unsafe{ GlobalFree(mem) };
+            return false;
+        }
+        unsafe{
+            ptr::copy_nonoverlapping(text.as_ptr(), data, text.len());
+            GlobalUnlock(mem);
+        }
+        // Without an owner window, emptying the clipboard takes the ownership
+        // from everyone, which makes SetClipboardData fail on some systems
+        let owner = self.window_handles.borrow().first().cloned().unwrap_or(ptr::null_mut());
+        if !open_clipboard(owner) {
+            unsafe{ GlobalFree(mem) };
+            return false;
+        }
+        let success = unsafe{
+            EmptyClipboard() != 0 && !SetClipboardData(CF_UNICODETEXT, mem).is_null()
+        };
+        if !success {
+            // On success the memory belongs to the system
+            unsafe{ GlobalFree(mem) };
+        }
+        unsafe{ CloseClipboard() };
+        success
     }
 
     fn run<F>(&mut self, mut f: F)
-        where F: FnMut(&mut ControlFlow, Event) + 'static {
+        where F: FnMut(&mut ControlFlow, TimedEvent) + 'static {
         // Set the user function
         let mut control_flow = ControlFlow::Poll;
-        for handle in &self.window_handles {
+        for handle in self.window_handles.borrow().iter() {
             if let Some(data) = Win32Window::user_data(*handle) {
                 data.handler = Some(&mut f);
                 data.control_flow = &mut control_flow;
@@ -476,9 +1034,15 @@ impl EventLoopTrait for Win32EventLoop {
                         break;
                     }
                 }
-                unsafe{
-                    TranslateMessage(&mut msg);
-                    DispatchMessageW(&mut msg);
+                if msg.hwnd.is_null() && msg.message == wakeup_message() {
+                    // A wakeup posted to the thread, there's no window to dispatch to
+                    f(&mut control_flow, TimedEvent::now(Event::UserEvent(())));
+                }
+                else {
+                    unsafe{
+                        TranslateMessage(&mut msg);
+                        DispatchMessageW(&mut msg);
+                    }
                 }
                 unread = false;
                 if msg.message == WM_PAINT {
@@ -491,7 +1055,7 @@ impl EventLoopTrait for Win32EventLoop {
 
             if !pushed_paint {
                 // No paint event happened, we explicitly do a LogicUpdate
-                f(&mut control_flow, Event::LogicUpdate);
+                f(&mut control_flow, TimedEvent::now(Event::LogicUpdate));
                 // The logic update could have enforced a redraw
                 loop {
                     let ret = unsafe{ PeekMessageW(
@@ -509,7 +1073,7 @@ impl EventLoopTrait for Win32EventLoop {
                 }
             }
 
-            f(&mut control_flow, Event::AfterRedraw);
+            f(&mut control_flow, TimedEvent::now(Event::AfterRedraw));
 
             match control_flow {
                 ControlFlow::Exit => break,
@@ -524,22 +1088,28 @@ impl EventLoopTrait for Win32EventLoop {
             }
         }
 
-        f(&mut control_flow, Event::LoopExited);
+        f(&mut control_flow, TimedEvent::now(Event::LoopExited));
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 struct HwndState {
-    maximized: bool,
     style: u32,
     exstyle: u32,
-    rect: RECT,
+    placement: WINDOWPLACEMENT,
+    // The display, that had its video mode changed for exclusive fullscreen
+    display_device: Option<[u16; 32]>,
 }
 
 struct HwndUser {
-    events: Vec<Event>,
+    events: Vec<TimedEvent>,
     control_flow: *mut ControlFlow,
-    handler: Option<*mut dyn FnMut(&mut ControlFlow, Event)>,
+    handler: Option<*mut dyn FnMut(&mut ControlFlow, TimedEvent)>,
+    resize_increments: Option<PhysicalSize>,
+    // The DPI reported by the last WM_DPICHANGED
+    dpi: Option<u32>,
+    // The cursor shown over the client area
+    cursor: *mut c_void,
 }
 
 impl HwndUser {
@@ -548,10 +1118,19 @@ impl HwndUser {
             events: Vec::new(),
             control_flow: ptr::null_mut(),
             handler: None,
+            resize_increments: None,
+            dpi: None,
+            cursor: load_system_cursor(IDC_ARROW),
         }
     }
 }
 
+/// Loads one of the predefined system cursors.
+fn load_system_cursor(id: u16) -> *mut c_void {
+    // The ID is passed like MAKEINTRESOURCE would
+    unsafe{ LoadCursorW(ptr::null_mut(), id as usize as *const u16) }
+}
+
 #[derive(Debug)]
 pub struct Win32Window {
     hwnd: *mut c_void,
@@ -568,6 +1147,91 @@ impl Win32Window {
         Some(data)
     }
 
+    /// Removes the decorations of the window and stretches it over the given
+    /// monitor. The windowed state is saved first, unless it's already saved.
+    fn enter_borderless(&mut self, hmonitor: *mut c_void) -> bool {
+        const FLAGS: u32 = WS_CAPTION | WS_THICKFRAME;
+        const EXFLAGS: u32 = WS_EX_DLGMODALFRAME | WS_EX_WINDOWEDGE
+            | WS_EX_CLIENTEDGE | WS_EX_STATICEDGE;
+
+        if self.windowed.is_none() {
+            // Save windowed state
+            let mut placement = WINDOWPLACEMENT::new();
+            let ret = unsafe{ GetWindowPlacement(self.hwnd, &mut placement) };
+            if ret == 0 {
+                return false;
+            }
+            let style = unsafe{ GetWindowLongW(self.hwnd, GWL_STYLE) } as u32;
+            let exstyle = unsafe{ GetWindowLongW(self.hwnd, GWL_EXSTYLE) } as u32;
+            self.windowed = Some(HwndState{ style, exstyle, placement, display_device: None });
+            // Remove windowed styles
+            unsafe{
+                SetWindowLongW(self.hwnd, GWL_STYLE, (style & !FLAGS) as i32);
+                SetWindowLongW(self.hwnd, GWL_EXSTYLE, (exstyle & !EXFLAGS) as i32);
+            }
+        }
+        // Stretch on the monitor
+        let mut minfo = MONITORINFO::new();
+        let ret = unsafe{ GetMonitorInfoW(hmonitor, (&mut minfo as *mut MONITORINFO).cast()) };
+        if ret == 0 {
+            return false;
+        }
+        let mrect = minfo.monitor_rect;
+        unsafe{ SetWindowPos(
+            self.hwnd,
+            HWND_TOP,
+            mrect.left, mrect.top, mrect.width(), mrect.height(),
+            SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED) != 0 }
+    }
+
+    /// Restores the video mode of the display, that was changed for exclusive
+    /// fullscreen, if any.
+    fn restore_display_mode(&mut self) {
+        let device = self.windowed.as_mut().and_then(|s| s.display_device.take());
+        if let Some(device) = device {
+            unsafe{ ChangeDisplaySettingsExW(
+                device.as_ptr(), ptr::null_mut(), ptr::null_mut(), 0, ptr::null_mut()) };
+        }
+    }
+
+    /// Modifies the rectangle of a `WM_SIZING` message, so the client area
+    /// becomes a multiple of the given increments. Only the edges being
+    /// dragged are moved.
+    fn snap_sizing_rect(hwnd: *mut c_void, edge: usize, rect: &mut RECT, inc: PhysicalSize) {
+        fn snap(size: i32, inc: i32) -> i32 {
+            std::cmp::max((size + inc / 2) / inc * inc, inc)
+        }
+
+        // The size of the decorations
+        let mut window_rect = RECT::new();
+        let mut client_rect = RECT::new();
+        unsafe{
+            GetWindowRect(hwnd, &mut window_rect);
+            GetClientRect(hwnd, &mut client_rect);
+        }
+        let frame_width = window_rect.width() - client_rect.width();
+        let frame_height = window_rect.height() - client_rect.height();
+
+        let width = snap(rect.width() - frame_width, inc.width as i32) + frame_width;
+        let height = snap(rect.height() - frame_height, inc.height as i32) + frame_height;
+        match edge {
+            WMSZ_LEFT | WMSZ_TOPLEFT | WMSZ_BOTTOMLEFT => rect.left = rect.right - width,
+            _ => rect.right = rect.left + width,
+        }
+        match edge {
+            WMSZ_TOP | WMSZ_TOPLEFT | WMSZ_TOPRIGHT => rect.top = rect.bottom - height,
+            _ => rect.bottom = rect.top + height,
+        }
+    }
+
+    /// Returns the time the last message was posted at. The message time is a
+    /// tick count, so it's converted relative to the current tick count.
+    fn message_time() -> Instant {
+        let now = Instant::now();
+        let elapsed = unsafe{ GetTickCount().wrapping_sub(GetMessageTime() as u32) };
+        now.checked_sub(Duration::from_millis(elapsed as u64)).unwrap_or(now)
+    }
+
     // NOTE: I really dislike the current event ordering of
     // - LoopDestroyed
     // - Window.FocusChanged(false)
@@ -578,9 +1242,10 @@ impl Win32Window {
         let window_event = |event: WindowEvent| {
             Event::WindowEvent{ window_id, event }
         };
+        let time = Self::message_time();
         let push_event = |e: Event| {
             if let Some(data) = Self::user_data(hwnd) {
-                data.events.push(e);
+                data.events.push(TimedEvent::new(time, e));
             }
         };
         // Handle events
@@ -599,6 +1264,7 @@ impl Win32Window {
                 0
             },
             WM_DESTROY => {
+                set_raw_mouse_window(hwnd, false);
                 push_event(window_event(WindowEvent::Closed));
                 unsafe{ DefWindowProcW(hwnd, msg, wparam, lparam) }
             },
@@ -613,7 +1279,11 @@ impl Win32Window {
             },
             // Size
             WM_SIZING => {
-                let rect = unsafe{ &*(lparam as *const RECT) };
+                let rect = unsafe{ &mut *(lparam as *mut RECT) };
+                let increments = Self::user_data(hwnd).and_then(|d| d.resize_increments);
+                if let Some(inc) = increments {
+                    Self::snap_sizing_rect(hwnd, wparam, rect, inc);
+                }
                 let width = rect.width() as u32;
                 let height = rect.height() as u32;
                 let size = PhysicalSize::new(width, height);
@@ -627,6 +1297,55 @@ impl Win32Window {
                 push_event(window_event(WindowEvent::Resized(size)));
                 unsafe{ DefWindowProcW(hwnd, msg, wparam, lparam) }
             },
+            // DPI
+            WM_DPICHANGED => {
+                // The X and Y DPI are always the same
+                if let Some(data) = Self::user_data(hwnd) {
+                    data.dpi = Some((wparam & 0xffff) as u32);
+                }
+                // Apply the suggested rectangle, so the window keeps its
+                // apparent size on the new monitor
+                let rect = unsafe{ &*(lparam as *const RECT) };
+                unsafe{ SetWindowPos(
+                    hwnd, HWND_TOP,
+                    rect.left, rect.top, rect.width(), rect.height(),
+                    SWP_NOZORDER | SWP_NOACTIVATE) };
+                0
+            },
+            // Cursor
+            WM_SETCURSOR => {
+                let cursor = Self::user_data(hwnd).map(|d| d.cursor);
+                match cursor {
+                    // Only override the cursor inside the client area
+                    Some(cursor) if (lparam & 0xffff) == HTCLIENT => {
+                        unsafe{ SetCursor(cursor) };
+                        1
+                    },
+                    _ => unsafe{ DefWindowProcW(hwnd, msg, wparam, lparam) },
+                }
+            },
+            // Drag and drop
+            WM_DROPFILES => {
+                let hdrop = wparam as *mut c_void;
+                for path in dropped_files(hdrop) {
+                    push_event(window_event(WindowEvent::FileDropped(path)));
+                }
+                unsafe{ DragFinish(hdrop) };
+                0
+            },
+            // Raw input
+            WM_INPUT => {
+                let raw_mouse = raw_mouse_windows().lock().unwrap().contains(&(hwnd as usize));
+                let motion = if raw_mouse { raw_mouse_motion(lparam as *mut c_void) } else { None };
+                if let Some((dx, dy)) = motion {
+                    if dx != 0 || dy != 0 {
+                        let (dx, dy) = (dx as f64, dy as f64);
+                        push_event(window_event(WindowEvent::RawMouseMotion{ dx, dy }));
+                    }
+                }
+                // The system cleans up the input here
+                unsafe{ DefWindowProcW(hwnd, msg, wparam, lparam) }
+            },
             // Redraw
             WM_PAINT => {
                 // We push a logic update event before redraw
@@ -635,6 +1354,11 @@ impl Win32Window {
                 push_event(Event::Redraw(window_id));
                 0
             },
+            // Wakeup from a Win32Waker
+            _ if msg == wakeup_message() => {
+                push_event(Event::UserEvent(()));
+                0
+            },
             // Others
             _ => unsafe{ DefWindowProcW(hwnd, msg, wparam, lparam) },
         };
@@ -653,15 +1377,14 @@ impl Win32Window {
 }
 
 impl WindowTrait for Win32Window {
-    fn new() -> Self {
+    fn new(attribs: &WindowAttributes) -> io::Result<Self> {
         let hinstance = unsafe{ GetModuleHandleW(ptr::null_mut()) };
         if hinstance.is_null() {
-            // TODO: Error handling
-            unimplemented!();
+            return Err(io::Error::last_os_error());
         }
 
         let class_name = to_wstring(OsStr::new("Cacti Window Class"));
-        let window_name = to_wstring(OsStr::new("Window Name"));
+        let window_name = to_wstring(OsStr::new(&attribs.title));
 
         // Window class
         let mut wndclass = WNDCLASSW::new();
@@ -670,42 +1393,82 @@ impl WindowTrait for Win32Window {
         wndclass.hinstance = hinstance;
         wndclass.class_name = class_name.as_ptr();
 
+        // Every window shares the class, it's only registered for the first
+        // one. The window procedure tells them apart by their user data
         let ret = unsafe{ RegisterClassW(&wndclass) };
-        if ret == 0 {
-            // TODO: Return error
-            unimplemented!();
+        if ret == 0 && unsafe{ GetLastError() } != ERROR_CLASS_ALREADY_EXISTS {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Style
+        let exstyle = WS_EX_LAYERED;
+        let mut style = WS_OVERLAPPEDWINDOW;
+        if !attribs.resizable {
+            style &= !(WS_MAXIMIZEBOX | WS_THICKFRAME);
         }
+        if attribs.visible {
+            style |= WS_VISIBLE;
+        }
+
+        // Geometry, the size has to include the decorations
+        let (x, y) = match attribs.position {
+            Some(pos) => (pos.x, pos.y),
+            None => (CW_USEDEFAULT, CW_USEDEFAULT),
+        };
+        let (width, height) = match attribs.inner_size {
+            Some(siz) => {
+                let mut rect = RECT{
+                    left: 0,
+                    top: 0,
+                    right: siz.width as i32,
+                    bottom: siz.height as i32,
+                };
+                if unsafe{ AdjustWindowRectEx(&mut rect, style, 0, exstyle) } == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                (rect.width(), rect.height())
+            },
+            None => (CW_USEDEFAULT, CW_USEDEFAULT),
+        };
 
         // User data
-        let user_data = Box::leak(Box::new(HwndUser::new()));
+        let user_data = Box::into_raw(Box::new(HwndUser::new()));
 
         // Window
         let hwnd = unsafe{ CreateWindowExW(
-            WS_EX_LAYERED,
+            exstyle,
             class_name.as_ptr(),
             window_name.as_ptr(),
-            WS_OVERLAPPEDWINDOW,
-            CW_USEDEFAULT, CW_USEDEFAULT,
-            CW_USEDEFAULT, CW_USEDEFAULT,
+            style,
+            x, y,
+            width, height,
             ptr::null_mut(),
             ptr::null_mut(),
             hinstance,
-            (user_data as *mut HwndUser).cast()) };
+            user_data.cast()) };
         if hwnd.is_null() {
-            // TODO: Return error
-            unimplemented!();
+            let err = io::Error::last_os_error();
+            drop(unsafe{ Box::from_raw(user_data) });
+            return Err(err);
         }
 
         unsafe{ SetLayeredWindowAttributes(hwnd, 0, 255, LWA_ALPHA) };
+        if attribs.file_drop {
+            unsafe{ DragAcceptFiles(hwnd, 1) };
+        }
 
-        Self{
+        Ok(Self{
             hwnd,
             windowed: None,
-        }
+        })
     }
 
     fn handle_ptr(&self) -> *mut c_void { self.hwnd }
 
+    fn is_valid(&self) -> bool {
+        unsafe{ IsWindow(self.hwnd) != 0 }
+    }
+
     fn monitor(&self) -> Win32Monitor {
         let monitor = unsafe{ MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST) };
         Win32Monitor{ hmonitor: monitor }
@@ -723,6 +1486,40 @@ impl WindowTrait for Win32Window {
         PhysicalSize::new(rect.width() as u32, rect.height() as u32)
     }
 
+    fn dpi(&self) -> Option<Dpi> {
+        let tracked = Self::user_data(self.hwnd).and_then(|d| d.dpi);
+        let dpi = match tracked {
+            Some(dpi) => dpi,
+            // Falls back to the DPI of the monitor on older systems
+            None => dpi_for_window(self.hwnd)?,
+        };
+        if dpi == 0 {
+            return None;
+        }
+        Some(Dpi::new(dpi as f64, dpi as f64))
+    }
+
+    fn scale(&self) -> Option<f64> {
+        self.dpi().map(|dpi| dpi.horizontal / USER_DEFAULT_SCREEN_DPI as f64)
+    }
+
+    fn position(&self) -> PhysicalPosition {
+        let mut rect = RECT::new();
+        unsafe{ GetWindowRect(self.hwnd, &mut rect) };
+        PhysicalPosition::new(rect.left, rect.top)
+    }
+
+    fn title(&self) -> Option<String> {
+        let len = unsafe{ GetWindowTextLengthW(self.hwnd) };
+        // Extra space for the 0-terminator
+        let mut buffer = vec![0u16; len as usize + 1];
+        let written = unsafe{ GetWindowTextW(self.hwnd, buffer.as_mut_ptr(), buffer.len() as i32) };
+        if written == 0 && len != 0 {
+            return None;
+        }
+        Some(String::from_utf16_lossy(&buffer[..(written as usize)]))
+    }
+
     fn set_visible(&mut self, vis: bool) {
         let cmd = if vis { SW_SHOW } else { SW_HIDE };
         unsafe{ ShowWindow(self.hwnd, cmd) };
@@ -736,6 +1533,36 @@ impl WindowTrait for Win32Window {
         true
     }
 
+    fn set_decorations(&mut self, d: bool) -> bool {
+        const FLAGS: u32 = WS_CAPTION | WS_THICKFRAME | WS_SYSMENU;
+        let style = unsafe{ GetWindowLongW(self.hwnd, GWL_STYLE) } as u32;
+        let exstyle = unsafe{ GetWindowLongW(self.hwnd, GWL_EXSTYLE) } as u32;
+        let newstyle = if d {
+            // A non-resizable window doesn't get the sizing border back
+            let flags = if style & WS_MAXIMIZEBOX != 0 { FLAGS } else { FLAGS & !WS_THICKFRAME };
+            style | flags
+        }
+        else {
+            style & !FLAGS
+        };
+        // The client area keeps its size, the frame grows or shrinks around it
+        let siz = self.inner_size();
+        let mut rect = RECT{
+            left: 0,
+            top: 0,
+            right: siz.width as i32,
+            bottom: siz.height as i32,
+        };
+        let ret = unsafe{ AdjustWindowRectEx(&mut rect, newstyle, 0, exstyle) };
+        if ret == 0 {
+            return false;
+        }
+        unsafe{ SetWindowLongW(self.hwnd, GWL_STYLE, newstyle as i32) };
+        unsafe{ SetWindowPos(
+            self.hwnd, HWND_TOP, 0, 0, rect.width(), rect.height(),
+            SWP_NOMOVE | SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED) != 0 }
+    }
+
     fn set_title(&mut self, title: &str) -> bool {
         let wtitle = to_wstring(OsStr::new(title));
         unsafe{ SetWindowTextW(self.hwnd, wtitle.as_ptr()) != 0 }
@@ -773,72 +1600,202 @@ impl WindowTrait for Win32Window {
     }
 
     fn set_transparency(&mut self, t: f64) -> bool {
-        let b = (t * 255.0) as u8;
-        unsafe{ SetLayeredWindowAttributes(self.hwnd, 0, b, LWA_ALPHA) != 0 }
+        // The alpha is only applied to layered windows
+        let exstyle = unsafe{ GetWindowLongW(self.hwnd, GWL_EXSTYLE) } as u32;
+        if exstyle & WS_EX_LAYERED == 0 {
+            unsafe{ SetWindowLongW(self.hwnd, GWL_EXSTYLE, (exstyle | WS_EX_LAYERED) as i32) };
+        }
+        let alpha = (clamp_opacity(t) * 255.0).round() as u8;
+        unsafe{ SetLayeredWindowAttributes(self.hwnd, 0, alpha, LWA_ALPHA) != 0 }
     }
 
-    fn set_fullscreen(&mut self, fs: bool) -> bool {
-        const FLAGS: u32 = WS_CAPTION | WS_THICKFRAME;
-        const EXFLAGS: u32 = WS_EX_DLGMODALFRAME | WS_EX_WINDOWEDGE
-            | WS_EX_CLIENTEDGE | WS_EX_STATICEDGE;
+    fn set_content_protected(&mut self, p: bool) -> bool {
+        if !p {
+            return unsafe{ SetWindowDisplayAffinity(self.hwnd, WDA_NONE) != 0 };
+        }
+        // Excluding needs Windows 10 version 2004, before that the content
+        // can only be blacked out
+        unsafe{
+            SetWindowDisplayAffinity(self.hwnd, WDA_EXCLUDEFROMCAPTURE) != 0
+            || SetWindowDisplayAffinity(self.hwnd, WDA_MONITOR) != 0
+        }
+    }
 
-        if fs == self.windowed.is_some() {
-            // Already in desired state
-            return true;
+    fn set_raw_mouse(&mut self, raw: bool) -> bool {
+        set_raw_mouse_window(self.hwnd, raw)
+    }
+
+    fn set_fullscreen(&mut self, fs: Fullscreen) -> bool {
+        match fs {
+            Fullscreen::None => {
+                self.restore_display_mode();
+                let state = match self.windowed.take() {
+                    Some(state) => state,
+                    // Already windowed
+                    None => return true,
+                };
+                unsafe{
+                    SetWindowLongW(self.hwnd, GWL_STYLE, state.style as i32);
+                    SetWindowLongW(self.hwnd, GWL_EXSTYLE, state.exstyle as i32);
+                }
+                // Apply the restored styles, then the placement
+                unsafe{ SetWindowPos(
+                    self.hwnd, HWND_TOP, 0, 0, 0, 0,
+                    SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED) };
+                unsafe{ SetWindowPlacement(self.hwnd, &state.placement) != 0 }
+            },
+            Fullscreen::Borderless(monitor) => {
+                self.restore_display_mode();
+                let hmonitor = match monitor {
+                    Some(m) => m.0.hmonitor,
+                    None => unsafe{ MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST) },
+                };
+                self.enter_borderless(hmonitor)
+            },
+            Fullscreen::Exclusive(mode) => {
+                self.restore_display_mode();
+                let hmonitor = unsafe{ MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST) };
+                let mut info = MONITORINFOEXW::new();
+                let ret = unsafe{ GetMonitorInfoW(hmonitor, &mut info) };
+                if ret == 0 {
+                    return false;
+                }
+                let mut devmode = DEVMODEW::new();
+                devmode.fields = DM_PELSWIDTH | DM_PELSHEIGHT | DM_BITSPERPEL;
+                devmode.pels_width = mode.size.width;
+                devmode.pels_height = mode.size.height;
+                devmode.bits_per_pel = mode.bit_depth;
+                if mode.refresh_rate > 0 {
+                    devmode.fields |= DM_DISPLAYFREQUENCY;
+                    devmode.display_frequency = mode.refresh_rate;
+                }
+                let ret = unsafe{ ChangeDisplaySettingsExW(
+                    info.dev_name.as_ptr(), &mut devmode, ptr::null_mut(), CDS_FULLSCREEN, ptr::null_mut()) };
+                if ret != DISP_CHANGE_SUCCESSFUL {
+                    return false;
+                }
+                // The monitor changed size, so this covers the new mode
+                let ok = self.enter_borderless(hmonitor);
+                match self.windowed.as_mut() {
+                    Some(state) => state.display_device = Some(info.dev_name),
+                    None => unsafe{ ChangeDisplaySettingsExW(
+                        info.dev_name.as_ptr(), ptr::null_mut(), ptr::null_mut(), 0, ptr::null_mut()); },
+                }
+                ok
+            },
         }
+    }
 
-        if fs {
-            // Save windowed state
-            let mut placement = WINDOWPLACEMENT::new();
-            let ret = unsafe{ GetWindowPlacement(self.hwnd, &mut placement) };
-            if ret == 0 {
+    fn set_resize_increments(&mut self, inc: Option<PhysicalSize>) -> bool {
+        if let Some(inc) = inc {
+            if inc.width == 0 || inc.height == 0 {
                 return false;
             }
-            let maximized = placement.show == SW_MAXIMIZE as u32;
-            let style = unsafe{ GetWindowLongW(self.hwnd, GWL_STYLE) } as u32;
-            let exstyle = unsafe{ GetWindowLongW(self.hwnd, GWL_EXSTYLE) } as u32;
-            let rect = placement.normal_pos;
-            self.windowed = Some(HwndState{ maximized, style, exstyle, rect });
-            // Remove windowed styles
-            unsafe{
-                SetWindowLongW(self.hwnd, GWL_STYLE, (style & !FLAGS) as i32);
-                SetWindowLongW(self.hwnd, GWL_EXSTYLE, (exstyle & !EXFLAGS) as i32);
-            }
-            // Stretch on current monitor
-            let monitor = unsafe{ MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST) };
-            let mut minfo = MONITORINFO::new();
-            let ret = unsafe{ GetMonitorInfoW(monitor, (&mut minfo as *mut MONITORINFO).cast()) };
-            if ret == 0 {
-                return false;
-            }
-            let mrect = minfo.monitor_rect;
-            unsafe{ SetWindowPos(
-                self.hwnd,
-                HWND_TOP,
-                mrect.left, mrect.top, mrect.width(), mrect.height(),
-                SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED) != 0 }
         }
-        else {
-            // Restore state
-            let state = self.windowed.take().unwrap();
-            unsafe{
-                SetWindowLongW(self.hwnd, GWL_STYLE, state.style as i32);
-                SetWindowLongW(self.hwnd, GWL_EXSTYLE, state.exstyle as i32);
-            }
-            let rect = state.rect;
-            let res = unsafe{ SetWindowPos(
-                self.hwnd,
-                HWND_TOP,
-                rect.left, rect.top, rect.width(), rect.height(),
-                SWP_NOZORDER | SWP_NOACTIVATE | SWP_FRAMECHANGED) };
-            if res == 0 {
-                return false;
-            }
-            if state.maximized {
-                unsafe{ ShowWindow(self.hwnd, SW_MAXIMIZE) };
-            }
-            true
+        match Self::user_data(self.hwnd) {
+            Some(data) => {
+                data.resize_increments = inc;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn set_cursor_icon(&mut self, icon: CursorIcon) -> bool {
+        let id = match icon {
+            CursorIcon::Default    => IDC_ARROW   ,
+            CursorIcon::Pointer    => IDC_HAND    ,
+            CursorIcon::Text       => IDC_IBEAM   ,
+            CursorIcon::Crosshair  => IDC_CROSS   ,
+            CursorIcon::Wait       => IDC_WAIT    ,
+            CursorIcon::ResizeNS   => IDC_SIZENS  ,
+            CursorIcon::ResizeEW   => IDC_SIZEWE  ,
+            CursorIcon::ResizeNESW => IDC_SIZENESW,
+            CursorIcon::ResizeNWSE => IDC_SIZENWSE,
+            CursorIcon::NotAllowed => IDC_NO      ,
+        };
+        let cursor = load_system_cursor(id);
+        if cursor.is_null() {
+            return false;
+        }
+        match Self::user_data(self.hwnd) {
+            Some(data) => data.cursor = cursor,
+            None => return false,
+        }
+        // WM_SETCURSOR only arrives when the mouse moves, update it right away,
+        // if it's over the window
+        let mut point = POINT{ x: 0, y: 0 };
+        if unsafe{ GetCursorPos(&mut point) } != 0
+            && unsafe{ WindowFromPoint(point) } == self.hwnd {
+            unsafe{ SetCursor(cursor) };
+        }
+        true
+    }
+
+    fn request_attention(&mut self, level: Option<AttentionLevel>) -> bool {
+        let (flags, count) = match level {
+            // Until the window comes to the foreground
+            Some(AttentionLevel::Critical) => (FLASHW_ALL | FLASHW_TIMERNOFG, 0),
+            Some(AttentionLevel::Informational) => (FLASHW_ALL, 1),
+            None => (FLASHW_STOP, 0),
+        };
+        let info = FLASHWINFO{
+            size: mem::size_of::<FLASHWINFO>() as u32,
+            hwnd: self.hwnd,
+            flags,
+            count,
+            // The default blink rate
+            timeout: 0,
+        };
+        // The return value is the previous state, not an error code
+        unsafe{ FlashWindowEx(&info) };
+        true
+    }
+
+    fn begin_drag(&mut self) -> bool {
+        let mut point = POINT{ x: 0, y: 0 };
+        if unsafe{ GetCursorPos(&mut point) } == 0 {
+            return false;
+        }
+        // The cursor position is passed as a POINTS
+        let lparam = ((point.x as u16 as u32) | ((point.y as u16 as u32) << 16)) as isize;
+        // We have to release the mouse, so the system can capture it for the
+        // move loop
+        unsafe{ ReleaseCapture() };
+        unsafe{ PostMessageW(self.hwnd, WM_NCLBUTTONDOWN, HTCAPTION, lparam) != 0 }
+    }
+
+    fn present_rgba(&mut self, pixels: &[u8], width: u32, height: u32) -> bool {
+        // Uncompressed 32 bit DIBs are BGRX
+        let bits = rgba_to_bgra(pixels);
+        let info = BITMAPINFO{
+            header: BITMAPINFOHEADER{
+                size: mem::size_of::<BITMAPINFOHEADER>() as u32,
+                width: width as i32,
+                // A negative height means a top-down bitmap
+                height: -(height as i32),
+                planes: 1,
+                bit_count: 32,
+                compression: BI_RGB,
+                size_image: 0,
+                x_pels_per_meter: 0,
+                y_pels_per_meter: 0,
+                clr_used: 0,
+                clr_important: 0,
+            },
+            colors: [0],
+        };
+        let hdc = unsafe{ GetDC(self.hwnd) };
+        if hdc.is_null() {
+            return false;
         }
+        let lines = unsafe{ SetDIBitsToDevice(
+            hdc,
+            0, 0, width, height,
+            0, 0, 0, height,
+            bits.as_ptr() as *const c_void, &info, DIB_RGB_COLORS) };
+        unsafe{ ReleaseDC(self.hwnd, hdc) };
+        lines > 0
     }
 }
 
@@ -1,12 +1,16 @@
 
 #![cfg(target_os = "linux")]
 
-use std::ffi::c_void;
-use std::os::raw::{c_char, c_int, c_uint, c_long, c_ulong};
+use std::ffi::{CStr, c_void};
+use std::os::raw::{c_char, c_short, c_int, c_uint, c_long, c_ulong};
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
 use std::ptr;
 use std::mem;
+use std::sync::Mutex;
+use std::time::Duration;
 use super::*;
 
 // ////////////////////////////////////////////////////////////////////////// //
@@ -36,6 +40,8 @@ extern "C" {
     ) -> c_int;
     fn XWidthMMOfScreen(screen: *mut c_void) -> c_int;
     fn XHeightMMOfScreen(screen: *mut c_void) -> c_int;
+    fn XScreenNumberOfScreen(screen: *mut c_void) -> c_int;
+    fn XDefaultDepthOfScreen(screen: *mut c_void) -> c_int;
     fn XCreateSimpleWindow(
         display     : *mut c_void,
         parent      : c_ulong    ,
@@ -62,6 +68,13 @@ extern "C" {
         height  : c_uint     ,
     ) -> c_int;
     fn XDefaultGC(display: *mut c_void, screen_idx: c_int) -> *mut c_void;
+    fn XCreateGC(
+        display  : *mut c_void,
+        drawable : c_ulong    ,
+        valuemask: c_ulong    ,
+        values   : *mut c_void,
+    ) -> *mut c_void;
+    fn XFreeGC(display: *mut c_void, gc: *mut c_void) -> c_int;
     fn XSelectInput(
         display: *mut c_void,
         window : c_ulong    ,
@@ -84,6 +97,21 @@ extern "C" {
         window : c_ulong      ,
         title  : *const c_char,
     ) -> c_int;
+    fn XFetchName(
+        display: *mut c_void     ,
+        window : c_ulong         ,
+        title  : *mut *mut c_char,
+    ) -> c_int;
+    fn XTranslateCoordinates(
+        display: *mut c_void ,
+        src    : c_ulong     ,
+        dst    : c_ulong     ,
+        src_x  : c_int       ,
+        src_y  : c_int       ,
+        dst_x  : *mut c_int  ,
+        dst_y  : *mut c_int  ,
+        child  : *mut c_ulong,
+    ) -> c_int;
     fn XMoveWindow(
         display: *mut c_void,
         window : c_ulong    ,
@@ -104,7 +132,106 @@ extern "C" {
         height : c_uint     ,
     ) -> c_int;
     fn XEventsQueued(display: *mut c_void, mode: c_int) -> c_int;
+    fn XInternAtom(
+        display       : *mut c_void  ,
+        name          : *const c_char,
+        only_if_exists: c_int        ,
+    ) -> c_ulong;
+    fn XSendEvent(
+        display  : *mut c_void,
+        window   : c_ulong    ,
+        propagate: c_int      ,
+        mask     : c_long     ,
+        event    : *mut XEvent,
+    ) -> c_int;
+    fn XQueryPointer(
+        display: *mut c_void ,
+        window : c_ulong     ,
+        root   : *mut c_ulong,
+        child  : *mut c_ulong,
+        root_x : *mut c_int  ,
+        root_y : *mut c_int  ,
+        win_x  : *mut c_int  ,
+        win_y  : *mut c_int  ,
+        mask   : *mut c_uint ,
+    ) -> c_int;
+    fn XUngrabPointer(display: *mut c_void, time: c_ulong) -> c_int;
+    fn XAllocWMHints() -> *mut XWMHints;
+    fn XGetWMHints(display: *mut c_void, window: c_ulong) -> *mut XWMHints;
+    fn XSetWMHints(display: *mut c_void, window: c_ulong, hints: *mut XWMHints) -> c_int;
+    fn XCreateFontCursor(display: *mut c_void, shape: c_uint) -> c_ulong;
+    fn XDefineCursor(display: *mut c_void, window: c_ulong, cursor: c_ulong) -> c_int;
+    fn XFreeCursor(display: *mut c_void, cursor: c_ulong) -> c_int;
+    fn XFlush(display: *mut c_void) -> c_int;
     fn XPending(display: *mut c_void) -> c_int;
+    fn XChangeProperty(
+        display : *mut c_void,
+        window  : c_ulong    ,
+        property: c_ulong    ,
+        ty      : c_ulong    ,
+        format  : c_int      ,
+        mode    : c_int      ,
+        data    : *const u8  ,
+        elements: c_int      ,
+    ) -> c_int;
+    fn XGetWindowProperty(
+        display      : *mut c_void  ,
+        window       : c_ulong      ,
+        property     : c_ulong      ,
+        offset       : c_long       ,
+        length       : c_long       ,
+        delete       : c_int        ,
+        req_type     : c_ulong      ,
+        actual_type  : *mut c_ulong ,
+        actual_format: *mut c_int   ,
+        items        : *mut c_ulong ,
+        bytes_after  : *mut c_ulong ,
+        data         : *mut *mut u8 ,
+    ) -> c_int;
+    fn XConvertSelection(
+        display  : *mut c_void,
+        selection: c_ulong    ,
+        target   : c_ulong    ,
+        property : c_ulong    ,
+        requestor: c_ulong    ,
+        time     : c_ulong    ,
+    ) -> c_int;
+    fn XSetSelectionOwner(
+        display  : *mut c_void,
+        selection: c_ulong    ,
+        owner    : c_ulong    ,
+        time     : c_ulong    ,
+    ) -> c_int;
+    fn XGetSelectionOwner(display: *mut c_void, selection: c_ulong) -> c_ulong;
+    fn XCheckTypedWindowEvent(
+        display: *mut c_void,
+        window : c_ulong    ,
+        ty     : c_int      ,
+        event  : *mut XEvent,
+    ) -> c_int;
+    fn XQueryExtension(
+        display    : *mut c_void  ,
+        name       : *const c_char,
+        opcode     : *mut c_int   ,
+        first_event: *mut c_int   ,
+        first_error: *mut c_int   ,
+    ) -> c_int;
+    fn XGetEventData(display: *mut c_void, cookie: *mut XGenericEventCookie) -> c_int;
+    fn XFreeEventData(display: *mut c_void, cookie: *mut XGenericEventCookie);
+    fn XGetInputFocus(display: *mut c_void, focus: *mut c_ulong, revert_to: *mut c_int) -> c_int;
+    fn XInitImage(image: *mut XImage) -> c_int;
+    fn XPutImage(
+        display : *mut c_void,
+        drawable: c_ulong    ,
+        gc      : *mut c_void,
+        image   : *mut XImage,
+        src_x   : c_int      ,
+        src_y   : c_int      ,
+        dest_x  : c_int      ,
+        dest_y  : c_int      ,
+        width   : c_uint     ,
+        height  : c_uint     ,
+    ) -> c_int;
 }
 
 const ExposureMask: c_long = 0x8000;
@@ -112,6 +239,7 @@ const StructureNotifyMask: c_long = 0x20000;
 const SubstructureNotifyMask: c_long = 0x80000;
 const FocusChangeMask: c_long = 0x200000;
 const ResizeRedirectMask: c_long = 0x40000;
+const SubstructureRedirectMask: c_long = 0x100000;
 
 const CWBackPixmap: c_ulong = 1 << 0;
 const CWBackPixel: c_ulong = 1 << 1;
@@ -129,6 +257,9 @@ const CWDontPropagate: c_ulong = 1 << 12;
 const CWColormap: c_ulong = 1 << 13;
 const CWCursor: c_ulong = 1 << 14;
 
+const ZPixmap: c_int = 2;
+const LSBFirst: c_int = 0;
+
 const IsUnmapped: c_int = 0;
 const IsUnviewable: c_int = 1;
 const IsViewable: c_int = 2;
@@ -149,9 +280,45 @@ const DestroyNotify: c_int = 17;
 const FocusIn: c_int = 9;
 const FocusOut: c_int = 10;
 const ResizeRequest: c_int = 25;
+const SelectionClear: c_int = 29;
+const SelectionRequest: c_int = 30;
+const SelectionNotify: c_int = 31;
+const ClientMessage: c_int = 33;
+const GenericEvent: c_int = 35;
+
+const CurrentTime: c_ulong = 0;
+
+// Shapes of the standard cursor font
+const XC_X_cursor: c_uint = 0;
+const XC_bottom_left_corner: c_uint = 12;
+const XC_bottom_right_corner: c_uint = 14;
+const XC_crosshair: c_uint = 34;
+const XC_hand2: c_uint = 60;
+const XC_left_ptr: c_uint = 68;
+const XC_sb_h_double_arrow: c_uint = 108;
+const XC_sb_v_double_arrow: c_uint = 116;
+const XC_watch: c_uint = 150;
+const XC_xterm: c_uint = 152;
+
+const _NET_WM_MOVERESIZE_MOVE: c_long = 8;
+
+const _NET_WM_STATE_REMOVE: c_long = 0;
+const _NET_WM_STATE_ADD: c_long = 1;
+
+const XUrgencyHint: c_long = 1 << 8;
 
 const QueuedAlready: c_int = 0;
 
+const XA_ATOM: c_ulong = 4;
+const XA_CARDINAL: c_ulong = 6;
+const AnyPropertyType: c_ulong = 0;
+const PropModeReplace: c_int = 0;
+
+const MWM_HINTS_DECORATIONS: c_ulong = 1 << 1;
+
+// The XDND protocol version we implement
+const XDND_VERSION: c_long = 5;
+
 #[repr(C)]
 union XEvent {
     ty: c_int,
@@ -160,6 +327,11 @@ union XEvent {
     focus: XFocusChangeEvent,
     resize: XResizeRequestEvent,
     expose: XExposeEvent,
+    client: XClientMessageEvent,
+    selection: XSelectionEvent,
+    selection_request: XSelectionRequestEvent,
+    selection_clear: XSelectionClearEvent,
+    cookie: XGenericEventCookie,
     pad: [c_long; 24],
 }
 
@@ -236,6 +408,73 @@ struct XExposeEvent {
     count: c_int,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct XClientMessageEvent {
+    ty: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    window: c_ulong,
+    message_type: c_ulong,
+    format: c_int,
+    data: [c_long; 5],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct XSelectionEvent {
+    ty: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    requestor: c_ulong,
+    selection: c_ulong,
+    target: c_ulong,
+    property: c_ulong,
+    time: c_ulong,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct XSelectionRequestEvent {
+    ty: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    owner: c_ulong,
+    requestor: c_ulong,
+    selection: c_ulong,
+    target: c_ulong,
+    property: c_ulong,
+    time: c_ulong,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct XSelectionClearEvent {
+    ty: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    window: c_ulong,
+    selection: c_ulong,
+    time: c_ulong,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct XGenericEventCookie {
+    ty: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut c_void,
+    extension: c_int,
+    evtype: c_int,
+    cookie: c_uint,
+    data: *mut c_void,
+}
+
 #[repr(C)]
 struct XWindowAttributes {
     x                    : c_int      ,
@@ -315,6 +554,242 @@ struct XSizeHints {
     win_gravity     : c_int ,
 }
 
+#[repr(C)]
+struct XImage {
+    width           : c_int          ,
+    height          : c_int          ,
+    xoffset         : c_int          ,
+    format          : c_int          ,
+    data            : *mut c_char    ,
+    byte_order      : c_int          ,
+    bitmap_unit     : c_int          ,
+    bitmap_bit_order: c_int          ,
+    bitmap_pad      : c_int          ,
+    depth           : c_int          ,
+    bytes_per_line  : c_int          ,
+    bits_per_pixel  : c_int          ,
+    red_mask        : c_ulong        ,
+    green_mask      : c_ulong        ,
+    blue_mask       : c_ulong        ,
+    obdata          : *mut c_char    ,
+    // Filled by XInitImage
+    funcs           : [*mut c_void; 6],
+}
+
+#[repr(C)]
+struct XWMHints {
+    flags        : c_long ,
+    input        : c_int  ,
+    initial_state: c_int  ,
+    icon_pixmap  : c_ulong,
+    icon_window  : c_ulong,
+    icon_x       : c_int  ,
+    icon_y       : c_int  ,
+    icon_mask    : c_ulong,
+    window_group : c_ulong,
+}
+
+// ////////////////////////////////////////////////////////////////////////// //
+//                              XRandR bindings                               //
+// ////////////////////////////////////////////////////////////////////////// //
+
+// NOTE: XRandR is loaded at runtime, so it's not required just to open windows
+
+#[link(name = "c")]
+extern "C" {
+    fn dlopen(fname: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlclose(handle: *mut c_void) -> c_int;
+}
+
+const RTLD_NOW: c_int = 0x2;
+
+#[repr(C)]
+struct XRRScreenSize {
+    width  : c_int,
+    height : c_int,
+    mwidth : c_int,
+    mheight: c_int,
+}
+
+type XRRSizes = unsafe extern "C" fn(
+    display: *mut c_void,
+    screen : c_int      ,
+    nsizes : *mut c_int ,
+) -> *mut XRRScreenSize;
+type XRRRates = unsafe extern "C" fn(
+    display: *mut c_void,
+    screen : c_int      ,
+    size_id: c_int      ,
+    nrates : *mut c_int ,
+) -> *mut c_short;
+
+/// The functions of the XRandR library we use.
+#[derive(Clone, Copy)]
+struct XRandR {
+    lib: *mut c_void,
+    sizes: XRRSizes,
+    rates: XRRRates,
+}
+
+impl XRandR {
+    /// Loads the XRandR library, returns `None` if it's not available.
+    fn load() -> Option<Self> {
+        let lib = unsafe{ dlopen(to_cstring("libXrandr.so.2").as_ptr(), RTLD_NOW) };
+        if lib.is_null() {
+            return None;
+        }
+        let sizes = unsafe{ dlsym(lib, to_cstring("XRRSizes").as_ptr()) };
+        let rates = unsafe{ dlsym(lib, to_cstring("XRRRates").as_ptr()) };
+        if sizes.is_null() || rates.is_null() {
+            unsafe{ dlclose(lib) };
+            return None;
+        }
+        Some(Self{
+            lib,
+            sizes: unsafe{ mem::transmute::<*mut c_void, XRRSizes>(sizes) },
+            rates: unsafe{ mem::transmute::<*mut c_void, XRRRates>(rates) },
+        })
+    }
+}
+
+// ////////////////////////////////////////////////////////////////////////// //
+//                             XInput 2 bindings                              //
+// ////////////////////////////////////////////////////////////////////////// //
+
+// NOTE: Like XRandR, XInput is loaded at runtime
+
+const XIAllMasterDevices: c_int = 1;
+const XI_RawMotion: c_int = 17;
+
+#[repr(C)]
+struct XIEventMask {
+    deviceid: c_int  ,
+    mask_len: c_int  ,
+    mask    : *mut u8,
+}
+
+#[repr(C)]
+struct XIValuatorState {
+    mask_len: c_int   ,
+    mask    : *mut u8 ,
+    values  : *mut f64,
+}
+
+#[repr(C)]
+struct XIRawEvent {
+    ty        : c_int          ,
+    serial    : c_ulong        ,
+    send_event: c_int          ,
+    display   : *mut c_void    ,
+    extension : c_int          ,
+    evtype    : c_int          ,
+    time      : c_ulong        ,
+    deviceid  : c_int          ,
+    sourceid  : c_int          ,
+    detail    : c_int          ,
+    flags     : c_int          ,
+    valuators : XIValuatorState,
+    raw_values: *mut f64       ,
+}
+
+type XIQueryVersion = unsafe extern "C" fn(
+    display: *mut c_void,
+    major  : *mut c_int ,
+    minor  : *mut c_int ,
+) -> c_int;
+type XISelectEvents = unsafe extern "C" fn(
+    display  : *mut c_void     ,
+    window   : c_ulong         ,
+    masks    : *mut XIEventMask,
+    num_masks: c_int           ,
+) -> c_int;
+
+/// The functions of the XInput library we use.
+#[derive(Clone, Copy)]
+struct XInput2 {
+    lib: *mut c_void,
+    select_events: XISelectEvents,
+}
+
+impl XInput2 {
+    /// Loads the XInput library, returns `None` if it's not available, or the
+    /// server doesn't support version 2.0.
+    fn load(display: *mut c_void) -> Option<Self> {
+        xinput_opcode(display)?;
+        let lib = unsafe{ dlopen(to_cstring("libXi.so.6").as_ptr(), RTLD_NOW) };
+        if lib.is_null() {
+            return None;
+        }
+        let query_version = unsafe{ dlsym(lib, to_cstring("XIQueryVersion").as_ptr()) };
+        let select_events = unsafe{ dlsym(lib, to_cstring("XISelectEvents").as_ptr()) };
+        if query_version.is_null() || select_events.is_null() {
+            unsafe{ dlclose(lib) };
+            return None;
+        }
+        let query_version = unsafe{ mem::transmute::<*mut c_void, XIQueryVersion>(query_version) };
+        // The server only sends XInput 2 events, if we tell it we know them
+        let mut major = 2;
+        let mut minor = 0;
+        if unsafe{ query_version(display, &mut major, &mut minor) } != 0 {
+            unsafe{ dlclose(lib) };
+            return None;
+        }
+        Some(Self{
+            lib,
+            select_events: unsafe{ mem::transmute::<*mut c_void, XISelectEvents>(select_events) },
+        })
+    }
+
+    /// Selects or deselects the raw motion events of all mice on the given
+    /// window. Returns `true`, if the request succeeded.
+    fn select_raw_motion(&self, display: *mut c_void, window: c_ulong, select: bool) -> bool {
+        let mut bits = [0u8; (XI_RawMotion as usize >> 3) + 1];
+        if select {
+            bits[XI_RawMotion as usize >> 3] |= 1 << (XI_RawMotion & 7);
+        }
+        let mut mask = XIEventMask{
+            deviceid: XIAllMasterDevices,
+            mask_len: bits.len() as c_int,
+            mask: bits.as_mut_ptr(),
+        };
+        let ret = unsafe{ (self.select_events)(display, window, &mut mask, 1) };
+        unsafe{ XFlush(display) };
+        ret == 0
+    }
+}
+
+/// Returns the major opcode of the XInput extension, that identifies its
+/// events, if the server has the extension.
+fn xinput_opcode(display: *mut c_void) -> Option<c_int> {
+    let name = to_cstring("XInputExtension");
+    let mut opcode = 0;
+    let mut first_event = 0;
+    let mut first_error = 0;
+    let ret = unsafe{ XQueryExtension(
+        display, name.as_ptr(), &mut opcode, &mut first_event, &mut first_error) };
+    if ret == 0 { None } else { Some(opcode) }
+}
+
+/// Returns the relative motion of a raw motion event. The values are only
+/// sent for the axes that moved, in the order of the axes.
+fn raw_motion_delta(raw: &XIRawEvent) -> (f64, f64) {
+    let mask = unsafe{ std::slice::from_raw_parts(
+        raw.valuators.mask, raw.valuators.mask_len as usize) };
+    let mut values = raw.raw_values;
+    let mut delta = (0.0, 0.0);
+    // The first two axes are X and Y
+    for axis in 0..std::cmp::min(mask.len() * 8, 2) {
+        if mask[axis >> 3] & (1 << (axis & 7)) == 0 {
+            continue;
+        }
+        let value = unsafe{ *values };
+        values = unsafe{ values.add(1) };
+        if axis == 0 { delta.0 = value } else { delta.1 = value }
+    }
+    delta
+}
+
 // TODO: Review all of these to_... functions, check CStr and such
 /// Converts the Rust &str into a C string.
 fn to_cstring(s: &str) -> Vec<c_char> {
@@ -329,28 +804,54 @@ fn to_cstring(s: &str) -> Vec<c_char> {
 // when no longer needed.
 // NOTE: Do we need all this crud?
 
+/// The display connection shared by everything on a thread.
+struct SharedConnection {
+    display: *mut c_void,
+    refs: usize,
+    // The XRandR library, loaded on first use, `Some(None)`, if it's missing
+    xrandr: Option<Option<XRandR>>,
+    // The XInput library, loaded on first use, `Some(None)`, if it's missing
+    xinput: Option<Option<XInput2>>,
+}
+
+impl SharedConnection {
+    fn new() -> Self {
+        Self{ display: ptr::null_mut(), refs: 0, xrandr: None, xinput: None }
+    }
+}
+
 thread_local! {
-    static CONNECTION: RefCell<(*mut c_void, usize)> = RefCell::new((ptr::null_mut(), 0));
+    static CONNECTION: RefCell<SharedConnection> = RefCell::new(SharedConnection::new());
+    // The windows, that asked for raw mouse motion
+    static RAW_MOUSE_WINDOWS: RefCell<HashSet<c_ulong>> = RefCell::new(HashSet::new());
 }
 
 fn ref_connection() -> *mut c_void {
     CONNECTION.with(|c| {
         let mut c = c.borrow_mut();
-        if c.1 == 0 {
-            c.0 = unsafe{ XOpenDisplay(ptr::null()) };
+        if c.refs == 0 {
+            c.display = unsafe{ XOpenDisplay(ptr::null()) };
         }
-        c.1 += 1;
-        c.0
+        c.refs += 1;
+        c.display
     })
 }
 
 fn forget_connection() {
     CONNECTION.with(|c| {
         let mut c = c.borrow_mut();
-        c.1 -= 1;
-        if c.1 == 0 {
-            unsafe{ XCloseDisplay(c.0) };
-            c.0 = ptr::null_mut();
+        c.refs -= 1;
+        if c.refs == 0 {
+            unsafe{ XCloseDisplay(c.display) };
+            c.display = ptr::null_mut();
+            // XRandR and XInput hook into the displays they were used with,
+            // so they can only be unloaded after the display is closed
+            if let Some(Some(xrandr)) = c.xrandr.take() {
+                unsafe{ dlclose(xrandr.lib) };
+            }
+            if let Some(Some(xinput)) = c.xinput.take() {
+                unsafe{ dlclose(xinput.lib) };
+            }
         }
     });
 }
@@ -362,6 +863,18 @@ impl Connection {
     fn new() -> Self {
         Self(ref_connection())
     }
+
+    /// Returns the XRandR library, loading it on first use, or `None`, if
+    /// it's not available.
+    fn xrandr(&self) -> Option<XRandR> {
+        CONNECTION.with(|c| *c.borrow_mut().xrandr.get_or_insert_with(XRandR::load))
+    }
+
+    /// Returns the XInput library, loading it on first use, or `None`, if
+    /// it's not available.
+    fn xinput(&self) -> Option<XInput2> {
+        CONNECTION.with(|c| *c.borrow_mut().xinput.get_or_insert_with(|| XInput2::load(self.0)))
+    }
 }
 
 impl Clone for Connection {
@@ -403,8 +916,13 @@ impl MonitorTrait for X11Monitor {
         self.handle
     }
 
+    fn is_valid(&self) -> bool {
+        !self.handle.is_null()
+    }
+
     fn name(&self) -> Option<String> {
-        // TODO
+        // NOTE: Screens have no names, only the XRandR outputs do, but a
+        // screen can span several of them
         None
     }
 
@@ -433,6 +951,38 @@ impl MonitorTrait for X11Monitor {
         PhysicalSize::new(width as u32, height as u32)
     }
 
+    fn work_area(&self) -> (PhysicalPosition, PhysicalSize) {
+        let whole = (self.position(), self.size());
+        // The window manager publishes the work area of each desktop on the
+        // root window, we use the first one
+        let name = to_cstring("_NET_WORKAREA");
+        let workarea = unsafe{ XInternAtom(self.srvr.0, name.as_ptr(), 1) };
+        if workarea == 0 {
+            return whole;
+        }
+        let root = unsafe{ XRootWindowOfScreen(self.handle) };
+        let mut ty = 0;
+        let mut format = 0;
+        let mut items = 0;
+        let mut bytes_after = 0;
+        let mut data = ptr::null_mut();
+        let ret = unsafe{ XGetWindowProperty(
+            self.srvr.0, root, workarea, 0, 4, 0, XA_CARDINAL,
+            &mut ty, &mut format, &mut items, &mut bytes_after, &mut data) };
+        if ret != 0 || data.is_null() {
+            return whole;
+        }
+        let mut result = whole;
+        if format == 32 && items == 4 {
+            // 32-bit items are returned as longs
+            let area = unsafe{ std::slice::from_raw_parts(data as *const c_long, 4) };
+            result = (PhysicalPosition::new(area[0] as i32, area[1] as i32),
+                PhysicalSize::new(area[2] as u32, area[3] as u32));
+        }
+        unsafe{ XFree(data.cast()) };
+        result
+    }
+
     fn dpi(&self) -> Dpi {
         let root = unsafe{ XRootWindowOfScreen(self.handle) };
         let (mut ret_root, mut xp, mut yp, mut width, mut height, mut border, mut depth) =
@@ -454,28 +1004,554 @@ impl MonitorTrait for X11Monitor {
         // TODO
         1.0
     }
+
+    fn video_modes(&self) -> Vec<VideoMode> {
+        let mut result = Vec::new();
+        let bit_depth = unsafe{ XDefaultDepthOfScreen(self.handle) } as u32;
+        if let Some(xrandr) = self.srvr.xrandr() {
+            let screen = unsafe{ XScreenNumberOfScreen(self.handle) };
+            let mut nsizes = 0;
+            let sizes = unsafe{ (xrandr.sizes)(self.srvr.0, screen, &mut nsizes) };
+            for i in 0..nsizes {
+                let size = unsafe{ &*sizes.add(i as usize) };
+                let size = PhysicalSize::new(size.width as u32, size.height as u32);
+                let mut nrates = 0;
+                let rates = unsafe{ (xrandr.rates)(self.srvr.0, screen, i, &mut nrates) };
+                if nrates == 0 {
+                    result.push(VideoMode{ size, bit_depth, refresh_rate: 0 });
+                }
+                for j in 0..nrates {
+                    let refresh_rate = unsafe{ *rates.add(j as usize) } as u32;
+                    result.push(VideoMode{ size, bit_depth, refresh_rate });
+                }
+            }
+        }
+        if result.is_empty() {
+            // Without XRandR only the current mode is known
+            result.push(VideoMode{ size: self.size(), bit_depth, refresh_rate: 0 });
+        }
+        result
+    }
+}
+
+/// Parses a `text/uri-list`, keeping only the local files.
+fn parse_uri_list(list: &[u8]) -> Vec<PathBuf> {
+    fn hex(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let mut result = Vec::new();
+    for line in list.split(|c| *c == b'\n') {
+        let line = match line.last() {
+            Some(b'\r') => &line[..line.len() - 1],
+            _ => line,
+        };
+        if line.is_empty() || line[0] == b'#' || !line.starts_with(b"file://") {
+            continue;
+        }
+        // Skip the host name
+        let line = &line[7..];
+        let path = match line.iter().position(|c| *c == b'/') {
+            Some(idx) => &line[idx..],
+            None => continue,
+        };
+        // Percent-decode
+        let mut bytes = Vec::with_capacity(path.len());
+        let mut i = 0;
+        while i < path.len() {
+            if path[i] == b'%' && i + 2 < path.len() {
+                if let (Some(h), Some(l)) = (hex(path[i + 1]), hex(path[i + 2])) {
+                    bytes.push(h * 16 + l);
+                    i += 3;
+                    continue;
+                }
+            }
+            bytes.push(path[i]);
+            i += 1;
+        }
+        result.push(PathBuf::from(OsString::from_vec(bytes)));
+    }
+    result
+}
+
+/// An XDND drag over one of our windows.
+struct XdndDrag {
+    source: c_ulong,
+    // `None` until the selection is converted
+    paths: Option<Vec<PathBuf>>,
+    requested: bool,
+    dropped: bool,
+}
+
+/// The receiving side of the XDND drag-and-drop protocol.
+struct Xdnd {
+    display: *mut c_void,
+    enter: c_ulong,
+    position: c_ulong,
+    status: c_ulong,
+    leave: c_ulong,
+    drop: c_ulong,
+    finished: c_ulong,
+    selection: c_ulong,
+    uri_list: c_ulong,
+    action_copy: c_ulong,
+    // The property of our window the selection is converted into
+    property: c_ulong,
+    drags: HashMap<c_ulong, XdndDrag>,
+}
+
+impl Xdnd {
+    fn new(display: *mut c_void) -> Self {
+        let atom = |name: &str| {
+            let name = to_cstring(name);
+            unsafe{ XInternAtom(display, name.as_ptr(), 0) }
+        };
+        Self{
+            display,
+            enter: atom("XdndEnter"),
+            position: atom("XdndPosition"),
+            status: atom("XdndStatus"),
+            leave: atom("XdndLeave"),
+            drop: atom("XdndDrop"),
+            finished: atom("XdndFinished"),
+            selection: atom("XdndSelection"),
+            uri_list: atom("text/uri-list"),
+            action_copy: atom("XdndActionCopy"),
+            property: atom("CACTI_XDND_SELECTION"),
+            drags: HashMap::new(),
+        }
+    }
+
+    /// Marks the window as a drop target.
+    fn set_aware(display: *mut c_void, window: c_ulong) {
+        let name = to_cstring("XdndAware");
+        let aware = unsafe{ XInternAtom(display, name.as_ptr(), 0) };
+        let version = XDND_VERSION;
+        unsafe{ XChangeProperty(
+            display, window, aware, XA_ATOM, 32, PropModeReplace,
+            &version as *const c_long as *const u8, 1) };
+    }
+
+    fn send(&self, target: c_ulong, message_type: c_ulong, data: [c_long; 5]) {
+        let mut e = XEvent::new();
+        e.client = XClientMessageEvent{
+            ty: ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: self.display,
+            window: target,
+            message_type,
+            format: 32,
+            data,
+        };
+        unsafe{
+            XSendEvent(self.display, target, 0, 0, &mut e);
+            XFlush(self.display);
+        }
+    }
+
+    fn request_paths(&self, window: c_ulong, time: c_ulong) {
+        unsafe{ XConvertSelection(
+            self.display, self.selection, self.uri_list, self.property, window, time) };
+    }
+
+    /// Finishes the drop on the given window, returning the dropped files.
+    fn finish(&mut self, window: c_ulong) -> Vec<WindowEvent> {
+        let drag = match self.drags.remove(&window) {
+            Some(drag) => drag,
+            None => return Vec::new(),
+        };
+        let paths = drag.paths.unwrap_or_default();
+        let accepted = !paths.is_empty();
+        let action = if accepted { self.action_copy as c_long } else { 0 };
+        self.send(drag.source, self.finished, [window as c_long, accepted as c_long, action, 0, 0]);
+        paths.into_iter().map(WindowEvent::FileDropped).collect()
+    }
+
+    /// Handles an XDND client message sent to one of our windows.
+    fn client_message(&mut self, msg: &XClientMessageEvent) -> Vec<WindowEvent> {
+        let window = msg.window;
+        let source = msg.data[0] as c_ulong;
+        if msg.message_type == self.enter {
+            let drag = XdndDrag{ source, paths: None, requested: false, dropped: false };
+            self.drags.insert(window, drag);
+        }
+        else if msg.message_type == self.position {
+            let (accept, request) = match self.drags.get_mut(&window) {
+                Some(drag) => {
+                    let request = !drag.requested;
+                    drag.requested = true;
+                    // Accept until we know there's nothing to drop
+                    let accept = !matches!(drag.paths.as_deref(), Some([]));
+                    (accept, request)
+                },
+                None => return Vec::new(),
+            };
+            if request {
+                self.request_paths(window, msg.data[3] as c_ulong);
+            }
+            let action = if accept { self.action_copy as c_long } else { 0 };
+            self.send(source, self.status, [window as c_long, accept as c_long, 0, 0, action]);
+        }
+        else if msg.message_type == self.leave {
+            // Only cancel, if something was reported as hovered
+            if let Some(XdndDrag{ paths: Some(paths), .. }) = self.drags.remove(&window) {
+                if !paths.is_empty() {
+                    return vec![WindowEvent::FileHoverCancelled];
+                }
+            }
+        }
+        else if msg.message_type == self.drop {
+            let (ready, request) = match self.drags.get_mut(&window) {
+                Some(drag) => {
+                    drag.dropped = true;
+                    let request = !drag.requested;
+                    drag.requested = true;
+                    (drag.paths.is_some(), request)
+                },
+                None => return Vec::new(),
+            };
+            if ready {
+                return self.finish(window);
+            }
+            if request {
+                self.request_paths(window, msg.data[2] as c_ulong);
+            }
+        }
+        Vec::new()
+    }
+
+    /// Handles the converted selection, holding the dragged paths.
+    fn selection_notify(&mut self, sel: &XSelectionEvent) -> Vec<WindowEvent> {
+        let window = sel.requestor;
+        if sel.selection != self.selection || !self.drags.contains_key(&window) {
+            return Vec::new();
+        }
+        let mut paths = Vec::new();
+        if sel.property != 0 {
+            let mut ty = 0;
+            let mut format = 0;
+            let mut items = 0;
+            let mut bytes_after = 0;
+            let mut data = ptr::null_mut();
+            let ret = unsafe{ XGetWindowProperty(
+                self.display, window, sel.property, 0, 0x1fffffff, 1, AnyPropertyType,
+                &mut ty, &mut format, &mut items, &mut bytes_after, &mut data) };
+            if ret == 0 && !data.is_null() {
+                if format == 8 {
+                    let list = unsafe{ std::slice::from_raw_parts(data, items as usize) };
+                    paths = parse_uri_list(list);
+                }
+                unsafe{ XFree(data.cast()) };
+            }
+        }
+        let drag = self.drags.get_mut(&window).unwrap();
+        let dropped = drag.dropped;
+        drag.paths = Some(paths.clone());
+        if dropped {
+            self.finish(window)
+        }
+        else {
+            paths.into_iter().map(WindowEvent::FileHovered).collect()
+        }
+    }
+}
+
+/// The `CLIPBOARD` selection. X11 has no central clipboard storage: the client
+/// that copied the text owns the selection, and sends the text to anyone who
+/// asks for it. So the text is only available to others while the owner is
+/// running, and its event loop answers the requests.
+#[derive(Debug)]
+struct Clipboard {
+    srvr: Connection,
+    // An unmapped window to own the selection and receive the text with
+    window: c_ulong,
+    clipboard: c_ulong,
+    targets: c_ulong,
+    utf8_string: c_ulong,
+    incr: c_ulong,
+    // The property the received text is stored in
+    property: c_ulong,
+    // The text, while we own the selection
+    text: Option<String>,
+}
+
+impl Clipboard {
+    fn new() -> Self {
+        let srvr = Connection::new();
+        let intern = |name: &str| {
+            let name = to_cstring(name);
+            unsafe{ XInternAtom(srvr.0, name.as_ptr(), 0) }
+        };
+        let clipboard = intern("CLIPBOARD");
+        let targets = intern("TARGETS");
+        let utf8_string = intern("UTF8_STRING");
+        let incr = intern("INCR");
+        let property = intern("CACTI_CLIPBOARD");
+        let window = unsafe{
+            let root = XRootWindowOfScreen(XDefaultScreenOfDisplay(srvr.0));
+            XCreateSimpleWindow(srvr.0, root, 0, 0, 1, 1, 0, 0, 0)
+        };
+        Self{ srvr, window, clipboard, targets, utf8_string, incr, property, text: None }
+    }
+
+    /// Returns the text of the selection, asking its owner for it.
+    fn text(&self) -> Option<String> {
+        // How long to wait for the owner to answer
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        let owner = unsafe{ XGetSelectionOwner(self.srvr.0, self.clipboard) };
+        if owner == 0 {
+            // Nobody copied anything
+            return None;
+        }
+        if owner == self.window {
+            return self.text.clone();
+        }
+        unsafe{
+            XConvertSelection(
+                self.srvr.0, self.clipboard, self.utf8_string, self.property, self.window, CurrentTime);
+            XFlush(self.srvr.0);
+        }
+        let start = Instant::now();
+        let mut e = XEvent::new();
+        while unsafe{ XCheckTypedWindowEvent(self.srvr.0, self.window, SelectionNotify, &mut e) } == 0 {
+            if start.elapsed() > TIMEOUT {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        if unsafe{ e.selection.property } == 0 {
+            // The owner can't give us text
+            return None;
+        }
+        let mut ty = 0;
+        let mut format = 0;
+        let mut items = 0;
+        let mut bytes_after = 0;
+        let mut data = ptr::null_mut();
+        let ret = unsafe{ XGetWindowProperty(
+            self.srvr.0, self.window, self.property, 0, 0x1fffffff, 1, AnyPropertyType,
+            &mut ty, &mut format, &mut items, &mut bytes_after, &mut data) };
+        if ret != 0 || data.is_null() {
+            return None;
+        }
+        // NOTE: Big texts are sent in chunks with INCR, we don't support that
+        let mut result = None;
+        if format == 8 && ty != self.incr {
+            let bytes = unsafe{ std::slice::from_raw_parts(data, items as usize) };
+            result = Some(String::from_utf8_lossy(bytes).into_owned());
+        }
+        unsafe{ XFree(data.cast()) };
+        result
+    }
+
+    /// Takes the ownership of the selection with the given text. Returns
+    /// `true`, if we became the owner.
+    fn set_text(&mut self, text: &str) -> bool {
+        self.text = Some(text.to_string());
+        unsafe{
+            XSetSelectionOwner(self.srvr.0, self.clipboard, self.window, CurrentTime);
+            XGetSelectionOwner(self.srvr.0, self.clipboard) == self.window
+        }
+    }
+
+    /// Answers a request of another client for our text.
+    fn selection_request(&self, req: &XSelectionRequestEvent) {
+        // Obsolete clients don't name a property
+        let property = if req.property == 0 { req.target } else { req.property };
+        let mut reply = XSelectionEvent{
+            ty: SelectionNotify,
+            serial: 0,
+            send_event: 1,
+            display: self.srvr.0,
+            requestor: req.requestor,
+            selection: req.selection,
+            target: req.target,
+            // Refused, unless we find something to send
+            property: 0,
+            time: req.time,
+        };
+        if let Some(text) = &self.text {
+            if req.target == self.targets {
+                let targets = [self.targets, self.utf8_string];
+                unsafe{ XChangeProperty(
+                    self.srvr.0, req.requestor, property, XA_ATOM, 32, PropModeReplace,
+                    targets.as_ptr().cast(), targets.len() as c_int) };
+                reply.property = property;
+            }
+            else if req.target == self.utf8_string {
+                unsafe{ XChangeProperty(
+                    self.srvr.0, req.requestor, property, self.utf8_string, 8, PropModeReplace,
+                    text.as_ptr(), text.len() as c_int) };
+                reply.property = property;
+            }
+        }
+        let mut e = XEvent::new();
+        e.selection = reply;
+        unsafe{
+            XSendEvent(self.srvr.0, req.requestor, 0, 0, &mut e);
+            XFlush(self.srvr.0);
+        }
+    }
+}
+
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        unsafe{ XDestroyWindow(self.srvr.0, self.window) };
+    }
+}
+
+/// An unmapped window, that the `X11Waker`s send their wakeup messages to.
+/// Client messages sent without an event mask go to the client that created
+/// the window, so the event loop receives them.
+#[derive(Debug)]
+struct WakeupWindow {
+    srvr: Connection,
+    window: c_ulong,
+    // The message type of the wakeups
+    message: c_ulong,
+}
+
+impl WakeupWindow {
+    fn new() -> Self {
+        let srvr = Connection::new();
+        let name = to_cstring("CACTI_WAKEUP");
+        let message = unsafe{ XInternAtom(srvr.0, name.as_ptr(), 0) };
+        let window = unsafe{
+            let root = XRootWindowOfScreen(XDefaultScreenOfDisplay(srvr.0));
+            XCreateSimpleWindow(srvr.0, root, 0, 0, 1, 1, 0, 0, 0)
+        };
+        unsafe{ XFlush(srvr.0) };
+        Self{ srvr, window, message }
+    }
+}
+
+impl Drop for WakeupWindow {
+    fn drop(&mut self) {
+        unsafe{ XDestroyWindow(self.srvr.0, self.window) };
+    }
+}
+
+/// Sends wakeup messages to the `WakeupWindow` of an event loop. A display
+/// connection must not be used by multiple threads at once, so every waker
+/// opens its own.
+#[derive(Debug)]
+pub struct X11Waker {
+    display: Mutex<*mut c_void>,
+    window: c_ulong,
+    message: c_ulong,
+}
+
+// SAFETY: The connection is only used while holding the lock
+unsafe impl Send for X11Waker {}
+unsafe impl Sync for X11Waker {}
+
+impl WakerTrait for X11Waker {
+    fn wake(&self) -> bool {
+        let display = match self.display.lock() {
+            Ok(display) => display,
+            Err(_) => return false,
+        };
+        if display.is_null() {
+            return false;
+        }
+        let mut e = XEvent::new();
+        e.client = XClientMessageEvent{
+            ty: ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: *display,
+            window: self.window,
+            message_type: self.message,
+            format: 32,
+            data: [0; 5],
+        };
+        let ret = unsafe{ XSendEvent(*display, self.window, 0, 0, &mut e) };
+        unsafe{ XFlush(*display) };
+        ret != 0
+    }
+}
+
+impl Drop for X11Waker {
+    fn drop(&mut self) {
+        let display = *self.display.get_mut().unwrap_or_else(|e| e.into_inner());
+        if !display.is_null() {
+            unsafe{ XCloseDisplay(display) };
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct X11EventLoop {
-    windows: HashSet<c_ulong>,
+    // Windows are added through a shared reference by the `WindowBuilder`
+    windows: RefCell<HashSet<c_ulong>>,
+    // Created on first use
+    clipboard: RefCell<Option<Clipboard>>,
+    // Created on first use
+    wakeup: RefCell<Option<WakeupWindow>>,
+}
+
+impl X11EventLoop {
+    fn with_clipboard<T>(&self, f: impl FnOnce(&mut Clipboard) -> T) -> T {
+        let mut clipboard = self.clipboard.borrow_mut();
+        f(clipboard.get_or_insert_with(Clipboard::new))
+    }
+
+    /// Checks, if the client message is a wakeup from an `X11Waker`.
+    fn is_wakeup(&self, msg: &XClientMessageEvent) -> bool {
+        match &*self.wakeup.borrow() {
+            Some(wakeup) => msg.window == wakeup.window && msg.message_type == wakeup.message,
+            None => false,
+        }
+    }
 }
 
 impl EventLoopTrait for X11EventLoop {
     fn new() -> Self {
         Self{
-            windows: HashSet::new(),
+            windows: RefCell::new(HashSet::new()),
+            clipboard: RefCell::new(None),
+            wakeup: RefCell::new(None),
         }
     }
 
-    fn add_window(&mut self, wnd: &X11Window) {
-        self.windows.insert(wnd.handle);
+    fn add_window(&self, wnd: &X11Window) {
+        self.windows.borrow_mut().insert(wnd.handle);
+    }
+
+    fn clipboard_text(&self) -> Option<String> {
+        self.with_clipboard(|c| c.text())
+    }
+
+    fn set_clipboard_text(&mut self, text: &str) -> bool {
+        self.with_clipboard(|c| c.set_text(text))
+    }
+
+    fn create_waker(&self) -> X11Waker {
+        let mut wakeup = self.wakeup.borrow_mut();
+        let wakeup = wakeup.get_or_insert_with(WakeupWindow::new);
+        X11Waker{
+            display: Mutex::new(unsafe{ XOpenDisplay(ptr::null()) }),
+            window: wakeup.window,
+            message: wakeup.message,
+        }
     }
 
     fn run<F>(&mut self, mut f: F)
-        where F: FnMut(&mut ControlFlow, Event) + 'static {
+        where F: FnMut(&mut ControlFlow, TimedEvent) + 'static {
+        // No windows can be added while running
+        let windows = self.windows.borrow();
         let srvr = Connection::new();
+        let mut xdnd = Xdnd::new(srvr.0);
+        let xinput = xinput_opcode(srvr.0);
+        // The focused window, kept up to date by the focus events, so raw
+        // motion can be routed without a round trip to the server
+        let mut focused = 0;
+        let mut revert_to = 0;
+        unsafe{ XGetInputFocus(srvr.0, &mut focused, &mut revert_to) };
         let mut e = XEvent::new();
+        let mut time = Instant::now();
         let mut unread = false;
         let mut control_flow = ControlFlow::Poll;
         let mut pushed_paint;
@@ -492,38 +1568,112 @@ impl EventLoopTrait for X11EventLoop {
                     }
                     // An event to handle
                     unsafe{ XNextEvent(srvr.0, &mut e) };
+                    time = Instant::now();
                 }
                 match unsafe{ e.ty } {
                     CreateNotify => {
                         let crea = unsafe{ &e.create_notify };
-                        if self.windows.contains(&crea.window) {
+                        if windows.contains(&crea.window) {
                             let window_id = WindowId(crea.window as *mut c_void);
-                            f(&mut control_flow, Event::WindowEvent{ window_id, event: WindowEvent::Created });
+                            let event = Event::WindowEvent{ window_id, event: WindowEvent::Created };
+                            f(&mut control_flow, TimedEvent::new(time, event));
                         }
                     },
                     DestroyNotify => {
                         let dest = unsafe{ &e.destroy_notify };
-                        if self.windows.contains(&dest.window) {
+                        if windows.contains(&dest.window) {
                             let window_id = WindowId(dest.window as *mut c_void);
-                            f(&mut control_flow, Event::WindowEvent{ window_id, event: WindowEvent::Closed });
+                            let event = Event::WindowEvent{ window_id, event: WindowEvent::Closed };
+                            f(&mut control_flow, TimedEvent::new(time, event));
                         }
                     },
                     FocusIn => {
                         let focus = unsafe{ &e.focus };
-                        let window_id = WindowId(focus.window as *mut c_void);
-                        f(&mut control_flow, Event::WindowEvent{ window_id, event: WindowEvent::FocusChanged(true) });
+                        focused = focus.window;
+                        if windows.contains(&focus.window) {
+                            let window_id = WindowId(focus.window as *mut c_void);
+                            let event = Event::WindowEvent{ window_id, event: WindowEvent::FocusChanged(true) };
+                            f(&mut control_flow, TimedEvent::new(time, event));
+                        }
                     },
                     FocusOut => {
                         let focus = unsafe{ &e.focus };
-                        let window_id = WindowId(focus.window as *mut c_void);
-                        f(&mut control_flow, Event::WindowEvent{ window_id, event: WindowEvent::FocusChanged(false) });
+                        if focused == focus.window {
+                            focused = 0;
+                        }
+                        if windows.contains(&focus.window) {
+                            let window_id = WindowId(focus.window as *mut c_void);
+                            let event = Event::WindowEvent{ window_id, event: WindowEvent::FocusChanged(false) };
+                            f(&mut control_flow, TimedEvent::new(time, event));
+                        }
                     },
                     ResizeRequest => {
                         // NOTE: A request is not exactly a resize...
                         let resize = unsafe{ &e.resize };
-                        let window_id = WindowId(resize.window as *mut c_void);
-                        let size = PhysicalSize::new(resize.width as u32, resize.height as u32);
-                        f(&mut control_flow, Event::WindowEvent{ window_id, event: WindowEvent::Resized(size) });
+                        if windows.contains(&resize.window) {
+                            let window_id = WindowId(resize.window as *mut c_void);
+                            let size = PhysicalSize::new(resize.width as u32, resize.height as u32);
+                            let event = Event::WindowEvent{ window_id, event: WindowEvent::Resized(size) };
+                            f(&mut control_flow, TimedEvent::new(time, event));
+                        }
+                    },
+                    ClientMessage => {
+                        let client = unsafe{ &e.client };
+                        if self.is_wakeup(client) {
+                            f(&mut control_flow, TimedEvent::new(time, Event::UserEvent(())));
+                        }
+                        else if windows.contains(&client.window) {
+                            let window_id = WindowId(client.window as *mut c_void);
+                            for event in xdnd.client_message(client) {
+                                let event = Event::WindowEvent{ window_id, event };
+                                f(&mut control_flow, TimedEvent::new(time, event));
+                            }
+                        }
+                    },
+                    SelectionNotify => {
+                        let sel = unsafe{ &e.selection };
+                        if windows.contains(&sel.requestor) {
+                            let window_id = WindowId(sel.requestor as *mut c_void);
+                            for event in xdnd.selection_notify(sel) {
+                                let event = Event::WindowEvent{ window_id, event };
+                                f(&mut control_flow, TimedEvent::new(time, event));
+                            }
+                        }
+                    },
+                    SelectionRequest => {
+                        let req = unsafe{ &e.selection_request };
+                        if let Some(clipboard) = &*self.clipboard.borrow() {
+                            if req.owner == clipboard.window {
+                                clipboard.selection_request(req);
+                            }
+                        }
+                    },
+                    SelectionClear => {
+                        let clear = unsafe{ &e.selection_clear };
+                        if let Some(clipboard) = &mut *self.clipboard.borrow_mut() {
+                            if clear.window == clipboard.window {
+                                // Someone else copied something
+                                clipboard.text = None;
+                            }
+                        }
+                    },
+                    GenericEvent => {
+                        let cookie = unsafe{ &mut e.cookie };
+                        if Some(cookie.extension) == xinput && cookie.evtype == XI_RawMotion
+                            && unsafe{ XGetEventData(srvr.0, cookie) } != 0 {
+                            let raw = unsafe{ &*(cookie.data as *const XIRawEvent) };
+                            let (dx, dy) = raw_motion_delta(raw);
+                            unsafe{ XFreeEventData(srvr.0, cookie) };
+                            // Raw events arrive on the root window, they belong
+                            // to the focused window
+                            let raw_mouse = RAW_MOUSE_WINDOWS.with(|w| w.borrow().contains(&focused));
+                            if raw_mouse && windows.contains(&focused) && (dx != 0.0 || dy != 0.0) {
+                                let window_id = WindowId(focused as *mut c_void);
+                                let event = WindowEvent::RawMouseMotion{ dx, dy };
+                                let event = Event::WindowEvent{ window_id, event };
+                                f(&mut control_flow, TimedEvent::new(time, event));
+                            }
+                        }
                     },
                     // TODO: Paint =>  { pushed_paint = true; break; }
                     _ => {},
@@ -534,16 +1684,16 @@ impl EventLoopTrait for X11EventLoop {
             // TODO: For now we always just paint here
             if !pushed_paint {
                 // No paint event happened, we explicitly do a LogicUpdate
-                f(&mut control_flow, Event::LogicUpdate);
+                f(&mut control_flow, TimedEvent::now(Event::LogicUpdate));
                 // The logic update could have enforced a redraw
                 // TODO: For now we just redraw anyway
-                for w in &self.windows {
+                for w in windows.iter() {
                     let window_id = WindowId(*w as *mut c_void);
-                    f(&mut control_flow, Event::Redraw(window_id));
+                    f(&mut control_flow, TimedEvent::now(Event::Redraw(window_id)));
                 }
             }
 
-            f(&mut control_flow, Event::AfterRedraw);
+            f(&mut control_flow, TimedEvent::now(Event::AfterRedraw));
 
             match control_flow {
                 ControlFlow::Exit => break,
@@ -553,6 +1703,7 @@ impl EventLoopTrait for X11EventLoop {
                     if res == 0 {
                         break;
                     }
+                    time = Instant::now();
                     unread = true;
                 }
             }
@@ -567,37 +1718,142 @@ pub struct X11Window {
     resizable: bool,
     // TODO: We need to actually update this!
     inner_size: PhysicalSize,
+    resize_increments: Option<PhysicalSize>,
+}
+
+impl X11Window {
+    /// Asks the window manager to add or remove the given `_NET_WM_STATE`
+    /// property of the window. Returns `None`, if the window manager doesn't
+    /// support the property, otherwise `true`, if the request was sent.
+    fn change_wm_state(&self, add: bool, property: &str) -> Option<bool> {
+        let name = to_cstring("_NET_WM_STATE");
+        let wm_state = unsafe{ XInternAtom(self.srvr.0, name.as_ptr(), 1) };
+        let name = to_cstring(property);
+        let property = unsafe{ XInternAtom(self.srvr.0, name.as_ptr(), 1) };
+        if wm_state == 0 || property == 0 {
+            return None;
+        }
+        let mut attribs = XWindowAttributes::new();
+        unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
+        let action = if add { _NET_WM_STATE_ADD } else { _NET_WM_STATE_REMOVE };
+        let mut e = XEvent::new();
+        e.client = XClientMessageEvent{
+            ty: ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: self.srvr.0,
+            window: self.handle,
+            message_type: wm_state,
+            format: 32,
+            // Action, property, no second property, normal application source
+            data: [action, property as c_long, 0, 1, 0],
+        };
+        let ret = unsafe{ XSendEvent(
+            self.srvr.0, attribs.root, 0,
+            SubstructureRedirectMask | SubstructureNotifyMask,
+            &mut e) };
+        unsafe{ XFlush(self.srvr.0) };
+        Some(ret != 0)
+    }
+
+    /// Sends the size hints, combining the fixed size of a non-resizable
+    /// window and the resize increments.
+    fn update_size_hints(&self) {
+        let hints_ptr = unsafe{ XAllocSizeHints() };
+        let mut hints = unsafe{ &mut *hints_ptr };
+
+        if !self.resizable {
+            let width = self.inner_size.width as c_int;
+            let height = self.inner_size.height as c_int;
+            hints.flags |= PMinSize | PMaxSize;
+            hints.min_width = width;
+            hints.max_width = width;
+            hints.min_height = height;
+            hints.max_height = height;
+        }
+        if let Some(inc) = self.resize_increments {
+            // The increments are counted from the base size
+            hints.flags |= PResizeInc | PBaseSize;
+            hints.width_inc = inc.width as c_int;
+            hints.height_inc = inc.height as c_int;
+            hints.base_width = 0;
+            hints.base_height = 0;
+        }
+
+        unsafe{ XSetWMNormalHints(self.srvr.0, self.handle, hints_ptr) };
+        unsafe{ XFree(hints_ptr as *mut c_void) };
+    }
+
+    /// Sets or clears the urgency hint of the window, keeping the other hints.
+    fn set_urgency_hint(&self, urgent: bool) {
+        let mut hints_ptr = unsafe{ XGetWMHints(self.srvr.0, self.handle) };
+        if hints_ptr.is_null() {
+            // No hints were set yet
+            hints_ptr = unsafe{ XAllocWMHints() };
+        }
+        let hints = unsafe{ &mut *hints_ptr };
+        if urgent {
+            hints.flags |= XUrgencyHint;
+        }
+        else {
+            hints.flags &= !XUrgencyHint;
+        }
+        unsafe{ XSetWMHints(self.srvr.0, self.handle, hints_ptr) };
+        unsafe{ XFree(hints_ptr as *mut c_void) };
+    }
 }
 
 impl WindowTrait for X11Window {
-    fn new() -> Self {
+    fn new(attribs: &WindowAttributes) -> io::Result<Self> {
         let srvr = Connection::new();
         let screen = unsafe{ XDefaultScreenOfDisplay(srvr.0) };
         let root = unsafe{ XRootWindowOfScreen(screen) };
         let black = unsafe{ XBlackPixel(srvr.0, 0) };
         let white = unsafe{ XWhitePixel(srvr.0, 0) };
-        let inner_size = PhysicalSize::new(200, 200);
+        let inner_size = attribs.inner_size.unwrap_or_else(|| PhysicalSize::new(200, 200));
+        let position = attribs.position.unwrap_or_else(|| PhysicalPosition::new(100, 100));
         unsafe{ XSelectInput(srvr.0, root, SubstructureNotifyMask) };
         let handle = unsafe{ XCreateSimpleWindow(
             srvr.0,
             root,
-            100, 100,
+            position.x, position.y,
             inner_size.width, inner_size.height,
             1,
             black, white) };
+        if handle == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "Failed to create the X11 window!"));
+        }
         unsafe{ XSelectInput(srvr.0, handle, ExposureMask | FocusChangeMask | ResizeRedirectMask) };
-        Self{
+        let mut result = Self{
             srvr,
             handle,
-            resizable: true,
+            resizable: attribs.resizable,
             inner_size,
+            resize_increments: None,
+        };
+        // Everything has to be set before mapping, so the window manager sees
+        // the final state
+        result.set_title(&attribs.title);
+        if !attribs.resizable {
+            result.update_size_hints();
+        }
+        if attribs.file_drop {
+            Xdnd::set_aware(result.srvr.0, handle);
         }
+        if attribs.visible {
+            result.set_visible(true);
+        }
+        Ok(result)
     }
 
     fn handle_ptr(&self) -> *mut c_void {
         self.handle as *mut c_void
     }
 
+    fn is_valid(&self) -> bool {
+        self.handle != 0
+    }
+
     fn monitor(&self) -> X11Monitor {
         let mut attribs = XWindowAttributes::new();
         unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
@@ -612,7 +1868,43 @@ impl WindowTrait for X11Window {
     }
 
     fn outer_size(&self) -> PhysicalSize {
-        unimplemented!()
+        // NOTE: This doesn't include the decorations of the window manager
+        let mut attribs = XWindowAttributes::new();
+        unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
+        let border = attribs.border_width * 2;
+        PhysicalSize::new((attribs.width + border) as u32, (attribs.height + border) as u32)
+    }
+
+    fn dpi(&self) -> Option<Dpi> {
+        // NOTE: X11 has no per-window DPI, the monitor value is used
+        None
+    }
+
+    fn scale(&self) -> Option<f64> {
+        None
+    }
+
+    fn position(&self) -> PhysicalPosition {
+        let mut attribs = XWindowAttributes::new();
+        unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
+        let (mut x, mut y, mut child) = (0, 0, 0);
+        unsafe{ XTranslateCoordinates(
+            self.srvr.0, self.handle, attribs.root,
+            0, 0,
+            &mut x, &mut y,
+            &mut child) };
+        PhysicalPosition::new(x, y)
+    }
+
+    fn title(&self) -> Option<String> {
+        let mut name: *mut c_char = ptr::null_mut();
+        let ret = unsafe{ XFetchName(self.srvr.0, self.handle, &mut name) };
+        if ret == 0 || name.is_null() {
+            return None;
+        }
+        let title = unsafe{ CStr::from_ptr(name) }.to_string_lossy().into_owned();
+        unsafe{ XFree(name as *mut c_void) };
+        Some(title)
     }
 
     fn set_visible(&mut self, vis: bool) {
@@ -626,21 +1918,25 @@ impl WindowTrait for X11Window {
 
     fn set_resizable(&mut self, res: bool) -> bool {
         self.resizable = res;
-        let hints_ptr = unsafe{ XAllocSizeHints() };
-        let mut hints = unsafe{ &mut *hints_ptr };
+        self.update_size_hints();
+        true
+    }
 
-        if !res {
-            let width = self.inner_size.width as i32;
-            let height = self.inner_size.height as i32;
-            hints.flags = PMinSize | PMaxSize;
-            hints.min_width = width;
-            hints.max_width = width;
-            hints.min_height = height;
-            hints.max_height = height;
+    fn set_decorations(&mut self, d: bool) -> bool {
+        let name = to_cstring("_MOTIF_WM_HINTS");
+        let motif_hints = unsafe{ XInternAtom(self.srvr.0, name.as_ptr(), 0) };
+        if motif_hints == 0 {
+            return false;
+        }
+        // Flags, functions, decorations, input mode and status, only the
+        // decorations are set
+        let hints: [c_ulong; 5] = [MWM_HINTS_DECORATIONS, 0, d as c_ulong, 0, 0];
+        unsafe{
+            XChangeProperty(
+                self.srvr.0, self.handle, motif_hints, motif_hints, 32, PropModeReplace,
+                hints.as_ptr() as *const u8, hints.len() as c_int);
+            XFlush(self.srvr.0);
         }
-
-        unsafe{ XSetWMNormalHints(self.srvr.0, self.handle, hints_ptr) };
-        unsafe{ XFree(hints_ptr as *mut c_void) };
         true
     }
 
@@ -673,15 +1969,7 @@ impl WindowTrait for X11Window {
         // X11 keeps reporting the old sizes, so set_resizable locks to the old
         // size.
         if !self.resizable {
-            let hints_ptr = unsafe{ XAllocSizeHints() };
-            let mut hints = unsafe{ &mut *hints_ptr };
-            hints.flags = PMinSize | PMaxSize;
-            hints.min_width = siz.width as c_int;
-            hints.max_width = siz.width as c_int;
-            hints.min_height = siz.height as c_int;
-            hints.max_height = siz.height as c_int;
-            unsafe{ XSetWMNormalHints(self.srvr.0, self.handle, hints_ptr) };
-            unsafe{ XFree(hints_ptr as *mut c_void) };
+            self.update_size_hints();
         }
         true
     }
@@ -690,17 +1978,214 @@ impl WindowTrait for X11Window {
         unimplemented!()
     }
 
+    fn set_resize_increments(&mut self, inc: Option<PhysicalSize>) -> bool {
+        if let Some(inc) = inc {
+            if inc.width == 0 || inc.height == 0 {
+                return false;
+            }
+        }
+        self.resize_increments = inc;
+        self.update_size_hints();
+        true
+    }
+
     fn set_pinned(&mut self, p: bool) -> bool {
         // TODO
         false
     }
 
     fn set_transparency(&mut self, t: f64) -> bool {
-        // TODO
+        // Compositing window managers read the opacity from this property
+        let name = to_cstring("_NET_WM_WINDOW_OPACITY");
+        let opacity = unsafe{ XInternAtom(self.srvr.0, name.as_ptr(), 0) };
+        if opacity == 0 {
+            return false;
+        }
+        // Format 32 properties are passed as longs
+        let value = (clamp_opacity(t) * 0xffffffffu32 as f64).round() as c_ulong;
+        unsafe{
+            XChangeProperty(
+                self.srvr.0, self.handle, opacity, XA_CARDINAL, 32, PropModeReplace,
+                &value as *const c_ulong as *const u8, 1);
+            XFlush(self.srvr.0);
+        }
+        true
+    }
+
+    fn set_content_protected(&mut self, _p: bool) -> bool {
+        // X11 has no way to keep other clients from reading the window
         false
     }
 
-    fn set_fullscreen(&mut self, fs: bool) -> bool {
-        unimplemented!()
+    fn set_raw_mouse(&mut self, raw: bool) -> bool {
+        let xinput = match self.srvr.xinput() {
+            Some(xinput) => xinput,
+            None => return false,
+        };
+        let mut attribs = XWindowAttributes::new();
+        unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
+        RAW_MOUSE_WINDOWS.with(|windows| {
+            let mut windows = windows.borrow_mut();
+            if raw {
+                windows.insert(self.handle);
+            }
+            else {
+                windows.remove(&self.handle);
+            }
+            // Raw events are only sent to the root window, the event loop
+            // routes them to the focused window
+            xinput.select_raw_motion(self.srvr.0, attribs.root, !windows.is_empty())
+        })
+    }
+
+    fn set_fullscreen(&mut self, fs: Fullscreen) -> bool {
+        match fs {
+            Fullscreen::None =>
+                self.change_wm_state(false, "_NET_WM_STATE_FULLSCREEN") == Some(true),
+            Fullscreen::Borderless(monitor) => {
+                // NOTE: Every monitor is a separate screen, the window can't
+                // be moved to another one
+                if let Some(monitor) = monitor {
+                    let mut attribs = XWindowAttributes::new();
+                    unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
+                    if attribs.screen != monitor.0.handle {
+                        return false;
+                    }
+                }
+                self.change_wm_state(true, "_NET_WM_STATE_FULLSCREEN") == Some(true)
+            },
+            // TODO: Change the video mode with XRandR
+            Fullscreen::Exclusive(_) => false,
+        }
+    }
+
+    fn set_cursor_icon(&mut self, icon: CursorIcon) -> bool {
+        let shape = match icon {
+            CursorIcon::Default    => XC_left_ptr           ,
+            CursorIcon::Pointer    => XC_hand2              ,
+            CursorIcon::Text       => XC_xterm              ,
+            CursorIcon::Crosshair  => XC_crosshair          ,
+            CursorIcon::Wait       => XC_watch              ,
+            CursorIcon::ResizeNS   => XC_sb_v_double_arrow  ,
+            CursorIcon::ResizeEW   => XC_sb_h_double_arrow  ,
+            CursorIcon::ResizeNESW => XC_bottom_left_corner ,
+            CursorIcon::ResizeNWSE => XC_bottom_right_corner,
+            CursorIcon::NotAllowed => XC_X_cursor           ,
+        };
+        let cursor = unsafe{ XCreateFontCursor(self.srvr.0, shape) };
+        if cursor == 0 {
+            return false;
+        }
+        // The server keeps the cursor alive, while the window uses it
+        unsafe{
+            XDefineCursor(self.srvr.0, self.handle, cursor);
+            XFreeCursor(self.srvr.0, cursor);
+            XFlush(self.srvr.0);
+        }
+        true
+    }
+
+    fn request_attention(&mut self, level: Option<AttentionLevel>) -> bool {
+        // Only critical requests are marked urgent
+        self.set_urgency_hint(level == Some(AttentionLevel::Critical));
+        match self.change_wm_state(level.is_some(), "_NET_WM_STATE_DEMANDS_ATTENTION") {
+            Some(sent) => sent,
+            None => {
+                // The window manager doesn't support it, but the hint is still set
+                unsafe{ XFlush(self.srvr.0) };
+                true
+            },
+        }
+    }
+
+    fn begin_drag(&mut self) -> bool {
+        let mut attribs = XWindowAttributes::new();
+        unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) };
+        // We need the pointer position relative to the root
+        let (mut root, mut child) = (0, 0);
+        let (mut root_x, mut root_y, mut win_x, mut win_y, mut mask) = (0, 0, 0, 0, 0);
+        let ret = unsafe{ XQueryPointer(
+            self.srvr.0, self.handle,
+            &mut root, &mut child,
+            &mut root_x, &mut root_y,
+            &mut win_x, &mut win_y,
+            &mut mask) };
+        if ret == 0 {
+            return false;
+        }
+        let name = to_cstring("_NET_WM_MOVERESIZE");
+        let moveresize = unsafe{ XInternAtom(self.srvr.0, name.as_ptr(), 1) };
+        if moveresize == 0 {
+            // The window manager doesn't support it
+            return false;
+        }
+        // We have to release the pointer, so the window manager can grab it
+        unsafe{ XUngrabPointer(self.srvr.0, CurrentTime) };
+        let mut e = XEvent::new();
+        e.client = XClientMessageEvent{
+            ty: ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: self.srvr.0,
+            window: self.handle,
+            message_type: moveresize,
+            format: 32,
+            // Position, direction, left button, normal application source
+            data: [root_x as c_long, root_y as c_long, _NET_WM_MOVERESIZE_MOVE, 1, 1],
+        };
+        let ret = unsafe{ XSendEvent(
+            self.srvr.0, attribs.root, 0,
+            SubstructureRedirectMask | SubstructureNotifyMask,
+            &mut e) };
+        unsafe{ XFlush(self.srvr.0) };
+        ret != 0
+    }
+
+    fn present_rgba(&mut self, pixels: &[u8], width: u32, height: u32) -> bool {
+        let mut attribs = XWindowAttributes::new();
+        if unsafe{ XGetWindowAttributes(self.srvr.0, self.handle, &mut attribs) } == 0 {
+            return false;
+        }
+        if attribs.depth != 24 && attribs.depth != 32 {
+            // TODO: Convert to other visuals
+            return false;
+        }
+        // In LSBFirst byte order, BGRX bytes give 0xXXRRGGBB pixels
+        let mut data = rgba_to_bgra(pixels);
+        let mut image = XImage{
+            width: width as c_int,
+            height: height as c_int,
+            xoffset: 0,
+            format: ZPixmap,
+            data: data.as_mut_ptr() as *mut c_char,
+            byte_order: LSBFirst,
+            bitmap_unit: 32,
+            bitmap_bit_order: LSBFirst,
+            bitmap_pad: 32,
+            depth: attribs.depth,
+            bytes_per_line: (width * 4) as c_int,
+            bits_per_pixel: 32,
+            red_mask: 0xff0000,
+            green_mask: 0x00ff00,
+            blue_mask: 0x0000ff,
+            obdata: ptr::null_mut(),
+            funcs: [ptr::null_mut(); 6],
+        };
+        // The image only borrows the data, so it's not destroyed with XDestroyImage
+        if unsafe{ XInitImage(&mut image) } == 0 {
+            return false;
+        }
+        // The default GC is made for the root window, its depth can differ
+        // from ours
+        let gc = unsafe{ XCreateGC(self.srvr.0, self.handle, 0, ptr::null_mut()) };
+        if gc.is_null() {
+            return false;
+        }
+        unsafe{
+            XPutImage(self.srvr.0, self.handle, gc, &mut image, 0, 0, 0, 0, width, height);
+            XFreeGC(self.srvr.0, gc);
+            XFlush(self.srvr.0);
+        }
+        true
     }
 }